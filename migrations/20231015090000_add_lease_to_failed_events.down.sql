@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE failed_events
+    DROP COLUMN lease_owner,
+    DROP COLUMN lease_expires_at;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE failed_events
+    ADD COLUMN lease_owner TEXT DEFAULT NULL,
+    ADD COLUMN lease_expires_at TIMESTAMPTZ DEFAULT NULL;
@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE events_published
+    DROP COLUMN dispatched_at,
+    DROP COLUMN lease_owner,
+    DROP COLUMN lease_expires_at;
@@ -0,0 +1,8 @@
+-- Add up migration script here
+ALTER TABLE events_published
+    ADD COLUMN dispatched_at TIMESTAMPTZ DEFAULT NULL,
+    ADD COLUMN lease_owner TEXT DEFAULT NULL,
+    ADD COLUMN lease_expires_at TIMESTAMPTZ DEFAULT NULL;
+
+-- Events published before dispatch tracking existed must not be dispatched again
+UPDATE events_published SET dispatched_at = COALESCE(fulfilled_at, created_at, NOW());
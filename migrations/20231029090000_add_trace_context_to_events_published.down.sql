@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE events_published
+    DROP COLUMN traceparent,
+    DROP COLUMN tracestate;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE events_published
+    ADD COLUMN traceparent TEXT,
+    ADD COLUMN tracestate TEXT;
@@ -0,0 +1,17 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS delivery_attempts (
+    id UUID PRIMARY KEY,
+    event_id UUID NOT NULL REFERENCES events_published (id) ON DELETE CASCADE,
+    service_name TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    attempt SMALLINT NOT NULL,
+    status_code INTEGER,
+    succeeded BOOLEAN NOT NULL,
+    latency_ms BIGINT NOT NULL,
+    error TEXT,
+    attempted_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX delivery_attempts_event_id_idx ON delivery_attempts (event_id, attempted_at);
+CREATE INDEX delivery_attempts_service_name_idx ON delivery_attempts (service_name, event_id);
+CREATE INDEX events_published_created_at_idx ON events_published (created_at);
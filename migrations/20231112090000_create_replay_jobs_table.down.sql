@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE delivery_attempts DROP COLUMN IF EXISTS replay_id;
+DROP TABLE IF EXISTS replay_jobs;
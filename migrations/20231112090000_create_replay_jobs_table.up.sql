@@ -0,0 +1,21 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS replay_jobs (
+    id UUID PRIMARY KEY,
+    service_name TEXT NOT NULL,
+    event_type TEXT,
+    published_after TIMESTAMPTZ,
+    published_before TIMESTAMPTZ,
+    event_ids UUID[],
+    rate_per_second INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    total BIGINT NOT NULL DEFAULT 0,
+    delivered BIGINT NOT NULL DEFAULT 0,
+    failed BIGINT NOT NULL DEFAULT 0,
+    skipped BIGINT NOT NULL DEFAULT 0,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL,
+    finished_at TIMESTAMPTZ
+);
+
+ALTER TABLE delivery_attempts
+    ADD COLUMN replay_id UUID REFERENCES replay_jobs (id) ON DELETE SET NULL;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE delivery_attempts
+    DROP COLUMN filtered;
+
+ALTER TABLE event_type_to_service
+    DROP COLUMN filter;
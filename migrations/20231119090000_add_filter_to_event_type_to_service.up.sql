@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE event_type_to_service
+    ADD COLUMN filter TEXT DEFAULT NULL;
+
+ALTER TABLE delivery_attempts
+    ADD COLUMN filtered BOOLEAN NOT NULL DEFAULT FALSE;
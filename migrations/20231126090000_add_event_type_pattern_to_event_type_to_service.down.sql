@@ -0,0 +1,5 @@
+-- Add down migration script here
+DELETE FROM event_type_to_service WHERE event_type_pattern IS NOT NULL;
+
+ALTER TABLE event_type_to_service
+    DROP COLUMN event_type_pattern;
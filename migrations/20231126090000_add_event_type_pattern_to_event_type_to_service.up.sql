@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Pattern subscriptions have no event type of their own, they cover every matching one
+ALTER TABLE event_type_to_service
+    ADD COLUMN event_type_pattern TEXT DEFAULT NULL;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE event_type_to_service
+    DROP COLUMN target_schema,
+    DROP COLUMN transform;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE event_type_to_service
+    ADD COLUMN transform TEXT DEFAULT NULL,
+    ADD COLUMN target_schema TEXT DEFAULT NULL;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE event_type_to_service
+    DROP COLUMN delivery_settings;
+
+ALTER TABLE services
+    DROP COLUMN delivery_settings;
@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE services
+    ADD COLUMN delivery_settings TEXT DEFAULT NULL;
+
+ALTER TABLE event_type_to_service
+    ADD COLUMN delivery_settings TEXT DEFAULT NULL;
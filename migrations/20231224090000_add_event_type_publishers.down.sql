@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS publish_denials;
+
+ALTER TABLE event_types
+    DROP COLUMN publishers;
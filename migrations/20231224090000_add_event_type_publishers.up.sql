@@ -0,0 +1,15 @@
+-- Add up migration script here
+ALTER TABLE event_types
+    ADD COLUMN publishers TEXT[] DEFAULT NULL;
+
+CREATE TABLE IF NOT EXISTS publish_denials (
+    id UUID PRIMARY KEY,
+    event_type TEXT NOT NULL,
+    api_key_id UUID NOT NULL,
+    api_key_name TEXT NOT NULL,
+    service_name TEXT,
+    reason TEXT NOT NULL,
+    attempted_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX publish_denials_attempted_at_idx ON publish_denials (attempted_at);
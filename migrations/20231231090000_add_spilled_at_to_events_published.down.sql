@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS events_published_spilled_at_idx;
+
+ALTER TABLE events_published
+    DROP COLUMN spilled_at;
@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE events_published
+    ADD COLUMN spilled_at TIMESTAMPTZ DEFAULT NULL;
+
+CREATE INDEX events_published_spilled_at_idx ON events_published (spilled_at)
+    WHERE spilled_at IS NOT NULL;
@@ -0,0 +1,2 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS events_published_undispatched_idx;
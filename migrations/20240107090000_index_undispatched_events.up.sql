@@ -0,0 +1,3 @@
+-- Add up migration script here
+CREATE INDEX events_published_undispatched_idx ON events_published (created_at)
+    WHERE dispatched_at IS NULL;
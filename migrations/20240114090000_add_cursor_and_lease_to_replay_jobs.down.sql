@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE replay_jobs
+    DROP COLUMN cursor_published_at,
+    DROP COLUMN cursor_event_id,
+    DROP COLUMN lease_owner,
+    DROP COLUMN lease_expires_at;
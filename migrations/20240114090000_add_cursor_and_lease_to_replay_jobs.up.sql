@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE replay_jobs
+    ADD COLUMN cursor_published_at TIMESTAMPTZ DEFAULT NULL,
+    ADD COLUMN cursor_event_id UUID DEFAULT NULL,
+    ADD COLUMN lease_owner TEXT DEFAULT NULL,
+    ADD COLUMN lease_expires_at TIMESTAMPTZ DEFAULT NULL;
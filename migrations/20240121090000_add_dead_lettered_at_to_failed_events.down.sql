@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE failed_events
+    DROP COLUMN dead_lettered_at;
@@ -0,0 +1,7 @@
+-- Add up migration script here
+ALTER TABLE failed_events
+    ADD COLUMN dead_lettered_at TIMESTAMPTZ DEFAULT NULL;
+
+-- Failures that ran out of their 3 retries (MAX_RETRIES) before this column existed are taken
+-- to have been dead-lettered at their last retry time
+UPDATE failed_events SET dead_lettered_at = retry_time WHERE retries >= 3;
@@ -0,0 +1,347 @@
+use std::{collections::HashMap, sync::Arc};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use reqwest::{header::AUTHORIZATION, Client};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::common::{
+    clock::Clock,
+    configuration::{DeliveryTlsSettings, QueueSettings},
+    delivery_settings::{DeliveryAuth, DeliverySettings, DeliveryTls},
+    errors::{DeliveryAuthError, InvalidDeliverySettingsError},
+    secrets::{SealedSecret, SecretCipher},
+    types::EventFulfillmentDetails,
+};
+
+// Seals delivery settings for storage and turns the stored settings of a subscription into the
+// client and headers its deliveries use, fetching OAuth2 tokens as needed and keeping them
+// until shortly before they expire
+#[derive(Debug)]
+pub struct DeliveryCredentials {
+    // None without a configured encryption key, when no delivery settings can be stored
+    cipher: Option<SecretCipher>,
+    // Delivers, and fetches tokens, with the configured TLS settings
+    client: Client,
+    tls: DeliveryTls,
+    delivery_timeout: std::time::Duration,
+    // Built once for each TLS setup services ask for
+    tls_clients: Mutex<HashMap<DeliveryTls, Client>>,
+    clock: Arc<dyn Clock>,
+    refresh_margin: Duration,
+    tokens: Mutex<HashMap<TokenKey, TokenSlot>>,
+}
+
+pub struct Credentials {
+    pub client: Client,
+    pub headers: Vec<(String, String)>,
+}
+
+// Subscriptions share a token only when they fetch it with the same credentials over the same
+// TLS setup. The secret is kept as a hash so the key can be logged and compared safely.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct TokenKey {
+    token_url: String,
+    client_id: String,
+    client_secret_hash: String,
+    scope: Option<String>,
+    tls: Option<DeliveryTls>,
+}
+
+impl TokenKey {
+    fn new(
+        token_url: String,
+        client_id: String,
+        client_secret: &str,
+        scope: Option<String>,
+        tls: Option<DeliveryTls>,
+    ) -> Self {
+        Self {
+            token_url,
+            client_id,
+            client_secret_hash: format!("{:x}", Sha256::digest(client_secret.as_bytes())),
+            scope,
+            tls,
+        }
+    }
+}
+
+// Locked while its token is fetched, so a slow token server only holds up the deliveries that
+// need its tokens
+type TokenSlot = Arc<Mutex<Option<CachedToken>>>;
+
+#[derive(Debug)]
+struct CachedToken {
+    access_token: Secret<String>,
+    // None when the token server did not say how long the token lasts; it is then kept until
+    // a subscriber refuses it
+    refresh_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+}
+
+impl DeliveryCredentials {
+    pub fn new(
+        settings: &QueueSettings,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, InvalidDeliverySettingsError> {
+        let cipher = settings
+            .delivery_auth
+            .encryption_key
+            .as_ref()
+            .map(SecretCipher::new)
+            .transpose()?;
+        let tls = configured_tls(&settings.delivery_tls)?;
+        let client = build_client(&tls, settings.delivery_timeout())?;
+        let refresh_margin = i64::try_from(settings.delivery_auth.token_refresh_margin_seconds)
+            .map(Duration::seconds)
+            .unwrap_or_else(|_| Duration::zero());
+        Ok(Self {
+            cipher,
+            client,
+            tls,
+            delivery_timeout: settings.delivery_timeout(),
+            tls_clients: Mutex::default(),
+            clock,
+            refresh_margin,
+            tokens: Mutex::default(),
+        })
+    }
+
+    // Validates the settings and encrypts them for storage. Without an encryption key they are
+    // refused, as they could not be stored.
+    pub fn seal(
+        &self,
+        settings: &DeliverySettings,
+    ) -> Result<SealedSecret, InvalidDeliverySettingsError> {
+        let cipher = self.cipher.as_ref().ok_or_else(|| {
+            InvalidDeliverySettingsError::new(
+                "Delivery settings can't be stored without delivery_auth.encryption_key set",
+            )
+        })?;
+        settings.validate()?;
+        let settings =
+            serde_json::to_string(settings).expect("Delivery settings serialize to JSON");
+        Ok(cipher.seal(&settings))
+    }
+
+    // The client a delivery to the subscription is sent with and the headers it carries
+    pub async fn credentials(
+        &self,
+        subscription: &EventFulfillmentDetails,
+    ) -> Result<Credentials, DeliveryAuthError> {
+        let settings = self.settings(subscription)?;
+        let client = self.client(settings.tls.clone()).await?;
+        let mut headers: Vec<(String, String)> = settings.headers.into_iter().collect();
+        let authorization = match settings.auth {
+            None => return Ok(Credentials { client, headers }),
+            Some(DeliveryAuth::Basic { username, password }) => format!(
+                "Basic {}",
+                STANDARD.encode(format!("{}:{}", username, password))
+            ),
+            Some(DeliveryAuth::OAuth2ClientCredentials {
+                token_url,
+                client_id,
+                client_secret,
+                scope,
+            }) => {
+                let key = TokenKey::new(token_url, client_id, &client_secret, scope, settings.tls);
+                let token = self.token(key, &client_secret, &client).await?;
+                format!("Bearer {}", token.expose_secret())
+            }
+        };
+        headers.push((AUTHORIZATION.to_string(), authorization));
+        Ok(Credentials { client, headers })
+    }
+
+    // Drops the token a subscriber refused so the next delivery fetches a new one
+    pub async fn forget_token(&self, subscription: &EventFulfillmentDetails) {
+        if let Ok(DeliverySettings {
+            auth:
+                Some(DeliveryAuth::OAuth2ClientCredentials {
+                    token_url,
+                    client_id,
+                    client_secret,
+                    scope,
+                }),
+            tls,
+            ..
+        }) = self.settings(subscription)
+        {
+            self.tokens.lock().await.remove(&TokenKey::new(
+                token_url,
+                client_id,
+                &client_secret,
+                scope,
+                tls,
+            ));
+        }
+    }
+
+    async fn client(&self, tls: Option<DeliveryTls>) -> Result<Client, DeliveryAuthError> {
+        let Some(tls) = tls else {
+            return Ok(self.client.clone());
+        };
+        let tls = tls.over(&self.tls);
+        let mut tls_clients = self.tls_clients.lock().await;
+        if let Some(client) = tls_clients.get(&tls) {
+            return Ok(client.clone());
+        }
+        let client = build_client(&tls, self.delivery_timeout)?;
+        tls_clients.insert(tls, client.clone());
+        Ok(client)
+    }
+
+    fn settings(
+        &self,
+        subscription: &EventFulfillmentDetails,
+    ) -> Result<DeliverySettings, DeliveryAuthError> {
+        let service = self.open(subscription.service_delivery_settings.as_ref())?;
+        let subscription = self.open(subscription.delivery_settings.as_ref())?;
+        Ok(DeliverySettings::merge(service, subscription))
+    }
+
+    fn open(
+        &self,
+        sealed: Option<&SealedSecret>,
+    ) -> Result<Option<DeliverySettings>, DeliveryAuthError> {
+        let Some(sealed) = sealed else {
+            return Ok(None);
+        };
+        let cipher = self.cipher.as_ref().ok_or_else(|| {
+            DeliveryAuthError::new(
+                "The stored delivery settings can't be read without delivery_auth.encryption_key set",
+            )
+        })?;
+        let settings = cipher.open(sealed)?;
+        serde_json::from_str(settings.expose_secret())
+            .map(Some)
+            .map_err(|err| {
+                DeliveryAuthError::new(&format!(
+                    "The stored delivery settings could not be read: {}",
+                    err
+                ))
+            })
+    }
+
+    // Only the client's slot is held while fetching, so concurrent deliveries for the client
+    // wait for one token request while other clients carry on. The token is fetched with the
+    // same client, and so the same TLS setup, as the delivery.
+    async fn token(
+        &self,
+        key: TokenKey,
+        client_secret: &str,
+        client: &Client,
+    ) -> Result<Secret<String>, DeliveryAuthError> {
+        let slot = Arc::clone(self.tokens.lock().await.entry(key.clone()).or_default());
+        let mut cached = slot.lock().await;
+        let now = self.clock.now();
+        if let Some(token) = cached
+            .as_ref()
+            .filter(|token| token.refresh_at.is_none_or(|refresh_at| now < refresh_at))
+        {
+            return Ok(token.access_token.clone());
+        }
+
+        let token = self.fetch_token(&key, client_secret, client, now).await?;
+        let access_token = token.access_token.clone();
+        *cached = Some(token);
+        Ok(access_token)
+    }
+
+    async fn fetch_token(
+        &self,
+        key: &TokenKey,
+        client_secret: &str,
+        client: &Client,
+        now: DateTime<Utc>,
+    ) -> Result<CachedToken, DeliveryAuthError> {
+        tracing::info!(
+            "Fetching an OAuth2 token for client {} from {}",
+            key.client_id,
+            key.token_url
+        );
+        let token_error = |err: reqwest::Error| {
+            DeliveryAuthError::new(&format!(
+                "Could not fetch an OAuth2 token from {}: {}",
+                key.token_url, err
+            ))
+        };
+        let mut form = vec![
+            ("grant_type", "client_credentials"),
+            ("client_id", key.client_id.as_str()),
+            ("client_secret", client_secret),
+        ];
+        if let Some(scope) = &key.scope {
+            form.push(("scope", scope.as_str()));
+        }
+
+        let token = client
+            .post(&key.token_url)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(token_error)?
+            .json::<TokenResponse>()
+            .await
+            .map_err(token_error)?;
+
+        Ok(CachedToken {
+            access_token: Secret::new(token.access_token),
+            refresh_at: token
+                .expires_in
+                .map(|expires_in| now + Duration::seconds(expires_in) - self.refresh_margin),
+        })
+    }
+}
+
+fn configured_tls(
+    settings: &DeliveryTlsSettings,
+) -> Result<DeliveryTls, InvalidDeliverySettingsError> {
+    let read = |path: &String| {
+        std::fs::read_to_string(path).map_err(|err| {
+            InvalidDeliverySettingsError::new(&format!("Could not read {}: {}", path, err))
+        })
+    };
+    let ca_certificates = settings
+        .ca_bundle_paths
+        .iter()
+        .map(read)
+        .collect::<Result<Vec<_>, _>>()?
+        .join("\n");
+    let tls = DeliveryTls {
+        ca_certificates: Some(ca_certificates).filter(|bundle| !bundle.is_empty()),
+        client_certificate: settings
+            .client_certificate_path
+            .as_ref()
+            .map(read)
+            .transpose()?,
+        client_key: settings.client_key_path.as_ref().map(read).transpose()?,
+    };
+    Ok(tls)
+}
+
+fn build_client(
+    tls: &DeliveryTls,
+    delivery_timeout: std::time::Duration,
+) -> Result<Client, InvalidDeliverySettingsError> {
+    let builder = tls.certificates()?.into_iter().fold(
+        Client::builder().timeout(delivery_timeout),
+        |builder, certificate| builder.add_root_certificate(certificate),
+    );
+    let builder = match tls.identity()? {
+        Some(identity) => builder.identity(identity),
+        None => builder,
+    };
+    builder.build().map_err(|err| {
+        InvalidDeliverySettingsError::new(&format!("Could not build the delivery client: {}", err))
+    })
+}
@@ -1,3 +1,10 @@
+pub mod delivery_credentials;
+pub mod replay;
+pub mod retention;
+pub mod retry_scheduler;
+pub mod stream_subscribers;
+pub mod subscription_filters;
+pub mod supervisor;
 pub mod ventrix_queue;
 
 pub enum ListenToEventResult {
@@ -0,0 +1,570 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use actix_web::web;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::StatusCode;
+use serde_json::Value;
+use tokio::time::MissedTickBehavior;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        clock::Clock,
+        configuration::QueueSettings,
+        errors::{InvalidReplayRequestError, VentrixError},
+        metrics::{status_class, METRICS},
+        types::{
+            DeliveryAttempt, EventFulfillmentDetails, ReplayJob, ReplayRequest, ReplayStatus,
+            VentrixEvent,
+        },
+    },
+    infrastructure::persistence::Database,
+};
+
+use super::{
+    delivery_credentials::DeliveryCredentials,
+    subscription_filters::SubscriptionFilters,
+    supervisor::SupervisedTask,
+    ventrix_queue::{
+        delivery_attempt, delivery_body, delivery_trace_context, event_payload, lease_duration,
+        restart_backoff, save_delivery_attempt, DELIVERY_AUTH_STATUS_CLASS,
+    },
+};
+
+// Sent with every replayed delivery, carrying the id of the replay
+pub const REPLAY_HEADER: &str = "X-Ventrix-Replay";
+const REPLAY_PAGE_SIZE: i64 = 100;
+
+enum ReplayOutcome {
+    Delivered,
+    Failed,
+    Skipped,
+}
+
+// Runs replays in the background, each supervised so one that panics picks up after the last
+// event it saved. A replay holds a lease on its job that it renews as it makes progress, so the
+// replays of an instance that stopped are carried on by another once their lease expires.
+#[derive(Debug)]
+pub(super) struct Replays {
+    database: web::Data<dyn Database>,
+    delivery_credentials: Arc<DeliveryCredentials>,
+    subscription_filters: Arc<SubscriptionFilters>,
+    clock: Arc<dyn Clock>,
+    settings: QueueSettings,
+    instance_id: String,
+    running: Mutex<HashMap<Uuid, SupervisedTask>>,
+}
+
+impl Replays {
+    pub(super) fn new(
+        database: web::Data<dyn Database>,
+        delivery_credentials: Arc<DeliveryCredentials>,
+        subscription_filters: Arc<SubscriptionFilters>,
+        clock: Arc<dyn Clock>,
+        settings: QueueSettings,
+        instance_id: String,
+    ) -> Self {
+        Self {
+            database,
+            delivery_credentials,
+            subscription_filters,
+            clock,
+            settings,
+            instance_id,
+            running: Mutex::default(),
+        }
+    }
+
+    pub(super) async fn start(&self, request: ReplayRequest) -> Result<ReplayJob, VentrixError> {
+        let database = self.database.get_ref();
+        let now = self.clock.now();
+        let mut job =
+            new_replay_job(request, database, now, self.settings.replay_per_second).await?;
+        job.lease_owner = Some(self.instance_id.clone());
+        job.lease_expires_at = Some(now + replay_lease(&self.settings, &job));
+        database.create_replay_job(&job).await?;
+
+        self.run(job.id);
+        Ok(job)
+    }
+
+    // Carries on the running replays whose lease has expired
+    pub(super) async fn resume_abandoned(&self) {
+        let lease = lease_duration(&self.settings);
+        match self
+            .database
+            .claim_replay_jobs(&self.instance_id, lease)
+            .await
+        {
+            Ok(jobs) => {
+                for job in jobs {
+                    tracing::info!("Resuming replay {}", job.id);
+                    self.run(job.id);
+                }
+            }
+            Err(err) => tracing::warn!("There was an issue claiming replays to resume: {}", err),
+        }
+    }
+
+    fn run(&self, replay_id: Uuid) {
+        let mut running_lock = self.running.lock().unwrap();
+        running_lock.retain(|_, task| !task.is_finished());
+        // A replay this instance still runs may be claimed back once it falls behind its lease
+        if running_lock.contains_key(&replay_id) {
+            return;
+        }
+
+        let database = web::Data::clone(&self.database);
+        let delivery_credentials = Arc::clone(&self.delivery_credentials);
+        let subscription_filters = Arc::clone(&self.subscription_filters);
+        let clock = Arc::clone(&self.clock);
+        let settings = self.settings.clone();
+        let instance_id = self.instance_id.clone();
+        let task = SupervisedTask::spawn_until_done(
+            "replay",
+            restart_backoff(&self.settings),
+            move || {
+                let replay_span = tracing::info_span!("Running replay", replay_id = %replay_id);
+                run_replay(
+                    replay_id,
+                    web::Data::clone(&database),
+                    Arc::clone(&delivery_credentials),
+                    Arc::clone(&subscription_filters),
+                    Arc::clone(&clock),
+                    settings.clone(),
+                    instance_id.clone(),
+                )
+                .instrument(replay_span)
+            },
+        );
+        running_lock.insert(replay_id, task);
+    }
+}
+
+// Resumes abandoned replays on startup and on every reconcile interval after
+pub(super) async fn replay_resumer(replays: Arc<Replays>) {
+    let reconcile_interval = replays.settings.retry_reconcile_interval();
+    loop {
+        replays.resume_abandoned().await;
+        tokio::time::sleep(reconcile_interval).await;
+    }
+}
+
+// Long enough for the replay to wait its turn and deliver an event before renewing it
+fn replay_lease(settings: &QueueSettings, job: &ReplayJob) -> Duration {
+    let interval = settings.replay_interval(job.rate_per_second.unsigned_abs());
+    lease_duration(settings) + Duration::from_std(interval).unwrap_or_else(|_| Duration::zero())
+}
+
+// Validates the request and builds the job for it, counting the events it selects
+pub(super) async fn new_replay_job(
+    request: ReplayRequest,
+    database: &dyn Database,
+    now: DateTime<Utc>,
+    default_rate_per_second: u32,
+) -> Result<ReplayJob, VentrixError> {
+    let rate_per_second = request.rate_per_second.unwrap_or(default_rate_per_second);
+    let rate_per_second = i32::try_from(rate_per_second)
+        .ok()
+        .filter(|rate| *rate > 0)
+        .ok_or_else(|| {
+            InvalidReplayRequestError::new("rate_per_second must be a positive number")
+        })?;
+
+    let (published_after, published_before) = match (&request.event_ids, &request.event_type) {
+        (Some(_), Some(_)) => {
+            return Err(InvalidReplayRequestError::new(
+                "Select events either by event_ids or by event_type, not both",
+            )
+            .into())
+        }
+        (Some(event_ids), None) => {
+            if event_ids.is_empty() {
+                return Err(InvalidReplayRequestError::new("event_ids must not be empty").into());
+            }
+            if request.published_after.is_some() || request.published_before.is_some() {
+                return Err(InvalidReplayRequestError::new(
+                    "A time window only applies when replaying by event_type",
+                )
+                .into());
+            }
+            (None, None)
+        }
+        (None, Some(_)) => {
+            let published_after = request.published_after.ok_or_else(|| {
+                InvalidReplayRequestError::new(
+                    "published_after is required when replaying by event_type",
+                )
+            })?;
+            let published_before = request.published_before.unwrap_or(now);
+            if published_after >= published_before {
+                return Err(InvalidReplayRequestError::new(
+                    "published_after must be before published_before",
+                )
+                .into());
+            }
+            (Some(published_after), Some(published_before))
+        }
+        (None, None) => {
+            return Err(InvalidReplayRequestError::new(
+                "Select events by event_ids or by event_type and a time window",
+            )
+            .into())
+        }
+    };
+
+    database.get_service(&request.service_name).await?;
+    if let Some(event_type) = &request.event_type {
+        if subscriptions(database, &request.service_name, event_type)
+            .await?
+            .is_empty()
+        {
+            return Err(InvalidReplayRequestError::new(&format!(
+                "Service {:?} does not listen to event type {:?}",
+                request.service_name, event_type
+            ))
+            .into());
+        }
+    }
+
+    let mut job = ReplayJob {
+        id: Uuid::new_v4(),
+        service_name: request.service_name,
+        event_type: request.event_type,
+        published_after,
+        published_before,
+        event_ids: request.event_ids,
+        rate_per_second,
+        status: ReplayStatus::Running,
+        total: 0,
+        delivered: 0,
+        failed: 0,
+        skipped: 0,
+        error: None,
+        created_at: now,
+        finished_at: None,
+        cursor_published_at: None,
+        cursor_event_id: None,
+        lease_owner: None,
+        lease_expires_at: None,
+    };
+    job.total = database.count_events_to_replay(&job).await?;
+    Ok(job)
+}
+
+// Delivers the selected events oldest first at the job's pace, saving progress after each
+// one, until they run out, the job is cancelled or another instance takes it over
+async fn run_replay(
+    replay_id: Uuid,
+    database: web::Data<dyn Database>,
+    delivery_credentials: Arc<DeliveryCredentials>,
+    subscription_filters: Arc<SubscriptionFilters>,
+    clock: Arc<dyn Clock>,
+    settings: QueueSettings,
+    lease_owner: String,
+) {
+    let database = database.get_ref();
+    let clock = clock.as_ref();
+    let mut job = match database.get_replay_job(replay_id).await {
+        Ok(job) if is_held_by(&job, &lease_owner) => job,
+        Ok(_) => return,
+        Err(err) => {
+            // Left for the lease to expire, after which the replay is resumed
+            tracing::warn!("Could not load replay {}. Err: {}", replay_id, err);
+            return;
+        }
+    };
+
+    match replay_events(
+        &mut job,
+        database,
+        &delivery_credentials,
+        &subscription_filters,
+        clock,
+        &settings,
+    )
+    .await
+    {
+        Ok(None) => return,
+        Ok(Some(status)) => job.status = status,
+        Err(err) => {
+            tracing::error!("Replay {} failed. Err: {}", job.id, err);
+            job.status = ReplayStatus::Failed;
+            job.error = Some(err.to_string());
+        }
+    }
+    job.finished_at = Some(clock.now());
+
+    if let Err(err) = database.update_replay_job(&job).await {
+        tracing::warn!(
+            "Could not save the outcome of replay {}. Err: {}",
+            job.id,
+            err
+        );
+    }
+}
+
+fn is_held_by(job: &ReplayJob, lease_owner: &str) -> bool {
+    job.status == ReplayStatus::Running && job.lease_owner.as_deref() == Some(lease_owner)
+}
+
+// Pages through the events with a cursor on when they were published, so events pruned during
+// the replay do not shift the pages. None when the replay stopped before the events ran out.
+async fn replay_events(
+    job: &mut ReplayJob,
+    database: &dyn Database,
+    delivery_credentials: &DeliveryCredentials,
+    subscription_filters: &SubscriptionFilters,
+    clock: &dyn Clock,
+    settings: &QueueSettings,
+) -> Result<Option<ReplayStatus>, VentrixError> {
+    let interval = settings.replay_interval(job.rate_per_second.unsigned_abs());
+    let lease = replay_lease(settings, job);
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        let events = database.get_events_to_replay(job, REPLAY_PAGE_SIZE).await?;
+        if events.is_empty() {
+            return Ok(Some(ReplayStatus::Completed));
+        }
+
+        for (published_at, event) in events {
+            ticker.tick().await;
+            let stored_job = database.get_replay_job(job.id).await?;
+            if stored_job.status != ReplayStatus::Running {
+                tracing::info!("Replay {} was cancelled", job.id);
+                return Ok(None);
+            }
+            if stored_job.lease_owner != job.lease_owner {
+                tracing::info!("Replay {} was taken over by another instance", job.id);
+                return Ok(None);
+            }
+
+            match replay_event(
+                job,
+                &event,
+                database,
+                delivery_credentials,
+                subscription_filters,
+                clock,
+            )
+            .await?
+            {
+                ReplayOutcome::Delivered => job.delivered += 1,
+                ReplayOutcome::Failed => job.failed += 1,
+                ReplayOutcome::Skipped => job.skipped += 1,
+            }
+            job.cursor_published_at = Some(published_at);
+            job.cursor_event_id = Some(event.id);
+            job.lease_expires_at = Some(clock.now() + lease);
+            database.update_replay_job(job).await?;
+        }
+    }
+}
+
+async fn replay_event(
+    job: &ReplayJob,
+    event: &VentrixEvent,
+    database: &dyn Database,
+    delivery_credentials: &DeliveryCredentials,
+    subscription_filters: &SubscriptionFilters,
+    clock: &dyn Clock,
+) -> Result<ReplayOutcome, VentrixError> {
+    // Events the service's filters no longer match are skipped like those it stopped listening to
+    let payload = event_payload(event);
+    let subscriptions: Vec<_> = subscriptions(database, &job.service_name, &event.event_type)
+        .await?
+        .into_iter()
+        .filter(|subscription| {
+            subscription_filters.matches(subscription.filter.as_deref(), event, &payload)
+        })
+        .collect();
+    if subscriptions.is_empty() {
+        return Ok(ReplayOutcome::Skipped);
+    }
+
+    let mut delivered = true;
+    for subscription in subscriptions {
+        delivered &= deliver(
+            job,
+            event,
+            &payload,
+            &subscription,
+            database,
+            delivery_credentials,
+            clock,
+        )
+        .await;
+    }
+
+    match delivered {
+        true => Ok(ReplayOutcome::Delivered),
+        false => Ok(ReplayOutcome::Failed),
+    }
+}
+
+async fn deliver(
+    job: &ReplayJob,
+    event: &VentrixEvent,
+    payload: &Value,
+    subscription: &EventFulfillmentDetails,
+    database: &dyn Database,
+    delivery_credentials: &DeliveryCredentials,
+    clock: &dyn Clock,
+) -> bool {
+    let replay_span = tracing::info_span!(
+        "Replaying event",
+        replay_id = %job.id,
+        event_id = %event.id,
+        service = %subscription.name,
+    );
+    let trace_context = delivery_trace_context(&replay_span, event);
+
+    let attempt = DeliveryAttempt {
+        replay_id: Some(job.id),
+        ..delivery_attempt(
+            event,
+            &subscription.name,
+            &subscription.endpoint,
+            clock.now(),
+            std::time::Duration::ZERO,
+        )
+    };
+    let body = match delivery_body(
+        event,
+        subscription.transform.as_deref(),
+        subscription.target_schema.as_deref(),
+        payload,
+    ) {
+        Ok(body) => body,
+        Err(err) => {
+            tracing::warn!(
+                "Replay {} could not prepare event {} for Service {}. Err: {}",
+                job.id,
+                event.id,
+                subscription.name,
+                err
+            );
+            METRICS.record_delivery(&subscription.name, "invalid_payload", false);
+            let attempt = DeliveryAttempt {
+                succeeded: false,
+                error: Some(err.to_string()),
+                ..attempt
+            };
+            save_delivery_attempt(database, &attempt).await;
+            return false;
+        }
+    };
+    let credentials = match delivery_credentials.credentials(subscription).await {
+        Ok(credentials) => credentials,
+        Err(err) => {
+            tracing::warn!(
+                "Replay {} could not authenticate event {} for Service {}. Err: {}",
+                job.id,
+                event.id,
+                subscription.name,
+                err
+            );
+            METRICS.record_delivery(&subscription.name, DELIVERY_AUTH_STATUS_CLASS, false);
+            let attempt = DeliveryAttempt {
+                succeeded: false,
+                error: Some(err.to_string()),
+                ..attempt
+            };
+            save_delivery_attempt(database, &attempt).await;
+            return false;
+        }
+    };
+    let request = trace_context.headers().fold(
+        credentials
+            .client
+            .post(format!("{}{}", subscription.url, subscription.endpoint))
+            .header(REPLAY_HEADER, job.id.to_string()),
+        |request, (name, value)| request.header(name, value),
+    );
+    let request = credentials
+        .headers
+        .into_iter()
+        .fold(request, |request, (name, value)| {
+            request.header(name, value)
+        });
+
+    let delivery_timer = METRICS.delivery_timer(&subscription.name);
+    let started_at = Instant::now();
+    let response = request.json(&body).send().instrument(replay_span).await;
+    delivery_timer.observe_duration();
+    let attempt = DeliveryAttempt {
+        latency_ms: i64::try_from(started_at.elapsed().as_millis()).unwrap_or(i64::MAX),
+        ..attempt
+    };
+
+    let (attempt, status_class) = match response {
+        Ok(response) => {
+            let status = response.status();
+            if status == StatusCode::UNAUTHORIZED {
+                delivery_credentials.forget_token(subscription).await;
+            }
+            let attempt = DeliveryAttempt {
+                status_code: Some(i32::from(status.as_u16())),
+                ..attempt
+            };
+            match response.error_for_status() {
+                Ok(_) => (attempt, status_class(status)),
+                Err(err) => (
+                    DeliveryAttempt {
+                        succeeded: false,
+                        error: Some(err.to_string()),
+                        ..attempt
+                    },
+                    status_class(status),
+                ),
+            }
+        }
+        Err(err) => {
+            let status_class = match err.is_timeout() {
+                true => "timeout",
+                false => "connection_error",
+            };
+            (
+                DeliveryAttempt {
+                    succeeded: false,
+                    error: Some(err.to_string()),
+                    ..attempt
+                },
+                status_class.to_string(),
+            )
+        }
+    };
+
+    METRICS.record_delivery(&subscription.name, &status_class, attempt.succeeded);
+    if let Some(err) = &attempt.error {
+        tracing::warn!(
+            "Replay {} could not deliver event {} to Service {}. Err: {}",
+            job.id,
+            event.id,
+            subscription.name,
+            err
+        );
+    }
+    save_delivery_attempt(database, &attempt).await;
+    attempt.succeeded
+}
+
+async fn subscriptions(
+    database: &dyn Database,
+    service_name: &str,
+    event_type: &str,
+) -> Result<Vec<EventFulfillmentDetails>, VentrixError> {
+    Ok(database
+        .get_service_by_event_type(event_type)
+        .await?
+        .into_iter()
+        .filter(|subscription| subscription.name == service_name)
+        .collect())
+}
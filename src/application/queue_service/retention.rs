@@ -0,0 +1,305 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use actix_web::web;
+use chrono::{DateTime, Duration, Utc};
+use flate2::{write::GzEncoder, Compression};
+use tokio::time::MissedTickBehavior;
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        clock::Clock,
+        configuration::RetentionSettings,
+        metrics::METRICS,
+        types::{DeliveryAttempt, EventHistory, PruneCriteria, PublishedEvent, RetainedEventKind},
+    },
+    infrastructure::persistence::Database,
+};
+
+// Deletes the events that are past their retention period on every prune interval. Instances
+// sharing the database take turns, so only one of them prunes and archives at a time.
+pub(super) async fn retention_pruner(
+    database: web::Data<dyn Database>,
+    settings: RetentionSettings,
+    clock: Arc<dyn Clock>,
+) {
+    let database = database.get_ref();
+    let clock = clock.as_ref();
+    let mut ticker = tokio::time::interval(settings.prune_interval());
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+        let _pruning_lock = match database.try_lock_pruning().await {
+            Ok(Some(pruning_lock)) => pruning_lock,
+            Ok(None) => {
+                tracing::debug!("Another instance is pruning events");
+                continue;
+            }
+            Err(err) => {
+                tracing::warn!("Could not take the pruning lock. Err: {}", err);
+                continue;
+            }
+        };
+        for criteria in prune_criteria(&settings, clock.now()) {
+            let pruned = prune_events(database, &settings, &criteria, clock).await;
+            if pruned > 0 {
+                tracing::info!("Pruned {} {} events", pruned, criteria.kind.as_str());
+            }
+        }
+    }
+}
+
+// One criteria per configured period. Event types with their own periods are left out of
+// the default ones, even when they keep those events forever.
+fn prune_criteria(settings: &RetentionSettings, now: DateTime<Utc>) -> Vec<PruneCriteria> {
+    let overridden: Vec<String> = settings
+        .event_types
+        .iter()
+        .map(|retention| retention.event_type.clone())
+        .collect();
+    let mut criteria = vec![];
+
+    for (kind, days) in [
+        (RetainedEventKind::Fulfilled, settings.fulfilled_days),
+        (RetainedEventKind::DeadLetter, settings.dead_letter_days),
+        (RetainedEventKind::Unfulfilled, settings.unfulfilled_days),
+    ] {
+        if let Some(days) = days {
+            criteria.push(PruneCriteria {
+                kind,
+                event_type: None,
+                excluded_event_types: overridden.clone(),
+                before: now - Duration::days(days.into()),
+            });
+        }
+    }
+
+    for retention in &settings.event_types {
+        for (kind, days) in [
+            (RetainedEventKind::Fulfilled, retention.fulfilled_days),
+            (RetainedEventKind::DeadLetter, retention.dead_letter_days),
+            (RetainedEventKind::Unfulfilled, retention.unfulfilled_days),
+        ] {
+            if let Some(days) = days {
+                criteria.push(PruneCriteria {
+                    kind,
+                    event_type: Some(retention.event_type.clone()),
+                    excluded_event_types: vec![],
+                    before: now - Duration::days(days.into()),
+                });
+            }
+        }
+    }
+
+    criteria
+}
+
+// Deletes the selected events one batch at a time, archiving each batch first when an
+// archive directory is set. A batch that could not be archived is kept for the next run.
+async fn prune_events(
+    database: &dyn Database,
+    settings: &RetentionSettings,
+    criteria: &PruneCriteria,
+    clock: &dyn Clock,
+) -> usize {
+    let batch_size = usize::try_from(settings.batch_size).unwrap_or_default();
+    let mut pruned = 0;
+
+    loop {
+        let events = match database
+            .get_events_to_prune(criteria, settings.batch_size)
+            .await
+        {
+            Ok(events) => events,
+            Err(err) => {
+                tracing::warn!("Could not find events to prune. Err: {}", err);
+                return pruned;
+            }
+        };
+        if events.is_empty() {
+            return pruned;
+        }
+
+        if let Some(archive_directory) = &settings.archive_directory {
+            match archive_events(database, Path::new(archive_directory), &events, clock.now()).await
+            {
+                Ok(path) => tracing::info!("Archived {} events to {:?}", events.len(), path),
+                Err(err) => {
+                    tracing::warn!("Could not archive events, so they were kept. Err: {}", err);
+                    return pruned;
+                }
+            }
+        }
+
+        let event_ids: Vec<Uuid> = events.iter().map(|event| event.id).collect();
+        if let Err(err) = database.delete_events(&event_ids).await {
+            tracing::warn!("Could not delete events past their retention. Err: {}", err);
+            return pruned;
+        }
+        METRICS.record_pruned_events(criteria.kind.as_str(), events.len());
+        pruned += events.len();
+
+        if events.len() < batch_size {
+            return pruned;
+        }
+        tokio::time::sleep(settings.batch_pause()).await;
+    }
+}
+
+// Writes the events and their delivery attempts to a new gzipped NDJSON file, one event per
+// line. The file only gets its final name once it is complete.
+async fn archive_events(
+    database: &dyn Database,
+    archive_directory: &Path,
+    events: &[PublishedEvent],
+    now: DateTime<Utc>,
+) -> Result<PathBuf, Box<dyn Error + Send + Sync>> {
+    let event_ids: Vec<Uuid> = events.iter().map(|event| event.id).collect();
+    let mut deliveries: HashMap<Uuid, Vec<DeliveryAttempt>> = HashMap::new();
+    for attempt in database
+        .get_delivery_attempts_for_events(&event_ids)
+        .await?
+    {
+        deliveries
+            .entry(attempt.event_id)
+            .or_default()
+            .push(attempt);
+    }
+
+    let mut lines = Vec::with_capacity(events.len());
+    for event in events {
+        let history = EventHistory {
+            event: event.clone(),
+            deliveries: deliveries.remove(&event.id).unwrap_or_default(),
+        };
+        lines.push(serde_json::to_string(&history)?);
+    }
+
+    let path = archive_directory.join(format!(
+        "events-{}-{}.ndjson.gz",
+        now.format("%Y%m%dT%H%M%SZ"),
+        Uuid::new_v4()
+    ));
+    let archive_path = path.clone();
+    tokio::task::spawn_blocking(move || write_archive(&archive_path, &lines)).await??;
+    Ok(path)
+}
+
+fn write_archive(path: &Path, lines: &[String]) -> std::io::Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    let partial_path = path.with_extension("partial");
+
+    let mut encoder = GzEncoder::new(
+        BufWriter::new(File::create(&partial_path)?),
+        Compression::default(),
+    );
+    for line in lines {
+        encoder.write_all(line.as_bytes())?;
+        encoder.write_all(b"\n")?;
+    }
+    encoder
+        .finish()?
+        .into_inner()
+        .map_err(|err| err.into_error())?
+        .sync_all()?;
+
+    std::fs::rename(partial_path, path)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::prune_criteria;
+    use crate::common::{
+        configuration::{EventTypeRetention, RetentionSettings},
+        types::RetainedEventKind,
+    };
+
+    #[test]
+    pub fn should_leave_event_types_with_their_own_periods_out_of_the_defaults() {
+        let now = Utc.with_ymd_and_hms(2023, 11, 19, 12, 0, 0).unwrap();
+        let settings = RetentionSettings {
+            prune_interval_milliseconds: 1000,
+            batch_size: 100,
+            batch_pause_milliseconds: 10,
+            fulfilled_days: Some(7),
+            dead_letter_days: None,
+            unfulfilled_days: Some(30),
+            event_types: vec![
+                EventTypeRetention {
+                    event_type: "audit".into(),
+                    fulfilled_days: None,
+                    dead_letter_days: None,
+                    unfulfilled_days: None,
+                },
+                EventTypeRetention {
+                    event_type: "ping".into(),
+                    fulfilled_days: Some(1),
+                    dead_letter_days: Some(2),
+                    unfulfilled_days: Some(3),
+                },
+            ],
+            archive_directory: None,
+        };
+
+        let criteria = prune_criteria(&settings, now);
+
+        let summary: Vec<_> = criteria
+            .iter()
+            .map(|criteria| {
+                (
+                    criteria.kind,
+                    criteria.event_type.as_deref(),
+                    criteria.excluded_event_types.clone(),
+                    now - criteria.before,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    RetainedEventKind::Fulfilled,
+                    None,
+                    vec!["audit".to_string(), "ping".to_string()],
+                    Duration::days(7)
+                ),
+                (
+                    RetainedEventKind::Unfulfilled,
+                    None,
+                    vec!["audit".to_string(), "ping".to_string()],
+                    Duration::days(30)
+                ),
+                (
+                    RetainedEventKind::Fulfilled,
+                    Some("ping"),
+                    vec![],
+                    Duration::days(1)
+                ),
+                (
+                    RetainedEventKind::DeadLetter,
+                    Some("ping"),
+                    vec![],
+                    Duration::days(2)
+                ),
+                (
+                    RetainedEventKind::Unfulfilled,
+                    Some("ping"),
+                    vec![],
+                    Duration::days(3)
+                ),
+            ],
+            summary
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use chrono::{DateTime, Utc};
+use tokio::sync::{Mutex, Notify};
+
+// Keeps the retry times this instance knows about in a min-heap so the retry loop can sleep
+// until exactly the next one. Which events actually get released is decided by the database
+// lease, so instances sharing a database never release the same retry twice.
+#[derive(Debug, Default)]
+pub struct RetryScheduler {
+    retry_times: Mutex<BinaryHeap<Reverse<DateTime<Utc>>>>,
+    changed: Notify,
+}
+
+impl RetryScheduler {
+    pub async fn schedule(&self, retry_time: DateTime<Utc>) {
+        let mut retry_times_lock = self.retry_times.lock().await;
+        let is_earliest = retry_times_lock
+            .peek()
+            .is_none_or(|Reverse(next)| retry_time < *next);
+        retry_times_lock.push(Reverse(retry_time));
+        if is_earliest {
+            self.changed.notify_one();
+        }
+    }
+
+    pub async fn next_retry_time(&self) -> Option<DateTime<Utc>> {
+        self.retry_times
+            .lock()
+            .await
+            .peek()
+            .map(|Reverse(next)| *next)
+    }
+
+    // Resolves when a retry earlier than the one currently awaited has been scheduled
+    pub async fn changed(&self) {
+        self.changed.notified().await
+    }
+
+    pub async fn pop_due(&self, now: DateTime<Utc>) {
+        let mut retry_times_lock = self.retry_times.lock().await;
+        while let Some(Reverse(next)) = retry_times_lock.peek() {
+            if *next > now {
+                break;
+            }
+            retry_times_lock.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::RetryScheduler;
+
+    #[tokio::test]
+    pub async fn should_return_earliest_retry_time_first() {
+        let scheduler = RetryScheduler::default();
+        let now = Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 0).unwrap();
+
+        scheduler.schedule(now + Duration::minutes(3)).await;
+        scheduler.schedule(now + Duration::minutes(1)).await;
+        scheduler.schedule(now + Duration::minutes(2)).await;
+        assert_eq!(
+            Some(now + Duration::minutes(1)),
+            scheduler.next_retry_time().await
+        );
+
+        scheduler.pop_due(now + Duration::minutes(2)).await;
+        assert_eq!(
+            Some(now + Duration::minutes(3)),
+            scheduler.next_retry_time().await
+        );
+    }
+
+    #[tokio::test]
+    pub async fn should_notify_when_an_earlier_retry_is_scheduled() {
+        let scheduler = RetryScheduler::default();
+        let now = Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 0).unwrap();
+
+        scheduler.schedule(now + Duration::minutes(5)).await;
+        scheduler.changed().await;
+
+        scheduler.schedule(now + Duration::minutes(1)).await;
+        tokio::time::timeout(std::time::Duration::from_secs(1), scheduler.changed())
+            .await
+            .expect("Scheduling an earlier retry should notify the retry loop");
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use tokio::sync::{
+    mpsc::{self, error::TrySendError, Receiver, Sender},
+    Mutex,
+};
+use uuid::Uuid;
+
+use crate::common::{
+    event_filter::EventFilter, event_type_pattern::EventTypePattern, types::VentrixEvent,
+};
+
+const STREAM_SUBSCRIBER_BUFFER: usize = 50;
+
+// What a stream subscriber receives. The filter is parsed once, when the stream is opened.
+#[derive(Debug, Clone)]
+pub struct StreamSubscription {
+    pub service_name: String,
+    pub event_types: EventTypePattern,
+    pub filter: Option<Arc<EventFilter>>,
+    pub transform: Option<String>,
+    pub target_schema: Option<String>,
+}
+
+#[derive(Debug)]
+struct StreamSubscriber {
+    subscription: StreamSubscription,
+    sender: Sender<VentrixEvent>,
+}
+
+#[derive(Debug)]
+struct PendingAck {
+    subscriber_id: Uuid,
+    event: VentrixEvent,
+    sent_at: DateTime<Utc>,
+    started_at: Instant,
+}
+
+pub enum StreamDispatchResult {
+    Delivered,
+    Failed,
+    Disconnected,
+}
+
+// A delivery the subscriber will no longer acknowledge, to be failed like an HTTP delivery
+pub struct UnacknowledgedDelivery {
+    pub service_name: String,
+    pub event: VentrixEvent,
+    pub reason: &'static str,
+}
+
+// Subscribers connected through a long lived stream (e.g. gRPC Subscribe) rather
+// than an HTTP endpoint. Deliveries stay pending until the subscriber acknowledges them.
+#[derive(Debug, Default)]
+pub struct StreamSubscribers {
+    subscribers: Mutex<HashMap<Uuid, StreamSubscriber>>,
+    pending_acks: Mutex<HashMap<(String, Uuid), PendingAck>>,
+}
+
+impl StreamSubscribers {
+    pub async fn subscribe(&self, subscription: StreamSubscription) -> Receiver<VentrixEvent> {
+        let (sender, receiver) = mpsc::channel::<VentrixEvent>(STREAM_SUBSCRIBER_BUFFER);
+        let mut subscribers_lock = self.subscribers.lock().await;
+        subscribers_lock.insert(
+            Uuid::new_v4(),
+            StreamSubscriber {
+                subscription,
+                sender,
+            },
+        );
+        receiver
+    }
+
+    // The subscriptions whose event types include this one, by subscriber id
+    pub async fn matching(&self, event_type: &str) -> Vec<(Uuid, StreamSubscription)> {
+        let subscribers_lock = self.subscribers.lock().await;
+        subscribers_lock
+            .iter()
+            .filter(|(_, subscriber)| subscriber.subscription.event_types.matches(event_type))
+            .map(|(id, subscriber)| (*id, subscriber.subscription.clone()))
+            .collect()
+    }
+
+    // Sends the body prepared for the subscriber and keeps the event until it is acknowledged
+    pub async fn send(
+        &self,
+        subscriber_id: Uuid,
+        event: &VentrixEvent,
+        body: VentrixEvent,
+        sent_at: DateTime<Utc>,
+    ) -> StreamDispatchResult {
+        let mut subscribers_lock = self.subscribers.lock().await;
+        let Some(subscriber) = subscribers_lock.get(&subscriber_id) else {
+            return StreamDispatchResult::Disconnected;
+        };
+
+        match subscriber.sender.try_send(body) {
+            Ok(_) => {
+                let service_name = subscriber.subscription.service_name.clone();
+                let mut pending_acks_lock = self.pending_acks.lock().await;
+                pending_acks_lock.insert(
+                    (service_name, event.id),
+                    PendingAck {
+                        subscriber_id,
+                        event: event.clone(),
+                        sent_at,
+                        started_at: Instant::now(),
+                    },
+                );
+                StreamDispatchResult::Delivered
+            }
+            Err(TrySendError::Full(_)) => StreamDispatchResult::Failed,
+            Err(TrySendError::Closed(_)) => {
+                tracing::info!(
+                    "Stream subscriber {} disconnected",
+                    subscriber.subscription.service_name
+                );
+                subscribers_lock.remove(&subscriber_id);
+                StreamDispatchResult::Disconnected
+            }
+        }
+    }
+
+    // Returns the event along with how long the subscriber took to acknowledge it
+    pub async fn take_pending(
+        &self,
+        service_name: &str,
+        event_id: Uuid,
+    ) -> Option<(VentrixEvent, Duration)> {
+        let mut pending_acks_lock = self.pending_acks.lock().await;
+        pending_acks_lock
+            .remove(&(service_name.to_string(), event_id))
+            .map(|pending| (pending.event, pending.started_at.elapsed()))
+    }
+
+    // Drops the subscribers whose stream has closed and takes back the deliveries they left
+    // unacknowledged, along with every delivery sent before the cutoff
+    pub async fn take_unacknowledged(
+        &self,
+        sent_before: DateTime<Utc>,
+    ) -> Vec<UnacknowledgedDelivery> {
+        let mut subscribers_lock = self.subscribers.lock().await;
+        subscribers_lock.retain(|_, subscriber| !subscriber.sender.is_closed());
+
+        let mut pending_acks_lock = self.pending_acks.lock().await;
+        let unacknowledged: Vec<_> = pending_acks_lock
+            .iter()
+            .filter_map(|(key, pending)| {
+                match subscribers_lock.contains_key(&pending.subscriber_id) {
+                    false => Some((key.clone(), "Stream subscriber disconnected")),
+                    true if pending.sent_at < sent_before => Some((
+                        key.clone(),
+                        "Stream subscriber did not acknowledge the event in time",
+                    )),
+                    true => None,
+                }
+            })
+            .collect();
+
+        unacknowledged
+            .into_iter()
+            .filter_map(|(key, reason)| {
+                let pending = pending_acks_lock.remove(&key)?;
+                Some(UnacknowledgedDelivery {
+                    service_name: key.0,
+                    event: pending.event,
+                    reason,
+                })
+            })
+            .collect()
+    }
+}
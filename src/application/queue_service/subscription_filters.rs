@@ -0,0 +1,53 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use serde_json::Value;
+
+use crate::common::{event_filter::EventFilter, types::VentrixEvent};
+
+// Distinct filters kept before the cache starts over, so filters of removed subscriptions do not
+// pile up
+const MAX_CACHED_FILTERS: usize = 1024;
+
+// The filters of stored subscriptions, parsed once per distinct filter rather than for every
+// event delivered or replayed. A stored filter that no longer parses is cached as None.
+#[derive(Debug, Default)]
+pub struct SubscriptionFilters {
+    parsed: Mutex<HashMap<String, Option<Arc<EventFilter>>>>,
+}
+
+impl SubscriptionFilters {
+    // Whether a subscription with this filter receives the event. A stored filter that no longer
+    // parses lets every event through rather than silently dropping them.
+    pub fn matches(&self, filter: Option<&str>, event: &VentrixEvent, payload: &Value) -> bool {
+        match filter.and_then(|filter| self.parsed(filter)) {
+            Some(filter) => filter.matches(event, payload),
+            None => true,
+        }
+    }
+
+    fn parsed(&self, filter: &str) -> Option<Arc<EventFilter>> {
+        let mut parsed_lock = self.parsed.lock().unwrap();
+        if let Some(parsed) = parsed_lock.get(filter) {
+            return parsed.clone();
+        }
+        if parsed_lock.len() >= MAX_CACHED_FILTERS {
+            parsed_lock.clear();
+        }
+        let parsed = match EventFilter::parse(filter) {
+            Ok(parsed) => Some(Arc::new(parsed)),
+            Err(err) => {
+                tracing::warn!(
+                    "Ignoring invalid subscription filter {:?}. Err: {}",
+                    filter,
+                    err
+                );
+                None
+            }
+        };
+        parsed_lock.insert(filter.to_string(), parsed.clone());
+        parsed
+    }
+}
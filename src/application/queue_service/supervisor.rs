@@ -0,0 +1,272 @@
+use std::{
+    any::Any,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::task::{AbortHandle, JoinHandle};
+
+use crate::common::metrics::METRICS;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RestartBackoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+#[derive(Debug, Default)]
+struct TaskState {
+    running: AtomicBool,
+    restarts: AtomicU64,
+}
+
+// A background task that is started again whenever it panics or exits, or for tasks spawned
+// until done, only when it panics. Restarts back off exponentially, and the backoff resets once
+// the task has stayed up for the longest delay. Dropping it stops the task.
+#[derive(Debug)]
+pub struct SupervisedTask {
+    name: &'static str,
+    state: Arc<TaskState>,
+    supervisor: JoinHandle<()>,
+}
+
+impl SupervisedTask {
+    pub fn spawn<F, Fut>(name: &'static str, backoff: RestartBackoff, task: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self::start(name, backoff, task, true)
+    }
+
+    // For tasks that finish, e.g. a replay; one that exits is done rather than restarted
+    pub fn spawn_until_done<F, Fut>(name: &'static str, backoff: RestartBackoff, task: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self::start(name, backoff, task, false)
+    }
+
+    fn start<F, Fut>(
+        name: &'static str,
+        backoff: RestartBackoff,
+        task: F,
+        restart_on_exit: bool,
+    ) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let state = Arc::new(TaskState::default());
+        let supervisor_state = Arc::clone(&state);
+        let supervisor = tokio::spawn(async move {
+            supervise(name, backoff, task, supervisor_state, restart_on_exit).await;
+        });
+
+        Self {
+            name,
+            state,
+            supervisor,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.running.load(Ordering::SeqCst) && !self.supervisor.is_finished()
+    }
+
+    pub fn restarts(&self) -> u64 {
+        self.state.restarts.load(Ordering::SeqCst)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.supervisor.is_finished()
+    }
+}
+
+impl Drop for SupervisedTask {
+    fn drop(&mut self) {
+        self.supervisor.abort();
+    }
+}
+
+struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+async fn supervise<F, Fut>(
+    name: &'static str,
+    backoff: RestartBackoff,
+    task: F,
+    state: Arc<TaskState>,
+    restart_on_exit: bool,
+) where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut delay = backoff.initial;
+
+    loop {
+        let started_at = Instant::now();
+        state.running.store(true, Ordering::SeqCst);
+        METRICS.set_task_running(name, true);
+
+        let handle = tokio::spawn(task());
+        // Aborts the task when the supervisor is aborted while waiting on it
+        let _abort_on_drop = AbortOnDrop(handle.abort_handle());
+        let result = handle.await;
+
+        state.running.store(false, Ordering::SeqCst);
+        METRICS.set_task_running(name, false);
+        match result {
+            Ok(_) if !restart_on_exit => return,
+            Ok(_) => tracing::error!("Background task {} exited unexpectedly", name),
+            Err(err) if err.is_panic() => tracing::error!(
+                "Background task {} panicked: {}",
+                name,
+                panic_message(err.into_panic())
+            ),
+            Err(_) => {
+                tracing::info!("Background task {} was cancelled", name);
+                return;
+            }
+        }
+
+        if started_at.elapsed() >= backoff.max {
+            delay = backoff.initial;
+        }
+        tracing::info!("Restarting background task {} in {:?}", name, delay);
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(backoff.max);
+
+        state.restarts.fetch_add(1, Ordering::SeqCst);
+        METRICS.record_task_restart(name);
+    }
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => panic
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .unwrap_or_else(|| "unknown panic".to_string()),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::{RestartBackoff, SupervisedTask};
+
+    #[tokio::test]
+    pub async fn should_restart_a_task_that_panics() {
+        let attempts = Arc::new(AtomicU64::new(0));
+        let task_attempts = Arc::clone(&attempts);
+        let backoff = RestartBackoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(100),
+        };
+
+        let task = SupervisedTask::spawn("flaky_task", backoff, move || {
+            let attempts = Arc::clone(&task_attempts);
+            async move {
+                if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    panic!("Task failed");
+                }
+                std::future::pending::<()>().await;
+            }
+        });
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !(task.is_running() && task.restarts() == 2) {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("Task was not restarted after panicking");
+        assert_eq!(3, attempts.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    pub async fn should_restart_a_task_spawned_until_done_only_until_it_finishes() {
+        let attempts = Arc::new(AtomicU64::new(0));
+        let task_attempts = Arc::clone(&attempts);
+        let backoff = RestartBackoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(100),
+        };
+
+        let task = SupervisedTask::spawn_until_done("finishing_task", backoff, move || {
+            let attempts = Arc::clone(&task_attempts);
+            async move {
+                if attempts.fetch_add(1, Ordering::SeqCst) < 1 {
+                    panic!("Task failed");
+                }
+            }
+        });
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !task.is_finished() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("Task did not finish");
+        assert_eq!(1, task.restarts());
+        assert_eq!(2, attempts.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    pub async fn should_stop_a_task_when_it_is_dropped() {
+        let ticks = Arc::new(AtomicU64::new(0));
+        let task_ticks = Arc::clone(&ticks);
+        let backoff = RestartBackoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(100),
+        };
+
+        let task = SupervisedTask::spawn("ticking_task", backoff, move || {
+            let ticks = Arc::clone(&task_ticks);
+            async move {
+                loop {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            }
+        });
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while ticks.load(Ordering::SeqCst) == 0 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("Task did not start");
+
+        drop(task);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let ticks_after_drop = ticks.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(ticks_after_drop, ticks.load(Ordering::SeqCst));
+    }
+}
@@ -1,52 +1,212 @@
-use std::{error::Error, sync::Arc};
+use std::{fmt::Display, sync::Arc};
 
 use actix_web::web;
-use chrono::{Duration, Utc};
-use reqwest::Client;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::StatusCode;
+use serde_json::Value;
 use tokio::sync::{
-    mpsc::{Receiver, Sender},
+    mpsc::{error::TrySendError, Receiver, Sender},
     Mutex,
 };
 
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use uuid::Uuid;
+
 use crate::{
-    common::types::{EventFulfillmentDetails, ListenToEventReq, RetryDetails, VentrixEvent},
-    infrastructure::persistence::{Database, InsertDataResponse},
+    common::{
+        clock::Clock,
+        configuration::{BackpressurePolicy, QueueSettings},
+        errors::{
+            DeliveryAuthError, EventNotFoundError, ForbiddenError, InvalidDeliverySettingsError,
+            InvalidTransformError, QueueError, QueueFullError, ReferencedEntityMissingError,
+            VentrixError,
+        },
+        event_filter::EventFilter,
+        event_type_pattern::EventTypePattern,
+        metrics::{status_class, METRICS},
+        payload_transform::PayloadTransform,
+        schema_validator::validate_payload,
+        types::{
+            ApiKey, DeliveryAttempt, EventFulfillmentDetails, ListenToEventReq, PublishDenial,
+            ReplayJob, ReplayRequest, RetryDetails, SubscribeToStreamReq, TraceContext,
+            VentrixEvent,
+        },
+    },
+    domain::models::service::RegisterServiceRequest,
+    infrastructure::persistence::{
+        notifier::PostgresNotifier, Database, InsertDataResponse, FIRST_RETRY_DELAY_MINUTES,
+        MAX_RETRIES,
+    },
+};
+
+use super::{
+    delivery_credentials::DeliveryCredentials,
+    replay::{self, Replays},
+    retention,
+    retry_scheduler::RetryScheduler,
+    stream_subscribers::{StreamDispatchResult, StreamSubscribers, StreamSubscription},
+    subscription_filters::SubscriptionFilters,
+    supervisor::{RestartBackoff, SupervisedTask},
 };
 
+const STREAM_ENDPOINT: &str = "stream";
+// Status class recorded for deliveries to stream subscribers, which have no HTTP status
+const STREAM_STATUS_CLASS: &str = "stream";
+const PENDING_CLAIM_BATCH: i64 = 50;
+// Status class recorded when a delivery could not be given its credentials
+pub(super) const DELIVERY_AUTH_STATUS_CLASS: &str = "auth_error";
+
+// The in-process queue the event processor takes events from. Published events claimed through
+// Postgres may wait there past the lease they were claimed with, so with a pending lease the
+// lease is renewed just before each is dispatched.
+#[derive(Debug, Clone)]
+struct QueuedEvents {
+    // Shared so a restarted processor picks up where the crashed one left off
+    receiver: Arc<Mutex<Receiver<VentrixEvent>>>,
+    pending_lease: Option<PendingLease>,
+}
+
+#[derive(Debug, Clone)]
+struct PendingLease {
+    owner: String,
+    duration: Duration,
+}
+
 #[derive(Debug)]
 pub struct VentrixQueue {
     pub sender: Sender<VentrixEvent>,
     database: web::Data<dyn Database>,
+    stream_subscribers: Arc<StreamSubscribers>,
+    retry_scheduler: Arc<RetryScheduler>,
+    delivery_credentials: Arc<DeliveryCredentials>,
+    subscription_filters: Arc<SubscriptionFilters>,
+    replays: Arc<Replays>,
+    settings: QueueSettings,
+    clock: Arc<dyn Clock>,
+    // Owner name used when leasing events in a database shared with other instances
+    instance_id: String,
+    notifier: Option<PostgresNotifier>,
+    background_tasks: Vec<SupervisedTask>,
 }
 
 impl VentrixQueue {
-    pub async fn new(database: web::Data<dyn Database>) -> Self {
-        let (sender, receiver) = tokio::sync::mpsc::channel::<VentrixEvent>(50);
-        let ventrix_queue = Self {
+    // Fails with an invalid delivery encryption key or unusable delivery TLS settings
+    pub async fn new(
+        database: web::Data<dyn Database>,
+        settings: QueueSettings,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, InvalidDeliverySettingsError> {
+        Self::start(database, settings, clock, None)
+    }
+
+    pub async fn with_postgres_dispatch(
+        database: web::Data<dyn Database>,
+        settings: QueueSettings,
+        clock: Arc<dyn Clock>,
+        notifier: PostgresNotifier,
+    ) -> Result<Self, InvalidDeliverySettingsError> {
+        Self::start(database, settings, clock, Some(notifier))
+    }
+
+    fn start(
+        database: web::Data<dyn Database>,
+        settings: QueueSettings,
+        clock: Arc<dyn Clock>,
+        notifier: Option<PostgresNotifier>,
+    ) -> Result<Self, InvalidDeliverySettingsError> {
+        // Checked before anything is spawned, so a bad key stops startup rather than a task
+        let delivery_credentials =
+            Arc::new(DeliveryCredentials::new(&settings, Arc::clone(&clock))?);
+        let subscription_filters = Arc::new(SubscriptionFilters::default());
+        let instance_id = format!("ventrix-{}", Uuid::new_v4());
+        let replays = Arc::new(Replays::new(
+            web::Data::clone(&database),
+            Arc::clone(&delivery_credentials),
+            Arc::clone(&subscription_filters),
+            Arc::clone(&clock),
+            settings.clone(),
+            instance_id.clone(),
+        ));
+        let (sender, receiver) = tokio::sync::mpsc::channel::<VentrixEvent>(settings.capacity);
+        let mut ventrix_queue = Self {
             sender: sender.clone(),
             database,
+            stream_subscribers: Arc::new(StreamSubscribers::default()),
+            retry_scheduler: Arc::new(RetryScheduler::default()),
+            delivery_credentials,
+            subscription_filters,
+            replays,
+            settings,
+            clock,
+            instance_id,
+            notifier,
+            background_tasks: vec![],
         };
-        ventrix_queue.start_event_processor(receiver, sender);
-        ventrix_queue
+        if ventrix_queue.notifier.is_some()
+            || ventrix_queue.settings.backpressure == BackpressurePolicy::Spill
+        {
+            let dispatch_listener = ventrix_queue.start_dispatch_listener(sender.clone());
+            ventrix_queue.background_tasks.push(dispatch_listener);
+        }
+        let processors = ventrix_queue.start_event_processor(receiver, sender);
+        ventrix_queue.background_tasks.extend(processors);
+        let stream_ack_reaper = ventrix_queue.start_stream_ack_reaper();
+        ventrix_queue.background_tasks.push(stream_ack_reaper);
+        let replay_resumer = ventrix_queue.start_replay_resumer();
+        ventrix_queue.background_tasks.push(replay_resumer);
+        if ventrix_queue.settings.retention.is_enabled() {
+            let retention_pruner = ventrix_queue.start_retention_pruner();
+            ventrix_queue.background_tasks.push(retention_pruner);
+        }
+        Ok(ventrix_queue)
     }
 
     async fn event_processor(
-        mut receiver: Receiver<VentrixEvent>,
+        queued_events: QueuedEvents,
         database: web::Data<dyn Database>,
+        stream_subscribers: Arc<StreamSubscribers>,
+        retry_scheduler: Arc<RetryScheduler>,
+        delivery_credentials: Arc<DeliveryCredentials>,
+        subscription_filters: Arc<SubscriptionFilters>,
+        clock: Arc<dyn Clock>,
     ) {
-        let client = Arc::new(Mutex::new(reqwest::Client::new()));
         let database = database.get_ref();
+        let clock = clock.as_ref();
+        let retry_scheduler = retry_scheduler.as_ref();
+        let pending_lease = &queued_events.pending_lease;
+        let mut receiver = queued_events.receiver.lock().await;
 
         while let Some(event) = receiver.recv().await {
             tracing::info!("Processing event: {}", &event.event_type);
+            let event_id = event.id;
+            let is_first_attempt = event.retry_details.is_none();
+
+            if let (true, Some(pending_lease)) = (is_first_attempt, pending_lease) {
+                if !renew_pending_lease(database, event_id, pending_lease).await {
+                    continue;
+                }
+            }
+
+            Self::send_to_stream_subscribers(
+                &stream_subscribers,
+                &event,
+                database,
+                clock,
+                retry_scheduler,
+            )
+            .await;
 
             match database.get_service_by_event_type(&event.event_type).await {
                 Ok(details_for_listening_services) => {
                     Self::send_to_listening_services(
                         details_for_listening_services,
                         event,
-                        Arc::clone(&client),
+                        &delivery_credentials,
+                        &subscription_filters,
                         database,
+                        clock,
+                        retry_scheduler,
                     )
                     .await
                 }
@@ -57,107 +217,901 @@ impl VentrixQueue {
                     );
                 }
             }
+
+            if is_first_attempt {
+                if let Err(err) = database.mark_event_dispatched(event_id).await {
+                    tracing::warn!(
+                        "Could not mark event {} as dispatched. Err: {}",
+                        event_id,
+                        err
+                    );
+                }
+            }
         }
     }
 
+    // Delivery settings are sealed here, so only their encrypted form reaches the database
+    pub async fn register_service(
+        &self,
+        mut reg_service_req: RegisterServiceRequest,
+    ) -> Result<InsertDataResponse, VentrixError> {
+        reg_service_req.sealed_delivery = reg_service_req
+            .delivery
+            .take()
+            .map(|delivery| self.delivery_credentials.seal(&delivery))
+            .transpose()?;
+
+        self.database
+            .get_ref()
+            .register_service(&reg_service_req)
+            .await
+    }
+
     pub async fn listen_to_event(
         &self,
-        listen_to_event_req: &ListenToEventReq,
-    ) -> Result<InsertDataResponse, Box<dyn Error>> {
+        mut listen_to_event_req: ListenToEventReq,
+    ) -> Result<InsertDataResponse, VentrixError> {
+        listen_to_event_req.sealed_delivery = listen_to_event_req
+            .delivery
+            .take()
+            .map(|delivery| self.delivery_credentials.seal(&delivery))
+            .transpose()?;
+        self.validate_subscription(
+            &listen_to_event_req.event_type,
+            listen_to_event_req.filter.as_deref(),
+            listen_to_event_req.transform.as_ref(),
+            listen_to_event_req.target_schema.as_ref(),
+        )
+        .await?;
+
         self.database
             .get_ref()
-            .register_service_for_event_type(listen_to_event_req)
+            .register_service_for_event_type(&listen_to_event_req)
             .await
     }
 
-    pub async fn publish_event(
+    // Checks the filter and transform of a subscription parse, and fit the event type's schema
+    async fn validate_subscription(
         &self,
-        event: VentrixEvent,
-    ) -> Result<(), tokio::sync::mpsc::error::SendError<VentrixEvent>> {
-        self.sender.send(event.clone()).await
+        event_type: &str,
+        filter: Option<&str>,
+        transform: Option<&Value>,
+        target_schema: Option<&Value>,
+    ) -> Result<(), VentrixError> {
+        let filter = filter.map(EventFilter::parse).transpose()?;
+        let transform = transform.map(PayloadTransform::parse).transpose()?;
+        if let Some(target_schema) = target_schema {
+            if !target_schema.is_object() {
+                return Err(
+                    InvalidTransformError::new("The target schema must be a JSON object").into(),
+                );
+            }
+        }
+
+        // The event types a pattern covers can be registered later with any schema, so only
+        // subscriptions to a single event type are checked against one
+        let is_pattern = EventTypePattern::is_pattern(event_type);
+        if !is_pattern && (filter.is_some() || transform.is_some()) {
+            let schema = self
+                .database
+                .get_schema_for_event_type(event_type)
+                .await
+                .map_err(|err| match err {
+                    VentrixError::EventTypeNotFound(_) => {
+                        ReferencedEntityMissingError::new("event type", event_type).into()
+                    }
+                    err => err,
+                })?;
+            if let Some(filter) = filter {
+                filter.validate_against_schema(&schema.payload_definition)?;
+            }
+            if let Some(transform) = transform {
+                transform.validate_against_schemas(&schema.payload_definition, target_schema)?;
+            }
+        }
+        Ok(())
+    }
+
+    // The event must already be stored, as dispatchers claim published events from the database
+    pub async fn publish_event(&self, event: VentrixEvent) -> Result<(), VentrixError> {
+        if let Some(notifier) = &self.notifier {
+            return notifier.notify_event_published(event.id).await;
+        }
+
+        match self.settings.backpressure {
+            BackpressurePolicy::Block => {
+                match tokio::time::timeout(
+                    self.settings.backpressure_timeout(),
+                    self.sender.send(event),
+                )
+                .await
+                {
+                    Ok(result) => result.map_err(|err| QueueError::new(err.to_string()).into()),
+                    Err(_) => Err(self.queue_full()),
+                }
+            }
+            BackpressurePolicy::Reject => match self.sender.try_send(event) {
+                Ok(_) => Ok(()),
+                Err(TrySendError::Full(_)) => Err(self.queue_full()),
+                Err(err) => Err(QueueError::new(err.to_string()).into()),
+            },
+            BackpressurePolicy::Spill => match self.sender.try_send(event) {
+                Ok(_) => Ok(()),
+                Err(TrySendError::Full(event)) => {
+                    tracing::warn!(
+                        "Queue is full, event {} was left in the database for the dispatcher",
+                        event.id
+                    );
+                    self.database.mark_event_spilled(event.id).await?;
+                    Ok(())
+                }
+                Err(err) => Err(QueueError::new(err.to_string()).into()),
+            },
+        }
+    }
+
+    // Number of events waiting in the in-process queue
+    pub fn depth(&self) -> usize {
+        self.settings.capacity - self.sender.capacity()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.settings.capacity
+    }
+
+    // Ready to take traffic while the backlog stays under the configured threshold
+    pub fn is_backlogged(&self) -> bool {
+        self.depth() >= self.settings.readiness_depth_threshold
+    }
+
+    pub fn readiness_depth_threshold(&self) -> usize {
+        self.settings.readiness_depth_threshold
+    }
+
+    pub fn background_tasks(&self) -> &[SupervisedTask] {
+        &self.background_tasks
+    }
+
+    fn queue_full(&self) -> VentrixError {
+        QueueFullError::new(self.settings.capacity, self.settings.retry_after_seconds).into()
+    }
+
+    // Streams take event type patterns, filters and transforms like HTTP subscriptions
+    pub async fn subscribe_to_stream(
+        &self,
+        request: SubscribeToStreamReq,
+    ) -> Result<Receiver<VentrixEvent>, VentrixError> {
+        let event_types = EventTypePattern::parse(&request.event_type)?;
+        self.validate_subscription(
+            &request.event_type,
+            request.filter.as_deref(),
+            request.transform.as_ref(),
+            request.target_schema.as_ref(),
+        )
+        .await?;
+
+        tracing::info!(
+            "Service {} subscribed to a stream of event type {}",
+            request.service_name,
+            request.event_type
+        );
+        let subscription = StreamSubscription {
+            service_name: request.service_name,
+            event_types,
+            filter: request
+                .filter
+                .as_deref()
+                .map(EventFilter::parse)
+                .transpose()?
+                .map(Arc::new),
+            transform: request.transform.as_ref().map(Value::to_string),
+            target_schema: request.target_schema.as_ref().map(Value::to_string),
+        };
+        Ok(self.stream_subscribers.subscribe(subscription).await)
+    }
+
+    pub async fn acknowledge_event(
+        &self,
+        service_name: &str,
+        event_id: Uuid,
+        success: bool,
+    ) -> Result<(), VentrixError> {
+        let (event, latency) = self
+            .stream_subscribers
+            .take_pending(service_name, event_id)
+            .await
+            .ok_or_else(|| EventNotFoundError::new(&event_id.to_string()))?;
+        let database = self.database.get_ref();
+        let retry_scheduler = self.retry_scheduler.as_ref();
+        METRICS.record_delivery(service_name, STREAM_STATUS_CLASS, success);
+
+        let attempted_at =
+            self.clock.now() - Duration::from_std(latency).unwrap_or_else(|_| Duration::zero());
+        let attempt =
+            delivery_attempt(&event, service_name, STREAM_ENDPOINT, attempted_at, latency);
+        let attempt = match success {
+            true => attempt,
+            false => DeliveryAttempt {
+                succeeded: false,
+                error: Some("Subscriber acknowledged the event as failed".to_string()),
+                ..attempt
+            },
+        };
+        save_delivery_attempt(database, &attempt).await;
+
+        match success {
+            true => Self::on_success_response(&event, database, service_name).await,
+            false => {
+                Self::on_failed_response(
+                    &event,
+                    service_name,
+                    STREAM_ENDPOINT,
+                    database,
+                    self.clock.as_ref(),
+                    retry_scheduler,
+                    "Subscriber acknowledged the event as failed",
+                )
+                .await
+            }
+        }
+
+        Ok(())
+    }
+
+    // Starts re-sending stored events to one service in the background
+    pub async fn start_replay(&self, request: ReplayRequest) -> Result<ReplayJob, VentrixError> {
+        self.replays.start(request).await
+    }
+
+    // Checks the key may publish the event type and, when the event type names the services
+    // allowed to publish it, that the key publishes as one of them. Refusals are recorded for audit.
+    pub async fn authorize_publish(
+        &self,
+        api_key: &ApiKey,
+        event_type: &str,
+    ) -> Result<(), VentrixError> {
+        let database = self.database.get_ref();
+        let reason = if !api_key.may_publish(event_type) {
+            format!(
+                "API key {:?} may not publish {:?}",
+                api_key.name, event_type
+            )
+        } else {
+            match database.get_publishers_for_event_type(event_type).await? {
+                Some(publishers) if !api_key.is_one_of_publishers(&publishers) => {
+                    match &api_key.service_name {
+                        Some(service_name) => format!(
+                            "Service {:?} is not an allowed publisher of {:?}",
+                            service_name, event_type
+                        ),
+                        None => format!(
+                            "API key {:?} does not publish as a service allowed to publish {:?}",
+                            api_key.name, event_type
+                        ),
+                    }
+                }
+                _ => return Ok(()),
+            }
+        };
+
+        tracing::warn!("Refused to publish {:?}: {}", event_type, reason);
+        let denial = PublishDenial {
+            id: Uuid::new_v4(),
+            event_type: event_type.to_string(),
+            api_key_id: api_key.id,
+            api_key_name: api_key.name.clone(),
+            service_name: api_key.service_name.clone(),
+            reason: reason.clone(),
+            attempted_at: self.clock.now(),
+        };
+        // The publish is refused either way, a failed record must not turn it into a 500
+        if let Err(err) = database.record_publish_denial(&denial).await {
+            tracing::error!("Could not record the refused publish: {}", err);
+        }
+        Err(ForbiddenError::new(&reason).into())
     }
 
     fn start_event_processor(
         &self,
         receiver: Receiver<VentrixEvent>,
         failed_event_sender: Sender<VentrixEvent>,
-    ) {
+    ) -> [SupervisedTask; 2] {
+        let backoff = restart_backoff(&self.settings);
+
+        let queued_events = QueuedEvents {
+            receiver: Arc::new(Mutex::new(receiver)),
+            pending_lease: self.notifier.as_ref().map(|_| PendingLease {
+                owner: self.instance_id.clone(),
+                duration: lease_duration(&self.settings),
+            }),
+        };
         let event_processor_db = web::Data::clone(&self.database);
-        tokio::spawn(async move {
-            Self::event_processor(receiver, event_processor_db).await;
+        let stream_subscribers = Arc::clone(&self.stream_subscribers);
+        let retry_scheduler = Arc::clone(&self.retry_scheduler);
+        let delivery_credentials = Arc::clone(&self.delivery_credentials);
+        let subscription_filters = Arc::clone(&self.subscription_filters);
+        let clock = Arc::clone(&self.clock);
+        let event_processor = SupervisedTask::spawn("event_processor", backoff, move || {
+            Self::event_processor(
+                queued_events.clone(),
+                web::Data::clone(&event_processor_db),
+                Arc::clone(&stream_subscribers),
+                Arc::clone(&retry_scheduler),
+                Arc::clone(&delivery_credentials),
+                Arc::clone(&subscription_filters),
+                Arc::clone(&clock),
+            )
         });
-        let failed_events_process_db = web::Data::clone(&self.database);
 
-        tokio::spawn(async move {
-            loop {
-                if let Ok(failed_events) = failed_events_process_db.get_failed_events().await.map_err(|err| {
-                    tracing::warn!("There was an issue fetching a list of failed events from the database: {}", err);
-                }) {
-                    handle_failed_events(failed_events, &failed_event_sender).await;
-                };
+        let retry_db = web::Data::clone(&self.database);
+        let retry_scheduler = Arc::clone(&self.retry_scheduler);
+        let settings = self.settings.clone();
+        let clock = Arc::clone(&self.clock);
+        let instance_id = self.instance_id.clone();
+        let retry_processor = SupervisedTask::spawn("retry_processor", backoff, move || {
+            Self::retry_processor(
+                failed_event_sender.clone(),
+                web::Data::clone(&retry_db),
+                Arc::clone(&retry_scheduler),
+                settings.clone(),
+                Arc::clone(&clock),
+                instance_id.clone(),
+            )
+        });
+
+        [event_processor, retry_processor]
+    }
+
+    // Sleeps until the earliest known retry is due (or a sooner one is scheduled), then
+    // releases the due failed events back onto the queue. The reconcile interval caps the
+    // sleep so retries recorded by other means are still picked up. A full claim may leave
+    // due events behind, so the next one follows without sleeping.
+    async fn retry_processor(
+        failed_event_sender: Sender<VentrixEvent>,
+        database: web::Data<dyn Database>,
+        retry_scheduler: Arc<RetryScheduler>,
+        settings: QueueSettings,
+        clock: Arc<dyn Clock>,
+        instance_id: String,
+    ) {
+        let reconcile_interval = settings.retry_reconcile_interval();
+        let release_interval = settings.retry_release_interval();
+        let lease = lease_duration(&settings);
+        let claim_limit = retry_claim_limit(&settings);
+        let mut more_due = false;
+
+        refresh_next_retry_time(database.get_ref(), &retry_scheduler, clock.now()).await;
+
+        loop {
+            let wait = match retry_scheduler.next_retry_time().await {
+                _ if more_due => std::time::Duration::ZERO,
+                Some(next_retry_time) => (next_retry_time - clock.now())
+                    .to_std()
+                    .unwrap_or_default()
+                    .min(reconcile_interval),
+                None => reconcile_interval,
+            };
 
-                tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = retry_scheduler.changed() => continue,
             }
-        });
+
+            let now = clock.now();
+            retry_scheduler.pop_due(now).await;
+
+            more_due = match database
+                .claim_failed_events(&instance_id, lease, claim_limit)
+                .await
+            {
+                Ok(failed_events) => {
+                    let is_full = failed_events.len() as i64 == claim_limit;
+                    release_failed_events(failed_events, &failed_event_sender, release_interval)
+                        .await;
+                    is_full
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "There was an issue claiming failed events from the database: {}",
+                        err
+                    );
+                    false
+                }
+            };
+
+            if retry_scheduler.next_retry_time().await.is_none() {
+                refresh_next_retry_time(database.get_ref(), &retry_scheduler, now).await;
+            }
+        }
+    }
+
+    fn start_dispatch_listener(&self, sender: Sender<VentrixEvent>) -> SupervisedTask {
+        let notifier = self.notifier.clone();
+        let database = web::Data::clone(&self.database);
+        let settings = self.settings.clone();
+        let clock = Arc::clone(&self.clock);
+        let instance_id = self.instance_id.clone();
+        SupervisedTask::spawn(
+            "dispatch_listener",
+            restart_backoff(&self.settings),
+            move || {
+                Self::dispatch_listener(
+                    notifier.clone(),
+                    web::Data::clone(&database),
+                    sender.clone(),
+                    settings.clone(),
+                    Arc::clone(&clock),
+                    instance_id.clone(),
+                )
+            },
+        )
+    }
+
+    fn start_replay_resumer(&self) -> SupervisedTask {
+        let replays = Arc::clone(&self.replays);
+        SupervisedTask::spawn(
+            "replay_resumer",
+            restart_backoff(&self.settings),
+            move || replay::replay_resumer(Arc::clone(&replays)),
+        )
+    }
+
+    fn start_retention_pruner(&self) -> SupervisedTask {
+        let database = web::Data::clone(&self.database);
+        let settings = self.settings.retention.clone();
+        let clock = Arc::clone(&self.clock);
+        SupervisedTask::spawn(
+            "retention_pruner",
+            restart_backoff(&self.settings),
+            move || {
+                retention::retention_pruner(
+                    web::Data::clone(&database),
+                    settings.clone(),
+                    Arc::clone(&clock),
+                )
+            },
+        )
+    }
+
+    // Claims published events whenever any instance sharing the database notifies, and on
+    // every reconcile interval to pick up events whose claiming instance died. Without a
+    // notifier only the events flagged as spilled by a full queue are taken, as the others are
+    // in the in-process queue however long they wait there.
+    async fn dispatch_listener(
+        notifier: Option<PostgresNotifier>,
+        database: web::Data<dyn Database>,
+        sender: Sender<VentrixEvent>,
+        settings: QueueSettings,
+        clock: Arc<dyn Clock>,
+        instance_id: String,
+    ) {
+        let reconcile_interval = settings.retry_reconcile_interval();
+        let lease = lease_duration(&settings);
+        let mut listener = None;
+
+        loop {
+            if let (Some(notifier), None) = (&notifier, &listener) {
+                listener = notifier
+                    .listen()
+                    .await
+                    .map_err(|err| {
+                        tracing::warn!("Could not listen for published events. Err: {}", err)
+                    })
+                    .ok();
+            }
+
+            match notifier {
+                Some(_) => {
+                    claim_pending_events(
+                        database.get_ref(),
+                        &sender,
+                        &instance_id,
+                        lease,
+                        clock.now(),
+                    )
+                    .await
+                }
+                None => take_spilled_events(database.get_ref(), &sender).await,
+            }
+
+            match listener.as_mut() {
+                Some(listener) => tokio::select! {
+                    notification = listener.recv() => {
+                        if let Err(err) = notification {
+                            tracing::warn!("Lost the published events listener. Err: {}", err);
+                        }
+                    }
+                    _ = tokio::time::sleep(reconcile_interval) => {}
+                },
+                None => tokio::time::sleep(reconcile_interval).await,
+            }
+        }
+    }
+
+    async fn send_to_stream_subscribers(
+        stream_subscribers: &StreamSubscribers,
+        event: &VentrixEvent,
+        database: &dyn Database,
+        clock: &dyn Clock,
+        retry_scheduler: &RetryScheduler,
+    ) {
+        let payload = event_payload(event);
+        for (subscriber_id, subscription) in stream_subscribers.matching(&event.event_type).await {
+            let service_name = &subscription.service_name;
+            let matches_filter = subscription
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(event, &payload));
+            if !matches_filter {
+                skip_filtered_event(event, service_name, STREAM_ENDPOINT, database, clock).await;
+                continue;
+            }
+            let body = match delivery_body(
+                event,
+                subscription.transform.as_deref(),
+                subscription.target_schema.as_deref(),
+                &payload,
+            ) {
+                Ok(body) => body,
+                Err(err) => {
+                    skip_unpreparable_event(
+                        event,
+                        service_name,
+                        STREAM_ENDPOINT,
+                        database,
+                        clock,
+                        err,
+                    )
+                    .await;
+                    continue;
+                }
+            };
+
+            match stream_subscribers
+                .send(subscriber_id, event, body, clock.now())
+                .await
+            {
+                StreamDispatchResult::Delivered => tracing::info!(
+                    "Event {} streamed to Service {}, awaiting acknowledgement",
+                    event.event_type,
+                    service_name
+                ),
+                StreamDispatchResult::Failed => {
+                    Self::on_stream_delivery_failed(
+                        event,
+                        service_name,
+                        database,
+                        clock,
+                        retry_scheduler,
+                        "Stream subscriber buffer is full",
+                    )
+                    .await
+                }
+                StreamDispatchResult::Disconnected => {}
+            }
+        }
+    }
+
+    async fn on_stream_delivery_failed(
+        event: &VentrixEvent,
+        service_name: &str,
+        database: &dyn Database,
+        clock: &dyn Clock,
+        retry_scheduler: &RetryScheduler,
+        reason: &str,
+    ) {
+        METRICS.record_delivery(service_name, STREAM_STATUS_CLASS, false);
+        let attempt = DeliveryAttempt {
+            succeeded: false,
+            error: Some(reason.to_string()),
+            ..delivery_attempt(
+                event,
+                service_name,
+                STREAM_ENDPOINT,
+                clock.now(),
+                std::time::Duration::ZERO,
+            )
+        };
+        save_delivery_attempt(database, &attempt).await;
+        Self::on_failed_response(
+            event,
+            service_name,
+            STREAM_ENDPOINT,
+            database,
+            clock,
+            retry_scheduler,
+            reason,
+        )
+        .await
+    }
+
+    fn start_stream_ack_reaper(&self) -> SupervisedTask {
+        let stream_subscribers = Arc::clone(&self.stream_subscribers);
+        let database = web::Data::clone(&self.database);
+        let retry_scheduler = Arc::clone(&self.retry_scheduler);
+        let ack_timeout = self.settings.stream_ack_timeout();
+        let clock = Arc::clone(&self.clock);
+        SupervisedTask::spawn(
+            "stream_ack_reaper",
+            restart_backoff(&self.settings),
+            move || {
+                Self::stream_ack_reaper(
+                    Arc::clone(&stream_subscribers),
+                    web::Data::clone(&database),
+                    Arc::clone(&retry_scheduler),
+                    ack_timeout,
+                    Arc::clone(&clock),
+                )
+            },
+        )
+    }
+
+    // Fails the stream deliveries that were not acknowledged in time or whose subscriber
+    // disconnected, so they are retried like failed HTTP deliveries
+    async fn stream_ack_reaper(
+        stream_subscribers: Arc<StreamSubscribers>,
+        database: web::Data<dyn Database>,
+        retry_scheduler: Arc<RetryScheduler>,
+        ack_timeout: std::time::Duration,
+        clock: Arc<dyn Clock>,
+    ) {
+        let ack_timeout_chrono =
+            Duration::from_std(ack_timeout).unwrap_or_else(|_| Duration::zero());
+        loop {
+            tokio::time::sleep(ack_timeout).await;
+            let sent_before = clock.now() - ack_timeout_chrono;
+            for delivery in stream_subscribers.take_unacknowledged(sent_before).await {
+                Self::on_stream_delivery_failed(
+                    &delivery.event,
+                    &delivery.service_name,
+                    database.get_ref(),
+                    clock.as_ref(),
+                    retry_scheduler.as_ref(),
+                    delivery.reason,
+                )
+                .await
+            }
+        }
     }
 
     async fn send_to_listening_services(
         details_for_listening_services: Vec<EventFulfillmentDetails>,
         event: VentrixEvent,
-        client: Arc<Mutex<Client>>,
+        delivery_credentials: &DeliveryCredentials,
+        subscription_filters: &SubscriptionFilters,
         database: &dyn Database,
+        clock: &dyn Clock,
+        retry_scheduler: &RetryScheduler,
     ) {
-        let client_lock = client.lock().await;
+        let payload = event_payload(&event);
         for fulfillment_details in details_for_listening_services {
-            let body = event.clone();
+            if !subscription_filters.matches(
+                fulfillment_details.filter.as_deref(),
+                &event,
+                &payload,
+            ) {
+                skip_filtered_event(
+                    &event,
+                    &fulfillment_details.name,
+                    &fulfillment_details.endpoint,
+                    database,
+                    clock,
+                )
+                .await;
+                continue;
+            }
+            let body = match delivery_body(
+                &event,
+                fulfillment_details.transform.as_deref(),
+                fulfillment_details.target_schema.as_deref(),
+                &payload,
+            ) {
+                Ok(body) => body,
+                Err(err) => {
+                    skip_unpreparable_event(
+                        &event,
+                        &fulfillment_details.name,
+                        &fulfillment_details.endpoint,
+                        database,
+                        clock,
+                        err,
+                    )
+                    .await;
+                    continue;
+                }
+            };
+            let credentials = match delivery_credentials.credentials(&fulfillment_details).await {
+                Ok(credentials) => credentials,
+                Err(err) => {
+                    Self::on_delivery_auth_error(
+                        &event,
+                        &fulfillment_details,
+                        database,
+                        clock,
+                        retry_scheduler,
+                        err,
+                    )
+                    .await;
+                    continue;
+                }
+            };
 
             let destination = format!(
                 "{}{}",
                 fulfillment_details.url, fulfillment_details.endpoint
             );
 
-            let response = client_lock
-                .post(destination)
+            // Each attempt gets its own span under the publish, whose context the subscriber
+            // receives so its own spans join the same trace
+            let delivery_span = tracing::info_span!(
+                "Delivering event",
+                event_id = %event.id,
+                service = %fulfillment_details.name,
+                retry_count = event.retry_details.map(|retry| retry.retry_count),
+            );
+            let trace_context = delivery_trace_context(&delivery_span, &event);
+            let request = trace_context.headers().fold(
+                credentials.client.post(destination),
+                |request, (name, value)| request.header(name, value),
+            );
+            let request = credentials
+                .headers
+                .into_iter()
+                .fold(request, |request, (name, value)| {
+                    request.header(name, value)
+                });
+
+            let delivery_timer = METRICS.delivery_timer(&fulfillment_details.name);
+            let attempted_at = clock.now();
+            let started_at = std::time::Instant::now();
+            let response = request
                 .json::<VentrixEvent>(&body)
                 .send()
+                .instrument(delivery_span)
                 .await;
+            delivery_timer.observe_duration();
+            let attempt = delivery_attempt(
+                &event,
+                &fulfillment_details.name,
+                &fulfillment_details.endpoint,
+                attempted_at,
+                started_at.elapsed(),
+            );
 
             match response {
-                Ok(response_details) => match response_details.error_for_status() {
-                    Ok(_) => Self::on_success_response(&event, database, fulfillment_details).await,
-                    Err(server_error) => {
-                        Self::on_failed_response(
-                            &event,
-                            fulfillment_details,
-                            database,
-                            server_error,
-                        )
-                        .await
+                Ok(response_details) => {
+                    let status = response_details.status();
+                    let status_class = status_class(status);
+                    let attempt = DeliveryAttempt {
+                        status_code: Some(i32::from(status.as_u16())),
+                        ..attempt
+                    };
+                    match response_details.error_for_status() {
+                        Ok(_) => {
+                            save_delivery_attempt(database, &attempt).await;
+                            METRICS.record_delivery(&fulfillment_details.name, &status_class, true);
+                            Self::on_success_response(&event, database, &fulfillment_details.name)
+                                .await
+                        }
+                        Err(server_error) => {
+                            if status == StatusCode::UNAUTHORIZED {
+                                delivery_credentials
+                                    .forget_token(&fulfillment_details)
+                                    .await;
+                            }
+                            let attempt = DeliveryAttempt {
+                                succeeded: false,
+                                error: Some(server_error.to_string()),
+                                ..attempt
+                            };
+                            save_delivery_attempt(database, &attempt).await;
+                            METRICS.record_delivery(
+                                &fulfillment_details.name,
+                                &status_class,
+                                false,
+                            );
+                            Self::on_failed_response(
+                                &event,
+                                &fulfillment_details.name,
+                                &fulfillment_details.endpoint,
+                                database,
+                                clock,
+                                retry_scheduler,
+                                server_error,
+                            )
+                            .await
+                        }
                     }
-                },
+                }
                 Err(err) => {
-                    tracing::error!("Could not retrieve response from server: {}", err)
+                    tracing::error!("Could not retrieve response from server: {}", err);
+                    let status_class = match err.is_timeout() {
+                        true => "timeout",
+                        false => "connection_error",
+                    };
+                    METRICS.record_delivery(&fulfillment_details.name, status_class, false);
+                    let attempt = DeliveryAttempt {
+                        succeeded: false,
+                        error: Some(err.to_string()),
+                        ..attempt
+                    };
+                    save_delivery_attempt(database, &attempt).await;
+                    Self::on_failed_response(
+                        &event,
+                        &fulfillment_details.name,
+                        &fulfillment_details.endpoint,
+                        database,
+                        clock,
+                        retry_scheduler,
+                        err,
+                    )
+                    .await
                 }
             }
         }
     }
 
+    // The next attempt may get its credentials, e.g. once the token server recovers, so the
+    // event is retried like any other failed delivery
+    async fn on_delivery_auth_error(
+        event: &VentrixEvent,
+        subscription: &EventFulfillmentDetails,
+        database: &dyn Database,
+        clock: &dyn Clock,
+        retry_scheduler: &RetryScheduler,
+        err: DeliveryAuthError,
+    ) {
+        let attempt = DeliveryAttempt {
+            succeeded: false,
+            error: Some(err.to_string()),
+            ..delivery_attempt(
+                event,
+                &subscription.name,
+                &subscription.endpoint,
+                clock.now(),
+                std::time::Duration::ZERO,
+            )
+        };
+        save_delivery_attempt(database, &attempt).await;
+        METRICS.record_delivery(&subscription.name, DELIVERY_AUTH_STATUS_CLASS, false);
+        Self::on_failed_response(
+            event,
+            &subscription.name,
+            &subscription.endpoint,
+            database,
+            clock,
+            retry_scheduler,
+            err,
+        )
+        .await
+    }
+
     async fn on_failed_response(
         event: &VentrixEvent,
-        fulfillment_details: EventFulfillmentDetails,
+        service_name: &str,
+        endpoint: &str,
         database: &dyn Database,
-        server_error: reqwest::Error,
+        clock: &dyn Clock,
+        retry_scheduler: &RetryScheduler,
+        server_error: impl Display,
     ) {
         tracing::warn!(
             "Event {} was not sent to Service {} - endpoint {} successfully. Error from server: {}",
             event.event_type,
-            fulfillment_details.name,
-            fulfillment_details.endpoint,
+            service_name,
+            endpoint,
             server_error
         );
 
         if let Some(retry_details) = event.retry_details {
-            update_failed_event(retry_details, database, event.clone()).await;
+            update_failed_event(
+                retry_details,
+                database,
+                clock,
+                retry_scheduler,
+                event.clone(),
+            )
+            .await;
             return;
         }
 
@@ -166,7 +1120,10 @@ impl VentrixQueue {
                 tracing::info!(
                     "Failed event {} was added to the failed_events table",
                     event.event_type
-                )
+                );
+                retry_scheduler
+                    .schedule(clock.now() + Duration::minutes(FIRST_RETRY_DELAY_MINUTES))
+                    .await;
             }
             Err(err) => {
                 tracing::warn!(
@@ -181,7 +1138,7 @@ impl VentrixQueue {
     async fn on_success_response(
         event: &VentrixEvent,
         database: &dyn Database,
-        fulfillment_details: EventFulfillmentDetails,
+        service_name: &str,
     ) {
         if event.retry_details.is_some() {
             match database.resolve_failed_event(event.id).await {
@@ -189,14 +1146,14 @@ impl VentrixQueue {
                     tracing::info!(
                         "Failed event {} was sent to Service {} successfully",
                         event.event_type,
-                        fulfillment_details.name
+                        service_name
                     );
                 }
                 Err(err) => {
                     tracing::warn!(
                     "Failed event {} was sent to Service {} successfully, but was not able to update the failed events table. Error: {}",
                     event.event_type,
-                    fulfillment_details.name,
+                    service_name,
                     err
                 );
                 }
@@ -208,64 +1165,355 @@ impl VentrixQueue {
                 tracing::info!(
                     "Event {} was sent to Service {} successfully",
                     event.event_type,
-                    fulfillment_details.name
+                    service_name
                 );
             }
             Err(_) => {
                 tracing::info!(
                     "Event {} was sent to Service {} successfully, but was not able to update the database",
                     event.event_type,
-                    fulfillment_details.name
+                    service_name
                 );
             }
         }
     }
 }
 
-async fn handle_failed_events(
+async fn release_failed_events(
     failed_events: Vec<VentrixEvent>,
     failed_event_sender: &Sender<VentrixEvent>,
+    release_interval: std::time::Duration,
 ) {
-    let count = failed_events.len();
-    let failed_events_iter = failed_events.into_iter();
-    tracing::info!("Retreived {} failed events from database", count);
-    for failed_event in failed_events_iter {
+    if failed_events.is_empty() {
+        return;
+    }
+    tracing::info!("Releasing {} failed events", failed_events.len());
+    for failed_event in failed_events {
         match failed_event_sender.send(failed_event.clone()).await {
-            Ok(_) => tracing::info!(
-                "Failed event {} published to queue",
-                failed_event.event_type
-            ),
+            Ok(_) => {
+                METRICS.record_retry();
+                tracing::info!(
+                    "Failed event {} published to queue",
+                    failed_event.event_type
+                )
+            }
             Err(err) => tracing::warn!("Unable to send events to inner channel. Error: {}", err),
         }
+        tokio::time::sleep(release_interval).await;
+    }
+}
+
+// How long an instance may hold a claimed event before others may claim it again
+pub(super) fn lease_duration(settings: &QueueSettings) -> Duration {
+    Duration::from_std(settings.delivery_timeout() + settings.retry_reconcile_interval())
+        .expect("Lease duration is out of range")
+}
+
+// Failed events are released at the retry rate, so only as many are claimed as can be released
+// within the reconcile interval. The last of them still has a delivery timeout left on its
+// lease once it is released.
+fn retry_claim_limit(settings: &QueueSettings) -> i64 {
+    let releasable = settings.retry_reconcile_interval().as_millis()
+        * u128::from(settings.retry_release_per_second.max(1))
+        / 1000;
+    i64::try_from(releasable).unwrap_or(i64::MAX).max(1)
+}
+
+// Parents the delivery span to the publish and returns the context to send the subscriber
+pub(super) fn delivery_trace_context(
+    delivery_span: &tracing::Span,
+    event: &VentrixEvent,
+) -> TraceContext {
+    delivery_span.set_parent(event.trace_context.to_context());
+    // A span filtered out by the log level has no context; pass the publish's on as is
+    Some(TraceContext::from_context(&delivery_span.context()))
+        .filter(|trace_context| trace_context.traceparent.is_some())
+        .unwrap_or_else(|| event.trace_context.clone())
+}
+
+// A successful attempt; callers fill in the status code and any error
+pub(super) fn delivery_attempt(
+    event: &VentrixEvent,
+    service_name: &str,
+    endpoint: &str,
+    attempted_at: DateTime<Utc>,
+    latency: std::time::Duration,
+) -> DeliveryAttempt {
+    DeliveryAttempt {
+        id: Uuid::new_v4(),
+        event_id: event.id,
+        service_name: service_name.to_string(),
+        endpoint: endpoint.to_string(),
+        attempt: event
+            .retry_details
+            .map_or(0, |retry_details| retry_details.retry_count + 1),
+        status_code: None,
+        succeeded: true,
+        latency_ms: i64::try_from(latency.as_millis()).unwrap_or(i64::MAX),
+        error: None,
+        attempted_at,
+        replay_id: None,
+        filtered: false,
+    }
+}
+
+// Payloads are validated against their schema when published, so this only falls back to null
+// for events stored before that
+pub(super) fn event_payload(event: &VentrixEvent) -> Value {
+    serde_json::from_str(&event.payload).unwrap_or(Value::Null)
+}
+
+// The event as a subscription receives it, with the payload reshaped by the subscription's
+// transform and checked against its target schema when it has them
+pub(super) fn delivery_body(
+    event: &VentrixEvent,
+    transform: Option<&str>,
+    target_schema: Option<&str>,
+    payload: &Value,
+) -> Result<VentrixEvent, InvalidTransformError> {
+    let mut body = event.clone();
+    if let Some(transform) = transform {
+        let transform: Value = serde_json::from_str(transform).map_err(|err| {
+            InvalidTransformError::new(&format!("The stored transform is not valid JSON: {}", err))
+        })?;
+        body.payload = PayloadTransform::parse(&transform)?
+            .apply(event, payload)
+            .to_string();
+    }
+    if let Some(target_schema) = target_schema {
+        validate_payload(&body.payload, target_schema).map_err(|_| {
+            InvalidTransformError::new(
+                "The payload does not match the subscription's target schema",
+            )
+        })?;
+    }
+    Ok(body)
+}
+
+pub(super) async fn skip_filtered_event(
+    event: &VentrixEvent,
+    service_name: &str,
+    endpoint: &str,
+    database: &dyn Database,
+    clock: &dyn Clock,
+) {
+    tracing::info!(
+        "Event {} did not match the filter of Service {}, so it was not sent",
+        event.id,
+        service_name
+    );
+    let attempt = DeliveryAttempt {
+        filtered: true,
+        ..delivery_attempt(
+            event,
+            service_name,
+            endpoint,
+            clock.now(),
+            std::time::Duration::ZERO,
+        )
+    };
+    save_delivery_attempt(database, &attempt).await;
+    METRICS.record_filtered_event(service_name);
+}
+
+// Retrying would only fail the same way, so the event is not retried
+pub(super) async fn skip_unpreparable_event(
+    event: &VentrixEvent,
+    service_name: &str,
+    endpoint: &str,
+    database: &dyn Database,
+    clock: &dyn Clock,
+    err: InvalidTransformError,
+) {
+    tracing::warn!(
+        "Event {} could not be prepared for Service {}, so it was not sent. Err: {}",
+        event.id,
+        service_name,
+        err
+    );
+    let attempt = DeliveryAttempt {
+        succeeded: false,
+        error: Some(err.to_string()),
+        ..delivery_attempt(
+            event,
+            service_name,
+            endpoint,
+            clock.now(),
+            std::time::Duration::ZERO,
+        )
+    };
+    save_delivery_attempt(database, &attempt).await;
+    METRICS.record_delivery(service_name, "invalid_payload", false);
+}
+
+pub(super) async fn save_delivery_attempt(database: &dyn Database, attempt: &DeliveryAttempt) {
+    if let Err(err) = database.record_delivery_attempt(attempt).await {
+        tracing::warn!(
+            "Could not record delivery attempt of event {} to Service {}. Err: {}",
+            attempt.event_id,
+            attempt.service_name,
+            err
+        );
+    }
+}
+
+pub(super) fn restart_backoff(settings: &QueueSettings) -> RestartBackoff {
+    RestartBackoff {
+        initial: std::time::Duration::from_millis(settings.task_restart_backoff_milliseconds),
+        max: std::time::Duration::from_millis(settings.task_restart_max_backoff_milliseconds),
+    }
+}
+
+// Claims no more events than the queue has room for, so claimed events do not wait on the
+// channel for room while their leases run down
+async fn claim_pending_events(
+    database: &dyn Database,
+    sender: &Sender<VentrixEvent>,
+    instance_id: &str,
+    lease: Duration,
+    published_before: DateTime<Utc>,
+) {
+    loop {
+        let limit = PENDING_CLAIM_BATCH.min(i64::try_from(sender.capacity()).unwrap_or(i64::MAX));
+        if limit == 0 {
+            return;
+        }
+        let pending_events = match database
+            .claim_pending_events(instance_id, lease, published_before, limit)
+            .await
+        {
+            Ok(pending_events) => pending_events,
+            Err(err) => {
+                tracing::warn!("There was an issue claiming published events: {}", err);
+                return;
+            }
+        };
+        let claimed = pending_events.len();
+
+        for event in pending_events {
+            if let Err(err) = sender.send(event).await {
+                tracing::warn!("Unable to send events to inner channel. Error: {}", err);
+                return;
+            }
+        }
+
+        if claimed < limit as usize {
+            return;
+        }
+    }
+}
+
+// Skips the event when another instance has claimed it since its lease ran out, or it was
+// already dispatched. It is also skipped when the lease can't be renewed, to be claimed again
+// once the lease expires.
+async fn renew_pending_lease(
+    database: &dyn Database,
+    event_id: Uuid,
+    pending_lease: &PendingLease,
+) -> bool {
+    match database
+        .renew_pending_lease(event_id, &pending_lease.owner, pending_lease.duration)
+        .await
+    {
+        Ok(true) => true,
+        Ok(false) => {
+            tracing::info!(
+                "Skipping event {} as it is dispatched by another instance",
+                event_id
+            );
+            false
+        }
+        Err(err) => {
+            tracing::warn!(
+                "Could not renew the lease on event {}. Err: {}",
+                event_id,
+                err
+            );
+            false
+        }
+    }
+}
+
+async fn take_spilled_events(database: &dyn Database, sender: &Sender<VentrixEvent>) {
+    loop {
+        let spilled_events = match database.take_spilled_events(PENDING_CLAIM_BATCH).await {
+            Ok(spilled_events) => spilled_events,
+            Err(err) => {
+                tracing::warn!("There was an issue taking spilled events: {}", err);
+                return;
+            }
+        };
+        let taken = spilled_events.len();
+
+        for event in spilled_events {
+            if let Err(err) = sender.send(event).await {
+                tracing::warn!("Unable to send events to inner channel. Error: {}", err);
+                return;
+            }
+        }
+
+        if taken < PENDING_CLAIM_BATCH as usize {
+            return;
+        }
+    }
+}
+
+// Seeds the scheduler with the earliest retry stored in the database. Retries that are
+// already due but weren't released (e.g. still in flight) are left to the reconcile interval.
+async fn refresh_next_retry_time(
+    database: &dyn Database,
+    retry_scheduler: &RetryScheduler,
+    now: DateTime<Utc>,
+) {
+    match database.get_next_retry_time().await {
+        Ok(Some(next_retry_time)) if next_retry_time > now => {
+            retry_scheduler.schedule(next_retry_time).await
+        }
+        Ok(_) => {}
+        Err(err) => tracing::warn!(
+            "There was an issue fetching the next retry time from the database: {}",
+            err
+        ),
     }
 }
 
 async fn update_failed_event(
     mut retry_details: RetryDetails,
     database: &dyn Database,
+    clock: &dyn Clock,
+    retry_scheduler: &RetryScheduler,
     event: VentrixEvent,
 ) {
     retry_details.retry_count += 1;
-    let minutes_to_wait: i64 = (retry_details.retry_count + 1).try_into().unwrap_or(2);
-    retry_details.retry_time = Utc::now() + Duration::minutes(minutes_to_wait);
-    let _ = database
+    let minutes_to_wait: i64 = (retry_details.retry_count + 1).into();
+    retry_details.retry_time = clock.now() + Duration::minutes(minutes_to_wait);
+    match database
         .update_retry_time(
             event.id,
             retry_details.retry_time,
             retry_details.retry_count,
         )
         .await
-        .map_err(|err| {
-            tracing::warn!(
-                "Failed to persist retry details for event {}. Err: {}",
-                event.event_type,
-                err
-            )
-        })
-        .map(|_| {
+    {
+        Ok(_) => {
             tracing::info!(
                 "Retry details for event {} successfully updated",
                 event.event_type
-            )
-        });
+            );
+            if retry_details.retry_count >= MAX_RETRIES {
+                tracing::warn!(
+                    "Event {} ran out of retries and was dead-lettered",
+                    event.event_type
+                );
+                METRICS.record_dead_letter();
+            } else {
+                retry_scheduler.schedule(retry_details.retry_time).await;
+            }
+        }
+        Err(err) => tracing::warn!(
+            "Failed to persist retry details for event {}. Err: {}",
+            event.event_type,
+            err
+        ),
+    }
 }
@@ -0,0 +1,223 @@
+use std::time::UNIX_EPOCH;
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::{
+    errors::InvalidApiKeyRequestError,
+    event_type_pattern::EventTypePattern,
+    types::{ApiKey, ApiKeyRole, CreateApiKeyRequest},
+};
+
+const KEY_PREFIX: &str = "vtx_";
+const KEY_BYTES: usize = 32;
+
+// A new random key. Only its hash is stored, so it is shown to the caller once.
+pub fn generate_key() -> String {
+    let mut bytes = [0u8; KEY_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    format!("{}{}", KEY_PREFIX, URL_SAFE_NO_PAD.encode(bytes))
+}
+
+// Keys are random, so an unsalted hash is enough to look them up without storing them
+pub fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+// Checks the request fits its role and returns the key to store along with the key itself
+pub fn new_api_key(
+    request: CreateApiKeyRequest,
+    now: DateTime<Utc>,
+) -> Result<(ApiKey, String), InvalidApiKeyRequestError> {
+    if request.name.trim().is_empty() {
+        return Err(InvalidApiKeyRequestError::new("The key needs a name"));
+    }
+    match request.role {
+        ApiKeyRole::Admin if request.event_types.is_some() || request.service_name.is_some() => {
+            return Err(InvalidApiKeyRequestError::new(
+                "Admin keys cannot be limited to event types or a service",
+            ));
+        }
+        ApiKeyRole::Publisher => {
+            let event_types = request.event_types.as_deref().unwrap_or_default();
+            if event_types.is_empty() {
+                return Err(InvalidApiKeyRequestError::new(
+                    "Publisher keys need the event types they may publish",
+                ));
+            }
+            for event_type in event_types {
+                EventTypePattern::parse(event_type)
+                    .map_err(|err| InvalidApiKeyRequestError::new(&err.message))?;
+            }
+        }
+        ApiKeyRole::Subscriber => {
+            if request.event_types.is_some() {
+                return Err(InvalidApiKeyRequestError::new(
+                    "Subscriber keys are limited to a service, not event types",
+                ));
+            }
+            if request.service_name.is_none() {
+                return Err(InvalidApiKeyRequestError::new(
+                    "Subscriber keys need the service they act for",
+                ));
+            }
+        }
+        ApiKeyRole::Admin => {}
+    }
+
+    let key = generate_key();
+    let api_key = ApiKey {
+        id: Uuid::new_v4(),
+        name: request.name,
+        role: request.role,
+        event_types: request.event_types,
+        service_name: request.service_name,
+        key_hash: hash_key(&key),
+        created_at: now,
+        revoked_at: None,
+    };
+    Ok((api_key, key))
+}
+
+impl ApiKey {
+    // The key configured to set up the first stored keys. It is never stored itself.
+    pub fn bootstrap_admin(key: &str) -> Self {
+        Self {
+            id: Uuid::nil(),
+            name: "bootstrap-admin".to_string(),
+            role: ApiKeyRole::Admin,
+            event_types: None,
+            service_name: None,
+            key_hash: hash_key(key),
+            created_at: DateTime::<Utc>::from(UNIX_EPOCH),
+            revoked_at: None,
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role == ApiKeyRole::Admin
+    }
+
+    pub fn may_publish(&self, event_type: &str) -> bool {
+        match self.role {
+            ApiKeyRole::Admin => true,
+            ApiKeyRole::Publisher => self
+                .event_types
+                .iter()
+                .flatten()
+                .filter_map(|pattern| EventTypePattern::parse(pattern).ok())
+                .any(|pattern| pattern.matches(event_type)),
+            ApiKeyRole::Subscriber => false,
+        }
+    }
+
+    // Whether the key publishes as one of the services an event type allows to publish it
+    pub fn is_one_of_publishers(&self, publishers: &[String]) -> bool {
+        match (self.role, &self.service_name) {
+            (ApiKeyRole::Admin, _) => true,
+            (ApiKeyRole::Publisher, Some(service_name)) => publishers.contains(service_name),
+            _ => false,
+        }
+    }
+
+    pub fn may_act_for(&self, service_name: &str) -> bool {
+        match self.role {
+            ApiKeyRole::Admin => true,
+            ApiKeyRole::Subscriber => self.service_name.as_deref() == Some(service_name),
+            ApiKeyRole::Publisher => false,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use chrono::Utc;
+
+    use super::{generate_key, hash_key, new_api_key};
+    use crate::common::types::{ApiKeyRole, CreateApiKeyRequest};
+
+    fn request(
+        role: ApiKeyRole,
+        event_types: Option<Vec<&str>>,
+        service_name: Option<&str>,
+    ) -> CreateApiKeyRequest {
+        CreateApiKeyRequest {
+            name: "orders".to_string(),
+            role,
+            event_types: event_types
+                .map(|event_types| event_types.into_iter().map(String::from).collect()),
+            service_name: service_name.map(String::from),
+        }
+    }
+
+    #[test]
+    pub fn should_generate_distinct_keys_and_store_only_their_hash() {
+        let (api_key, key) =
+            new_api_key(request(ApiKeyRole::Admin, None, None), Utc::now()).unwrap();
+
+        assert!(key.starts_with("vtx_"));
+        assert_ne!(key, generate_key());
+        assert_eq!(hash_key(&key), api_key.key_hash);
+        assert!(!api_key.key_hash.contains(&key));
+        assert!(!serde_json::to_string(&api_key)
+            .unwrap()
+            .contains(&api_key.key_hash));
+    }
+
+    #[test]
+    pub fn should_limit_keys_to_their_event_types_or_service() {
+        let (publisher, _) = new_api_key(
+            request(ApiKeyRole::Publisher, Some(vec!["orders.*"]), None),
+            Utc::now(),
+        )
+        .unwrap();
+        assert!(publisher.may_publish("orders.created"));
+        assert!(!publisher.may_publish("payments.settled"));
+        assert!(!publisher.may_act_for("orders"));
+        assert!(!publisher.is_one_of_publishers(&["orders".to_string()]));
+
+        let (orders, _) = new_api_key(
+            request(
+                ApiKeyRole::Publisher,
+                Some(vec!["orders.*"]),
+                Some("orders"),
+            ),
+            Utc::now(),
+        )
+        .unwrap();
+        assert!(orders.is_one_of_publishers(&["orders".to_string()]));
+        assert!(!orders.is_one_of_publishers(&["payments".to_string()]));
+        assert!(!orders.may_act_for("orders"));
+
+        let (subscriber, _) = new_api_key(
+            request(ApiKeyRole::Subscriber, None, Some("orders")),
+            Utc::now(),
+        )
+        .unwrap();
+        assert!(subscriber.may_act_for("orders"));
+        assert!(!subscriber.may_act_for("payments"));
+        assert!(!subscriber.may_publish("orders.created"));
+    }
+
+    #[test]
+    pub fn should_reject_scopes_that_do_not_fit_the_role() {
+        for invalid in [
+            request(ApiKeyRole::Admin, None, Some("orders")),
+            request(ApiKeyRole::Publisher, None, None),
+            request(ApiKeyRole::Publisher, Some(vec![]), None),
+            request(ApiKeyRole::Publisher, Some(vec!["orders.*x"]), None),
+            request(ApiKeyRole::Subscriber, None, None),
+            request(ApiKeyRole::Subscriber, Some(vec!["orders"]), Some("orders")),
+        ] {
+            let description = format!("{:?}", invalid);
+            assert!(
+                new_api_key(invalid, Utc::now()).is_err(),
+                "{} should not be valid",
+                description
+            );
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::{fmt::Debug, sync::Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+// A clock that only moves when told to, so retry schedules can be tested without waiting
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl MockClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new(Utc::now())
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::{Clock, MockClock};
+
+    #[test]
+    pub fn should_only_move_mock_clock_when_advanced() {
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        assert_eq!(start, clock.now());
+
+        clock.advance(Duration::minutes(5));
+        assert_eq!(start + Duration::minutes(5), clock.now());
+
+        clock.set(start);
+        assert_eq!(start, clock.now());
+    }
+}
@@ -4,12 +4,187 @@ use secrecy::{ExposeSecret, Secret};
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
+    pub queue: QueueSettings,
+    pub telemetry: TelemetrySettings,
 }
 
 #[derive(serde::Deserialize)]
 pub struct ApplicationSettings {
     pub port: u16,
+    pub grpc_port: u16,
     pub host: String,
+    // Serves the API over TLS when set
+    pub tls: Option<ApiTlsSettings>,
+    // Accepted as an admin API key next to the stored keys, to create the first of them. Only
+    // read from APP_APPLICATION__BOOTSTRAP_ADMIN_KEY, never committed to the configuration files.
+    pub bootstrap_admin_key: Option<Secret<String>>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct ApiTlsSettings {
+    pub certificate_path: String,
+    pub key_path: String,
+    // Publishers must present a certificate issued by one of these CAs when set
+    pub client_ca_path: Option<String>,
+    // How often the certificate and key files are checked for changes
+    pub reload_interval_milliseconds: u64,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct QueueSettings {
+    // Upper bound on how long the retry scheduler sleeps before checking the database again
+    pub retry_reconcile_interval_milliseconds: u64,
+    pub retry_release_per_second: u32,
+    pub delivery_timeout_milliseconds: u64,
+    pub dispatch_mode: DispatchMode,
+    pub capacity: usize,
+    pub backpressure: BackpressurePolicy,
+    // How long a publish may wait for room in the queue under the `block` policy
+    pub backpressure_timeout_milliseconds: u64,
+    // Sent as Retry-After when a publish is turned away because the queue is full
+    pub retry_after_seconds: u64,
+    // The instance reports itself not ready once this many events are waiting in the queue
+    pub readiness_depth_threshold: usize,
+    // Delay before restarting a crashed background task, doubling up to the maximum
+    pub task_restart_backoff_milliseconds: u64,
+    pub task_restart_max_backoff_milliseconds: u64,
+    // Default pace of a replay that does not ask for its own
+    pub replay_per_second: u32,
+    // Stream deliveries not acknowledged within this are failed and retried. Streams are
+    // checked this often for them and for subscribers that disconnected.
+    pub stream_ack_timeout_milliseconds: u64,
+    pub retention: RetentionSettings,
+    pub delivery_auth: DeliveryAuthSettings,
+    pub delivery_tls: DeliveryTlsSettings,
+}
+
+// How long published events are kept once they are done with. A period that is not set keeps
+// those events forever.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct RetentionSettings {
+    pub prune_interval_milliseconds: u64,
+    // Events deleted per transaction; pruning pauses between batches so it never holds locks
+    // for long
+    pub batch_size: i64,
+    pub batch_pause_milliseconds: u64,
+    pub fulfilled_days: Option<u32>,
+    pub dead_letter_days: Option<u32>,
+    // Events that were neither fulfilled nor failed, counted from when they were published
+    pub unfulfilled_days: Option<u32>,
+    // Replaces all three periods for the listed event types
+    #[serde(default)]
+    pub event_types: Vec<EventTypeRetention>,
+    // Pruned events are written here as gzipped NDJSON before they are deleted
+    pub archive_directory: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct EventTypeRetention {
+    pub event_type: String,
+    pub fulfilled_days: Option<u32>,
+    pub dead_letter_days: Option<u32>,
+    pub unfulfilled_days: Option<u32>,
+}
+
+// Secrets in delivery settings are encrypted with this base64 encoded 32 byte key before they
+// are stored. Without it, services and subscriptions can't be registered with delivery settings;
+// it is only read from APP_QUEUE__DELIVERY_AUTH__ENCRYPTION_KEY, never committed to the
+// configuration files.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct DeliveryAuthSettings {
+    pub encryption_key: Option<Secret<String>>,
+    // OAuth2 tokens are fetched again this long before they expire
+    pub token_refresh_margin_seconds: u64,
+}
+
+// TLS for every delivery, which services can add to or replace in their delivery settings.
+// Paths are to PEM files; the key must be PKCS#8.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct DeliveryTlsSettings {
+    // Trusted on top of the system roots, e.g. a private CA
+    #[serde(default)]
+    pub ca_bundle_paths: Vec<String>,
+    pub client_certificate_path: Option<String>,
+    pub client_key_path: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct TelemetrySettings {
+    // Base URL of an OTLP/HTTP collector; spans are not exported when unset
+    pub otlp_endpoint: Option<String>,
+    pub export_interval_milliseconds: u64,
+}
+
+impl TelemetrySettings {
+    pub fn export_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.export_interval_milliseconds)
+    }
+}
+
+// How published events reach the queue that delivers them. `Postgres` lets every instance
+// sharing the database compete for them via LISTEN/NOTIFY.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DispatchMode {
+    InProcess,
+    Postgres,
+}
+
+// What publishing does when the in-process queue is full. `Spill` leaves the already stored
+// event undispatched for the dispatcher to pick up once there is room.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackpressurePolicy {
+    Block,
+    Reject,
+    Spill,
+}
+
+impl QueueSettings {
+    pub fn retry_reconcile_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.retry_reconcile_interval_milliseconds)
+    }
+
+    pub fn retry_release_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(1) / self.retry_release_per_second.max(1)
+    }
+
+    pub fn replay_interval(&self, per_second: u32) -> std::time::Duration {
+        std::time::Duration::from_secs(1) / per_second.max(1)
+    }
+
+    pub fn delivery_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.delivery_timeout_milliseconds)
+    }
+
+    pub fn backpressure_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.backpressure_timeout_milliseconds)
+    }
+
+    pub fn stream_ack_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.stream_ack_timeout_milliseconds)
+    }
+}
+
+impl RetentionSettings {
+    pub fn prune_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.prune_interval_milliseconds)
+    }
+
+    pub fn batch_pause(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.batch_pause_milliseconds)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.fulfilled_days.is_some()
+            || self.dead_letter_days.is_some()
+            || self.unfulfilled_days.is_some()
+            || self.event_types.iter().any(|retention| {
+                retention.fulfilled_days.is_some()
+                    || retention.dead_letter_days.is_some()
+                    || retention.unfulfilled_days.is_some()
+            })
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -39,6 +214,12 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         .add_source(config::File::from(
             configuration_directory.join(environment_filename),
         ))
+        // e.g. APP_APPLICATION__PORT=8001 sets application.port
+        .add_source(
+            config::Environment::with_prefix("APP")
+                .prefix_separator("_")
+                .separator("__"),
+        )
         .build()?;
 
     settings.try_deserialize::<Settings>()
@@ -0,0 +1,366 @@
+use std::collections::BTreeMap;
+
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    Certificate, Identity, Url,
+};
+use serde::{Deserialize, Serialize};
+
+use super::errors::InvalidDeliverySettingsError;
+
+// Headers set by Ventrix itself, which settings may not replace
+const RESERVED_HEADERS: [&str; 6] = [
+    "content-type",
+    "content-length",
+    "host",
+    "traceparent",
+    "tracestate",
+    "x-ventrix-replay",
+];
+
+// What deliveries to a service, or to one of its subscriptions, carry on top of the event:
+// static headers and a way to authenticate. Stored encrypted, as both may hold secrets.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DeliverySettings {
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub auth: Option<DeliveryAuth>,
+    pub tls: Option<DeliveryTls>,
+}
+
+// TLS for services behind a private CA or that require a client certificate. The CA
+// certificates are trusted on top of the configured ones, and the client certificate replaces
+// the configured one.
+#[derive(Clone, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct DeliveryTls {
+    // PEM bundle of one or more certificates
+    pub ca_certificates: Option<String>,
+    // PEM certificate chain, presented with the PKCS#8 PEM key
+    pub client_certificate: Option<String>,
+    pub client_key: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DeliveryAuth {
+    Basic {
+        username: String,
+        password: String,
+    },
+    // A bearer token fetched from the token URL with the client credentials grant
+    #[serde(rename = "oauth2_client_credentials")]
+    OAuth2ClientCredentials {
+        token_url: String,
+        client_id: String,
+        client_secret: String,
+        scope: Option<String>,
+    },
+}
+
+impl DeliverySettings {
+    pub fn validate(&self) -> Result<(), InvalidDeliverySettingsError> {
+        for (name, value) in &self.headers {
+            let header_name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+                InvalidDeliverySettingsError::new(&format!("{:?} is not a valid header name", name))
+            })?;
+            if RESERVED_HEADERS.contains(&header_name.as_str()) {
+                return Err(InvalidDeliverySettingsError::new(&format!(
+                    "Header {} is set by Ventrix and cannot be configured",
+                    name
+                )));
+            }
+            if self.auth.is_some() && header_name == reqwest::header::AUTHORIZATION {
+                return Err(InvalidDeliverySettingsError::new(
+                    "Set either an Authorization header or auth, not both",
+                ));
+            }
+            HeaderValue::from_str(value).map_err(|_| {
+                InvalidDeliverySettingsError::new(&format!(
+                    "The value of header {} is not a valid header value",
+                    name
+                ))
+            })?;
+        }
+
+        if let Some(tls) = &self.tls {
+            tls.certificates()?;
+            tls.identity()?;
+        }
+
+        match &self.auth {
+            Some(DeliveryAuth::Basic { username, .. }) if username.is_empty() => Err(
+                InvalidDeliverySettingsError::new("Basic auth needs a username"),
+            ),
+            Some(DeliveryAuth::Basic { username, .. }) if username.contains(':') => Err(
+                InvalidDeliverySettingsError::new("A basic auth username cannot contain ':'"),
+            ),
+            Some(DeliveryAuth::OAuth2ClientCredentials {
+                token_url,
+                client_id,
+                ..
+            }) => {
+                let is_http =
+                    Url::parse(token_url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"));
+                if !is_http {
+                    return Err(InvalidDeliverySettingsError::new(&format!(
+                        "Token URL {:?} is not an http(s) URL",
+                        token_url
+                    )));
+                }
+                if client_id.is_empty() {
+                    return Err(InvalidDeliverySettingsError::new(
+                        "OAuth2 client credentials need a client_id",
+                    ));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // The settings a subscription delivers with: the service's headers, replaced by any the
+    // subscription sets under the same name, and the subscription's auth and TLS when it has
+    // its own
+    pub fn merge(service: Option<Self>, subscription: Option<Self>) -> Self {
+        let mut merged = service.unwrap_or_default();
+        if let Some(subscription) = subscription {
+            merged.headers.retain(|name, _| {
+                !subscription
+                    .headers
+                    .keys()
+                    .any(|overridden| overridden.eq_ignore_ascii_case(name))
+            });
+            merged.headers.extend(subscription.headers);
+            if subscription.auth.is_some() {
+                merged.auth = subscription.auth;
+            }
+            if subscription.tls.is_some() {
+                merged.tls = subscription.tls;
+            }
+        }
+        merged
+    }
+}
+
+impl DeliveryTls {
+    // These settings on top of the configured ones
+    pub fn over(&self, configured: &DeliveryTls) -> DeliveryTls {
+        let ca_certificates = match (&configured.ca_certificates, &self.ca_certificates) {
+            (Some(configured), Some(own)) => Some(format!("{}\n{}", configured, own)),
+            (configured, own) => own.clone().or_else(|| configured.clone()),
+        };
+        let (client_certificate, client_key) = match &self.client_certificate {
+            Some(_) => (self.client_certificate.clone(), self.client_key.clone()),
+            None => (
+                configured.client_certificate.clone(),
+                configured.client_key.clone(),
+            ),
+        };
+        DeliveryTls {
+            ca_certificates,
+            client_certificate,
+            client_key,
+        }
+    }
+
+    pub fn certificates(&self) -> Result<Vec<Certificate>, InvalidDeliverySettingsError> {
+        let Some(bundle) = &self.ca_certificates else {
+            return Ok(vec![]);
+        };
+        let certificates: Vec<Certificate> = pem_blocks(bundle)
+            .map(|pem| {
+                Certificate::from_pem(pem.as_bytes()).map_err(|err| {
+                    InvalidDeliverySettingsError::new(&format!(
+                        "A CA certificate could not be read: {}",
+                        err
+                    ))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        if certificates.is_empty() {
+            return Err(InvalidDeliverySettingsError::new(
+                "ca_certificates has no PEM encoded certificate",
+            ));
+        }
+        Ok(certificates)
+    }
+
+    pub fn identity(&self) -> Result<Option<Identity>, InvalidDeliverySettingsError> {
+        match (&self.client_certificate, &self.client_key) {
+            (None, None) => Ok(None),
+            (Some(certificate), Some(key)) => {
+                Identity::from_pkcs8_pem(certificate.as_bytes(), key.as_bytes())
+                    .map(Some)
+                    .map_err(|err| {
+                        InvalidDeliverySettingsError::new(&format!(
+                    "The client certificate and key could not be read, the key must be PKCS#8: {}",
+                    err
+                ))
+                    })
+            }
+            _ => Err(InvalidDeliverySettingsError::new(
+                "A client certificate needs its key, and a key its certificate",
+            )),
+        }
+    }
+}
+
+// The certificates of a PEM bundle, one per block
+fn pem_blocks(bundle: &str) -> impl Iterator<Item = &str> {
+    const END: &str = "-----END CERTIFICATE-----";
+    bundle
+        .split_inclusive(END)
+        .filter(|block| block.ends_with(END))
+        .map(str::trim_start)
+}
+
+// Secrets are left out so settings can be traced along with the requests that carry them
+impl std::fmt::Debug for DeliverySettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeliverySettings")
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .field("auth", &self.auth)
+            .field("tls", &self.tls)
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for DeliveryTls {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeliveryTls")
+            .field("ca_certificates", &self.ca_certificates.is_some())
+            .field("client_certificate", &self.client_certificate.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl std::fmt::Debug for DeliveryAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeliveryAuth::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .finish_non_exhaustive(),
+            DeliveryAuth::OAuth2ClientCredentials {
+                token_url,
+                client_id,
+                scope,
+                ..
+            } => f
+                .debug_struct("OAuth2ClientCredentials")
+                .field("token_url", token_url)
+                .field("client_id", client_id)
+                .field("scope", scope)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use serde_json::json;
+
+    use super::{DeliverySettings, DeliveryTls};
+
+    fn settings(settings: serde_json::Value) -> DeliverySettings {
+        serde_json::from_value(settings).unwrap()
+    }
+
+    #[test]
+    pub fn should_let_subscription_headers_and_auth_replace_the_services() {
+        let service = settings(json!({
+            "headers": { "X-Tenant": "acme", "X-Api-Key": "service-key" },
+            "auth": { "type": "basic", "username": "ventrix", "password": "service-password" }
+        }));
+        let subscription = settings(json!({ "headers": { "x-api-key": "subscription-key" } }));
+
+        let merged = DeliverySettings::merge(Some(service.clone()), Some(subscription));
+
+        assert_eq!(
+            settings(json!({
+                "headers": { "X-Tenant": "acme", "x-api-key": "subscription-key" },
+                "auth": { "type": "basic", "username": "ventrix", "password": "service-password" }
+            })),
+            merged
+        );
+
+        let subscription = settings(json!({
+            "auth": {
+                "type": "oauth2_client_credentials",
+                "token_url": "https://auth.example.com/token",
+                "client_id": "ventrix",
+                "client_secret": "subscription-secret"
+            }
+        }));
+        let merged = DeliverySettings::merge(Some(service), Some(subscription.clone()));
+        assert_eq!(subscription.auth, merged.auth);
+        assert!(!format!("{:?}", merged).contains("subscription-secret"));
+    }
+
+    #[test]
+    pub fn should_reject_reserved_or_invalid_headers_and_incomplete_auth() {
+        for invalid in [
+            json!({ "headers": { "Content-Type": "text/plain" } }),
+            json!({ "headers": { "Bad Header": "value" } }),
+            json!({ "headers": { "X-Line": "one\ntwo" } }),
+            json!({
+                "headers": { "Authorization": "Bearer static" },
+                "auth": { "type": "basic", "username": "ventrix", "password": "secret" }
+            }),
+            json!({ "auth": { "type": "basic", "username": "", "password": "secret" } }),
+            json!({
+                "auth": {
+                    "type": "oauth2_client_credentials",
+                    "token_url": "ftp://auth.example.com/token",
+                    "client_id": "ventrix",
+                    "client_secret": "secret"
+                }
+            }),
+        ] {
+            assert!(
+                settings(invalid.clone()).validate().is_err(),
+                "{} should not be valid",
+                invalid
+            );
+        }
+        assert!(
+            settings(json!({ "headers": { "Authorization": "Bearer static" } }))
+                .validate()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    pub fn should_add_service_cas_to_the_configured_ones_and_replace_the_identity() {
+        let configured = DeliveryTls {
+            ca_certificates: Some("configured-ca".to_string()),
+            client_certificate: Some("configured-certificate".to_string()),
+            client_key: Some("configured-key".to_string()),
+        };
+
+        let own = DeliveryTls {
+            ca_certificates: Some("service-ca".to_string()),
+            ..DeliveryTls::default()
+        };
+        assert_eq!(
+            DeliveryTls {
+                ca_certificates: Some("configured-ca\nservice-ca".to_string()),
+                ..configured.clone()
+            },
+            own.over(&configured)
+        );
+
+        let own = DeliveryTls {
+            ca_certificates: None,
+            client_certificate: Some("service-certificate".to_string()),
+            client_key: Some("service-key".to_string()),
+        };
+        assert_eq!(
+            DeliveryTls {
+                ca_certificates: Some("configured-ca".to_string()),
+                ..own.clone()
+            },
+            own.over(&configured)
+        );
+    }
+}
@@ -1,5 +1,16 @@
 use std::{error::Error, fmt::Display};
 
+use actix_web::{
+    http::{
+        header::{RETRY_AFTER, WWW_AUTHENTICATE},
+        StatusCode,
+    },
+    HttpResponse, ResponseError,
+};
+use serde::Serialize;
+
+pub const PROBLEM_JSON_CONTENT_TYPE: &str = "application/problem+json";
+
 #[derive(Debug)]
 pub struct EventTypeAlreadyExistsError {
     pub message: String,
@@ -145,7 +156,7 @@ impl Display for InvalidPropertyDef {
 }
 
 #[derive(Debug)]
-struct InvalidPropertyTypeError {
+pub struct InvalidPropertyTypeError {
     message: String,
 }
 
@@ -156,3 +167,763 @@ impl Display for InvalidPropertyTypeError {
 }
 
 impl Error for InvalidPropertyTypeError {}
+
+#[derive(Debug)]
+pub struct InvalidPayloadError {
+    pub message: String,
+}
+
+impl InvalidPayloadError {
+    pub fn new(message: String) -> Self {
+        Self { message }
+    }
+}
+
+impl Error for InvalidPayloadError {}
+
+impl Display for InvalidPayloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct ReferencedEntityMissingError {
+    pub message: String,
+}
+
+impl ReferencedEntityMissingError {
+    pub fn new(entity: &str, name: &str) -> Self {
+        Self {
+            message: format!("Referenced {}: {:?} does not exist", entity, name),
+        }
+    }
+}
+
+impl Error for ReferencedEntityMissingError {}
+
+impl Display for ReferencedEntityMissingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct QueueError {
+    pub message: String,
+}
+
+impl QueueError {
+    pub fn new(message: String) -> Self {
+        Self { message }
+    }
+}
+
+impl Error for QueueError {}
+
+impl Display for QueueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct QueueFullError {
+    pub message: String,
+    pub retry_after_seconds: u64,
+}
+
+impl QueueFullError {
+    pub fn new(capacity: usize, retry_after_seconds: u64) -> Self {
+        Self {
+            message: format!(
+                "Queue is at capacity ({} events), try again later",
+                capacity
+            ),
+            retry_after_seconds,
+        }
+    }
+}
+
+impl Error for QueueFullError {}
+
+impl Display for QueueFullError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct PendingMigrationsError {
+    pub message: String,
+}
+
+impl PendingMigrationsError {
+    pub fn new(versions: &[i64]) -> Self {
+        let versions = versions
+            .iter()
+            .map(i64::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        Self {
+            message: format!("Database migrations have not been applied: {}", versions),
+        }
+    }
+}
+
+impl Error for PendingMigrationsError {}
+
+impl Display for PendingMigrationsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct ReplayNotFoundError {
+    pub message: String,
+}
+
+impl ReplayNotFoundError {
+    pub fn new(id: &str) -> Self {
+        Self {
+            message: format!("Replay: {:?} not found", id),
+        }
+    }
+}
+
+impl Error for ReplayNotFoundError {}
+
+impl Display for ReplayNotFoundError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidReplayRequestError {
+    pub message: String,
+}
+
+impl InvalidReplayRequestError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Error for InvalidReplayRequestError {}
+
+impl Display for InvalidReplayRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidFilterError {
+    pub message: String,
+}
+
+impl InvalidFilterError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Error for InvalidFilterError {}
+
+impl Display for InvalidFilterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidEventTypePatternError {
+    pub message: String,
+}
+
+impl InvalidEventTypePatternError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Error for InvalidEventTypePatternError {}
+
+impl Display for InvalidEventTypePatternError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidTransformError {
+    pub message: String,
+}
+
+impl InvalidTransformError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Error for InvalidTransformError {}
+
+impl Display for InvalidTransformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidDeliverySettingsError {
+    pub message: String,
+}
+
+impl InvalidDeliverySettingsError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Error for InvalidDeliverySettingsError {}
+
+impl Display for InvalidDeliverySettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// A delivery could not be given the credentials its settings ask for. Deliveries are retried
+// rather than this ever reaching a caller.
+#[derive(Debug)]
+pub struct DeliveryAuthError {
+    pub message: String,
+}
+
+impl DeliveryAuthError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Error for DeliveryAuthError {}
+
+impl Display for DeliveryAuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<InvalidDeliverySettingsError> for DeliveryAuthError {
+    fn from(err: InvalidDeliverySettingsError) -> Self {
+        Self {
+            message: err.message,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ApiKeyNotFoundError {
+    pub message: String,
+}
+
+impl ApiKeyNotFoundError {
+    pub fn new(id: &str) -> Self {
+        Self {
+            message: format!("API key: {:?} not found", id),
+        }
+    }
+}
+
+impl Error for ApiKeyNotFoundError {}
+
+impl Display for ApiKeyNotFoundError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidApiKeyRequestError {
+    pub message: String,
+}
+
+impl InvalidApiKeyRequestError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Error for InvalidApiKeyRequestError {}
+
+impl Display for InvalidApiKeyRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// The request carried no API key, or one that is unknown or revoked
+#[derive(Debug)]
+pub struct UnauthorizedError {
+    pub message: String,
+}
+
+impl UnauthorizedError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Error for UnauthorizedError {}
+
+impl Display for UnauthorizedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// The caller's API key does not grant what the request asks for
+#[derive(Debug)]
+pub struct ForbiddenError {
+    pub message: String,
+}
+
+impl ForbiddenError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Error for ForbiddenError {}
+
+impl Display for ForbiddenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum VentrixError {
+    ServiceAlreadyExists(ServiceAlreadyExistsError),
+    EventTypeAlreadyExists(EventTypeAlreadyExistsError),
+    ServiceNotFound(ServiceNotFoundError),
+    EventNotFound(EventNotFoundError),
+    EventTypeNotFound(EventTypeNotFoundError),
+    ReferencedEntityMissing(ReferencedEntityMissingError),
+    ParsingRecordToStruct(ParsingRecordToStructError),
+    InvalidPropertyDef(InvalidPropertyDef),
+    InvalidPayload(InvalidPayloadError),
+    Queue(QueueError),
+    QueueFull(QueueFullError),
+    PendingMigrations(PendingMigrationsError),
+    ReplayNotFound(ReplayNotFoundError),
+    InvalidReplayRequest(InvalidReplayRequestError),
+    InvalidFilter(InvalidFilterError),
+    InvalidEventTypePattern(InvalidEventTypePatternError),
+    InvalidTransform(InvalidTransformError),
+    InvalidDeliverySettings(InvalidDeliverySettingsError),
+    ApiKeyNotFound(ApiKeyNotFoundError),
+    InvalidApiKeyRequest(InvalidApiKeyRequestError),
+    Unauthorized(UnauthorizedError),
+    Forbidden(ForbiddenError),
+    Database(sqlx::Error),
+}
+
+impl VentrixError {
+    // Short identifier used as the problem type, e.g. urn:ventrix:error:service-not-found
+    pub fn kind(&self) -> &'static str {
+        match self {
+            VentrixError::ServiceAlreadyExists(_) => "service-already-exists",
+            VentrixError::EventTypeAlreadyExists(_) => "event-type-already-exists",
+            VentrixError::ServiceNotFound(_) => "service-not-found",
+            VentrixError::EventNotFound(_) => "event-not-found",
+            VentrixError::EventTypeNotFound(_) => "event-type-not-found",
+            VentrixError::ReferencedEntityMissing(_) => "referenced-entity-missing",
+            VentrixError::ParsingRecordToStruct(_) => "parsing-record",
+            VentrixError::InvalidPropertyDef(_) => "invalid-property-definition",
+            VentrixError::InvalidPayload(_) => "invalid-payload",
+            VentrixError::Queue(_) => "queue-unavailable",
+            VentrixError::QueueFull(_) => "queue-full",
+            VentrixError::PendingMigrations(_) => "pending-migrations",
+            VentrixError::ReplayNotFound(_) => "replay-not-found",
+            VentrixError::InvalidReplayRequest(_) => "invalid-replay-request",
+            VentrixError::InvalidFilter(_) => "invalid-filter",
+            VentrixError::InvalidEventTypePattern(_) => "invalid-event-type-pattern",
+            VentrixError::InvalidTransform(_) => "invalid-transform",
+            VentrixError::InvalidDeliverySettings(_) => "invalid-delivery-settings",
+            VentrixError::ApiKeyNotFound(_) => "api-key-not-found",
+            VentrixError::InvalidApiKeyRequest(_) => "invalid-api-key-request",
+            VentrixError::Unauthorized(_) => "unauthorized",
+            VentrixError::Forbidden(_) => "forbidden",
+            VentrixError::Database(sqlx::Error::RowNotFound) => "record-not-found",
+            VentrixError::Database(_) => "database",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            VentrixError::ServiceAlreadyExists(_) => "Service already exists",
+            VentrixError::EventTypeAlreadyExists(_) => "Event type already exists",
+            VentrixError::ServiceNotFound(_) => "Service not found",
+            VentrixError::EventNotFound(_) => "Event not found",
+            VentrixError::EventTypeNotFound(_) => "Event type not found",
+            VentrixError::ReferencedEntityMissing(_) => "Referenced entity missing",
+            VentrixError::ParsingRecordToStruct(_) => "Could not parse record",
+            VentrixError::InvalidPropertyDef(_) => "Invalid payload definition",
+            VentrixError::InvalidPayload(_) => "Invalid payload",
+            VentrixError::Queue(_) => "Queue unavailable",
+            VentrixError::QueueFull(_) => "Queue full",
+            VentrixError::PendingMigrations(_) => "Pending migrations",
+            VentrixError::ReplayNotFound(_) => "Replay not found",
+            VentrixError::InvalidReplayRequest(_) => "Invalid replay request",
+            VentrixError::InvalidFilter(_) => "Invalid subscription filter",
+            VentrixError::InvalidEventTypePattern(_) => "Invalid event type pattern",
+            VentrixError::InvalidTransform(_) => "Invalid subscription transform",
+            VentrixError::InvalidDeliverySettings(_) => "Invalid delivery settings",
+            VentrixError::ApiKeyNotFound(_) => "API key not found",
+            VentrixError::InvalidApiKeyRequest(_) => "Invalid API key request",
+            VentrixError::Unauthorized(_) => "Unauthorized",
+            VentrixError::Forbidden(_) => "Forbidden",
+            VentrixError::Database(sqlx::Error::RowNotFound) => "Record not found",
+            VentrixError::Database(_) => "Database error",
+        }
+    }
+}
+
+impl Display for VentrixError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VentrixError::ServiceAlreadyExists(err) => write!(f, "{}", err),
+            VentrixError::EventTypeAlreadyExists(err) => write!(f, "{}", err),
+            VentrixError::ServiceNotFound(err) => write!(f, "{}", err),
+            VentrixError::EventNotFound(err) => write!(f, "{}", err),
+            VentrixError::EventTypeNotFound(err) => write!(f, "{}", err),
+            VentrixError::ReferencedEntityMissing(err) => write!(f, "{}", err),
+            VentrixError::ParsingRecordToStruct(err) => write!(f, "{}", err),
+            VentrixError::InvalidPropertyDef(err) => write!(f, "{}", err),
+            VentrixError::InvalidPayload(err) => write!(f, "{}", err),
+            VentrixError::Queue(err) => write!(f, "{}", err),
+            VentrixError::QueueFull(err) => write!(f, "{}", err),
+            VentrixError::PendingMigrations(err) => write!(f, "{}", err),
+            VentrixError::ReplayNotFound(err) => write!(f, "{}", err),
+            VentrixError::InvalidReplayRequest(err) => write!(f, "{}", err),
+            VentrixError::InvalidFilter(err) => write!(f, "{}", err),
+            VentrixError::InvalidEventTypePattern(err) => write!(f, "{}", err),
+            VentrixError::InvalidTransform(err) => write!(f, "{}", err),
+            VentrixError::InvalidDeliverySettings(err) => write!(f, "{}", err),
+            VentrixError::ApiKeyNotFound(err) => write!(f, "{}", err),
+            VentrixError::InvalidApiKeyRequest(err) => write!(f, "{}", err),
+            VentrixError::Unauthorized(err) => write!(f, "{}", err),
+            VentrixError::Forbidden(err) => write!(f, "{}", err),
+            VentrixError::Database(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for VentrixError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VentrixError::ServiceAlreadyExists(err) => Some(err),
+            VentrixError::EventTypeAlreadyExists(err) => Some(err),
+            VentrixError::ServiceNotFound(err) => Some(err),
+            VentrixError::EventNotFound(err) => Some(err),
+            VentrixError::EventTypeNotFound(err) => Some(err),
+            VentrixError::ReferencedEntityMissing(err) => Some(err),
+            VentrixError::ParsingRecordToStruct(err) => Some(err),
+            VentrixError::InvalidPropertyDef(err) => Some(err),
+            VentrixError::InvalidPayload(err) => Some(err),
+            VentrixError::Queue(err) => Some(err),
+            VentrixError::QueueFull(err) => Some(err),
+            VentrixError::PendingMigrations(err) => Some(err),
+            VentrixError::ReplayNotFound(err) => Some(err),
+            VentrixError::InvalidReplayRequest(err) => Some(err),
+            VentrixError::InvalidFilter(err) => Some(err),
+            VentrixError::InvalidEventTypePattern(err) => Some(err),
+            VentrixError::InvalidTransform(err) => Some(err),
+            VentrixError::InvalidDeliverySettings(err) => Some(err),
+            VentrixError::ApiKeyNotFound(err) => Some(err),
+            VentrixError::InvalidApiKeyRequest(err) => Some(err),
+            VentrixError::Unauthorized(err) => Some(err),
+            VentrixError::Forbidden(err) => Some(err),
+            VentrixError::Database(err) => Some(err),
+        }
+    }
+}
+
+impl From<ServiceAlreadyExistsError> for VentrixError {
+    fn from(err: ServiceAlreadyExistsError) -> Self {
+        VentrixError::ServiceAlreadyExists(err)
+    }
+}
+
+impl From<EventTypeAlreadyExistsError> for VentrixError {
+    fn from(err: EventTypeAlreadyExistsError) -> Self {
+        VentrixError::EventTypeAlreadyExists(err)
+    }
+}
+
+impl From<ServiceNotFoundError> for VentrixError {
+    fn from(err: ServiceNotFoundError) -> Self {
+        VentrixError::ServiceNotFound(err)
+    }
+}
+
+impl From<EventNotFoundError> for VentrixError {
+    fn from(err: EventNotFoundError) -> Self {
+        VentrixError::EventNotFound(err)
+    }
+}
+
+impl From<EventTypeNotFoundError> for VentrixError {
+    fn from(err: EventTypeNotFoundError) -> Self {
+        VentrixError::EventTypeNotFound(err)
+    }
+}
+
+impl From<ReferencedEntityMissingError> for VentrixError {
+    fn from(err: ReferencedEntityMissingError) -> Self {
+        VentrixError::ReferencedEntityMissing(err)
+    }
+}
+
+impl From<ParsingRecordToStructError> for VentrixError {
+    fn from(err: ParsingRecordToStructError) -> Self {
+        VentrixError::ParsingRecordToStruct(err)
+    }
+}
+
+impl From<InvalidPropertyDef> for VentrixError {
+    fn from(err: InvalidPropertyDef) -> Self {
+        VentrixError::InvalidPropertyDef(err)
+    }
+}
+
+impl From<InvalidPayloadError> for VentrixError {
+    fn from(err: InvalidPayloadError) -> Self {
+        VentrixError::InvalidPayload(err)
+    }
+}
+
+impl From<QueueError> for VentrixError {
+    fn from(err: QueueError) -> Self {
+        VentrixError::Queue(err)
+    }
+}
+
+impl From<QueueFullError> for VentrixError {
+    fn from(err: QueueFullError) -> Self {
+        VentrixError::QueueFull(err)
+    }
+}
+
+impl From<PendingMigrationsError> for VentrixError {
+    fn from(err: PendingMigrationsError) -> Self {
+        VentrixError::PendingMigrations(err)
+    }
+}
+
+impl From<ReplayNotFoundError> for VentrixError {
+    fn from(err: ReplayNotFoundError) -> Self {
+        VentrixError::ReplayNotFound(err)
+    }
+}
+
+impl From<InvalidReplayRequestError> for VentrixError {
+    fn from(err: InvalidReplayRequestError) -> Self {
+        VentrixError::InvalidReplayRequest(err)
+    }
+}
+
+impl From<InvalidFilterError> for VentrixError {
+    fn from(err: InvalidFilterError) -> Self {
+        VentrixError::InvalidFilter(err)
+    }
+}
+
+impl From<InvalidEventTypePatternError> for VentrixError {
+    fn from(err: InvalidEventTypePatternError) -> Self {
+        VentrixError::InvalidEventTypePattern(err)
+    }
+}
+
+impl From<InvalidTransformError> for VentrixError {
+    fn from(err: InvalidTransformError) -> Self {
+        VentrixError::InvalidTransform(err)
+    }
+}
+
+impl From<InvalidDeliverySettingsError> for VentrixError {
+    fn from(err: InvalidDeliverySettingsError) -> Self {
+        VentrixError::InvalidDeliverySettings(err)
+    }
+}
+
+impl From<ApiKeyNotFoundError> for VentrixError {
+    fn from(err: ApiKeyNotFoundError) -> Self {
+        VentrixError::ApiKeyNotFound(err)
+    }
+}
+
+impl From<InvalidApiKeyRequestError> for VentrixError {
+    fn from(err: InvalidApiKeyRequestError) -> Self {
+        VentrixError::InvalidApiKeyRequest(err)
+    }
+}
+
+impl From<UnauthorizedError> for VentrixError {
+    fn from(err: UnauthorizedError) -> Self {
+        VentrixError::Unauthorized(err)
+    }
+}
+
+impl From<ForbiddenError> for VentrixError {
+    fn from(err: ForbiddenError) -> Self {
+        VentrixError::Forbidden(err)
+    }
+}
+
+impl From<sqlx::Error> for VentrixError {
+    fn from(err: sqlx::Error) -> Self {
+        VentrixError::Database(err)
+    }
+}
+
+// RFC 7807 problem details body
+#[derive(Debug, Serialize)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+}
+
+impl ResponseError for VentrixError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            VentrixError::ServiceAlreadyExists(_) | VentrixError::EventTypeAlreadyExists(_) => {
+                StatusCode::CONFLICT
+            }
+            VentrixError::ServiceNotFound(_)
+            | VentrixError::EventNotFound(_)
+            | VentrixError::EventTypeNotFound(_)
+            | VentrixError::ReplayNotFound(_)
+            | VentrixError::ApiKeyNotFound(_)
+            | VentrixError::Database(sqlx::Error::RowNotFound) => StatusCode::NOT_FOUND,
+            VentrixError::ReferencedEntityMissing(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            VentrixError::InvalidPropertyDef(_)
+            | VentrixError::InvalidPayload(_)
+            | VentrixError::InvalidReplayRequest(_)
+            | VentrixError::InvalidFilter(_)
+            | VentrixError::InvalidEventTypePattern(_)
+            | VentrixError::InvalidTransform(_)
+            | VentrixError::InvalidDeliverySettings(_)
+            | VentrixError::InvalidApiKeyRequest(_) => StatusCode::BAD_REQUEST,
+            VentrixError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            VentrixError::Forbidden(_) => StatusCode::FORBIDDEN,
+            VentrixError::Queue(_) | VentrixError::PendingMigrations(_) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            VentrixError::QueueFull(_) => StatusCode::TOO_MANY_REQUESTS,
+            VentrixError::ParsingRecordToStruct(_) | VentrixError::Database(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        let problem = ProblemDetails {
+            problem_type: format!("urn:ventrix:error:{}", self.kind()),
+            title: self.title().to_string(),
+            status: status.as_u16(),
+            detail: self.to_string(),
+        };
+
+        let mut response = HttpResponse::build(status);
+        match self {
+            VentrixError::QueueFull(err) => {
+                response.insert_header((RETRY_AFTER, err.retry_after_seconds.to_string()));
+            }
+            VentrixError::Unauthorized(_) => {
+                response.insert_header((WWW_AUTHENTICATE, "Bearer"));
+            }
+            _ => {}
+        }
+        response
+            .content_type(PROBLEM_JSON_CONTENT_TYPE)
+            .json(problem)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use actix_web::{body::to_bytes, http::StatusCode, ResponseError};
+    use serde_json::Value;
+
+    use super::{
+        EventTypeAlreadyExistsError, InvalidPayloadError, QueueFullError, ServiceNotFoundError,
+        VentrixError, PROBLEM_JSON_CONTENT_TYPE,
+    };
+
+    #[test]
+    pub fn should_map_errors_to_status_codes() {
+        let already_exists =
+            VentrixError::from(EventTypeAlreadyExistsError::new(String::from("test_event")));
+        let not_found = VentrixError::from(ServiceNotFoundError::new("test_service"));
+        let invalid_payload = VentrixError::from(InvalidPayloadError::new(String::from("bad")));
+        let row_not_found = VentrixError::from(sqlx::Error::RowNotFound);
+
+        assert_eq!(StatusCode::CONFLICT, already_exists.status_code());
+        assert_eq!(StatusCode::NOT_FOUND, not_found.status_code());
+        assert_eq!(StatusCode::BAD_REQUEST, invalid_payload.status_code());
+        assert_eq!(StatusCode::NOT_FOUND, row_not_found.status_code());
+    }
+
+    #[actix_web::test]
+    pub async fn should_render_problem_details_body() {
+        let err = VentrixError::from(ServiceNotFoundError::new("test_service"));
+        let response = err.error_response();
+
+        assert_eq!(
+            PROBLEM_JSON_CONTENT_TYPE,
+            response.headers().get("content-type").unwrap()
+        );
+
+        let body = to_bytes(response.into_body()).await.unwrap();
+        let problem: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!("urn:ventrix:error:service-not-found", problem["type"]);
+        assert_eq!("Service not found", problem["title"]);
+        assert_eq!(404, problem["status"]);
+        assert_eq!("Service: \"test_service\" not found", problem["detail"]);
+    }
+
+    #[test]
+    pub fn should_tell_clients_when_to_retry_a_full_queue() {
+        let response = VentrixError::from(QueueFullError::new(50, 3)).error_response();
+
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, response.status());
+        assert_eq!("3", response.headers().get("retry-after").unwrap());
+    }
+}
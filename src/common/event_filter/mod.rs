@@ -0,0 +1,600 @@
+use std::{cmp::Ordering, fmt::Display};
+
+use serde_json::Value;
+
+use super::{errors::InvalidFilterError, types::VentrixEvent};
+
+// Longest filter source accepted, in bytes
+pub const MAX_FILTER_LENGTH: usize = 4096;
+// How deeply parentheses and `!` may nest
+pub const MAX_FILTER_DEPTH: usize = 32;
+
+// A condition a subscription puts on the events it receives, e.g.
+// `payload.country == "GB" && payload.amount > 100`. Fields are either `payload.<path>` into the
+// JSON payload or the event's `event_type` and `id`. A payload field the event does not have
+// compares as null.
+//
+// Filters come from subscribers, so their length and nesting are capped: `&&` and `||` chains
+// are kept flat and only parentheses and `!` nest, which keeps parsing, evaluating and dropping
+// a filter within a bounded stack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventFilter {
+    expression: Expression,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    And(Vec<Expression>),
+    Or(Vec<Expression>),
+    Not(Box<Expression>),
+    Comparison {
+        field: Field,
+        operator: Operator,
+        value: Value,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Payload(Vec<String>),
+    EventType,
+    Id,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Literal(Value),
+    Operator(Operator),
+    And,
+    Or,
+    Not,
+    OpenParen,
+    CloseParen,
+}
+
+impl EventFilter {
+    pub fn parse(source: &str) -> Result<Self, InvalidFilterError> {
+        if source.len() > MAX_FILTER_LENGTH {
+            return Err(InvalidFilterError::new(&format!(
+                "The filter is longer than {} bytes",
+                MAX_FILTER_LENGTH
+            )));
+        }
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            depth: 0,
+        };
+        let expression = parser.or()?;
+        match parser.next() {
+            None => Ok(Self { expression }),
+            Some(token) => Err(InvalidFilterError::new(&format!(
+                "Unexpected {} after the end of the filter",
+                token
+            ))),
+        }
+    }
+
+    // Checks every payload field against the event type's payload definition, and that each
+    // is compared with a value of its type
+    pub fn validate_against_schema(&self, schema: &str) -> Result<(), InvalidFilterError> {
+        let schema: Value = serde_json::from_str(schema).map_err(|err| {
+            InvalidFilterError::new(&format!("The event type schema is not valid JSON: {}", err))
+        })?;
+        self.expression.validate(&schema)
+    }
+
+    pub fn matches(&self, event: &VentrixEvent, payload: &Value) -> bool {
+        self.expression.evaluate(event, payload)
+    }
+}
+
+impl Expression {
+    fn validate(&self, schema: &Value) -> Result<(), InvalidFilterError> {
+        match self {
+            Expression::And(expressions) | Expression::Or(expressions) => expressions
+                .iter()
+                .try_for_each(|expression| expression.validate(schema)),
+            Expression::Not(expression) => expression.validate(schema),
+            Expression::Comparison {
+                field,
+                operator,
+                value,
+            } => {
+                let field_type = match field {
+                    Field::Payload(path) => payload_field_type(schema, path)?,
+                    Field::EventType | Field::Id => Some("string"),
+                };
+                validate_comparison(field, field_type, *operator, value)
+            }
+        }
+    }
+
+    fn evaluate(&self, event: &VentrixEvent, payload: &Value) -> bool {
+        match self {
+            Expression::And(expressions) => expressions
+                .iter()
+                .all(|expression| expression.evaluate(event, payload)),
+            Expression::Or(expressions) => expressions
+                .iter()
+                .any(|expression| expression.evaluate(event, payload)),
+            Expression::Not(expression) => !expression.evaluate(event, payload),
+            Expression::Comparison {
+                field,
+                operator,
+                value,
+            } => {
+                let field_value = match field {
+                    Field::Payload(path) => path
+                        .iter()
+                        .try_fold(payload, |value, segment| value.get(segment))
+                        .cloned()
+                        .unwrap_or(Value::Null),
+                    Field::EventType => Value::String(event.event_type.clone()),
+                    Field::Id => Value::String(event.id.to_string()),
+                };
+                operator.applies(&field_value, value)
+            }
+        }
+    }
+}
+
+impl Operator {
+    fn applies(&self, left: &Value, right: &Value) -> bool {
+        let ordering = compare(left, right);
+        match self {
+            Operator::Eq => ordering == Some(Ordering::Equal),
+            Operator::Ne => ordering != Some(Ordering::Equal),
+            Operator::Lt => ordering == Some(Ordering::Less),
+            Operator::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            Operator::Gt => ordering == Some(Ordering::Greater),
+            Operator::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        }
+    }
+
+    fn is_ordering(&self) -> bool {
+        !matches!(self, Operator::Eq | Operator::Ne)
+    }
+}
+
+// Numbers compare by value whatever their representation; values of different types are
+// never equal and have no order
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => left.as_f64()?.partial_cmp(&right.as_f64()?),
+        (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+        (left, right) if left == right => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+// The declared type of a payload field, or None when its definition does not say
+fn payload_field_type<'a>(
+    schema: &'a Value,
+    path: &[String],
+) -> Result<Option<&'a str>, InvalidFilterError> {
+    let mut definition = schema;
+    for (depth, segment) in path.iter().enumerate() {
+        definition = definition
+            .get("properties")
+            .and_then(|properties| properties.get(segment))
+            .ok_or_else(|| {
+                InvalidFilterError::new(&format!(
+                    "payload.{} is not defined by the event type schema",
+                    path[..=depth].join(".")
+                ))
+            })?;
+    }
+    Ok(definition.get("type").and_then(Value::as_str))
+}
+
+fn validate_comparison(
+    field: &Field,
+    field_type: Option<&str>,
+    operator: Operator,
+    value: &Value,
+) -> Result<(), InvalidFilterError> {
+    let value_fits = match (field_type, value) {
+        (_, Value::Null) => !operator.is_ordering(),
+        (None, _) => true,
+        (Some("number" | "integer"), Value::Number(_)) => true,
+        (Some("string"), Value::String(_)) => true,
+        (Some("boolean"), Value::Bool(_)) => !operator.is_ordering(),
+        _ => false,
+    };
+
+    match value_fits {
+        true => Ok(()),
+        false => Err(InvalidFilterError::new(&format!(
+            "{} cannot be compared with {} using {}",
+            field, value, operator
+        ))),
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, InvalidFilterError> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(start, char)) = chars.peek() {
+        if char.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let token = match char {
+            '(' => {
+                chars.next();
+                Token::OpenParen
+            }
+            ')' => {
+                chars.next();
+                Token::CloseParen
+            }
+            '"' => {
+                chars.next();
+                let mut escaped = false;
+                let end = chars
+                    .by_ref()
+                    .find(|&(_, char)| {
+                        let closes = char == '"' && !escaped;
+                        escaped = char == '\\' && !escaped;
+                        closes
+                    })
+                    .map(|(end, _)| end)
+                    .ok_or_else(|| InvalidFilterError::new("Unterminated string in filter"))?;
+                let literal = serde_json::from_str(&source[start..=end]).map_err(|err| {
+                    InvalidFilterError::new(&format!("Invalid string in filter: {}", err))
+                })?;
+                Token::Literal(literal)
+            }
+            '0'..='9' | '-' => {
+                let end = take_while(&mut chars, |char| {
+                    char.is_ascii_digit() || matches!(char, '.' | '-' | '+' | 'e' | 'E')
+                });
+                let literal = source[start..end]
+                    .parse::<serde_json::Number>()
+                    .map_err(|_| {
+                        InvalidFilterError::new(&format!(
+                            "Invalid number in filter: {}",
+                            &source[start..end]
+                        ))
+                    })?;
+                Token::Literal(Value::Number(literal))
+            }
+            char if char.is_ascii_alphabetic() || char == '_' => {
+                let end = take_while(&mut chars, |char| {
+                    char.is_ascii_alphanumeric() || matches!(char, '_' | '.')
+                });
+                match &source[start..end] {
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" => Token::Literal(Value::Null),
+                    identifier => Token::Identifier(identifier.to_string()),
+                }
+            }
+            _ => {
+                let end = take_while(&mut chars, |char| "=!<>&|".contains(char));
+                match &source[start..end.max(start + char.len_utf8())] {
+                    "==" => Token::Operator(Operator::Eq),
+                    "!=" => Token::Operator(Operator::Ne),
+                    "<" => Token::Operator(Operator::Lt),
+                    "<=" => Token::Operator(Operator::Le),
+                    ">" => Token::Operator(Operator::Gt),
+                    ">=" => Token::Operator(Operator::Ge),
+                    "&&" => Token::And,
+                    "||" => Token::Or,
+                    "!" => Token::Not,
+                    other => {
+                        return Err(InvalidFilterError::new(&format!(
+                            "Unexpected {:?} in filter",
+                            other
+                        )))
+                    }
+                }
+            }
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+// Consumes the characters matching the predicate and returns the index after the last one
+fn take_while(
+    chars: &mut std::iter::Peekable<std::str::CharIndices>,
+    predicate: impl Fn(char) -> bool,
+) -> usize {
+    let mut end = chars.peek().map_or(0, |&(start, _)| start);
+    while let Some(&(index, char)) = chars.peek() {
+        if !predicate(char) {
+            break;
+        }
+        end = index + char.len_utf8();
+        chars.next();
+    }
+    end
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    // Parentheses and `!` currently open
+    depth: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_is(&mut self, expected: &Token) -> bool {
+        let matches = self.tokens.get(self.position) == Some(expected);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn or(&mut self) -> Result<Expression, InvalidFilterError> {
+        let mut expressions = vec![self.and()?];
+        while self.next_is(&Token::Or) {
+            expressions.push(self.and()?);
+        }
+        Ok(match expressions.len() {
+            1 => expressions.remove(0),
+            _ => Expression::Or(expressions),
+        })
+    }
+
+    fn and(&mut self) -> Result<Expression, InvalidFilterError> {
+        let mut expressions = vec![self.unary()?];
+        while self.next_is(&Token::And) {
+            expressions.push(self.unary()?);
+        }
+        Ok(match expressions.len() {
+            1 => expressions.remove(0),
+            _ => Expression::And(expressions),
+        })
+    }
+
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, InvalidFilterError>,
+    ) -> Result<T, InvalidFilterError> {
+        if self.depth == MAX_FILTER_DEPTH {
+            return Err(InvalidFilterError::new(&format!(
+                "The filter nests more than {} levels deep",
+                MAX_FILTER_DEPTH
+            )));
+        }
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+
+    fn unary(&mut self) -> Result<Expression, InvalidFilterError> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expression::Not(Box::new(self.nested(Self::unary)?))),
+            Some(Token::OpenParen) => {
+                let expression = self.nested(Self::or)?;
+                match self.next() {
+                    Some(Token::CloseParen) => Ok(expression),
+                    _ => Err(InvalidFilterError::new("Missing ) in filter")),
+                }
+            }
+            Some(Token::Identifier(identifier)) => {
+                let field = Field::parse(&identifier)?;
+                let operator = match self.next() {
+                    Some(Token::Operator(operator)) => operator,
+                    _ => {
+                        return Err(InvalidFilterError::new(&format!(
+                            "Expected a comparison after {}",
+                            identifier
+                        )))
+                    }
+                };
+                match self.next() {
+                    Some(Token::Literal(value)) => Ok(Expression::Comparison {
+                        field,
+                        operator,
+                        value,
+                    }),
+                    _ => Err(InvalidFilterError::new(&format!(
+                        "Expected a value to compare {} with",
+                        identifier
+                    ))),
+                }
+            }
+            Some(token) => Err(InvalidFilterError::new(&format!(
+                "Expected a comparison but found {}",
+                token
+            ))),
+            None => Err(InvalidFilterError::new("The filter ended unexpectedly")),
+        }
+    }
+}
+
+impl Field {
+    fn parse(identifier: &str) -> Result<Self, InvalidFilterError> {
+        match identifier.split('.').collect::<Vec<_>>().as_slice() {
+            ["event_type"] => Ok(Field::EventType),
+            ["id"] => Ok(Field::Id),
+            ["payload", path @ ..]
+                if !path.is_empty() && path.iter().all(|segment| !segment.is_empty()) =>
+            {
+                Ok(Field::Payload(
+                    path.iter().map(|segment| segment.to_string()).collect(),
+                ))
+            }
+            _ => Err(InvalidFilterError::new(&format!(
+                "Unknown field {}; use payload.<path>, event_type or id",
+                identifier
+            ))),
+        }
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Field::Payload(path) => write!(f, "payload.{}", path.join(".")),
+            Field::EventType => write!(f, "event_type"),
+            Field::Id => write!(f, "id"),
+        }
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operator = match self {
+            Operator::Eq => "==",
+            Operator::Ne => "!=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+        };
+        write!(f, "{}", operator)
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Identifier(identifier) => write!(f, "{}", identifier),
+            Token::Literal(value) => write!(f, "{}", value),
+            Token::Operator(operator) => write!(f, "{}", operator),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+            Token::OpenParen => write!(f, "("),
+            Token::CloseParen => write!(f, ")"),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    use super::{EventFilter, MAX_FILTER_DEPTH, MAX_FILTER_LENGTH};
+    use crate::common::types::{TraceContext, VentrixEvent};
+
+    fn event(payload: &Value) -> VentrixEvent {
+        VentrixEvent {
+            id: Uuid::new_v4(),
+            event_type: String::from("order_placed"),
+            payload: payload.to_string(),
+            retry_details: None,
+            trace_context: TraceContext::default(),
+        }
+    }
+
+    fn matches(filter: &str, payload: Value) -> bool {
+        EventFilter::parse(filter)
+            .unwrap()
+            .matches(&event(&payload), &payload)
+    }
+
+    fn order_schema() -> String {
+        json!({
+            "type": "object",
+            "properties": {
+                "country": { "type": "string" },
+                "amount": { "type": "number" },
+                "customer": {
+                    "type": "object",
+                    "properties": { "vip": { "type": "boolean" } },
+                    "required": []
+                }
+            },
+            "required": []
+        })
+        .to_string()
+    }
+
+    #[test]
+    pub fn should_match_events_by_payload_and_metadata() {
+        let filter = r#"payload.country == "GB" && payload.amount > 100"#;
+        assert!(matches(filter, json!({ "country": "GB", "amount": 150 })));
+        assert!(!matches(filter, json!({ "country": "GB", "amount": 100 })));
+        assert!(!matches(
+            filter,
+            json!({ "country": "FR", "amount": 150.5 })
+        ));
+        assert!(matches(
+            r#"event_type == "order_placed" && !(payload.customer.vip == true || payload.amount <= 10)"#,
+            json!({ "customer": { "vip": false }, "amount": 11 })
+        ));
+        assert!(matches("payload.missing == null", json!({})));
+        assert!(!matches("payload.amount >= 1", json!({ "amount": "1" })));
+    }
+
+    #[test]
+    pub fn should_reject_filters_that_do_not_parse() {
+        for filter in [
+            "",
+            "payload.amount >",
+            "payload.amount > 1 &&",
+            "(payload.amount > 1",
+            "amount > 1",
+            "payload.amount = 1",
+            r#"payload.country == "GB"#,
+            "payload.amount > 1 payload.amount < 2",
+        ] {
+            assert!(EventFilter::parse(filter).is_err(), "{} parsed", filter);
+        }
+    }
+
+    #[test]
+    pub fn should_reject_filters_nested_or_sized_past_the_limits() {
+        let nested =
+            |depth: usize| format!("{}payload.a == 1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(EventFilter::parse(&nested(MAX_FILTER_DEPTH)).is_ok());
+        assert!(EventFilter::parse(&nested(MAX_FILTER_DEPTH + 1)).is_err());
+        assert!(EventFilter::parse(&nested(1000)).is_err());
+        assert!(EventFilter::parse(&"!".repeat(1000)).is_err());
+
+        // Long chains stay flat, so they match without deep recursion
+        let chain = vec!["payload.a == 1"; 200].join(" && ");
+        assert!(chain.len() <= MAX_FILTER_LENGTH);
+        assert!(matches(&chain, json!({ "a": 1 })));
+        let too_long = vec!["payload.a == 1"; 300].join(" || ");
+        assert!(EventFilter::parse(&too_long).is_err());
+    }
+
+    #[test]
+    pub fn should_validate_filters_against_the_event_type_schema() {
+        let validate = |filter: &str| {
+            EventFilter::parse(filter)
+                .unwrap()
+                .validate_against_schema(&order_schema())
+        };
+
+        assert!(validate(r#"payload.country == "GB" && payload.customer.vip != true"#).is_ok());
+        assert!(validate("payload.amount > -2.5e3 || payload.country == null").is_ok());
+        assert!(validate("payload.currency == \"GBP\"").is_err());
+        assert!(validate("payload.customer.name == \"John\"").is_err());
+        assert!(validate("payload.amount == \"100\"").is_err());
+        assert!(validate("payload.customer.vip > false").is_err());
+        assert!(validate("payload.amount > null").is_err());
+        assert!(validate("event_type == 1").is_err());
+    }
+}
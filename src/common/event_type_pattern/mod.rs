@@ -0,0 +1,99 @@
+use super::errors::InvalidEventTypePatternError;
+
+// A subscription to every event type whose dotted name matches, e.g. `orders.*` or `orders.#`.
+// `*` stands for exactly one segment and `#` for any number of them, including none, so
+// `orders.#` also matches `orders` itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventTypePattern {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    One,
+    Any,
+}
+
+impl EventTypePattern {
+    // Event types are names, anything with a wildcard is a pattern
+    pub fn is_pattern(event_type: &str) -> bool {
+        event_type.contains(['*', '#'])
+    }
+
+    pub fn parse(pattern: &str) -> Result<Self, InvalidEventTypePatternError> {
+        let segments = pattern
+            .split('.')
+            .map(|segment| match segment {
+                "" => Err(InvalidEventTypePatternError::new(&format!(
+                    "Event type pattern {:?} has an empty segment",
+                    pattern
+                ))),
+                "*" => Ok(Segment::One),
+                "#" => Ok(Segment::Any),
+                segment if Self::is_pattern(segment) => {
+                    Err(InvalidEventTypePatternError::new(&format!(
+                        "Wildcards must be a whole segment of event type pattern {:?}",
+                        pattern
+                    )))
+                }
+                segment => Ok(Segment::Literal(segment.to_string())),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { segments })
+    }
+
+    pub fn matches(&self, event_type: &str) -> bool {
+        let names: Vec<&str> = event_type.split('.').collect();
+        matches_segments(&self.segments, &names)
+    }
+}
+
+fn matches_segments(segments: &[Segment], names: &[&str]) -> bool {
+    match (segments.split_first(), names.split_first()) {
+        (None, None) => true,
+        (Some((Segment::Any, rest)), _) => {
+            matches_segments(rest, names)
+                || (!names.is_empty() && matches_segments(segments, &names[1..]))
+        }
+        (Some((Segment::One, rest)), Some((_, names))) => matches_segments(rest, names),
+        (Some((Segment::Literal(literal), rest)), Some((name, names))) => {
+            literal == name && matches_segments(rest, names)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::EventTypePattern;
+
+    #[test]
+    pub fn should_match_one_segment_for_a_star_and_any_number_for_a_hash() {
+        let star = EventTypePattern::parse("orders.*").unwrap();
+        let hash = EventTypePattern::parse("orders.#").unwrap();
+        let inner = EventTypePattern::parse("orders.*.created").unwrap();
+
+        assert!(star.matches("orders.created"));
+        assert!(!star.matches("orders"));
+        assert!(!star.matches("orders.items.added"));
+        assert!(!star.matches("invoices.created"));
+        assert!(hash.matches("orders"));
+        assert!(hash.matches("orders.created"));
+        assert!(hash.matches("orders.items.added"));
+        assert!(!hash.matches("ordersx.created"));
+        assert!(inner.matches("orders.items.created"));
+        assert!(!inner.matches("orders.created"));
+    }
+
+    #[test]
+    pub fn should_reject_wildcards_that_are_not_whole_segments() {
+        for pattern in ["orders.cre*", "orders..*", "orders.#x", ".orders.*"] {
+            assert!(
+                EventTypePattern::parse(pattern).is_err(),
+                "{} should not parse",
+                pattern
+            );
+        }
+    }
+}
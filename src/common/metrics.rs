@@ -0,0 +1,316 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    histogram_opts, opts, Encoder, HistogramTimer, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, IntGaugeVec, Registry, TextEncoder,
+};
+use reqwest::StatusCode;
+
+use super::errors::VentrixError;
+
+const DB_QUERY_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+];
+
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+    events_published: IntCounterVec,
+    schema_validation_failures: IntCounterVec,
+    queue_depth: IntGauge,
+    delivery_attempts: IntCounterVec,
+    delivery_successes: IntCounterVec,
+    delivery_failures: IntCounterVec,
+    delivery_duration: HistogramVec,
+    retries: IntCounter,
+    dead_letters: IntCounter,
+    db_query_duration: HistogramVec,
+    background_tasks_running: IntGaugeVec,
+    background_task_restarts: IntCounterVec,
+    events_pruned: IntCounterVec,
+    events_filtered: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("ventrix".to_string()), None)
+            .expect("Failed to create metrics registry");
+
+        let events_published = IntCounterVec::new(
+            opts!("events_published_total", "Publish requests by outcome"),
+            &["event_type", "outcome"],
+        )
+        .unwrap();
+        let schema_validation_failures = IntCounterVec::new(
+            opts!(
+                "schema_validation_failures_total",
+                "Published payloads rejected by the event type schema"
+            ),
+            &["event_type"],
+        )
+        .unwrap();
+        let queue_depth =
+            IntGauge::new("queue_depth", "Events waiting in the in-process queue").unwrap();
+        let delivery_attempts = IntCounterVec::new(
+            opts!(
+                "delivery_attempts_total",
+                "Deliveries attempted to services"
+            ),
+            &["service", "status_class"],
+        )
+        .unwrap();
+        let delivery_successes = IntCounterVec::new(
+            opts!(
+                "delivery_successes_total",
+                "Deliveries accepted by services"
+            ),
+            &["service", "status_class"],
+        )
+        .unwrap();
+        let delivery_failures = IntCounterVec::new(
+            opts!("delivery_failures_total", "Deliveries that failed"),
+            &["service", "status_class"],
+        )
+        .unwrap();
+        let delivery_duration = HistogramVec::new(
+            histogram_opts!(
+                "delivery_duration_seconds",
+                "Time taken for a service to respond to a delivery"
+            ),
+            &["service"],
+        )
+        .unwrap();
+        let retries = IntCounter::new(
+            "retries_total",
+            "Failed events released back onto the queue",
+        )
+        .unwrap();
+        let dead_letters = IntCounter::new(
+            "dead_letters_total",
+            "Failed events that ran out of retries",
+        )
+        .unwrap();
+        let db_query_duration = HistogramVec::new(
+            histogram_opts!("db_query_duration_seconds", "Database query latency")
+                .buckets(DB_QUERY_BUCKETS.to_vec()),
+            &["backend", "operation"],
+        )
+        .unwrap();
+
+        let background_tasks_running = IntGaugeVec::new(
+            opts!(
+                "background_task_running",
+                "Whether a queue background task is running"
+            ),
+            &["task"],
+        )
+        .unwrap();
+        let background_task_restarts = IntCounterVec::new(
+            opts!(
+                "background_task_restarts_total",
+                "Queue background tasks restarted after panicking or exiting"
+            ),
+            &["task"],
+        )
+        .unwrap();
+        let events_pruned = IntCounterVec::new(
+            opts!(
+                "events_pruned_total",
+                "Published events deleted once past their retention period"
+            ),
+            &["kind"],
+        )
+        .unwrap();
+        let events_filtered = IntCounterVec::new(
+            opts!(
+                "events_filtered_total",
+                "Events not sent to a service because they did not match its filter"
+            ),
+            &["service"],
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(events_published.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(schema_validation_failures.clone()))
+            .unwrap();
+        registry.register(Box::new(queue_depth.clone())).unwrap();
+        registry
+            .register(Box::new(delivery_attempts.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(delivery_successes.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(delivery_failures.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(delivery_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(retries.clone())).unwrap();
+        registry.register(Box::new(dead_letters.clone())).unwrap();
+        registry
+            .register(Box::new(db_query_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(background_tasks_running.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(background_task_restarts.clone()))
+            .unwrap();
+        registry.register(Box::new(events_pruned.clone())).unwrap();
+        registry
+            .register(Box::new(events_filtered.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            events_published,
+            schema_validation_failures,
+            queue_depth,
+            delivery_attempts,
+            delivery_successes,
+            delivery_failures,
+            delivery_duration,
+            retries,
+            dead_letters,
+            db_query_duration,
+            background_tasks_running,
+            background_task_restarts,
+            events_pruned,
+            events_filtered,
+        }
+    }
+
+    // Error kinds are used as the outcome, and publishes to event types that are not registered
+    // count as "unknown", so clients can't grow the number of series
+    pub fn record_publish<T>(
+        &self,
+        registered_event_type: Option<&str>,
+        result: &Result<T, VentrixError>,
+    ) {
+        let outcome = match result {
+            Ok(_) => "accepted",
+            Err(err) => err.kind(),
+        };
+        self.events_published
+            .with_label_values(&[registered_event_type.unwrap_or("unknown"), outcome])
+            .inc();
+    }
+
+    pub fn record_schema_validation_failure(&self, event_type: &str) {
+        self.schema_validation_failures
+            .with_label_values(&[event_type])
+            .inc();
+    }
+
+    pub fn set_queue_depth(&self, depth: usize) {
+        self.queue_depth.set(depth as i64);
+    }
+
+    pub fn record_delivery(&self, service: &str, status_class: &str, success: bool) {
+        self.delivery_attempts
+            .with_label_values(&[service, status_class])
+            .inc();
+        let outcome = match success {
+            true => &self.delivery_successes,
+            false => &self.delivery_failures,
+        };
+        outcome.with_label_values(&[service, status_class]).inc();
+    }
+
+    pub fn delivery_timer(&self, service: &str) -> HistogramTimer {
+        self.delivery_duration
+            .with_label_values(&[service])
+            .start_timer()
+    }
+
+    pub fn record_retry(&self) {
+        self.retries.inc();
+    }
+
+    pub fn record_dead_letter(&self) {
+        self.dead_letters.inc();
+    }
+
+    // Observes the query latency when the returned timer is dropped
+    pub fn db_query_timer(&self, backend: &str, operation: &str) -> HistogramTimer {
+        self.db_query_duration
+            .with_label_values(&[backend, operation])
+            .start_timer()
+    }
+
+    pub fn set_task_running(&self, task: &str, running: bool) {
+        self.background_tasks_running
+            .with_label_values(&[task])
+            .set(running.into());
+    }
+
+    pub fn record_task_restart(&self, task: &str) {
+        self.background_task_restarts
+            .with_label_values(&[task])
+            .inc();
+    }
+
+    pub fn record_pruned_events(&self, kind: &str, count: usize) {
+        self.events_pruned
+            .with_label_values(&[kind])
+            .inc_by(count as u64);
+    }
+
+    pub fn record_filtered_event(&self, service: &str) {
+        self.events_filtered.with_label_values(&[service]).inc();
+    }
+
+    pub fn render(&self) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Failed to encode metrics");
+        String::from_utf8(buffer).expect("Metrics are not valid UTF-8")
+    }
+}
+
+pub fn status_class(status: StatusCode) -> String {
+    format!("{}xx", status.as_u16() / 100)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use reqwest::StatusCode;
+
+    use super::{status_class, Metrics};
+    use crate::common::errors::{QueueFullError, VentrixError};
+
+    #[test]
+    pub fn should_group_status_codes_into_classes() {
+        assert_eq!("2xx", status_class(StatusCode::NO_CONTENT));
+        assert_eq!("4xx", status_class(StatusCode::NOT_FOUND));
+        assert_eq!("5xx", status_class(StatusCode::BAD_GATEWAY));
+    }
+
+    #[test]
+    pub fn should_label_publish_outcomes_by_error_kind() {
+        let metrics = Metrics::new();
+        metrics.record_publish(Some("order_placed"), &Ok::<_, VentrixError>(()));
+        metrics.record_publish::<()>(
+            Some("order_placed"),
+            &Err(QueueFullError::new(50, 1).into()),
+        );
+        metrics.record_publish::<()>(None, &Err(QueueFullError::new(50, 1).into()));
+
+        let rendered = metrics.render();
+        assert!(rendered.contains(
+            r#"ventrix_events_published_total{event_type="order_placed",outcome="accepted"} 1"#
+        ));
+        assert!(rendered.contains(
+            r#"ventrix_events_published_total{event_type="order_placed",outcome="queue-full"} 1"#
+        ));
+        assert!(rendered.contains(
+            r#"ventrix_events_published_total{event_type="unknown",outcome="queue-full"} 1"#
+        ));
+    }
+}
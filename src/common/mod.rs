@@ -1,6 +1,13 @@
+pub mod api_keys;
+pub mod clock;
 pub mod configuration;
+pub mod delivery_settings;
 pub mod errors;
-pub mod helpers;
+pub mod event_filter;
+pub mod event_type_pattern;
+pub mod metrics;
+pub mod payload_transform;
 pub mod schema_validator;
+pub mod secrets;
 pub mod telemetry;
 pub mod types;
@@ -0,0 +1,294 @@
+use serde_json::{Map, Value};
+
+use super::{errors::InvalidTransformError, types::VentrixEvent};
+
+// Reshapes the payload a subscription receives. A transform is a JSON object whose keys are the
+// fields of the new payload and whose values say where each comes from: `payload` or
+// `payload.<path>` into the published payload, `event_type`, `id`, or a nested object of the
+// same form, e.g. `{ "country": "payload.address.country", "kind": "event_type" }`. Payload
+// fields the event does not have are left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayloadTransform {
+    fields: Vec<(String, Projection)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Projection {
+    Source(Source),
+    Object(Vec<(String, Projection)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    Payload(Vec<String>),
+    EventType,
+    Id,
+}
+
+impl PayloadTransform {
+    pub fn parse(transform: &Value) -> Result<Self, InvalidTransformError> {
+        match Projection::parse(transform, "")? {
+            Projection::Object(fields) => Ok(Self { fields }),
+            Projection::Source(_) => Err(InvalidTransformError::new(
+                "A transform must be an object of the fields to send",
+            )),
+        }
+    }
+
+    // Checks every payload field against the event type's payload definition and, when the
+    // subscription declares a target schema, that the new payload has each required field and
+    // the declared field types
+    pub fn validate_against_schemas(
+        &self,
+        source_schema: &str,
+        target_schema: Option<&Value>,
+    ) -> Result<(), InvalidTransformError> {
+        let source_schema: Value = serde_json::from_str(source_schema).map_err(|err| {
+            InvalidTransformError::new(&format!("The event type schema is not valid JSON: {}", err))
+        })?;
+        validate_fields(&self.fields, &source_schema, target_schema, "")
+    }
+
+    pub fn apply(&self, event: &VentrixEvent, payload: &Value) -> Value {
+        apply_fields(&self.fields, event, payload)
+    }
+}
+
+impl Projection {
+    fn parse(projection: &Value, name: &str) -> Result<Self, InvalidTransformError> {
+        match projection {
+            Value::String(source) => Source::parse(source, name).map(Projection::Source),
+            Value::Object(fields) => fields
+                .iter()
+                .map(|(field, projection)| {
+                    Projection::parse(projection, &join(name, field))
+                        .map(|projection| (field.clone(), projection))
+                })
+                .collect::<Result<_, _>>()
+                .map(Projection::Object),
+            _ => Err(InvalidTransformError::new(&format!(
+                "Field {} must be a source such as payload.<path>, or an object of fields",
+                name
+            ))),
+        }
+    }
+
+    // The JSON schema type of the value, or None when the source schema does not say
+    fn value_type<'a>(
+        &self,
+        source_schema: &'a Value,
+    ) -> Result<Option<&'a str>, InvalidTransformError> {
+        match self {
+            Projection::Object(_) => Ok(Some("object")),
+            Projection::Source(Source::EventType | Source::Id) => Ok(Some("string")),
+            Projection::Source(Source::Payload(path)) => payload_field_type(source_schema, path),
+        }
+    }
+}
+
+impl Source {
+    fn parse(source: &str, name: &str) -> Result<Self, InvalidTransformError> {
+        match source.split('.').collect::<Vec<_>>().as_slice() {
+            ["event_type"] => Ok(Source::EventType),
+            ["id"] => Ok(Source::Id),
+            ["payload", path @ ..] if path.iter().all(|segment| !segment.is_empty()) => Ok(
+                Source::Payload(path.iter().map(|segment| segment.to_string()).collect()),
+            ),
+            _ => Err(InvalidTransformError::new(&format!(
+                "Unknown source {:?} for field {}; use payload.<path>, event_type or id",
+                source, name
+            ))),
+        }
+    }
+
+    fn value(&self, event: &VentrixEvent, payload: &Value) -> Option<Value> {
+        match self {
+            Source::Payload(path) => path
+                .iter()
+                .try_fold(payload, |value, segment| value.get(segment))
+                .cloned(),
+            Source::EventType => Some(Value::String(event.event_type.clone())),
+            Source::Id => Some(Value::String(event.id.to_string())),
+        }
+    }
+}
+
+fn apply_fields(fields: &[(String, Projection)], event: &VentrixEvent, payload: &Value) -> Value {
+    let mut object = Map::new();
+    for (name, projection) in fields {
+        let value = match projection {
+            Projection::Source(source) => source.value(event, payload),
+            Projection::Object(fields) => Some(apply_fields(fields, event, payload)),
+        };
+        if let Some(value) = value {
+            object.insert(name.clone(), value);
+        }
+    }
+    Value::Object(object)
+}
+
+fn validate_fields(
+    fields: &[(String, Projection)],
+    source_schema: &Value,
+    target_schema: Option<&Value>,
+    parent: &str,
+) -> Result<(), InvalidTransformError> {
+    if let Some(required) = target_schema
+        .and_then(|schema| schema.get("required"))
+        .and_then(Value::as_array)
+    {
+        for required in required.iter().filter_map(Value::as_str) {
+            if !fields.iter().any(|(name, _)| name == required) {
+                return Err(InvalidTransformError::new(&format!(
+                    "The target schema requires {}, which the transform does not produce",
+                    join(parent, required)
+                )));
+            }
+        }
+    }
+
+    for (name, projection) in fields {
+        let target_definition = target_schema
+            .and_then(|schema| schema.get("properties"))
+            .and_then(|properties| properties.get(name));
+        let value_type = projection.value_type(source_schema)?;
+        let target_type = target_definition
+            .and_then(|definition| definition.get("type"))
+            .and_then(Value::as_str);
+        if let (Some(value_type), Some(target_type)) = (value_type, target_type) {
+            if value_type != target_type && !(value_type == "integer" && target_type == "number") {
+                return Err(InvalidTransformError::new(&format!(
+                    "Field {} is a {} but the target schema declares a {}",
+                    join(parent, name),
+                    value_type,
+                    target_type
+                )));
+            }
+        }
+        if let Projection::Object(fields) = projection {
+            validate_fields(
+                fields,
+                source_schema,
+                target_definition,
+                &join(parent, name),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// The declared type of a payload field, or None when its definition does not say
+fn payload_field_type<'a>(
+    schema: &'a Value,
+    path: &[String],
+) -> Result<Option<&'a str>, InvalidTransformError> {
+    let mut definition = schema;
+    for (depth, segment) in path.iter().enumerate() {
+        definition = definition
+            .get("properties")
+            .and_then(|properties| properties.get(segment))
+            .ok_or_else(|| {
+                InvalidTransformError::new(&format!(
+                    "payload.{} is not defined by the event type schema",
+                    path[..=depth].join(".")
+                ))
+            })?;
+    }
+    Ok(definition.get("type").and_then(Value::as_str))
+}
+
+fn join(parent: &str, name: &str) -> String {
+    match parent {
+        "" => name.to_string(),
+        parent => format!("{}.{}", parent, name),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::PayloadTransform;
+    use crate::common::types::{TraceContext, VentrixEvent};
+
+    fn source_schema() -> String {
+        json!({
+            "type": "object",
+            "properties": {
+                "amount": { "type": "integer" },
+                "customer": {
+                    "type": "object",
+                    "properties": { "name": { "type": "string" } }
+                }
+            }
+        })
+        .to_string()
+    }
+
+    #[test]
+    pub fn should_project_and_rename_fields() {
+        let event = VentrixEvent {
+            id: Uuid::new_v4(),
+            event_type: "orders.created".to_string(),
+            payload: String::new(),
+            retry_details: None,
+            trace_context: TraceContext::default(),
+        };
+        let transform = PayloadTransform::parse(&json!({
+            "total": "payload.amount",
+            "kind": "event_type",
+            "buyer": { "name": "payload.customer.name", "email": "payload.customer.email" }
+        }))
+        .unwrap();
+
+        let payload = transform.apply(
+            &event,
+            &json!({ "amount": 120, "country": "GB", "customer": { "name": "Ferris" } }),
+        );
+
+        assert_eq!(
+            json!({ "total": 120, "kind": "orders.created", "buyer": { "name": "Ferris" } }),
+            payload
+        );
+    }
+
+    #[test]
+    pub fn should_reject_transforms_that_are_not_objects_of_sources() {
+        for transform in [
+            json!("payload.amount"),
+            json!({ "total": 120 }),
+            json!({ "total": "amount" }),
+            json!({ "total": "payload..amount" }),
+        ] {
+            assert!(
+                PayloadTransform::parse(&transform).is_err(),
+                "{} should not parse",
+                transform
+            );
+        }
+    }
+
+    #[test]
+    pub fn should_validate_transforms_against_the_source_and_target_schemas() {
+        let target_schema = json!({
+            "type": "object",
+            "properties": { "total": { "type": "number" }, "buyer": { "type": "string" } },
+            "required": ["total"]
+        });
+        let validate = |transform| {
+            PayloadTransform::parse(&transform)
+                .unwrap()
+                .validate_against_schemas(&source_schema(), Some(&target_schema))
+        };
+
+        assert!(validate(json!({ "total": "payload.amount" })).is_ok());
+        assert!(validate(json!({ "total": "payload.country" })).is_err());
+        assert!(validate(json!({ "buyer": "payload.customer.name" })).is_err());
+        assert!(validate(json!({
+            "total": "payload.amount",
+            "buyer": "payload.customer"
+        }))
+        .is_err());
+    }
+}
@@ -7,7 +7,7 @@ use valico::{
     json_schema::{self},
 };
 
-use super::errors::InvalidPropertyDef;
+use super::errors::{InvalidPayloadError, InvalidPropertyDef};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 enum JsonSchemaType {
@@ -38,6 +38,36 @@ pub fn payload_is_valid(payload: &str, schema: &str) -> bool {
     r_schema.validate(&payload_as_obj).is_valid()
 }
 
+// Strict validation of a published payload, reporting why it was rejected
+pub fn validate_payload(payload: &str, schema: &str) -> Result<(), InvalidPayloadError> {
+    let schema_value: Value = from_str(schema).map_err(|err| {
+        InvalidPayloadError::new(format!("Couldn't parse Value from schema string: {}", err))
+    })?;
+
+    let payload_value: Value = from_str(payload).map_err(|err| {
+        InvalidPayloadError::new(format!("Couldn't parse Value from payload string: {}", err))
+    })?;
+
+    let mut scope = json_schema::Scope::new();
+    let scoped_schema = scope
+        .compile_and_return(schema_value.clone(), false)
+        .map_err(|err| {
+            InvalidPayloadError::new(format!(
+                "Couldn't compile scoped schema from schema value: {}",
+                err
+            ))
+        })?;
+
+    if !scoped_schema.validate(&payload_value).is_strictly_valid() {
+        return Err(InvalidPayloadError::new(format!(
+            "Payload did not match the event type payload definition: {}",
+            schema_value
+        )));
+    }
+
+    Ok(())
+}
+
 impl Display for JsonSchemaType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -210,6 +240,7 @@ pub mod tests {
                 },
                 "required": []
             }),
+            publishers: None,
         };
 
         is_valid_property_def(&mut request.payload_definition).unwrap();
@@ -231,6 +262,7 @@ pub mod tests {
                 },
                 "required": []
             }),
+            publishers: None,
         };
 
         is_valid_property_def(&mut request.payload_definition).unwrap();
@@ -0,0 +1,118 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use secrecy::{ExposeSecret, Secret};
+
+use super::errors::InvalidDeliverySettingsError;
+
+const NONCE_LENGTH: usize = 12;
+
+// Encrypts secrets before they are stored, with AES-256-GCM under the configured key
+pub struct SecretCipher {
+    cipher: Aes256Gcm,
+}
+
+// Base64 of the nonce followed by the ciphertext. Only a SecretCipher with the same key can
+// read it back.
+#[derive(Clone, PartialEq, Eq, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct SealedSecret(String);
+
+impl SecretCipher {
+    // The key is 32 bytes, base64 encoded
+    pub fn new(key: &Secret<String>) -> Result<Self, InvalidDeliverySettingsError> {
+        let key = STANDARD.decode(key.expose_secret()).map_err(|_| {
+            InvalidDeliverySettingsError::new("The encryption key is not valid base64")
+        })?;
+        let cipher = Aes256Gcm::new_from_slice(&key).map_err(|_| {
+            InvalidDeliverySettingsError::new("The encryption key must be 32 bytes long")
+        })?;
+        Ok(Self { cipher })
+    }
+
+    pub fn seal(&self, secret: &str) -> SealedSecret {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, secret.as_bytes())
+            .expect("Encrypting with AES-256-GCM does not fail");
+        let sealed = [nonce.as_slice(), &ciphertext].concat();
+        SealedSecret(STANDARD.encode(sealed))
+    }
+
+    pub fn open(
+        &self,
+        sealed: &SealedSecret,
+    ) -> Result<Secret<String>, InvalidDeliverySettingsError> {
+        let unreadable = || {
+            InvalidDeliverySettingsError::new(
+                "The stored secret could not be decrypted with the configured key",
+            )
+        };
+        let sealed = STANDARD.decode(&sealed.0).map_err(|_| unreadable())?;
+        if sealed.len() < NONCE_LENGTH {
+            return Err(unreadable());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+        let secret = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| unreadable())?;
+        String::from_utf8(secret)
+            .map(Secret::new)
+            .map_err(|_| unreadable())
+    }
+}
+
+impl std::fmt::Debug for SecretCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretCipher([REDACTED])")
+    }
+}
+
+impl std::fmt::Debug for SealedSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SealedSecret([REDACTED])")
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use secrecy::{ExposeSecret, Secret};
+
+    use super::{SealedSecret, SecretCipher};
+
+    fn cipher(key_byte: u8) -> SecretCipher {
+        SecretCipher::new(&Secret::new(STANDARD.encode([key_byte; 32]))).unwrap()
+    }
+
+    #[test]
+    pub fn should_open_what_it_sealed_and_nothing_else() {
+        let other_key = cipher(8);
+        let cipher = cipher(7);
+        let sealed = cipher.seal("client-secret");
+
+        assert_eq!(
+            "client-secret",
+            cipher.open(&sealed).unwrap().expose_secret()
+        );
+        assert!(!sealed.0.contains("client-secret"));
+        assert_ne!(sealed, cipher.seal("client-secret"));
+        assert!(other_key.open(&sealed).is_err());
+
+        let mut tampered = STANDARD.decode(&sealed.0).unwrap();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(cipher
+            .open(&SealedSecret(STANDARD.encode(tampered)))
+            .is_err());
+    }
+
+    #[test]
+    pub fn should_reject_keys_that_are_not_32_bytes() {
+        assert!(SecretCipher::new(&Secret::new(STANDARD.encode([7; 16]))).is_err());
+        assert!(SecretCipher::new(&Secret::new("not base64!".to_string())).is_err());
+    }
+}
@@ -1,14 +1,29 @@
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{BatchConfig, Config, Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
 use tracing::subscriber::set_global_default;
 use tracing::Subscriber;
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::fmt::MakeWriter;
 use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
 
+use super::configuration::TelemetrySettings;
+use super::types::TraceContext;
+
+const TRACEPARENT_HEADER: &str = "traceparent";
+const TRACESTATE_HEADER: &str = "tracestate";
+
 pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
     sink: Sink,
+    tracer: Tracer,
 ) -> impl Subscriber + Sync + Send
 where
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
@@ -16,14 +31,121 @@ where
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
     let formatting_layer = BunyanFormattingLayer::new(name, sink);
+    let telemetry_layer = tracing_opentelemetry::layer().with_tracer(tracer);
 
     Registry::default()
+        .with(telemetry_layer)
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
 }
 
+// Spans are always recorded so trace context reaches subscribers, but only exported when an
+// OTLP endpoint is configured. The exporter runs on the Tokio runtime this is called from.
+pub fn get_tracer(name: String, settings: &TelemetrySettings) -> Tracer {
+    let trace_config =
+        Config::default().with_resource(Resource::new([KeyValue::new("service.name", name)]));
+
+    match &settings.otlp_endpoint {
+        Some(endpoint) => opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .http()
+                    .with_endpoint(endpoint),
+            )
+            .with_trace_config(trace_config)
+            .with_batch_config(
+                BatchConfig::default().with_scheduled_delay(settings.export_interval()),
+            )
+            .install_batch(runtime::Tokio)
+            .expect("Failed to install the OTLP exporter"),
+        None => {
+            let provider = TracerProvider::builder().with_config(trace_config).build();
+            let tracer = provider.tracer("ventrix");
+            global::set_tracer_provider(provider);
+            tracer
+        }
+    }
+}
+
 pub fn init_tracing_subscriber(subscriber: impl Subscriber + Send + Sync) {
     LogTracer::init().expect("Failed to set logger");
+    global::set_text_map_propagator(TraceContextPropagator::new());
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
+
+impl TraceContext {
+    pub fn current() -> Self {
+        Self::from_context(&tracing::Span::current().context())
+    }
+
+    pub fn from_context(context: &Context) -> Self {
+        let mut trace_context = Self::default();
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(context, &mut trace_context)
+        });
+        trace_context
+    }
+
+    pub fn to_context(&self) -> Context {
+        global::get_text_map_propagator(|propagator| propagator.extract(self))
+    }
+
+    pub fn headers(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            (TRACEPARENT_HEADER, self.traceparent.as_deref()),
+            (TRACESTATE_HEADER, self.tracestate.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+    }
+}
+
+impl Injector for TraceContext {
+    fn set(&mut self, key: &str, value: String) {
+        match key {
+            TRACEPARENT_HEADER => self.traceparent = Some(value),
+            TRACESTATE_HEADER => self.tracestate = Some(value).filter(|state| !state.is_empty()),
+            _ => {}
+        }
+    }
+}
+
+impl Extractor for TraceContext {
+    fn get(&self, key: &str) -> Option<&str> {
+        match key {
+            TRACEPARENT_HEADER => self.traceparent.as_deref(),
+            TRACESTATE_HEADER => self.tracestate.as_deref(),
+            _ => None,
+        }
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.headers().map(|(name, _)| name).collect()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use opentelemetry::trace::TraceContextExt;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+
+    use crate::common::types::TraceContext;
+
+    #[test]
+    pub fn should_round_trip_trace_context_through_the_propagator() {
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        let trace_context = TraceContext {
+            traceparent: Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".into()),
+            tracestate: Some("vendor=value".into()),
+        };
+
+        let context = trace_context.to_context();
+        assert_eq!(
+            "4bf92f3577b34da6a3ce929d0e0e4736",
+            context.span().span_context().trace_id().to_string()
+        );
+        assert_eq!(trace_context, TraceContext::from_context(&context));
+    }
+}
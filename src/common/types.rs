@@ -5,6 +5,8 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
 use uuid::Uuid;
 
+use super::{delivery_settings::DeliverySettings, secrets::SealedSecret};
+
 pub fn datetime_utc_to_string<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -18,9 +20,31 @@ where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(deserialize)?;
-    Ok(DateTime::parse_from_rfc3339(&s)
-        .unwrap()
-        .with_timezone(&Utc))
+    DateTime::parse_from_rfc3339(&s)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(serde::de::Error::custom)
+}
+
+pub fn optional_datetime_utc_to_string<S>(
+    date: &Option<DateTime<Utc>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match date {
+        Some(date) => datetime_utc_to_string(date, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn string_to_optional_datetime_utc<'de, D>(
+    deserialize: D,
+) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    string_to_datetime_utc(deserialize).map(Some)
 }
 
 #[derive(Deserialize, Serialize, Clone, Copy, Debug)]
@@ -33,12 +57,22 @@ pub struct RetryDetails {
     pub retry_time: DateTime<Utc>,
 }
 
+// W3C trace context of the request that published an event
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    pub traceparent: Option<String>,
+    pub tracestate: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct VentrixEvent {
     pub id: Uuid,
     pub event_type: String,
     pub payload: String,
     pub retry_details: Option<RetryDetails>,
+    // Sent to subscribers as headers rather than in the body
+    #[serde(skip)]
+    pub trace_context: TraceContext,
 }
 
 impl VentrixEvent {
@@ -51,6 +85,10 @@ impl VentrixEvent {
                 retry_count: failed_event.retries,
                 retry_time: failed_event.retry_time,
             }),
+            trace_context: TraceContext {
+                traceparent: failed_event.traceparent,
+                tracestate: failed_event.tracestate,
+            },
         }
     }
 }
@@ -70,13 +108,29 @@ impl ServiceDetails {
 pub struct EventTypeDetails {
     description: String,
     payload_def: Value,
+    publishers: Option<Vec<String>>,
 }
 
 impl EventTypeDetails {
-    pub fn new(description: String, payload_def: Value) -> Self {
+    pub fn new(description: String, payload_def: Value, publishers: Option<Vec<String>>) -> Self {
         Self {
             description,
             payload_def,
+            publishers,
+        }
+    }
+
+    pub fn payload_def(&self) -> &Value {
+        &self.payload_def
+    }
+
+    pub fn publishers(&self) -> Option<&Vec<String>> {
+        self.publishers.as_ref()
+    }
+
+    pub fn remove_publisher(&mut self, service_name: &str) {
+        if let Some(publishers) = &mut self.publishers {
+            publishers.retain(|publisher| publisher != service_name);
         }
     }
 }
@@ -109,6 +163,8 @@ pub struct NewEventTypeRequest {
     pub name: String,
     pub description: String,
     pub payload_definition: Value,
+    // Services allowed to publish the event type; anyone with a publisher key for it when unset
+    pub publishers: Option<Vec<String>>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -124,9 +180,29 @@ pub struct ListenToEventReq {
     pub service_name: String,
     pub event_type: String,
     pub endpoint: String,
+    // Only events matching this expression are delivered, see EventFilter
+    pub filter: Option<String>,
+    // Reshapes the payload before delivery, see PayloadTransform
+    pub transform: Option<Value>,
+    // Payload definition the delivered payload is checked against before sending
+    pub target_schema: Option<Value>,
+    // Headers and auth for this subscription's deliveries, on top of the service's
+    pub delivery: Option<DeliverySettings>,
+    #[serde(skip)]
+    pub sealed_delivery: Option<SealedSecret>,
 }
 
-#[derive(sqlx::FromRow)]
+// A subscription served over a stream rather than an HTTP endpoint
+#[derive(Debug)]
+pub struct SubscribeToStreamReq {
+    pub service_name: String,
+    pub event_type: String,
+    pub filter: Option<String>,
+    pub transform: Option<Value>,
+    pub target_schema: Option<Value>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
 pub struct PayloadSchema {
     pub payload_definition: String,
 }
@@ -136,6 +212,21 @@ pub struct EventFulfillmentDetails {
     pub name: String,
     pub url: String,
     pub endpoint: String,
+    pub filter: Option<String>,
+    // Stored as JSON, like payload definitions
+    pub transform: Option<String>,
+    pub target_schema: Option<String>,
+    pub service_delivery_settings: Option<SealedSecret>,
+    pub delivery_settings: Option<SealedSecret>,
+}
+
+// What a service listens to: an event type, or a pattern with the event types it matches now
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Subscription {
+    pub event_type: String,
+    pub endpoint: String,
+    pub filter: Option<String>,
+    pub resolved_event_types: Vec<String>,
 }
 
 #[derive(Debug, sqlx::FromRow)]
@@ -174,4 +265,244 @@ pub struct FailedEventRow {
     pub payload: String,
     pub retry_time: DateTime<Utc>,
     pub retries: i16,
+    pub traceparent: Option<String>,
+    pub tracestate: Option<String>,
+}
+
+// One attempt at delivering an event to a subscriber, successful or not
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct DeliveryAttempt {
+    pub id: Uuid,
+    pub event_id: Uuid,
+    pub service_name: String,
+    pub endpoint: String,
+    // 0 for the first delivery, then the retry count of the attempt
+    pub attempt: i16,
+    // Absent when no HTTP response was received, e.g. on timeouts and stream deliveries
+    pub status_code: Option<i32>,
+    pub succeeded: bool,
+    pub latency_ms: i64,
+    pub error: Option<String>,
+    #[serde(serialize_with = "datetime_utc_to_string")]
+    pub attempted_at: DateTime<Utc>,
+    // Set when the attempt was made by a replay rather than the queue
+    pub replay_id: Option<Uuid>,
+    // The event did not match the subscription's filter, so it was not sent
+    pub filtered: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FulfilmentStatus {
+    Fulfilled,
+    Pending,
+}
+
+// Filters for listing published events, newest first
+#[derive(Debug, Default, Deserialize)]
+pub struct EventQuery {
+    pub event_type: Option<String>,
+    pub status: Option<FulfilmentStatus>,
+    // Only events with a delivery attempt to this service
+    pub service: Option<String>,
+    #[serde(default, deserialize_with = "string_to_optional_datetime_utc")]
+    pub published_after: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "string_to_optional_datetime_utc")]
+    pub published_before: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+impl EventQuery {
+    pub const DEFAULT_LIMIT: i64 = 100;
+    pub const MAX_LIMIT: i64 = 1000;
+
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(Self::DEFAULT_LIMIT)
+            .clamp(1, Self::MAX_LIMIT)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct PublishedEvent {
+    pub id: Uuid,
+    pub event_type: String,
+    pub payload: String,
+    #[serde(serialize_with = "datetime_utc_to_string")]
+    pub published_at: DateTime<Utc>,
+    #[serde(serialize_with = "optional_datetime_utc_to_string")]
+    pub dispatched_at: Option<DateTime<Utc>>,
+    #[serde(serialize_with = "optional_datetime_utc_to_string")]
+    pub fulfilled_at: Option<DateTime<Utc>>,
+}
+
+// A published event with every attempt at delivering it, oldest first
+#[derive(Debug, Serialize)]
+pub struct EventHistory {
+    #[serde(flatten)]
+    pub event: PublishedEvent,
+    pub deliveries: Vec<DeliveryAttempt>,
+}
+
+// The stored events a retention period applies to. Events still waiting on a retry are none
+// of them, so they are never pruned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetainedEventKind {
+    Fulfilled,
+    DeadLetter,
+    // Never fulfilled and not failing either, e.g. lost before they were dispatched
+    Unfulfilled,
+}
+
+impl RetainedEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetainedEventKind::Fulfilled => "fulfilled",
+            RetainedEventKind::DeadLetter => "dead_letter",
+            RetainedEventKind::Unfulfilled => "unfulfilled",
+        }
+    }
+}
+
+// Selects events of one kind that are past their retention period. Fulfilled events age from
+// when they were fulfilled, dead letters from when they ran out of retries and unfulfilled
+// events from when they were published.
+#[derive(Debug, Clone)]
+pub struct PruneCriteria {
+    pub kind: RetainedEventKind,
+    // Only events of this type, or when unset of every type not excluded
+    pub event_type: Option<String>,
+    pub excluded_event_types: Vec<String>,
+    pub before: DateTime<Utc>,
+}
+
+// Re-sends stored events to a single service, either every event of a type published in a
+// time window or the listed events
+#[derive(Debug, Deserialize)]
+pub struct ReplayRequest {
+    pub service_name: String,
+    pub event_type: Option<String>,
+    #[serde(default, deserialize_with = "string_to_optional_datetime_utc")]
+    pub published_after: Option<DateTime<Utc>>,
+    // Defaults to when the replay starts, so events published during it are not included
+    #[serde(default, deserialize_with = "string_to_optional_datetime_utc")]
+    pub published_before: Option<DateTime<Utc>>,
+    pub event_ids: Option<Vec<Uuid>>,
+    pub rate_per_second: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum ReplayStatus {
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ReplayJob {
+    pub id: Uuid,
+    pub service_name: String,
+    pub event_type: Option<String>,
+    #[serde(serialize_with = "optional_datetime_utc_to_string")]
+    pub published_after: Option<DateTime<Utc>>,
+    #[serde(serialize_with = "optional_datetime_utc_to_string")]
+    pub published_before: Option<DateTime<Utc>>,
+    pub event_ids: Option<Vec<Uuid>>,
+    pub rate_per_second: i32,
+    pub status: ReplayStatus,
+    // Number of events selected when the replay started
+    pub total: i64,
+    pub delivered: i64,
+    pub failed: i64,
+    // Events of a type the service no longer listens to, or that its filters do not match
+    pub skipped: i64,
+    pub error: Option<String>,
+    #[serde(serialize_with = "datetime_utc_to_string")]
+    pub created_at: DateTime<Utc>,
+    #[serde(serialize_with = "optional_datetime_utc_to_string")]
+    pub finished_at: Option<DateTime<Utc>>,
+    // The last event replayed, so a replay picks up after it when resumed
+    #[serde(skip)]
+    pub cursor_published_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub cursor_event_id: Option<Uuid>,
+    // The instance running the replay, which renews the lease as the replay makes progress
+    #[serde(skip)]
+    pub lease_owner: Option<String>,
+    #[serde(skip)]
+    pub lease_expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum ApiKeyRole {
+    // May call every endpoint, including key management
+    Admin,
+    // May publish events of its event types, as its service when it has one
+    Publisher,
+    // May manage the subscriptions and replays of its service
+    Subscriber,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub role: ApiKeyRole,
+    // Event types or patterns a publisher key may publish
+    pub event_types: Option<Vec<String>>,
+    // The service a subscriber key acts for, or a publisher key publishes as
+    pub service_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub name: String,
+    pub role: ApiKeyRole,
+    pub event_types: Option<Vec<String>>,
+    pub service_name: Option<String>,
+    // SHA-256 of the key, the key itself is only returned when it is created
+    #[serde(skip)]
+    pub key_hash: String,
+    #[serde(serialize_with = "datetime_utc_to_string")]
+    pub created_at: DateTime<Utc>,
+    #[serde(serialize_with = "optional_datetime_utc_to_string")]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PublishDenialQuery {
+    pub limit: Option<i64>,
+}
+
+impl PublishDenialQuery {
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(EventQuery::DEFAULT_LIMIT)
+            .clamp(1, EventQuery::MAX_LIMIT)
+    }
+}
+
+// A publish refused because the key or its service may not publish the event type
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct PublishDenial {
+    pub id: Uuid,
+    pub event_type: String,
+    pub api_key_id: Uuid,
+    pub api_key_name: String,
+    pub service_name: Option<String>,
+    pub reason: String,
+    #[serde(serialize_with = "datetime_utc_to_string")]
+    pub attempted_at: DateTime<Utc>,
 }
@@ -3,6 +3,8 @@ use std::fmt::Display;
 use serde::Deserialize;
 use uuid::Uuid;
 
+use crate::common::{delivery_settings::DeliverySettings, secrets::SealedSecret};
+
 #[derive(Debug, Eq, Hash, PartialEq, Clone, sqlx::FromRow, Deserialize)]
 pub struct Service {
     pub id: Uuid,
@@ -20,4 +22,9 @@ impl Display for Service {
 pub struct RegisterServiceRequest {
     pub name: String,
     pub url: String,
+    // Headers and auth for every delivery to the service, see DeliverySettings
+    pub delivery: Option<DeliverySettings>,
+    // The delivery settings encrypted for storage, which is all the database ever sees
+    #[serde(skip)]
+    pub sealed_delivery: Option<SealedSecret>,
 }
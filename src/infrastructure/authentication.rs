@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use actix_web::web;
+use secrecy::{ExposeSecret, Secret};
+
+use crate::{
+    common::{
+        api_keys::hash_key,
+        errors::{ForbiddenError, UnauthorizedError, VentrixError},
+        types::ApiKey,
+    },
+    infrastructure::persistence::Database,
+};
+
+// The API key a request was authenticated with. Handlers of both APIs take it to check the
+// key grants what the request asks for.
+#[derive(Debug, Clone)]
+pub struct Caller(pub ApiKey);
+
+impl Caller {
+    pub fn require_admin(&self) -> Result<(), VentrixError> {
+        match self.0.is_admin() {
+            true => Ok(()),
+            false => Err(self.forbidden("is not an admin key")),
+        }
+    }
+
+    pub fn require_service(&self, service_name: &str) -> Result<(), VentrixError> {
+        match self.0.may_act_for(service_name) {
+            true => Ok(()),
+            false => Err(self.forbidden(&format!("may not act for service {:?}", service_name))),
+        }
+    }
+
+    fn forbidden(&self, reason: &str) -> VentrixError {
+        ForbiddenError::new(&format!("API key {:?} {}", self.0.name, reason)).into()
+    }
+}
+
+// Resolves the `Authorization: Bearer <key>` header of REST and gRPC requests to the key it
+// names
+#[derive(Clone)]
+pub struct Authenticator {
+    bootstrap_admin: Option<Arc<ApiKey>>,
+    database: web::Data<dyn Database>,
+}
+
+impl Authenticator {
+    // The bootstrap admin key is accepted next to the stored keys, so the first keys can be
+    // created. A blank one is ignored.
+    pub fn new(
+        database: web::Data<dyn Database>,
+        bootstrap_admin_key: Option<&Secret<String>>,
+    ) -> Self {
+        Self {
+            bootstrap_admin: bootstrap_admin_key
+                .filter(|key| !key.expose_secret().trim().is_empty())
+                .map(|key| Arc::new(ApiKey::bootstrap_admin(key.expose_secret()))),
+            database,
+        }
+    }
+
+    pub async fn authenticate(&self, authorization: Option<&str>) -> Result<Caller, VentrixError> {
+        let key = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| UnauthorizedError::new("The request carries no API key"))?;
+        let key_hash = hash_key(key.trim());
+
+        if let Some(bootstrap_admin) = self
+            .bootstrap_admin
+            .as_deref()
+            .filter(|admin| admin.key_hash == key_hash)
+        {
+            return Ok(Caller(bootstrap_admin.clone()));
+        }
+        self.database
+            .find_api_key(&key_hash)
+            .await?
+            .map(Caller)
+            .ok_or_else(|| UnauthorizedError::new("The API key is unknown or revoked").into())
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tonic::{
+    body::BoxBody,
+    codegen::http::{header::AUTHORIZATION, Request, Response},
+    transport::Body,
+    Status,
+};
+use tower::{Layer, Service};
+
+use crate::infrastructure::authentication::Authenticator;
+
+// Answers calls without a valid `authorization: Bearer <key>` header with UNAUTHENTICATED and
+// hands the key of the others to the RPCs as their Caller, like ApiKeyAuthentication does for
+// the REST API
+#[derive(Clone)]
+pub struct GrpcAuthentication {
+    authenticator: Authenticator,
+}
+
+impl GrpcAuthentication {
+    pub fn new(authenticator: Authenticator) -> Self {
+        Self { authenticator }
+    }
+}
+
+impl<S> Layer<S> for GrpcAuthentication {
+    type Service = GrpcAuthenticationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcAuthenticationService {
+            inner,
+            authenticator: self.authenticator.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GrpcAuthenticationService<S> {
+    inner: S,
+    authenticator: Authenticator,
+}
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+impl<S> Service<Request<Body>> for GrpcAuthenticationService<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        // The clone may not be ready, so the inner service that was polled takes the call
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let authenticator = self.authenticator.clone();
+        Box::pin(async move {
+            let authorization = request
+                .headers()
+                .get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            match authenticator.authenticate(authorization.as_deref()).await {
+                Ok(caller) => {
+                    request.extensions_mut().insert(caller);
+                    inner.call(request).await
+                }
+                Err(err) => Ok(Status::from(err).to_http()),
+            }
+        })
+    }
+}
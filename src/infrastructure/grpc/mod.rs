@@ -0,0 +1,8 @@
+pub mod auth;
+pub mod service;
+pub mod startup;
+pub mod tls;
+
+pub mod proto {
+    tonic::include_proto!("ventrix.v1");
+}
@@ -0,0 +1,462 @@
+use std::pin::Pin;
+
+use actix_web::web;
+use serde_json::Value;
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tonic::{Request, Response, Status, Streaming};
+use uuid::Uuid;
+
+use crate::{
+    application::queue_service::ventrix_queue::VentrixQueue,
+    common::{
+        delivery_settings::DeliverySettings,
+        errors::{
+            ForbiddenError, InvalidDeliverySettingsError, InvalidTransformError, UnauthorizedError,
+            VentrixError,
+        },
+        event_type_pattern::EventTypePattern,
+        metrics::METRICS,
+        schema_validator::{is_valid_property_def, validate_payload},
+        types::{
+            FeatureFlagConfig, ListenToEventReq, NewEventTypeRequest, SubscribeToStreamReq,
+            TraceContext, VentrixEvent,
+        },
+    },
+    domain::models::service::RegisterServiceRequest,
+    infrastructure::{authentication::Caller, persistence::Database},
+};
+
+use super::{
+    proto::{
+        self, ventrix_server::Ventrix, AcknowledgeRequest, AcknowledgeResponse, Delivery,
+        EventTypeSchema, GetEventTypeSchemaRequest, GetListenersRequest, GetListenersResponse,
+        GetServiceRequest, ListenToEventRequest, ListenToEventResponse, Listener,
+        PublishEventRequest, PublishEventResponse, PublishEventsResponse, RegisterEventTypeRequest,
+        RegisterEventTypeResponse, RegisterServiceResponse, RemoveServiceRequest,
+        RemoveServiceResponse, SubscribeRequest,
+    },
+    tls::GrpcConnectInfo,
+};
+
+#[derive(Debug)]
+pub struct VentrixGrpcService {
+    database: web::Data<dyn Database>,
+    queue: web::Data<VentrixQueue>,
+    feature_flags: web::Data<FeatureFlagConfig>,
+    // Set when a client CA is configured, as on the REST publish route
+    requires_client_certificate: bool,
+}
+
+impl VentrixGrpcService {
+    pub fn new(
+        database: web::Data<dyn Database>,
+        queue: web::Data<VentrixQueue>,
+        feature_flags: web::Data<FeatureFlagConfig>,
+        requires_client_certificate: bool,
+    ) -> Self {
+        Self {
+            database,
+            queue,
+            feature_flags,
+            requires_client_certificate,
+        }
+    }
+
+    // Refuses the call when a certificate is required but the client did not present one
+    fn check_client_certificate<T>(&self, request: &Request<T>) -> Result<(), VentrixError> {
+        let presented = request
+            .extensions()
+            .get::<GrpcConnectInfo>()
+            .is_some_and(|connect_info| connect_info.client_certificate);
+        match self.requires_client_certificate && !presented {
+            true => Err(ForbiddenError::new("Publishing requires a client certificate").into()),
+            false => Ok(()),
+        }
+    }
+
+    async fn publish(
+        &self,
+        publish_event_req: PublishEventRequest,
+        caller: &Caller,
+    ) -> Result<Uuid, Status> {
+        let event_type = publish_event_req.event_type.clone();
+        // Authorized before the schema lookup as on the REST API, so a refused caller is told so
+        // whether or not the event type is registered
+        let authorized = self.queue.authorize_publish(&caller.0, &event_type).await;
+        let schema = self.database.get_schema_for_event_type(&event_type).await;
+        let registered_event_type = schema.is_ok().then_some(event_type.as_str());
+        let result = match (authorized, schema) {
+            (Ok(()), Ok(schema)) => {
+                self.try_publish(publish_event_req, schema.payload_definition)
+                    .await
+            }
+            (Err(err), _) | (Ok(()), Err(err)) => Err(err),
+        };
+        METRICS.record_publish(registered_event_type, &result);
+        Ok(result?)
+    }
+
+    async fn try_publish(
+        &self,
+        publish_event_req: PublishEventRequest,
+        schema: String,
+    ) -> Result<Uuid, VentrixError> {
+        validate_payload(&publish_event_req.payload, &schema).inspect_err(|_| {
+            METRICS.record_schema_validation_failure(&publish_event_req.event_type)
+        })?;
+
+        let event = VentrixEvent {
+            id: Uuid::new_v4(),
+            event_type: publish_event_req.event_type,
+            payload: publish_event_req.payload,
+            retry_details: None,
+            trace_context: TraceContext::current(),
+        };
+
+        self.database.save_published_event(&event).await?;
+
+        let event_id = event.id;
+        self.queue.publish_event(event).await?;
+
+        Ok(event_id)
+    }
+}
+
+// Set by GrpcAuthentication on every call that reaches the service
+fn caller<T>(request: &Request<T>) -> Result<Caller, VentrixError> {
+    request
+        .extensions()
+        .get::<Caller>()
+        .cloned()
+        .ok_or_else(|| UnauthorizedError::new("The request is not authenticated").into())
+}
+
+// Delivery settings travel as JSON, in the same shape the REST API takes
+fn parse_delivery(
+    delivery: Option<String>,
+) -> Result<Option<DeliverySettings>, InvalidDeliverySettingsError> {
+    delivery
+        .map(|delivery| serde_json::from_str(&delivery))
+        .transpose()
+        .map_err(|err| {
+            InvalidDeliverySettingsError::new(&format!("Couldn't parse delivery settings: {}", err))
+        })
+}
+
+// Transforms and target schemas arrive JSON encoded
+fn parse_json(json: Option<String>, name: &str) -> Result<Option<Value>, InvalidTransformError> {
+    json.map(|json| serde_json::from_str::<Value>(&json))
+        .transpose()
+        .map_err(|err| InvalidTransformError::new(&format!("Couldn't parse {}: {}", name, err)))
+}
+
+impl From<VentrixError> for Status {
+    fn from(err: VentrixError) -> Self {
+        let message = err.to_string();
+        match err {
+            VentrixError::ServiceAlreadyExists(_) | VentrixError::EventTypeAlreadyExists(_) => {
+                Status::already_exists(message)
+            }
+            VentrixError::ServiceNotFound(_)
+            | VentrixError::EventNotFound(_)
+            | VentrixError::EventTypeNotFound(_)
+            | VentrixError::ReplayNotFound(_)
+            | VentrixError::ApiKeyNotFound(_)
+            | VentrixError::Database(sqlx::Error::RowNotFound) => Status::not_found(message),
+            VentrixError::ReferencedEntityMissing(_) => Status::failed_precondition(message),
+            VentrixError::InvalidPropertyDef(_)
+            | VentrixError::InvalidPayload(_)
+            | VentrixError::InvalidReplayRequest(_)
+            | VentrixError::InvalidFilter(_)
+            | VentrixError::InvalidEventTypePattern(_)
+            | VentrixError::InvalidTransform(_)
+            | VentrixError::InvalidDeliverySettings(_)
+            | VentrixError::InvalidApiKeyRequest(_) => Status::invalid_argument(message),
+            VentrixError::Unauthorized(_) => Status::unauthenticated(message),
+            VentrixError::Forbidden(_) => Status::permission_denied(message),
+            VentrixError::Queue(_) | VentrixError::PendingMigrations(_) => {
+                Status::unavailable(message)
+            }
+            VentrixError::QueueFull(_) => Status::resource_exhausted(message),
+            VentrixError::ParsingRecordToStruct(_) | VentrixError::Database(_) => {
+                Status::internal(message)
+            }
+        }
+    }
+}
+
+impl From<VentrixEvent> for Delivery {
+    fn from(event: VentrixEvent) -> Self {
+        Self {
+            id: event.id.to_string(),
+            event_type: event.event_type,
+            payload: event.payload,
+            retry_count: event
+                .retry_details
+                .map(|retry_details| retry_details.retry_count as u32)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+type DeliveryStream = Pin<Box<dyn Stream<Item = Result<Delivery, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Ventrix for VentrixGrpcService {
+    // The request is left out as its delivery settings may hold secrets
+    #[tracing::instrument(
+        name = "gRPC: Registering a new service",
+        skip(self, request),
+        fields(name = %request.get_ref().name)
+    )]
+    async fn register_service(
+        &self,
+        request: Request<proto::RegisterServiceRequest>,
+    ) -> Result<Response<RegisterServiceResponse>, Status> {
+        caller(&request)?.require_admin()?;
+        let request = request.into_inner();
+        let reg_service_req = RegisterServiceRequest {
+            name: request.name.clone(),
+            url: request.url.clone(),
+            delivery: parse_delivery(request.delivery).map_err(VentrixError::from)?,
+            sealed_delivery: None,
+        };
+
+        self.queue.register_service(reg_service_req).await?;
+
+        Ok(Response::new(RegisterServiceResponse {
+            name: request.name,
+            url: request.url,
+        }))
+    }
+
+    #[tracing::instrument(name = "gRPC: Removing a service", skip(self))]
+    async fn remove_service(
+        &self,
+        request: Request<RemoveServiceRequest>,
+    ) -> Result<Response<RemoveServiceResponse>, Status> {
+        caller(&request)?.require_admin()?;
+        let name = request.into_inner().name;
+
+        self.database.remove_service(&name).await?;
+
+        Ok(Response::new(RemoveServiceResponse {
+            message: format!("Record successfully deleted for service: {}", name),
+        }))
+    }
+
+    #[tracing::instrument(name = "gRPC: Getting a service", skip(self))]
+    async fn get_service(
+        &self,
+        request: Request<GetServiceRequest>,
+    ) -> Result<Response<proto::Service>, Status> {
+        let name = &request.get_ref().name;
+        caller(&request)?.require_service(name)?;
+        let service = self.database.get_service(name).await?;
+
+        Ok(Response::new(proto::Service {
+            id: service.id.to_string(),
+            name: service.name,
+            url: service.url,
+        }))
+    }
+
+    #[tracing::instrument(name = "gRPC: Registering new event type", skip(self))]
+    async fn register_event_type(
+        &self,
+        request: Request<RegisterEventTypeRequest>,
+    ) -> Result<Response<RegisterEventTypeResponse>, Status> {
+        caller(&request)?.require_admin()?;
+        let request = request.into_inner();
+        let mut payload_definition: Value = serde_json::from_str(&request.payload_definition)
+            .map_err(|err| {
+                Status::invalid_argument(format!("Couldn't parse payload definition: {}", err))
+            })?;
+
+        if self
+            .feature_flags
+            .get("validate_event_def")
+            .is_some_and(|feature_on| *feature_on)
+        {
+            is_valid_property_def(&mut payload_definition).map_err(VentrixError::from)?;
+        }
+
+        let new_event_type_req = NewEventTypeRequest {
+            name: request.name,
+            description: request.description,
+            payload_definition,
+            // Proto3 can't tell an empty list from none, so an empty list leaves the event type
+            // open to any publisher
+            publishers: Some(request.publishers).filter(|publishers| !publishers.is_empty()),
+        };
+
+        self.database
+            .register_event_type(&new_event_type_req)
+            .await?;
+
+        Ok(Response::new(RegisterEventTypeResponse {
+            name: new_event_type_req.name,
+            description: new_event_type_req.description,
+            payload_definition: new_event_type_req.payload_definition.to_string(),
+            publishers: new_event_type_req.publishers.unwrap_or_default(),
+        }))
+    }
+
+    #[tracing::instrument(name = "gRPC: Getting event type schema", skip(self))]
+    async fn get_event_type_schema(
+        &self,
+        request: Request<GetEventTypeSchemaRequest>,
+    ) -> Result<Response<EventTypeSchema>, Status> {
+        let event_type = request.into_inner().event_type;
+        let schema = self.database.get_schema_for_event_type(&event_type).await?;
+
+        Ok(Response::new(EventTypeSchema {
+            event_type,
+            payload_definition: schema.payload_definition,
+        }))
+    }
+
+    #[tracing::instrument(name = "gRPC: Getting listeners for event type", skip(self))]
+    async fn get_listeners(
+        &self,
+        request: Request<GetListenersRequest>,
+    ) -> Result<Response<GetListenersResponse>, Status> {
+        caller(&request)?.require_admin()?;
+        let listeners = self
+            .database
+            .get_service_by_event_type(&request.into_inner().event_type)
+            .await?
+            .into_iter()
+            .map(|details| Listener {
+                name: details.name,
+                url: details.url,
+                endpoint: details.endpoint,
+                filter: details.filter,
+            })
+            .collect();
+
+        Ok(Response::new(GetListenersResponse { listeners }))
+    }
+
+    #[tracing::instrument(
+        name = "gRPC: Listening to event type",
+        skip(self, request),
+        fields(
+            service_name = %request.get_ref().service_name,
+            event_type = %request.get_ref().event_type
+        )
+    )]
+    async fn listen_to_event(
+        &self,
+        request: Request<ListenToEventRequest>,
+    ) -> Result<Response<ListenToEventResponse>, Status> {
+        caller(&request)?.require_service(&request.get_ref().service_name)?;
+        let request = request.into_inner();
+        let listen_request = ListenToEventReq {
+            service_name: request.service_name,
+            event_type: request.event_type,
+            endpoint: request.endpoint,
+            filter: request.filter,
+            transform: parse_json(request.transform, "transform").map_err(VentrixError::from)?,
+            target_schema: parse_json(request.target_schema, "target schema")
+                .map_err(VentrixError::from)?,
+            delivery: parse_delivery(request.delivery).map_err(VentrixError::from)?,
+            sealed_delivery: None,
+        };
+        let message = format!(
+            "Service {} successfully registered to listen to event type {}",
+            listen_request.service_name, listen_request.event_type
+        );
+
+        self.queue.listen_to_event(listen_request).await?;
+
+        Ok(Response::new(ListenToEventResponse { message }))
+    }
+
+    #[tracing::instrument(name = "gRPC: Publishing event", skip(self))]
+    async fn publish_event(
+        &self,
+        request: Request<PublishEventRequest>,
+    ) -> Result<Response<PublishEventResponse>, Status> {
+        self.check_client_certificate(&request)?;
+        let caller = caller(&request)?;
+        let event_id = self.publish(request.into_inner(), &caller).await?;
+
+        Ok(Response::new(PublishEventResponse {
+            id: event_id.to_string(),
+        }))
+    }
+
+    #[tracing::instrument(name = "gRPC: Publishing event stream", skip(self, request))]
+    async fn publish_events(
+        &self,
+        request: Request<Streaming<PublishEventRequest>>,
+    ) -> Result<Response<PublishEventsResponse>, Status> {
+        self.check_client_certificate(&request)?;
+        let caller = caller(&request)?;
+        let mut stream = request.into_inner();
+        let mut ids = vec![];
+
+        while let Some(publish_event_req) = stream.message().await? {
+            let event_id = self.publish(publish_event_req, &caller).await?;
+            ids.push(event_id.to_string());
+        }
+
+        Ok(Response::new(PublishEventsResponse {
+            published: ids.len() as u32,
+            ids,
+        }))
+    }
+
+    type SubscribeStream = DeliveryStream;
+
+    #[tracing::instrument(name = "gRPC: Subscribing to event type", skip(self))]
+    async fn subscribe(
+        &self,
+        request: Request<SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        caller(&request)?.require_service(&request.get_ref().service_name)?;
+        let request = request.into_inner();
+
+        self.database.get_service(&request.service_name).await?;
+        if !EventTypePattern::is_pattern(&request.event_type) {
+            self.database
+                .get_schema_for_event_type(&request.event_type)
+                .await?;
+        }
+
+        let receiver = self
+            .queue
+            .subscribe_to_stream(SubscribeToStreamReq {
+                service_name: request.service_name,
+                event_type: request.event_type,
+                filter: request.filter,
+                transform: parse_json(request.transform, "transform")
+                    .map_err(VentrixError::from)?,
+                target_schema: parse_json(request.target_schema, "target schema")
+                    .map_err(VentrixError::from)?,
+            })
+            .await?;
+
+        let stream = ReceiverStream::new(receiver).map(Delivery::from).map(Ok);
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[tracing::instrument(name = "gRPC: Acknowledging event", skip(self))]
+    async fn acknowledge(
+        &self,
+        request: Request<AcknowledgeRequest>,
+    ) -> Result<Response<AcknowledgeResponse>, Status> {
+        caller(&request)?.require_service(&request.get_ref().service_name)?;
+        let request = request.into_inner();
+        let event_id = Uuid::parse_str(&request.event_id)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        self.queue
+            .acknowledge_event(&request.service_name, event_id, request.success)
+            .await?;
+
+        Ok(Response::new(AcknowledgeResponse {}))
+    }
+}
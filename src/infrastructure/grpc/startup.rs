@@ -0,0 +1,53 @@
+use std::{future::Future, net::TcpListener, pin::Pin};
+
+use actix_web::web::Data;
+
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+
+use crate::{
+    application::queue_service::ventrix_queue::VentrixQueue,
+    common::{configuration::ApiTlsSettings, types::FeatureFlagConfig},
+    infrastructure::{authentication::Authenticator, persistence::Database, web},
+};
+
+use super::{
+    auth::GrpcAuthentication, proto::ventrix_server::VentrixServer, service::VentrixGrpcService,
+    tls,
+};
+
+pub type GrpcServer = Pin<Box<dyn Future<Output = Result<(), tonic::transport::Error>> + Send>>;
+
+pub fn run_grpc(
+    listener: TcpListener,
+    database: Data<dyn Database>,
+    ventrix_queue: Data<VentrixQueue>,
+    feature_flags: FeatureFlagConfig,
+    tls_settings: Option<&ApiTlsSettings>,
+    authenticator: Authenticator,
+) -> Result<GrpcServer, std::io::Error> {
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+
+    // Served with the same certificate and client CA as the REST API
+    let requires_client_certificate =
+        tls_settings.is_some_and(|tls_settings| tls_settings.client_ca_path.is_some());
+    let service = VentrixGrpcService::new(
+        database,
+        ventrix_queue,
+        Data::new(feature_flags),
+        requires_client_certificate,
+    );
+    let router = Server::builder()
+        .layer(GrpcAuthentication::new(authenticator))
+        .add_service(VentrixServer::new(service));
+
+    let server: GrpcServer = match tls_settings {
+        Some(tls_settings) => {
+            let config = web::tls::server_config(tls_settings)?;
+            Box::pin(router.serve_with_incoming(tls::incoming(listener, config)))
+        }
+        None => Box::pin(router.serve_with_incoming(TcpListenerStream::new(listener))),
+    };
+    Ok(server)
+}
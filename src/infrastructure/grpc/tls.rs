@@ -0,0 +1,119 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use rustls::ServerConfig;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::server::Connected;
+
+// Handshaken connections waiting for the server to take them
+const ACCEPTED_BACKLOG: usize = 64;
+
+// Whether the client of a gRPC call presented a certificate. As with the REST API, the handshake
+// fails for certificates the client CA did not issue, so any one presented is trusted.
+#[derive(Debug, Clone, Copy)]
+pub struct GrpcConnectInfo {
+    pub client_certificate: bool,
+}
+
+// A TLS connection to the gRPC server, which hands its GrpcConnectInfo to every call made on it
+pub struct GrpcTlsStream(TlsStream<TcpStream>);
+
+// Accepts TLS connections on the listener, each handshake on its own task so a slow or failed
+// one only holds up its own connection
+pub fn incoming(
+    listener: TcpListener,
+    mut config: ServerConfig,
+) -> ReceiverStream<io::Result<GrpcTlsStream>> {
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let (sender, receiver) = mpsc::channel(ACCEPTED_BACKLOG);
+
+    tokio::spawn(async move {
+        while !sender.is_closed() {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    tracing::warn!("Could not accept a gRPC connection. Err: {}", err);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let acceptor = acceptor.clone();
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                match acceptor.accept(stream).await {
+                    Ok(stream) => {
+                        let _ = sender.send(Ok(GrpcTlsStream(stream))).await;
+                    }
+                    Err(err) => tracing::debug!("gRPC TLS handshake failed. Err: {}", err),
+                }
+            });
+        }
+    });
+
+    ReceiverStream::new(receiver)
+}
+
+impl Connected for GrpcTlsStream {
+    type ConnectInfo = GrpcConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        let (_, session) = self.0.get_ref();
+        GrpcConnectInfo {
+            client_certificate: session
+                .peer_certificates()
+                .is_some_and(|certificates| !certificates.is_empty()),
+        }
+    }
+}
+
+impl AsyncRead for GrpcTlsStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for GrpcTlsStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.0.is_write_vectored()
+    }
+}
@@ -1,2 +1,4 @@
+pub mod authentication;
+pub mod grpc;
 pub mod persistence;
 pub mod web;
@@ -1,16 +1,31 @@
-use chrono::DateTime;
+use chrono::{DateTime, Duration};
+use serde_json::Value;
 use std::collections::HashMap;
-use std::error::Error;
+use std::sync::Arc;
 
+use crate::common::clock::{Clock, SystemClock};
+use crate::common::errors::ApiKeyNotFoundError;
 use crate::common::errors::EventNotFoundError;
 use crate::common::errors::EventTypeAlreadyExistsError;
+use crate::common::errors::EventTypeNotFoundError;
+use crate::common::errors::ReferencedEntityMissingError;
+use crate::common::errors::ReplayNotFoundError;
 use crate::common::errors::ServiceAlreadyExistsError;
 use crate::common::errors::ServiceNotFoundError;
+use crate::common::errors::VentrixError;
+use crate::common::event_type_pattern::EventTypePattern;
+use crate::common::metrics::METRICS;
+use crate::common::secrets::SealedSecret;
 use crate::common::types::EventFulfillmentDetails;
 use crate::common::types::ListenToEventReq;
 use crate::common::types::NewEventTypeRequest;
 use crate::common::types::PayloadSchema;
-use crate::common::types::{EventTypeDetails, VentrixEvent};
+use crate::common::types::Subscription;
+use crate::common::types::{
+    ApiKey, DeliveryAttempt, EventQuery, FulfilmentStatus, PruneCriteria, PublishDenial,
+    PublishedEvent, ReplayJob, ReplayStatus, RetainedEventKind,
+};
+use crate::common::types::{EventTypeDetails, RetryDetails, VentrixEvent};
 use crate::domain::models::service::RegisterServiceRequest;
 use crate::domain::models::service::Service;
 use async_trait::async_trait;
@@ -21,14 +36,159 @@ use uuid::Uuid;
 use super::Database;
 use super::DeleteDataResponse;
 use super::InsertDataResponse;
+use super::PruningLock;
 use super::UpdateDataResponse;
+use super::FIRST_RETRY_DELAY_MINUTES;
+use super::MAX_RETRIES;
+use super::{matches_pattern, wildcard_event_type_error};
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone)]
+struct ServiceEndpoint {
+    service_name: String,
+    endpoint: String,
+    filter: Option<String>,
+    transform: Option<String>,
+    target_schema: Option<String>,
+    delivery_settings: Option<SealedSecret>,
+}
+
+#[derive(Debug, Clone)]
+struct FailedEventDetails {
+    retry_time: DateTime<Utc>,
+    retries: i16,
+    resolved_at: Option<DateTime<Utc>>,
+    lease_owner: Option<String>,
+    lease_expires_at: Option<DateTime<Utc>>,
+    // When the failure ran out of retries
+    dead_lettered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+struct PublishedEventDetails {
+    event: VentrixEvent,
+    fulfilled_at: Option<DateTime<Utc>>,
+    published_at: DateTime<Utc>,
+    dispatched_at: Option<DateTime<Utc>>,
+    lease_owner: Option<String>,
+    lease_expires_at: Option<DateTime<Utc>>,
+    spilled_at: Option<DateTime<Utc>>,
+}
+
+impl PublishedEventDetails {
+    fn to_published_event(&self) -> PublishedEvent {
+        PublishedEvent {
+            id: self.event.id,
+            event_type: self.event.event_type.clone(),
+            payload: self.event.payload.clone(),
+            published_at: self.published_at,
+            dispatched_at: self.dispatched_at,
+            fulfilled_at: self.fulfilled_at,
+        }
+    }
+}
+
+impl ReplayJob {
+    fn selects(&self, published_event: &PublishedEventDetails) -> bool {
+        if let Some(event_ids) = &self.event_ids {
+            return event_ids.contains(&published_event.event.id);
+        }
+        self.event_type
+            .as_ref()
+            .is_none_or(|event_type| &published_event.event.event_type == event_type)
+            && self
+                .published_after
+                .is_none_or(|published_after| published_event.published_at >= published_after)
+            && self
+                .published_before
+                .is_none_or(|published_before| published_event.published_at < published_before)
+    }
+}
+
+impl PruneCriteria {
+    fn selects(
+        &self,
+        published_event: &PublishedEventDetails,
+        failed_event: Option<&FailedEventDetails>,
+    ) -> bool {
+        let event_type = &published_event.event.event_type;
+        let unresolved_failure =
+            failed_event.filter(|failed_event| failed_event.resolved_at.is_none());
+        let expired = match self.kind {
+            RetainedEventKind::Fulfilled => {
+                unresolved_failure.is_none()
+                    && published_event
+                        .fulfilled_at
+                        .is_some_and(|fulfilled_at| fulfilled_at < self.before)
+            }
+            RetainedEventKind::DeadLetter => unresolved_failure.is_some_and(|failed_event| {
+                failed_event.retries >= MAX_RETRIES
+                    && failed_event
+                        .dead_lettered_at
+                        .is_some_and(|dead_lettered_at| dead_lettered_at < self.before)
+            }),
+            RetainedEventKind::Unfulfilled => {
+                unresolved_failure.is_none()
+                    && published_event.fulfilled_at.is_none()
+                    && published_event.published_at < self.before
+            }
+        };
+
+        expired
+            && self
+                .event_type
+                .as_ref()
+                .is_none_or(|selected| selected == event_type)
+            && !self.excluded_event_types.contains(event_type)
+    }
+}
+
+impl FailedEventDetails {
+    fn release_lease(&mut self) {
+        self.lease_owner = None;
+        self.lease_expires_at = None;
+    }
+}
+
+#[derive(Debug)]
 pub struct InMemoryDatabase {
     service_register: Mutex<HashMap<String, Service>>,
+    // Kept apart from Service, which is returned by the read API
+    service_delivery_settings: Mutex<HashMap<String, SealedSecret>>,
     event_types: Mutex<HashMap<String, EventTypeDetails>>,
-    published_events: Mutex<HashMap<Uuid, (VentrixEvent, bool)>>,
-    event_type_to_service: Mutex<HashMap<String, Vec<Service>>>,
+    published_events: Mutex<HashMap<Uuid, PublishedEventDetails>>,
+    event_type_to_service: Mutex<HashMap<String, Vec<ServiceEndpoint>>>,
+    failed_events: Mutex<HashMap<Uuid, FailedEventDetails>>,
+    delivery_attempts: Mutex<HashMap<Uuid, Vec<DeliveryAttempt>>>,
+    replay_jobs: Mutex<HashMap<Uuid, ReplayJob>>,
+    api_keys: Mutex<HashMap<Uuid, ApiKey>>,
+    publish_denials: Mutex<Vec<PublishDenial>>,
+    pruning_lock: Arc<Mutex<()>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl InMemoryDatabase {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            service_register: Mutex::default(),
+            service_delivery_settings: Mutex::default(),
+            event_types: Mutex::default(),
+            published_events: Mutex::default(),
+            event_type_to_service: Mutex::default(),
+            failed_events: Mutex::default(),
+            delivery_attempts: Mutex::default(),
+            replay_jobs: Mutex::default(),
+            api_keys: Mutex::default(),
+            publish_denials: Mutex::default(),
+            pruning_lock: Arc::default(),
+            clock,
+        }
+    }
+}
+
+impl Default for InMemoryDatabase {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
 }
 
 #[async_trait]
@@ -36,11 +196,12 @@ impl Database for InMemoryDatabase {
     async fn register_service(
         &self,
         reg_service_req: &RegisterServiceRequest,
-    ) -> Result<InsertDataResponse, Box<dyn Error>> {
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "register_service");
         let mut locked_service_register = self.service_register.lock().await;
         match locked_service_register.get::<String>(&reg_service_req.name) {
             Some(_) => {
-                return Err(Box::new(ServiceAlreadyExistsError::new(
+                return Err(VentrixError::from(ServiceAlreadyExistsError::new(
                     reg_service_req.name.clone(),
                 )))
             }
@@ -50,11 +211,17 @@ impl Database for InMemoryDatabase {
                     name: reg_service_req.name.clone(),
                     url: reg_service_req.url.clone(),
                 };
+                if let Some(sealed_delivery) = &reg_service_req.sealed_delivery {
+                    self.service_delivery_settings
+                        .lock()
+                        .await
+                        .insert(service.name.clone(), sealed_delivery.clone());
+                }
                 let insert_result =
                     locked_service_register.insert(service.name.clone(), service.clone());
                 match insert_result {
                     Some(_) => {
-                        return Err(Box::new(ServiceAlreadyExistsError::new(
+                        return Err(VentrixError::from(ServiceAlreadyExistsError::new(
                             service.name.clone(),
                         )))
                     }
@@ -64,61 +231,98 @@ impl Database for InMemoryDatabase {
         }
     }
 
-    async fn remove_service(
-        &self,
-        service_name: &str,
-    ) -> Result<DeleteDataResponse, Box<dyn Error>> {
+    async fn remove_service(&self, service_name: &str) -> Result<DeleteDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "remove_service");
         let mut service_register_lock = self.service_register.lock().await;
         match service_register_lock.remove(service_name) {
-            Some(_) => Ok(DeleteDataResponse::InMemory),
-            None => Err(Box::new(ServiceNotFoundError::new(service_name))),
+            Some(_) => {
+                self.service_delivery_settings
+                    .lock()
+                    .await
+                    .remove(service_name);
+                self.api_keys
+                    .lock()
+                    .await
+                    .retain(|_, api_key| api_key.service_name.as_deref() != Some(service_name));
+                for event_type_details in self.event_types.lock().await.values_mut() {
+                    event_type_details.remove_publisher(service_name);
+                }
+                Ok(DeleteDataResponse::InMemory)
+            }
+            None => Err(VentrixError::from(ServiceNotFoundError::new(service_name))),
         }
     }
 
     async fn register_event_type(
         &self,
         new_event_type_req: &NewEventTypeRequest,
-    ) -> Result<InsertDataResponse, Box<dyn Error>> {
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "register_event_type");
         let event_type_details = EventTypeDetails::new(
             new_event_type_req.description.clone(),
             new_event_type_req.payload_definition.clone(),
+            new_event_type_req.publishers.clone(),
         );
+        if EventTypePattern::is_pattern(&new_event_type_req.name) {
+            return Err(wildcard_event_type_error(&new_event_type_req.name));
+        }
+        let service_register_lock = self.service_register.lock().await;
+        if let Some(missing) = new_event_type_req
+            .publishers
+            .iter()
+            .flatten()
+            .find(|publisher| !service_register_lock.contains_key(*publisher))
+        {
+            return Err(ReferencedEntityMissingError::new("service", missing).into());
+        }
         let mut event_types_lock = self.event_types.lock().await;
-        match event_types_lock.insert(new_event_type_req.name.clone(), event_type_details.clone()) {
-            Some(_) => Err(Box::new(EventTypeAlreadyExistsError::new(
+        if event_types_lock.contains_key(&new_event_type_req.name) {
+            return Err(VentrixError::from(EventTypeAlreadyExistsError::new(
                 new_event_type_req.name.clone(),
-            ))),
-            None => Ok(InsertDataResponse::InMemory),
+            )));
         }
+        event_types_lock.insert(new_event_type_req.name.clone(), event_type_details);
+        Ok(InsertDataResponse::InMemory)
     }
 
-    async fn get_service(&self, name: &str) -> Result<Service, Box<dyn Error>> {
+    async fn get_service(&self, name: &str) -> Result<Service, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "get_service");
         let service_register_lock = self.service_register.lock().await;
         match service_register_lock.get(name) {
             Some(service) => Ok(service.clone()),
-            None => Err(Box::new(ServiceNotFoundError::new(name))),
+            None => Err(VentrixError::from(ServiceNotFoundError::new(name))),
         }
     }
 
     async fn save_published_event(
         &self,
         event: &VentrixEvent,
-    ) -> Result<InsertDataResponse, Box<dyn Error>> {
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "save_published_event");
         let mut events_vec = self.published_events.lock().await;
-        events_vec.insert(event.id, (event.clone(), false));
+        events_vec.insert(
+            event.id,
+            PublishedEventDetails {
+                event: event.clone(),
+                fulfilled_at: None,
+                published_at: self.clock.now(),
+                dispatched_at: None,
+                lease_owner: None,
+                lease_expires_at: None,
+                spilled_at: None,
+            },
+        );
         Ok(InsertDataResponse::InMemory)
     }
 
-    async fn fulfil_event(
-        &self,
-        event: &VentrixEvent,
-    ) -> Result<UpdateDataResponse, Box<dyn Error>> {
+    async fn fulfil_event(&self, event: &VentrixEvent) -> Result<UpdateDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "fulfil_event");
         let mut events_map_lock = self.published_events.lock().await;
         events_map_lock
             .get_mut(&event.id)
             .ok_or_else(|| EventNotFoundError::new(&event.id.to_string()))
-            .map(|(_, is_fulfilled)| {
-                *is_fulfilled = true;
+            .map(|published_event| {
+                published_event.fulfilled_at = Some(self.clock.now());
             })?;
         Ok(UpdateDataResponse::InMemory)
     }
@@ -126,56 +330,709 @@ impl Database for InMemoryDatabase {
     async fn register_service_for_event_type(
         &self,
         listen_to_event_req: &ListenToEventReq,
-    ) -> Result<InsertDataResponse, Box<dyn Error>> {
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "register_service_for_event_type");
         let mut service_to_event_type_lock = self.event_type_to_service.lock().await;
+        let event_types_lock = self.event_types.lock().await;
         let service_register_lock = self.service_register.lock().await;
-        let service = service_register_lock
-            .get(&listen_to_event_req.service_name.clone())
-            .ok_or_else(|| ServiceNotFoundError::new(&listen_to_event_req.service_name.clone()))?;
+        if EventTypePattern::is_pattern(&listen_to_event_req.event_type) {
+            EventTypePattern::parse(&listen_to_event_req.event_type)?;
+        } else if !event_types_lock.contains_key(&listen_to_event_req.event_type) {
+            return Err(VentrixError::from(ReferencedEntityMissingError::new(
+                "event type",
+                &listen_to_event_req.event_type,
+            )));
+        }
+        if !service_register_lock.contains_key(&listen_to_event_req.service_name) {
+            return Err(VentrixError::from(ReferencedEntityMissingError::new(
+                "service",
+                &listen_to_event_req.service_name,
+            )));
+        }
         service_to_event_type_lock
-            .get_mut(&listen_to_event_req.event_type.clone())
-            .ok_or_else(|| ServiceNotFoundError::new(&listen_to_event_req.service_name))
-            .map(|service_vec| service_vec.push(service.clone()))?;
+            .entry(listen_to_event_req.event_type.clone())
+            .or_default()
+            .push(ServiceEndpoint {
+                service_name: listen_to_event_req.service_name.clone(),
+                endpoint: listen_to_event_req.endpoint.clone(),
+                filter: listen_to_event_req.filter.clone(),
+                transform: listen_to_event_req.transform.as_ref().map(Value::to_string),
+                target_schema: listen_to_event_req
+                    .target_schema
+                    .as_ref()
+                    .map(Value::to_string),
+                delivery_settings: listen_to_event_req.sealed_delivery.clone(),
+            });
         Ok(InsertDataResponse::InMemory)
     }
 
     async fn get_service_by_event_type(
         &self,
-        _event_type: &str,
-    ) -> Result<Vec<EventFulfillmentDetails>, Box<dyn Error + Sync + Send>> {
-        todo!()
+        event_type: &str,
+    ) -> Result<Vec<EventFulfillmentDetails>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "get_service_by_event_type");
+        let service_to_event_type_lock = self.event_type_to_service.lock().await;
+        let service_register_lock = self.service_register.lock().await;
+        let service_delivery_settings_lock = self.service_delivery_settings.lock().await;
+        let pattern_endpoints = service_to_event_type_lock
+            .iter()
+            .filter(|(pattern, _)| matches_pattern(pattern, event_type))
+            .flat_map(|(_, service_endpoints)| service_endpoints);
+        let fulfillment_details = service_to_event_type_lock
+            .get(event_type)
+            .into_iter()
+            .flatten()
+            .chain(pattern_endpoints)
+            .filter_map(|service_endpoint| {
+                service_register_lock
+                    .get(&service_endpoint.service_name)
+                    .map(|service| EventFulfillmentDetails {
+                        name: service.name.clone(),
+                        url: service.url.clone(),
+                        endpoint: service_endpoint.endpoint.clone(),
+                        filter: service_endpoint.filter.clone(),
+                        transform: service_endpoint.transform.clone(),
+                        target_schema: service_endpoint.target_schema.clone(),
+                        service_delivery_settings: service_delivery_settings_lock
+                            .get(&service.name)
+                            .cloned(),
+                        delivery_settings: service_endpoint.delivery_settings.clone(),
+                    })
+            })
+            .collect();
+        Ok(fulfillment_details)
+    }
+
+    async fn get_subscriptions(
+        &self,
+        service_name: &str,
+    ) -> Result<Vec<Subscription>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "get_subscriptions");
+        let service_to_event_type_lock = self.event_type_to_service.lock().await;
+        let event_types_lock = self.event_types.lock().await;
+        if !self
+            .service_register
+            .lock()
+            .await
+            .contains_key(service_name)
+        {
+            return Err(ServiceNotFoundError::new(service_name).into());
+        }
+
+        let mut subscriptions: Vec<Subscription> = service_to_event_type_lock
+            .iter()
+            .flat_map(|(event_type, service_endpoints)| {
+                let mut resolved_event_types: Vec<String> =
+                    match EventTypePattern::is_pattern(event_type) {
+                        true => event_types_lock
+                            .keys()
+                            .filter(|name| matches_pattern(event_type, name))
+                            .cloned()
+                            .collect(),
+                        false => vec![event_type.clone()],
+                    };
+                resolved_event_types.sort();
+                service_endpoints
+                    .iter()
+                    .filter(|service_endpoint| service_endpoint.service_name == service_name)
+                    .map(move |service_endpoint| Subscription {
+                        event_type: event_type.clone(),
+                        endpoint: service_endpoint.endpoint.clone(),
+                        filter: service_endpoint.filter.clone(),
+                        resolved_event_types: resolved_event_types.clone(),
+                    })
+            })
+            .collect();
+        subscriptions.sort_by(|a, b| a.event_type.cmp(&b.event_type));
+        Ok(subscriptions)
     }
 
     async fn get_schema_for_event_type(
         &self,
-        _event_type: &str,
-    ) -> Result<PayloadSchema, Box<dyn Error>> {
-        todo!()
+        event_type: &str,
+    ) -> Result<PayloadSchema, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "get_schema_for_event_type");
+        let event_types_lock = self.event_types.lock().await;
+        event_types_lock
+            .get(event_type)
+            .map(|event_type_details| PayloadSchema {
+                payload_definition: event_type_details.payload_def().to_string(),
+            })
+            .ok_or_else(|| EventTypeNotFoundError::new(event_type).into())
+    }
+
+    async fn get_publishers_for_event_type(
+        &self,
+        event_type: &str,
+    ) -> Result<Option<Vec<String>>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "get_publishers_for_event_type");
+        let event_types_lock = self.event_types.lock().await;
+        event_types_lock
+            .get(event_type)
+            .map(|event_type_details| event_type_details.publishers().cloned())
+            .ok_or_else(|| EventTypeNotFoundError::new(event_type).into())
     }
 
     async fn resolve_failed_event(
         &self,
-        _event_id: Uuid,
-    ) -> Result<UpdateDataResponse, Box<dyn Error>> {
-        todo!()
+        event_id: Uuid,
+    ) -> Result<UpdateDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "resolve_failed_event");
+        let mut failed_events_lock = self.failed_events.lock().await;
+        failed_events_lock
+            .get_mut(&event_id)
+            .ok_or_else(|| EventNotFoundError::new(&event_id.to_string()))
+            .map(|failed_event| {
+                failed_event.resolved_at = Some(self.clock.now());
+                failed_event.release_lease();
+            })?;
+        Ok(UpdateDataResponse::InMemory)
     }
 
     async fn add_failed_event(
         &self,
-        _event: &VentrixEvent,
-    ) -> Result<InsertDataResponse, Box<dyn Error>> {
-        todo!()
+        event: &VentrixEvent,
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "add_failed_event");
+        let events_map_lock = self.published_events.lock().await;
+        if !events_map_lock.contains_key(&event.id) {
+            return Err(VentrixError::from(EventNotFoundError::new(
+                &event.id.to_string(),
+            )));
+        }
+        let mut failed_events_lock = self.failed_events.lock().await;
+        failed_events_lock.insert(
+            event.id,
+            FailedEventDetails {
+                retry_time: self.clock.now() + Duration::minutes(FIRST_RETRY_DELAY_MINUTES),
+                retries: 0,
+                resolved_at: None,
+                lease_owner: None,
+                lease_expires_at: None,
+                dead_lettered_at: None,
+            },
+        );
+        Ok(InsertDataResponse::InMemory)
     }
 
     async fn update_retry_time(
         &self,
-        _event_id: Uuid,
-        _new_retry_time: DateTime<Utc>,
-        _retries: i16,
-    ) -> Result<UpdateDataResponse, Box<dyn Error>> {
-        todo!()
+        event_id: Uuid,
+        new_retry_time: DateTime<Utc>,
+        retries: i16,
+    ) -> Result<UpdateDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "update_retry_time");
+        let now = self.clock.now();
+        let mut failed_events_lock = self.failed_events.lock().await;
+        failed_events_lock
+            .get_mut(&event_id)
+            .ok_or_else(|| EventNotFoundError::new(&event_id.to_string()))
+            .map(|failed_event| {
+                failed_event.retry_time = new_retry_time;
+                failed_event.retries = retries;
+                failed_event.dead_lettered_at = match retries >= MAX_RETRIES {
+                    true => failed_event.dead_lettered_at.or(Some(now)),
+                    false => None,
+                };
+                failed_event.release_lease();
+            })?;
+        Ok(UpdateDataResponse::InMemory)
+    }
+
+    async fn claim_failed_events(
+        &self,
+        lease_owner: &str,
+        lease_duration: Duration,
+        limit: i64,
+    ) -> Result<Vec<VentrixEvent>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "claim_failed_events");
+        let events_map_lock = self.published_events.lock().await;
+        let mut failed_events_lock = self.failed_events.lock().await;
+        let now = self.clock.now();
+        let mut due: Vec<_> = failed_events_lock
+            .iter_mut()
+            .filter(|(_, failed_event)| {
+                failed_event.retries < MAX_RETRIES
+                    && failed_event.retry_time < now
+                    && failed_event.resolved_at.is_none()
+                    && failed_event
+                        .lease_expires_at
+                        .is_none_or(|lease_expires_at| lease_expires_at < now)
+            })
+            .collect();
+        due.sort_by_key(|(_, failed_event)| failed_event.retry_time);
+        let failed_events = due
+            .into_iter()
+            .take(usize::try_from(limit).unwrap_or_default())
+            .filter_map(|(event_id, failed_event)| {
+                failed_event.lease_owner = Some(lease_owner.to_string());
+                failed_event.lease_expires_at = Some(now + lease_duration);
+                events_map_lock
+                    .get(event_id)
+                    .map(|published_event| VentrixEvent {
+                        retry_details: Some(RetryDetails {
+                            retry_count: failed_event.retries,
+                            retry_time: failed_event.retry_time,
+                        }),
+                        ..published_event.event.clone()
+                    })
+            })
+            .collect();
+        Ok(failed_events)
+    }
+
+    async fn get_next_retry_time(&self) -> Result<Option<DateTime<Utc>>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "get_next_retry_time");
+        let failed_events_lock = self.failed_events.lock().await;
+        Ok(failed_events_lock
+            .values()
+            .filter(|failed_event| {
+                failed_event.retries < MAX_RETRIES && failed_event.resolved_at.is_none()
+            })
+            .map(|failed_event| failed_event.retry_time)
+            .min())
+    }
+
+    async fn claim_pending_events(
+        &self,
+        lease_owner: &str,
+        lease_duration: Duration,
+        published_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<VentrixEvent>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "claim_pending_events");
+        let mut events_map_lock = self.published_events.lock().await;
+        let now = self.clock.now();
+        let mut pending_events: Vec<&mut PublishedEventDetails> = events_map_lock
+            .values_mut()
+            .filter(|published_event| {
+                published_event.dispatched_at.is_none()
+                    && published_event.published_at <= published_before
+                    && published_event
+                        .lease_expires_at
+                        .is_none_or(|lease_expires_at| lease_expires_at < now)
+            })
+            .collect();
+        pending_events.sort_by_key(|published_event| published_event.published_at);
+        Ok(pending_events
+            .into_iter()
+            .take(usize::try_from(limit).unwrap_or_default())
+            .map(|published_event| {
+                published_event.lease_owner = Some(lease_owner.to_string());
+                published_event.lease_expires_at = Some(now + lease_duration);
+                published_event.event.clone()
+            })
+            .collect())
+    }
+
+    async fn renew_pending_lease(
+        &self,
+        event_id: Uuid,
+        lease_owner: &str,
+        lease_duration: Duration,
+    ) -> Result<bool, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "renew_pending_lease");
+        let mut events_map_lock = self.published_events.lock().await;
+        let now = self.clock.now();
+        let Some(published_event) = events_map_lock
+            .get_mut(&event_id)
+            .filter(|published_event| {
+                published_event.dispatched_at.is_none()
+                    && (published_event.lease_owner.as_deref() == Some(lease_owner)
+                        || published_event
+                            .lease_expires_at
+                            .is_none_or(|lease_expires_at| lease_expires_at < now))
+            })
+        else {
+            return Ok(false);
+        };
+        published_event.lease_owner = Some(lease_owner.to_string());
+        published_event.lease_expires_at = Some(now + lease_duration);
+        Ok(true)
+    }
+
+    async fn mark_event_dispatched(
+        &self,
+        event_id: Uuid,
+    ) -> Result<UpdateDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "mark_event_dispatched");
+        let mut events_map_lock = self.published_events.lock().await;
+        events_map_lock
+            .get_mut(&event_id)
+            .ok_or_else(|| EventNotFoundError::new(&event_id.to_string()))
+            .map(|published_event| {
+                published_event.dispatched_at = Some(self.clock.now());
+                published_event.lease_owner = None;
+                published_event.lease_expires_at = None;
+            })?;
+        Ok(UpdateDataResponse::InMemory)
+    }
+
+    async fn mark_event_spilled(&self, event_id: Uuid) -> Result<UpdateDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "mark_event_spilled");
+        let mut events_map_lock = self.published_events.lock().await;
+        events_map_lock
+            .get_mut(&event_id)
+            .ok_or_else(|| EventNotFoundError::new(&event_id.to_string()))
+            .map(|published_event| published_event.spilled_at = Some(self.clock.now()))?;
+        Ok(UpdateDataResponse::InMemory)
+    }
+
+    async fn take_spilled_events(&self, limit: i64) -> Result<Vec<VentrixEvent>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "take_spilled_events");
+        let mut events_map_lock = self.published_events.lock().await;
+        let mut spilled_events: Vec<&mut PublishedEventDetails> = events_map_lock
+            .values_mut()
+            .filter(|published_event| {
+                published_event.spilled_at.is_some() && published_event.dispatched_at.is_none()
+            })
+            .collect();
+        spilled_events.sort_by_key(|published_event| published_event.spilled_at);
+        Ok(spilled_events
+            .into_iter()
+            .take(usize::try_from(limit).unwrap_or_default())
+            .map(|published_event| {
+                published_event.spilled_at = None;
+                published_event.event.clone()
+            })
+            .collect())
+    }
+
+    async fn record_delivery_attempt(
+        &self,
+        attempt: &DeliveryAttempt,
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "record_delivery_attempt");
+        let events_map_lock = self.published_events.lock().await;
+        if !events_map_lock.contains_key(&attempt.event_id) {
+            return Err(VentrixError::from(EventNotFoundError::new(
+                &attempt.event_id.to_string(),
+            )));
+        }
+        let mut delivery_attempts_lock = self.delivery_attempts.lock().await;
+        delivery_attempts_lock
+            .entry(attempt.event_id)
+            .or_default()
+            .push(attempt.clone());
+        Ok(InsertDataResponse::InMemory)
+    }
+
+    async fn list_events(&self, query: &EventQuery) -> Result<Vec<PublishedEvent>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "list_events");
+        let events_map_lock = self.published_events.lock().await;
+        let delivery_attempts_lock = self.delivery_attempts.lock().await;
+        let mut events: Vec<&PublishedEventDetails> = events_map_lock
+            .values()
+            .filter(|published_event| {
+                query
+                    .event_type
+                    .as_ref()
+                    .is_none_or(|event_type| &published_event.event.event_type == event_type)
+            })
+            .filter(|published_event| match query.status {
+                Some(FulfilmentStatus::Fulfilled) => published_event.fulfilled_at.is_some(),
+                Some(FulfilmentStatus::Pending) => published_event.fulfilled_at.is_none(),
+                None => true,
+            })
+            .filter(|published_event| {
+                query.service.as_ref().is_none_or(|service| {
+                    delivery_attempts_lock
+                        .get(&published_event.event.id)
+                        .is_some_and(|attempts| {
+                            attempts
+                                .iter()
+                                .any(|attempt| &attempt.service_name == service)
+                        })
+                })
+            })
+            .filter(|published_event| {
+                query
+                    .published_after
+                    .is_none_or(|published_after| published_event.published_at >= published_after)
+                    && query.published_before.is_none_or(|published_before| {
+                        published_event.published_at < published_before
+                    })
+            })
+            .collect();
+        events.sort_by(|a, b| {
+            b.published_at
+                .cmp(&a.published_at)
+                .then(a.event.id.cmp(&b.event.id))
+        });
+        Ok(events
+            .into_iter()
+            .take(usize::try_from(query.limit()).unwrap_or_default())
+            .map(PublishedEventDetails::to_published_event)
+            .collect())
+    }
+
+    async fn get_event(&self, event_id: Uuid) -> Result<PublishedEvent, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "get_event");
+        let events_map_lock = self.published_events.lock().await;
+        events_map_lock
+            .get(&event_id)
+            .map(PublishedEventDetails::to_published_event)
+            .ok_or_else(|| EventNotFoundError::new(&event_id.to_string()).into())
+    }
+
+    async fn get_delivery_attempts(
+        &self,
+        event_id: Uuid,
+    ) -> Result<Vec<DeliveryAttempt>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "get_delivery_attempts");
+        let delivery_attempts_lock = self.delivery_attempts.lock().await;
+        let mut attempts = delivery_attempts_lock
+            .get(&event_id)
+            .cloned()
+            .unwrap_or_default();
+        attempts.sort_by_key(|attempt| (attempt.attempted_at, attempt.attempt));
+        Ok(attempts)
+    }
+
+    async fn get_delivery_attempts_for_events(
+        &self,
+        event_ids: &[Uuid],
+    ) -> Result<Vec<DeliveryAttempt>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "get_delivery_attempts_for_events");
+        let delivery_attempts_lock = self.delivery_attempts.lock().await;
+        let mut attempts: Vec<DeliveryAttempt> = event_ids
+            .iter()
+            .filter_map(|event_id| delivery_attempts_lock.get(event_id))
+            .flatten()
+            .cloned()
+            .collect();
+        attempts.sort_by_key(|attempt| (attempt.attempted_at, attempt.attempt));
+        Ok(attempts)
     }
-    async fn get_failed_events(&self) -> Result<Vec<VentrixEvent>, Box<dyn Error + Sync + Send>> {
-        todo!()
+
+    async fn create_replay_job(&self, job: &ReplayJob) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "create_replay_job");
+        let mut replay_jobs_lock = self.replay_jobs.lock().await;
+        replay_jobs_lock.insert(job.id, job.clone());
+        Ok(InsertDataResponse::InMemory)
+    }
+
+    async fn get_replay_job(&self, replay_id: Uuid) -> Result<ReplayJob, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "get_replay_job");
+        let replay_jobs_lock = self.replay_jobs.lock().await;
+        replay_jobs_lock
+            .get(&replay_id)
+            .cloned()
+            .ok_or_else(|| ReplayNotFoundError::new(&replay_id.to_string()).into())
+    }
+
+    async fn update_replay_job(&self, job: &ReplayJob) -> Result<UpdateDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "update_replay_job");
+        let mut replay_jobs_lock = self.replay_jobs.lock().await;
+        if let Some(stored_job) = replay_jobs_lock.get_mut(&job.id).filter(|stored_job| {
+            stored_job.status == ReplayStatus::Running && stored_job.lease_owner == job.lease_owner
+        }) {
+            *stored_job = ReplayJob {
+                status: job.status,
+                total: job.total,
+                delivered: job.delivered,
+                failed: job.failed,
+                skipped: job.skipped,
+                error: job.error.clone(),
+                finished_at: job.finished_at,
+                cursor_published_at: job.cursor_published_at,
+                cursor_event_id: job.cursor_event_id,
+                lease_expires_at: job.lease_expires_at,
+                ..stored_job.clone()
+            };
+        }
+        Ok(UpdateDataResponse::InMemory)
+    }
+
+    async fn claim_replay_jobs(
+        &self,
+        lease_owner: &str,
+        lease_duration: Duration,
+    ) -> Result<Vec<ReplayJob>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "claim_replay_jobs");
+        let mut replay_jobs_lock = self.replay_jobs.lock().await;
+        let now = self.clock.now();
+        Ok(replay_jobs_lock
+            .values_mut()
+            .filter(|job| {
+                job.status == ReplayStatus::Running
+                    && job
+                        .lease_expires_at
+                        .is_none_or(|lease_expires_at| lease_expires_at < now)
+            })
+            .map(|job| {
+                job.lease_owner = Some(lease_owner.to_string());
+                job.lease_expires_at = Some(now + lease_duration);
+                job.clone()
+            })
+            .collect())
+    }
+
+    async fn cancel_replay_job(&self, replay_id: Uuid) -> Result<ReplayJob, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "cancel_replay_job");
+        let mut replay_jobs_lock = self.replay_jobs.lock().await;
+        let job = replay_jobs_lock
+            .get_mut(&replay_id)
+            .ok_or_else(|| ReplayNotFoundError::new(&replay_id.to_string()))?;
+        if job.status == ReplayStatus::Running {
+            job.status = ReplayStatus::Cancelled;
+            job.finished_at = Some(self.clock.now());
+        }
+        Ok(job.clone())
+    }
+
+    async fn count_events_to_replay(&self, job: &ReplayJob) -> Result<i64, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "count_events_to_replay");
+        let events_map_lock = self.published_events.lock().await;
+        let count = events_map_lock
+            .values()
+            .filter(|published_event| job.selects(published_event))
+            .count();
+        Ok(i64::try_from(count).unwrap_or(i64::MAX))
+    }
+
+    async fn get_events_to_replay(
+        &self,
+        job: &ReplayJob,
+        limit: i64,
+    ) -> Result<Vec<(DateTime<Utc>, VentrixEvent)>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "get_events_to_replay");
+        let events_map_lock = self.published_events.lock().await;
+        let cursor = job.cursor_published_at.zip(job.cursor_event_id);
+        let mut events: Vec<&PublishedEventDetails> = events_map_lock
+            .values()
+            .filter(|published_event| {
+                job.selects(published_event)
+                    && cursor.is_none_or(|cursor| {
+                        (published_event.published_at, published_event.event.id) > cursor
+                    })
+            })
+            .collect();
+        events.sort_by(|a, b| {
+            a.published_at
+                .cmp(&b.published_at)
+                .then(a.event.id.cmp(&b.event.id))
+        });
+        Ok(events
+            .into_iter()
+            .take(usize::try_from(limit).unwrap_or_default())
+            .map(|published_event| (published_event.published_at, published_event.event.clone()))
+            .collect())
+    }
+
+    async fn try_lock_pruning(&self) -> Result<Option<PruningLock>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "try_lock_pruning");
+        Ok(Arc::clone(&self.pruning_lock)
+            .try_lock_owned()
+            .ok()
+            .map(PruningLock::InMemory))
+    }
+
+    async fn get_events_to_prune(
+        &self,
+        criteria: &PruneCriteria,
+        limit: i64,
+    ) -> Result<Vec<PublishedEvent>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "get_events_to_prune");
+        let events_map_lock = self.published_events.lock().await;
+        let failed_events_lock = self.failed_events.lock().await;
+        let mut events: Vec<&PublishedEventDetails> = events_map_lock
+            .values()
+            .filter(|published_event| {
+                criteria.selects(
+                    published_event,
+                    failed_events_lock.get(&published_event.event.id),
+                )
+            })
+            .collect();
+        events.sort_by(|a, b| {
+            a.published_at
+                .cmp(&b.published_at)
+                .then(a.event.id.cmp(&b.event.id))
+        });
+        Ok(events
+            .into_iter()
+            .take(usize::try_from(limit).unwrap_or_default())
+            .map(PublishedEventDetails::to_published_event)
+            .collect())
+    }
+
+    async fn delete_events(&self, event_ids: &[Uuid]) -> Result<DeleteDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "delete_events");
+        let mut events_map_lock = self.published_events.lock().await;
+        let mut failed_events_lock = self.failed_events.lock().await;
+        let mut delivery_attempts_lock = self.delivery_attempts.lock().await;
+        for event_id in event_ids {
+            events_map_lock.remove(event_id);
+            failed_events_lock.remove(event_id);
+            delivery_attempts_lock.remove(event_id);
+        }
+        Ok(DeleteDataResponse::InMemory)
+    }
+
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "create_api_key");
+        let service_register_lock = self.service_register.lock().await;
+        if let Some(service_name) = &api_key.service_name {
+            if !service_register_lock.contains_key(service_name) {
+                return Err(ReferencedEntityMissingError::new("service", service_name).into());
+            }
+        }
+        self.api_keys
+            .lock()
+            .await
+            .insert(api_key.id, api_key.clone());
+        Ok(InsertDataResponse::InMemory)
+    }
+
+    async fn find_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "find_api_key");
+        let api_keys_lock = self.api_keys.lock().await;
+        Ok(api_keys_lock
+            .values()
+            .find(|api_key| api_key.key_hash == key_hash && api_key.revoked_at.is_none())
+            .cloned())
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "list_api_keys");
+        let api_keys_lock = self.api_keys.lock().await;
+        let mut api_keys: Vec<ApiKey> = api_keys_lock.values().cloned().collect();
+        api_keys.sort_by_key(|api_key| (api_key.created_at, api_key.id));
+        Ok(api_keys)
+    }
+
+    async fn revoke_api_key(&self, key_id: Uuid) -> Result<ApiKey, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "revoke_api_key");
+        let mut api_keys_lock = self.api_keys.lock().await;
+        let api_key = api_keys_lock
+            .get_mut(&key_id)
+            .ok_or_else(|| ApiKeyNotFoundError::new(&key_id.to_string()))?;
+        if api_key.revoked_at.is_none() {
+            api_key.revoked_at = Some(self.clock.now());
+        }
+        Ok(api_key.clone())
+    }
+
+    async fn record_publish_denial(
+        &self,
+        denial: &PublishDenial,
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "record_publish_denial");
+        self.publish_denials.lock().await.push(denial.clone());
+        Ok(InsertDataResponse::InMemory)
+    }
+
+    async fn list_publish_denials(&self, limit: i64) -> Result<Vec<PublishDenial>, VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "list_publish_denials");
+        let mut denials = self.publish_denials.lock().await.clone();
+        denials.sort_by(|a, b| b.attempted_at.cmp(&a.attempted_at).then(a.id.cmp(&b.id)));
+        denials.truncate(limit as usize);
+        Ok(denials)
+    }
+
+    async fn check_health(&self) -> Result<(), VentrixError> {
+        let _timer = METRICS.db_query_timer("in_memory", "check_health");
+        Ok(())
     }
 }
@@ -1,80 +1,220 @@
 pub mod inmemory;
+pub mod notifier;
 pub mod postgres;
 
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
-use std::{error::Error, fmt::Debug};
+use chrono::{DateTime, Duration, Utc};
+use std::fmt::Debug;
+use tokio::sync::OwnedMutexGuard;
 use uuid::Uuid;
 
 use crate::{
+    common::errors::{InvalidEventTypePatternError, VentrixError},
+    common::event_type_pattern::EventTypePattern,
     common::types::{
-        EventFulfillmentDetails, ListenToEventReq, NewEventTypeRequest, PayloadSchema, VentrixEvent,
+        ApiKey, DeliveryAttempt, EventFulfillmentDetails, EventQuery, ListenToEventReq,
+        NewEventTypeRequest, PayloadSchema, PruneCriteria, PublishDenial, PublishedEvent,
+        ReplayJob, Subscription, VentrixEvent,
     },
     domain::models::service::{RegisterServiceRequest, Service},
 };
 
+// Failed events are retried until they have been attempted this many times
+pub const MAX_RETRIES: i16 = 3;
+// A newly failed event is first retried this many minutes after the failure
+pub const FIRST_RETRY_DELAY_MINUTES: i64 = 1;
+
 #[async_trait]
 pub trait Database: Debug + Send + Sync {
     async fn register_service(
         &self,
         service: &RegisterServiceRequest,
-    ) -> Result<InsertDataResponse, Box<dyn Error>>;
-    async fn remove_service(
-        &self,
-        service_name: &str,
-    ) -> Result<DeleteDataResponse, Box<dyn Error>>;
+    ) -> Result<InsertDataResponse, VentrixError>;
+    async fn remove_service(&self, service_name: &str) -> Result<DeleteDataResponse, VentrixError>;
     async fn register_event_type(
         &self,
         event_type: &NewEventTypeRequest,
-    ) -> Result<InsertDataResponse, Box<dyn Error>>;
-    async fn get_service(&self, service_name: &str) -> Result<Service, Box<dyn Error>>;
+    ) -> Result<InsertDataResponse, VentrixError>;
+    async fn get_service(&self, service_name: &str) -> Result<Service, VentrixError>;
     async fn save_published_event(
         &self,
         event: &VentrixEvent,
-    ) -> Result<InsertDataResponse, Box<dyn Error>>;
-    async fn fulfil_event(
-        &self,
-        event: &VentrixEvent,
-    ) -> Result<UpdateDataResponse, Box<dyn Error>>;
+    ) -> Result<InsertDataResponse, VentrixError>;
+    async fn fulfil_event(&self, event: &VentrixEvent) -> Result<UpdateDataResponse, VentrixError>;
+    // The event type may be a pattern, see EventTypePattern
     async fn register_service_for_event_type(
         &self,
         listen_to_event_req: &ListenToEventReq,
-    ) -> Result<InsertDataResponse, Box<dyn Error>>;
+    ) -> Result<InsertDataResponse, VentrixError>;
+    // Includes the services listening to a pattern the event type matches
     async fn get_service_by_event_type(
         &self,
         event_type: &str,
-    ) -> Result<Vec<EventFulfillmentDetails>, Box<dyn Error + Sync + Send>>;
+    ) -> Result<Vec<EventFulfillmentDetails>, VentrixError>;
+    async fn get_subscriptions(
+        &self,
+        service_name: &str,
+    ) -> Result<Vec<Subscription>, VentrixError>;
     async fn get_schema_for_event_type(
         &self,
         event_type: &str,
-    ) -> Result<PayloadSchema, Box<dyn Error>>;
+    ) -> Result<PayloadSchema, VentrixError>;
+    // The services allowed to publish the event type, None when any publisher may
+    async fn get_publishers_for_event_type(
+        &self,
+        event_type: &str,
+    ) -> Result<Option<Vec<String>>, VentrixError>;
     async fn add_failed_event(
         &self,
         event: &VentrixEvent,
-    ) -> Result<InsertDataResponse, Box<dyn Error>>;
+    ) -> Result<InsertDataResponse, VentrixError>;
     async fn resolve_failed_event(
         &self,
         event_id: Uuid,
-    ) -> Result<UpdateDataResponse, Box<dyn Error>>;
+    ) -> Result<UpdateDataResponse, VentrixError>;
     async fn update_retry_time(
         &self,
         event_id: Uuid,
         new_retry_time: DateTime<Utc>,
         retries: i16,
-    ) -> Result<UpdateDataResponse, Box<dyn Error>>;
-    async fn get_failed_events(&self) -> Result<Vec<VentrixEvent>, Box<dyn Error + Sync + Send>>;
+    ) -> Result<UpdateDataResponse, VentrixError>;
+    // Leases up to `limit` due failed events, earliest retry first, to `lease_owner` so no
+    // other instance releases them until the lease expires or the retry outcome is recorded
+    async fn claim_failed_events(
+        &self,
+        lease_owner: &str,
+        lease_duration: Duration,
+        limit: i64,
+    ) -> Result<Vec<VentrixEvent>, VentrixError>;
+    async fn get_next_retry_time(&self) -> Result<Option<DateTime<Utc>>, VentrixError>;
+    // Leases published events that no instance has dispatched yet, oldest first
+    async fn claim_pending_events(
+        &self,
+        lease_owner: &str,
+        lease_duration: Duration,
+        published_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<VentrixEvent>, VentrixError>;
+    // Extends the lease on a published event that is not dispatched yet, as long as no other
+    // instance has claimed it since. Returns false when another instance holds it or it has
+    // already been dispatched.
+    async fn renew_pending_lease(
+        &self,
+        event_id: Uuid,
+        lease_owner: &str,
+        lease_duration: Duration,
+    ) -> Result<bool, VentrixError>;
+    async fn mark_event_dispatched(
+        &self,
+        event_id: Uuid,
+    ) -> Result<UpdateDataResponse, VentrixError>;
+    // Flags an event the full in-process queue could not take
+    async fn mark_event_spilled(&self, event_id: Uuid) -> Result<UpdateDataResponse, VentrixError>;
+    // Unflags and returns spilled events, oldest first, so each is handed back to the queue
+    // only once
+    async fn take_spilled_events(&self, limit: i64) -> Result<Vec<VentrixEvent>, VentrixError>;
+    async fn record_delivery_attempt(
+        &self,
+        attempt: &DeliveryAttempt,
+    ) -> Result<InsertDataResponse, VentrixError>;
+    async fn list_events(&self, query: &EventQuery) -> Result<Vec<PublishedEvent>, VentrixError>;
+    async fn get_event(&self, event_id: Uuid) -> Result<PublishedEvent, VentrixError>;
+    // Oldest attempt first
+    async fn get_delivery_attempts(
+        &self,
+        event_id: Uuid,
+    ) -> Result<Vec<DeliveryAttempt>, VentrixError>;
+    // The attempts of each of the events, oldest attempt first
+    async fn get_delivery_attempts_for_events(
+        &self,
+        event_ids: &[Uuid],
+    ) -> Result<Vec<DeliveryAttempt>, VentrixError>;
+    async fn create_replay_job(&self, job: &ReplayJob) -> Result<InsertDataResponse, VentrixError>;
+    async fn get_replay_job(&self, replay_id: Uuid) -> Result<ReplayJob, VentrixError>;
+    // Saves the status, progress, cursor and lease of a running replay; a replay that is no
+    // longer running, e.g. because it was cancelled, or that another instance has taken over is
+    // left as it is
+    async fn update_replay_job(&self, job: &ReplayJob) -> Result<UpdateDataResponse, VentrixError>;
+    // Leases the running replays whose lease has expired, e.g. because their instance stopped,
+    // so `lease_owner` carries them on
+    async fn claim_replay_jobs(
+        &self,
+        lease_owner: &str,
+        lease_duration: Duration,
+    ) -> Result<Vec<ReplayJob>, VentrixError>;
+    // Stops a running replay and returns it; a finished replay is returned unchanged
+    async fn cancel_replay_job(&self, replay_id: Uuid) -> Result<ReplayJob, VentrixError>;
+    async fn count_events_to_replay(&self, job: &ReplayJob) -> Result<i64, VentrixError>;
+    // The events a replay selects after its cursor, oldest first, along with when each was
+    // published so the cursor can be moved past it
+    async fn get_events_to_replay(
+        &self,
+        job: &ReplayJob,
+        limit: i64,
+    ) -> Result<Vec<(DateTime<Utc>, VentrixEvent)>, VentrixError>;
+    // Held by one instance at a time while it prunes, so events are only archived once. None
+    // while another instance holds it.
+    async fn try_lock_pruning(&self) -> Result<Option<PruningLock>, VentrixError>;
+    // Events past their retention period, oldest first
+    async fn get_events_to_prune(
+        &self,
+        criteria: &PruneCriteria,
+        limit: i64,
+    ) -> Result<Vec<PublishedEvent>, VentrixError>;
+    // Deletes the events along with their failures and delivery attempts
+    async fn delete_events(&self, event_ids: &[Uuid]) -> Result<DeleteDataResponse, VentrixError>;
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<InsertDataResponse, VentrixError>;
+    // The key with the hash, unless it was revoked
+    async fn find_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, VentrixError>;
+    // Oldest first, revoked keys included
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>, VentrixError>;
+    // Revokes the key and returns it; a revoked key is returned unchanged
+    async fn revoke_api_key(&self, key_id: Uuid) -> Result<ApiKey, VentrixError>;
+    async fn record_publish_denial(
+        &self,
+        denial: &PublishDenial,
+    ) -> Result<InsertDataResponse, VentrixError>;
+    // Newest first
+    async fn list_publish_denials(&self, limit: i64) -> Result<Vec<PublishDenial>, VentrixError>;
+    // Fails when the backend cannot serve queries, e.g. it is unreachable or not fully migrated
+    async fn check_health(&self) -> Result<(), VentrixError>;
+}
+
+// Whether a subscription's event type is a pattern matching the event type
+fn matches_pattern(pattern: &str, event_type: &str) -> bool {
+    EventTypePattern::is_pattern(pattern)
+        && EventTypePattern::parse(pattern).is_ok_and(|pattern| pattern.matches(event_type))
 }
 
+// Names with wildcards are reserved for subscribing to patterns of event types
+fn wildcard_event_type_error(event_type: &str) -> VentrixError {
+    InvalidEventTypePatternError::new(&format!(
+        "Event type names cannot contain wildcards, but {:?} does",
+        event_type
+    ))
+    .into()
+}
+
+#[derive(Debug)]
 pub enum InsertDataResponse {
     InMemory,
     Postgres(u64),
 }
 
+// Released when dropped
+#[derive(Debug)]
+pub enum PruningLock {
+    InMemory(OwnedMutexGuard<()>),
+    Postgres(postgres::AdvisoryLock),
+}
+
+#[derive(Debug)]
 pub enum DeleteDataResponse {
     InMemory,
     Postgres(u64),
 }
 
+#[derive(Debug)]
 pub enum UpdateDataResponse {
     InMemory,
     Postgres(u64),
@@ -0,0 +1,34 @@
+use sqlx::{postgres::PgListener, PgPool};
+use uuid::Uuid;
+
+use crate::common::errors::VentrixError;
+
+pub const EVENT_PUBLISHED_CHANNEL: &str = "ventrix_event_published";
+
+// Wakes every instance sharing the database when an event is published, so they can compete
+// to claim it instead of it only being processed by the instance that received it
+#[derive(Debug, Clone)]
+pub struct PostgresNotifier {
+    pool: PgPool,
+}
+
+impl PostgresNotifier {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn notify_event_published(&self, event_id: Uuid) -> Result<(), VentrixError> {
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(EVENT_PUBLISHED_CHANNEL)
+            .bind(event_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn listen(&self) -> Result<PgListener, VentrixError> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(EVENT_PUBLISHED_CHANNEL).await?;
+        Ok(listener)
+    }
+}
@@ -1,27 +1,132 @@
-use crate::common::helpers::{err_to_boxed, err_to_boxed_send_sync};
+use crate::common::clock::Clock;
+use crate::common::errors::{
+    ApiKeyNotFoundError, EventNotFoundError, EventTypeAlreadyExistsError, EventTypeNotFoundError,
+    PendingMigrationsError, ReferencedEntityMissingError, ReplayNotFoundError,
+    ServiceAlreadyExistsError, ServiceNotFoundError, VentrixError,
+};
+use crate::common::event_type_pattern::EventTypePattern;
+use crate::common::metrics::METRICS;
 use crate::common::types::{
-    EventFulfillmentDetails, FailedEventRow, ListenToEventReq, PayloadSchema,
+    ApiKey, DeliveryAttempt, EventFulfillmentDetails, EventQuery, FailedEventRow, FulfilmentStatus,
+    ListenToEventReq, PayloadSchema, PruneCriteria, PublishDenial, PublishedEvent, ReplayJob,
+    ReplayStatus, RetainedEventKind, Subscription, TraceContext,
 };
 use crate::domain::models::service::RegisterServiceRequest;
 use crate::infrastructure::persistence::NewEventTypeRequest;
 use crate::{common::types::VentrixEvent, domain::models::service::Service};
-use std::error::Error;
 
 use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
-use sqlx::PgPool;
+use serde_json::Value;
+use sqlx::migrate::Migrator;
+use sqlx::pool::PoolConnection;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use std::sync::Arc;
 use uuid::Uuid;
 
-use super::{Database, DeleteDataResponse, InsertDataResponse, UpdateDataResponse};
+use super::{
+    matches_pattern, wildcard_event_type_error, Database, DeleteDataResponse, InsertDataResponse,
+    PruningLock, UpdateDataResponse, FIRST_RETRY_DELAY_MINUTES, MAX_RETRIES,
+};
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
 
 #[derive(Debug)]
 pub struct PostgresDatabase {
     pub pool: PgPool,
+    clock: Arc<dyn Clock>,
 }
 
 impl PostgresDatabase {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool, clock: Arc<dyn Clock>) -> Self {
+        Self { pool, clock }
+    }
+}
+
+// Key of the session advisory lock held while pruning, "ventrix" in ASCII
+const PRUNING_LOCK_KEY: i64 = 0x0076_656e_7472_6978;
+
+// A session advisory lock, held by the connection it was taken on
+#[derive(Debug)]
+pub struct AdvisoryLock {
+    key: i64,
+    connection: Option<Box<PoolConnection<Postgres>>>,
+}
+
+// The lock is released in the background before the connection goes back to the pool. When
+// that fails, or there is no runtime to do it on, the connection is closed, which releases it
+// too.
+impl Drop for AdvisoryLock {
+    fn drop(&mut self) {
+        let Some(mut connection) = self.connection.take() else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            drop((*connection).detach());
+            return;
+        };
+        let key = self.key;
+        runtime.spawn(async move {
+            let unlocked = sqlx::query("SELECT pg_advisory_unlock($1)")
+                .bind(key)
+                .execute(&mut **connection)
+                .await;
+            if let Err(err) = unlocked {
+                tracing::warn!("Could not release advisory lock {}. Err: {}", key, err);
+                drop((*connection).detach());
+            }
+        });
+    }
+}
+
+fn is_unique_violation(err: &sqlx::Error) -> bool {
+    matches!(err, sqlx::Error::Database(db_err) if db_err.is_unique_violation())
+}
+
+fn is_foreign_key_violation(err: &sqlx::Error) -> bool {
+    matches!(err, sqlx::Error::Database(db_err) if db_err.is_foreign_key_violation())
+}
+
+const LISTENER_COLUMNS: &str = "services.name, services.url, event_type_to_service.endpoint, event_type_to_service.filter, event_type_to_service.transform, event_type_to_service.target_schema, services.delivery_settings AS service_delivery_settings, event_type_to_service.delivery_settings";
+
+#[derive(sqlx::FromRow)]
+struct PatternListener {
+    #[sqlx(flatten)]
+    listener: EventFulfillmentDetails,
+    event_type_pattern: String,
+}
+
+const REPLAY_JOB_COLUMNS: &str = "id, service_name, event_type, published_after, published_before, event_ids, rate_per_second, status, total, delivered, failed, skipped, error, created_at, finished_at, cursor_published_at, cursor_event_id, lease_owner, lease_expires_at";
+
+const PUBLISH_DENIAL_COLUMNS: &str =
+    "id, event_type, api_key_id, api_key_name, service_name, reason, attempted_at";
+
+const API_KEY_COLUMNS: &str =
+    "id, name, role, event_types, service_name, key_hash, created_at, revoked_at";
+
+// Restricts a query on `events_published AS e` to the events a replay selects
+fn push_replay_selection(builder: &mut QueryBuilder<'_, Postgres>, job: &ReplayJob) {
+    if let Some(event_ids) = &job.event_ids {
+        builder
+            .push(" AND e.id = ANY(")
+            .push_bind(event_ids.clone())
+            .push(")");
+        return;
+    }
+    if let Some(event_type) = &job.event_type {
+        builder
+            .push(" AND e.event_type = ")
+            .push_bind(event_type.clone());
+    }
+    if let Some(published_after) = job.published_after {
+        builder
+            .push(" AND e.created_at >= ")
+            .push_bind(published_after);
+    }
+    if let Some(published_before) = job.published_before {
+        builder
+            .push(" AND e.created_at < ")
+            .push_bind(published_before);
     }
 }
 
@@ -30,174 +135,342 @@ impl Database for PostgresDatabase {
     async fn register_service(
         &self,
         service: &RegisterServiceRequest,
-    ) -> Result<InsertDataResponse, Box<dyn Error>> {
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "register_service");
         let uuid = Uuid::new_v4();
         sqlx::query(
             "
-        INSERT INTO services (id, name, url)
-        VALUES ($1, $2, $3)
+        INSERT INTO services (id, name, url, delivery_settings)
+        VALUES ($1, $2, $3, $4)
         ",
         )
         .bind(uuid)
         .bind(service.name.clone())
         .bind(service.url.clone())
+        .bind(&service.sealed_delivery)
         .execute(&self.pool)
         .await
-        .map_err(err_to_boxed)
+        .map_err(|err| match is_unique_violation(&err) {
+            true => ServiceAlreadyExistsError::new(service.name.clone()).into(),
+            false => VentrixError::from(err),
+        })
         .map(|response| InsertDataResponse::Postgres(response.rows_affected()))
     }
 
-    async fn remove_service(
-        &self,
-        service_name: &str,
-    ) -> Result<DeleteDataResponse, Box<dyn Error>> {
-        sqlx::query("DELETE services WHERE name = $1")
+    async fn remove_service(&self, service_name: &str) -> Result<DeleteDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "remove_service");
+        let mut transaction = self.pool.begin().await?;
+        let response = sqlx::query("DELETE FROM services WHERE name = $1")
             .bind(service_name)
-            .execute(&self.pool)
-            .await
-            .map_err(err_to_boxed)
-            .map(|response| DeleteDataResponse::Postgres(response.rows_affected()))
+            .execute(&mut *transaction)
+            .await?;
+        if response.rows_affected() == 0 {
+            return Err(ServiceNotFoundError::new(service_name).into());
+        }
+        sqlx::query(
+            "UPDATE event_types SET publishers = array_remove(publishers, $1) WHERE $1 = ANY(publishers)",
+        )
+        .bind(service_name)
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(DeleteDataResponse::Postgres(response.rows_affected()))
     }
 
     async fn register_event_type(
         &self,
         event_type: &NewEventTypeRequest,
-    ) -> Result<InsertDataResponse, Box<dyn Error>> {
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "register_event_type");
+        if EventTypePattern::is_pattern(&event_type.name) {
+            return Err(wildcard_event_type_error(&event_type.name));
+        }
+        if let Some(publishers) = &event_type.publishers {
+            let known: Vec<String> =
+                sqlx::query_scalar("SELECT name FROM services WHERE name = ANY($1)")
+                    .bind(publishers)
+                    .fetch_all(&self.pool)
+                    .await?;
+            if let Some(missing) = publishers
+                .iter()
+                .find(|publisher| !known.contains(publisher))
+            {
+                return Err(ReferencedEntityMissingError::new("service", missing).into());
+            }
+        }
         let uuid = Uuid::new_v4();
         sqlx::query(
             "
-        INSERT INTO event_types (id, name, description, payload_definition)
-        VALUES ($1, $2, $3, $4)
+        INSERT INTO event_types (id, name, description, payload_definition, publishers)
+        VALUES ($1, $2, $3, $4, $5)
         ",
         )
         .bind(uuid)
         .bind(event_type.name.clone())
         .bind(event_type.description.clone())
         .bind(event_type.payload_definition.to_string())
+        .bind(&event_type.publishers)
         .execute(&self.pool)
         .await
-        .map_err(err_to_boxed)
+        .map_err(|err| match is_unique_violation(&err) {
+            true => EventTypeAlreadyExistsError::new(event_type.name.clone()).into(),
+            false => VentrixError::from(err),
+        })
         .map(|response| InsertDataResponse::Postgres(response.rows_affected()))
     }
 
-    async fn get_service(&self, name: &str) -> Result<Service, Box<dyn Error>> {
+    async fn get_service(&self, name: &str) -> Result<Service, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "get_service");
         sqlx::query_as::<_, Service>(
             r#"
         SELECT id, name, url FROM services WHERE name = $1"#,
         )
         .bind(name)
-        .fetch_one(&self.pool)
-        .await
-        .map_err(err_to_boxed)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| ServiceNotFoundError::new(name).into())
     }
 
     async fn save_published_event(
         &self,
         event: &VentrixEvent,
-    ) -> Result<InsertDataResponse, Box<dyn Error>> {
-        sqlx::query("INSERT INTO events_published (id, event_type, payload) VALUES ($1, $2, $3)")
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "save_published_event");
+        sqlx::query(
+            "INSERT INTO events_published (id, event_type, payload, created_at, traceparent, tracestate) VALUES ($1, $2, $3, $4, $5, $6)",
+        )
             .bind(event.id)
             .bind(event.event_type.clone())
             .bind(event.payload.clone())
+            .bind(self.clock.now())
+            .bind(event.trace_context.traceparent.clone())
+            .bind(event.trace_context.tracestate.clone())
             .execute(&self.pool)
             .await
-            .map_err(err_to_boxed)
+            .map_err(VentrixError::from)
             .map(|response| InsertDataResponse::Postgres(response.rows_affected()))
     }
 
     async fn add_failed_event(
         &self,
         event: &VentrixEvent,
-    ) -> Result<InsertDataResponse, Box<dyn Error>> {
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "add_failed_event");
         let uuid = Uuid::new_v4();
-        let retry_time = Utc::now() + Duration::minutes(1);
+        let retry_time = self.clock.now() + Duration::minutes(FIRST_RETRY_DELAY_MINUTES);
         sqlx::query("INSERT INTO failed_events (id, event_id, retry_time) VALUES ($1, $2, $3)")
             .bind(uuid)
             .bind(event.id)
             .bind(retry_time)
             .execute(&self.pool)
             .await
-            .map_err(err_to_boxed)
+            .map_err(|err| match is_foreign_key_violation(&err) {
+                true => EventNotFoundError::new(&event.id.to_string()).into(),
+                false => VentrixError::from(err),
+            })
             .map(|response| InsertDataResponse::Postgres(response.rows_affected()))
     }
 
-    async fn fulfil_event(
-        &self,
-        event: &VentrixEvent,
-    ) -> Result<UpdateDataResponse, Box<dyn Error>> {
-        sqlx::query(r#"UPDATE events_published SET fulfilled_at = NOW() WHERE id = $1"#)
-            .bind(event.id)
-            .execute(&self.pool)
-            .await
-            .map_err(err_to_boxed)
-            .map(|response| UpdateDataResponse::Postgres(response.rows_affected()))
+    async fn fulfil_event(&self, event: &VentrixEvent) -> Result<UpdateDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "fulfil_event");
+        let response =
+            sqlx::query(r#"UPDATE events_published SET fulfilled_at = $1 WHERE id = $2"#)
+                .bind(self.clock.now())
+                .bind(event.id)
+                .execute(&self.pool)
+                .await?;
+
+        match response.rows_affected() {
+            0 => Err(EventNotFoundError::new(&event.id.to_string()).into()),
+            rows_affected => Ok(UpdateDataResponse::Postgres(rows_affected)),
+        }
     }
 
     async fn register_service_for_event_type(
         &self,
         listen_to_event_req: &ListenToEventReq,
-    ) -> Result<InsertDataResponse, Box<dyn Error>> {
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "register_service_for_event_type");
+        let (event_type_id, event_type_pattern) =
+            match EventTypePattern::is_pattern(&listen_to_event_req.event_type) {
+                true => {
+                    EventTypePattern::parse(&listen_to_event_req.event_type)?;
+                    (None, Some(&listen_to_event_req.event_type))
+                }
+                false => {
+                    let event_type_id: Uuid =
+                        sqlx::query_scalar("SELECT id FROM event_types WHERE name = $1")
+                            .bind(&listen_to_event_req.event_type)
+                            .fetch_optional(&self.pool)
+                            .await?
+                            .ok_or_else(|| {
+                                ReferencedEntityMissingError::new(
+                                    "event type",
+                                    &listen_to_event_req.event_type,
+                                )
+                            })?;
+                    (Some(event_type_id), None)
+                }
+            };
+
+        let service_id: Uuid = sqlx::query_scalar("SELECT id FROM services WHERE name = $1")
+            .bind(&listen_to_event_req.service_name)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| {
+                ReferencedEntityMissingError::new("service", &listen_to_event_req.service_name)
+            })?;
+
         let uuid = Uuid::new_v4();
         sqlx::query(
-            "WITH EventType AS (
-                SELECT id FROM event_types WHERE name = $2
-            ),
-            Service AS (
-                SELECT id FROM services WHERE name = $3
-            )
-            INSERT INTO event_type_to_service (id, event_type_id, service_id, endpoint)
-            VALUES ($1, (SELECT id FROM EventType), (SELECT id FROM Service), $4)",
+            "INSERT INTO event_type_to_service (id, event_type_id, service_id, endpoint, filter, event_type_pattern, transform, target_schema, delivery_settings)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
         )
         .bind(uuid)
-        .bind(&listen_to_event_req.event_type.clone())
-        .bind(&listen_to_event_req.service_name.clone())
-        .bind(&listen_to_event_req.endpoint.clone())
+        .bind(event_type_id)
+        .bind(service_id)
+        .bind(listen_to_event_req.endpoint.clone())
+        .bind(&listen_to_event_req.filter)
+        .bind(event_type_pattern)
+        .bind(listen_to_event_req.transform.as_ref().map(Value::to_string))
+        .bind(listen_to_event_req.target_schema.as_ref().map(Value::to_string))
+        .bind(&listen_to_event_req.sealed_delivery)
         .execute(&self.pool)
         .await
-        .map_err(err_to_boxed)
+        .map_err(VentrixError::from)
         .map(|response| InsertDataResponse::Postgres(response.rows_affected()))
     }
 
     async fn get_service_by_event_type(
         &self,
         event_type_name: &str,
-    ) -> Result<Vec<EventFulfillmentDetails>, Box<dyn Error + Sync + Send>> {
-        sqlx::query_as::<_, EventFulfillmentDetails>(
-            "SELECT services.name, services.url, event_type_to_service.endpoint 
-            FROM services 
-            INNER JOIN event_type_to_service ON event_type_to_service.service_id = services.id 
-            INNER JOIN event_types ON event_type_to_service.event_type_id = event_types.id 
-            WHERE event_types.name = $1;",
-        )
-        .bind(event_type_name.clone())
+    ) -> Result<Vec<EventFulfillmentDetails>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "get_service_by_event_type");
+        let listeners = sqlx::query_as::<_, EventFulfillmentDetails>(&format!(
+            "SELECT {LISTENER_COLUMNS}
+            FROM services
+            INNER JOIN event_type_to_service ON event_type_to_service.service_id = services.id
+            INNER JOIN event_types ON event_type_to_service.event_type_id = event_types.id
+            WHERE event_types.name = $1;"
+        ))
+        .bind(event_type_name)
         .fetch_all(&self.pool)
-        .await
-        .map_err(err_to_boxed_send_sync)
+        .await?;
+
+        // Patterns are matched here rather than in SQL, so both backends share EventTypePattern
+        let pattern_listeners = sqlx::query_as::<_, PatternListener>(&format!(
+            "SELECT {LISTENER_COLUMNS}, event_type_to_service.event_type_pattern
+            FROM services
+            INNER JOIN event_type_to_service ON event_type_to_service.service_id = services.id
+            WHERE event_type_to_service.event_type_pattern IS NOT NULL"
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(listeners
+            .into_iter()
+            .chain(
+                pattern_listeners
+                    .into_iter()
+                    .filter(|listener| {
+                        matches_pattern(&listener.event_type_pattern, event_type_name)
+                    })
+                    .map(|listener| listener.listener),
+            )
+            .collect())
+    }
+
+    async fn get_subscriptions(
+        &self,
+        service_name: &str,
+    ) -> Result<Vec<Subscription>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "get_subscriptions");
+        let service_id: Uuid = sqlx::query_scalar("SELECT id FROM services WHERE name = $1")
+            .bind(service_name)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| ServiceNotFoundError::new(service_name))?;
+
+        let subscriptions = sqlx::query_as::<_, (String, String, Option<String>, bool)>(
+            "SELECT COALESCE(event_types.name, event_type_to_service.event_type_pattern) AS event_type, event_type_to_service.endpoint, event_type_to_service.filter, event_type_to_service.event_type_pattern IS NOT NULL
+            FROM event_type_to_service
+            LEFT JOIN event_types ON event_types.id = event_type_to_service.event_type_id
+            WHERE event_type_to_service.service_id = $1
+            ORDER BY event_type, event_type_to_service.created_at",
+        )
+        .bind(service_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let event_types: Vec<String> =
+            sqlx::query_scalar("SELECT name FROM event_types ORDER BY name")
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(subscriptions
+            .into_iter()
+            .map(|(event_type, endpoint, filter, is_pattern)| {
+                let resolved_event_types = match is_pattern {
+                    true => event_types
+                        .iter()
+                        .filter(|name| matches_pattern(&event_type, name))
+                        .cloned()
+                        .collect(),
+                    false => vec![event_type.clone()],
+                };
+                Subscription {
+                    event_type,
+                    endpoint,
+                    filter,
+                    resolved_event_types,
+                }
+            })
+            .collect())
     }
 
     async fn get_schema_for_event_type(
         &self,
         event_type_name: &str,
-    ) -> Result<PayloadSchema, Box<dyn Error>> {
+    ) -> Result<PayloadSchema, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "get_schema_for_event_type");
         sqlx::query_as::<_, PayloadSchema>(
             r#"SELECT payload_definition FROM event_types WHERE name = $1"#,
         )
         .bind(event_type_name)
-        .fetch_one(&self.pool)
-        .await
-        .map_err(err_to_boxed)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| EventTypeNotFoundError::new(event_type_name).into())
+    }
+
+    async fn get_publishers_for_event_type(
+        &self,
+        event_type_name: &str,
+    ) -> Result<Option<Vec<String>>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "get_publishers_for_event_type");
+        sqlx::query_scalar::<_, Option<Vec<String>>>(
+            "SELECT publishers FROM event_types WHERE name = $1",
+        )
+        .bind(event_type_name)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| EventTypeNotFoundError::new(event_type_name).into())
     }
 
     async fn resolve_failed_event(
         &self,
         event_id: Uuid,
-    ) -> Result<UpdateDataResponse, Box<dyn Error>> {
-        sqlx::query("UPDATE failed_events SET resolved_at = NOW() WHERE event_id = $1")
+    ) -> Result<UpdateDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "resolve_failed_event");
+        let response = sqlx::query("UPDATE failed_events SET resolved_at = $1, lease_owner = NULL, lease_expires_at = NULL WHERE event_id = $2")
+            .bind(self.clock.now())
             .bind(event_id)
             .execute(&self.pool)
-            .await
-            .map_err(err_to_boxed)
-            .map(|response| UpdateDataResponse::Postgres(response.rows_affected()))
+            .await?;
+
+        match response.rows_affected() {
+            0 => Err(EventNotFoundError::new(&event_id.to_string()).into()),
+            rows_affected => Ok(UpdateDataResponse::Postgres(rows_affected)),
+        }
     }
 
     async fn update_retry_time(
@@ -205,24 +478,654 @@ impl Database for PostgresDatabase {
         event_id: Uuid,
         new_retry_time: DateTime<Utc>,
         retries: i16,
-    ) -> Result<UpdateDataResponse, Box<dyn Error>> {
-        sqlx::query("UPDATE failed_events SET retry_time = $1, retries = $2 WHERE event_id = $3")
-            .bind(new_retry_time)
-            .bind(retries)
+    ) -> Result<UpdateDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "update_retry_time");
+        let response = sqlx::query(
+            "UPDATE failed_events SET retry_time = $1, retries = $2, lease_owner = NULL, lease_expires_at = NULL, dead_lettered_at = CASE WHEN $2 >= $4 THEN COALESCE(dead_lettered_at, $5) END WHERE event_id = $3",
+        )
+        .bind(new_retry_time)
+        .bind(retries)
+        .bind(event_id)
+        .bind(MAX_RETRIES)
+        .bind(self.clock.now())
+        .execute(&self.pool)
+        .await?;
+
+        match response.rows_affected() {
+            0 => Err(EventNotFoundError::new(&event_id.to_string()).into()),
+            rows_affected => Ok(UpdateDataResponse::Postgres(rows_affected)),
+        }
+    }
+
+    async fn claim_failed_events(
+        &self,
+        lease_owner: &str,
+        lease_duration: Duration,
+        limit: i64,
+    ) -> Result<Vec<VentrixEvent>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "claim_failed_events");
+        let now = self.clock.now();
+        sqlx::query_as::<_, FailedEventRow>(
+            r#"WITH due AS (SELECT id FROM failed_events WHERE retries < $1 AND retry_time < $2 AND resolved_at IS NULL AND (lease_expires_at IS NULL OR lease_expires_at < $2) ORDER BY retry_time LIMIT $5 FOR UPDATE SKIP LOCKED) UPDATE failed_events AS f SET lease_owner = $3, lease_expires_at = $4 FROM due, events_published AS e WHERE f.id = due.id AND e.id = f.event_id RETURNING e.id, e.event_type, e.payload, f.retry_time, f.retries, e.traceparent, e.tracestate"#
+        )
+        .bind(MAX_RETRIES)
+        .bind(now)
+        .bind(lease_owner)
+        .bind(now + lease_duration)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(VentrixError::from)
+        .map(|failed_events| failed_events.into_iter().map(VentrixEvent::from_failed_event).collect())
+    }
+
+    async fn get_next_retry_time(&self) -> Result<Option<DateTime<Utc>>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "get_next_retry_time");
+        sqlx::query_scalar(
+            "SELECT MIN(retry_time) FROM failed_events WHERE retries < $1 AND resolved_at IS NULL",
+        )
+        .bind(MAX_RETRIES)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(VentrixError::from)
+    }
+
+    async fn claim_pending_events(
+        &self,
+        lease_owner: &str,
+        lease_duration: Duration,
+        published_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<VentrixEvent>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "claim_pending_events");
+        let now = self.clock.now();
+        sqlx::query_as::<_, (Uuid, String, String, Option<String>, Option<String>)>(
+            r#"WITH pending AS (SELECT id FROM events_published WHERE dispatched_at IS NULL AND created_at <= $5 AND (lease_expires_at IS NULL OR lease_expires_at < $1) ORDER BY created_at LIMIT $2 FOR UPDATE SKIP LOCKED) UPDATE events_published AS e SET lease_owner = $3, lease_expires_at = $4 FROM pending WHERE e.id = pending.id RETURNING e.id, e.event_type, e.payload, e.traceparent, e.tracestate"#
+        )
+        .bind(now)
+        .bind(limit)
+        .bind(lease_owner)
+        .bind(now + lease_duration)
+        .bind(published_before)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(VentrixError::from)
+        .map(|pending_events| {
+            pending_events
+                .into_iter()
+                .map(
+                    |(id, event_type, payload, traceparent, tracestate)| VentrixEvent {
+                        id,
+                        event_type,
+                        payload,
+                        retry_details: None,
+                        trace_context: TraceContext {
+                            traceparent,
+                            tracestate,
+                        },
+                    },
+                )
+                .collect()
+        })
+    }
+
+    async fn renew_pending_lease(
+        &self,
+        event_id: Uuid,
+        lease_owner: &str,
+        lease_duration: Duration,
+    ) -> Result<bool, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "renew_pending_lease");
+        let now = self.clock.now();
+        let response = sqlx::query(
+            "UPDATE events_published SET lease_owner = $1, lease_expires_at = $2 WHERE id = $3 AND dispatched_at IS NULL AND (lease_owner = $1 OR lease_expires_at IS NULL OR lease_expires_at < $4)",
+        )
+        .bind(lease_owner)
+        .bind(now + lease_duration)
+        .bind(event_id)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(response.rows_affected() > 0)
+    }
+
+    async fn mark_event_dispatched(
+        &self,
+        event_id: Uuid,
+    ) -> Result<UpdateDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "mark_event_dispatched");
+        let response = sqlx::query(
+            "UPDATE events_published SET dispatched_at = $1, lease_owner = NULL, lease_expires_at = NULL WHERE id = $2",
+        )
+        .bind(self.clock.now())
+        .bind(event_id)
+        .execute(&self.pool)
+        .await?;
+
+        match response.rows_affected() {
+            0 => Err(EventNotFoundError::new(&event_id.to_string()).into()),
+            rows_affected => Ok(UpdateDataResponse::Postgres(rows_affected)),
+        }
+    }
+
+    async fn mark_event_spilled(&self, event_id: Uuid) -> Result<UpdateDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "mark_event_spilled");
+        let response = sqlx::query("UPDATE events_published SET spilled_at = $1 WHERE id = $2")
+            .bind(self.clock.now())
             .bind(event_id)
             .execute(&self.pool)
+            .await?;
+
+        match response.rows_affected() {
+            0 => Err(EventNotFoundError::new(&event_id.to_string()).into()),
+            rows_affected => Ok(UpdateDataResponse::Postgres(rows_affected)),
+        }
+    }
+
+    async fn take_spilled_events(&self, limit: i64) -> Result<Vec<VentrixEvent>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "take_spilled_events");
+        sqlx::query_as::<_, (Uuid, String, String, Option<String>, Option<String>)>(
+            r#"WITH spilled AS (SELECT id FROM events_published WHERE spilled_at IS NOT NULL AND dispatched_at IS NULL ORDER BY spilled_at LIMIT $1 FOR UPDATE SKIP LOCKED) UPDATE events_published AS e SET spilled_at = NULL FROM spilled WHERE e.id = spilled.id RETURNING e.id, e.event_type, e.payload, e.traceparent, e.tracestate"#
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(VentrixError::from)
+        .map(|spilled_events| {
+            spilled_events
+                .into_iter()
+                .map(
+                    |(id, event_type, payload, traceparent, tracestate)| VentrixEvent {
+                        id,
+                        event_type,
+                        payload,
+                        retry_details: None,
+                        trace_context: TraceContext {
+                            traceparent,
+                            tracestate,
+                        },
+                    },
+                )
+                .collect()
+        })
+    }
+
+    async fn record_delivery_attempt(
+        &self,
+        attempt: &DeliveryAttempt,
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "record_delivery_attempt");
+        sqlx::query(
+            "INSERT INTO delivery_attempts (id, event_id, service_name, endpoint, attempt, status_code, succeeded, latency_ms, error, attempted_at, replay_id, filtered) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+        )
+        .bind(attempt.id)
+        .bind(attempt.event_id)
+        .bind(&attempt.service_name)
+        .bind(&attempt.endpoint)
+        .bind(attempt.attempt)
+        .bind(attempt.status_code)
+        .bind(attempt.succeeded)
+        .bind(attempt.latency_ms)
+        .bind(&attempt.error)
+        .bind(attempt.attempted_at)
+        .bind(attempt.replay_id)
+        .bind(attempt.filtered)
+        .execute(&self.pool)
+        .await
+        .map_err(|err| match is_foreign_key_violation(&err) {
+            true => EventNotFoundError::new(&attempt.event_id.to_string()).into(),
+            false => VentrixError::from(err),
+        })
+        .map(|response| InsertDataResponse::Postgres(response.rows_affected()))
+    }
+
+    async fn list_events(&self, query: &EventQuery) -> Result<Vec<PublishedEvent>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "list_events");
+        let mut builder = QueryBuilder::<Postgres>::new(
+            "SELECT e.id, e.event_type, e.payload, e.created_at AS published_at, e.dispatched_at, e.fulfilled_at FROM events_published AS e WHERE TRUE",
+        );
+        if let Some(event_type) = &query.event_type {
+            builder.push(" AND e.event_type = ").push_bind(event_type);
+        }
+        match query.status {
+            Some(FulfilmentStatus::Fulfilled) => builder.push(" AND e.fulfilled_at IS NOT NULL"),
+            Some(FulfilmentStatus::Pending) => builder.push(" AND e.fulfilled_at IS NULL"),
+            None => &mut builder,
+        };
+        if let Some(service) = &query.service {
+            builder
+                .push(" AND EXISTS (SELECT 1 FROM delivery_attempts AS d WHERE d.event_id = e.id AND d.service_name = ")
+                .push_bind(service)
+                .push(")");
+        }
+        if let Some(published_after) = query.published_after {
+            builder
+                .push(" AND e.created_at >= ")
+                .push_bind(published_after);
+        }
+        if let Some(published_before) = query.published_before {
+            builder
+                .push(" AND e.created_at < ")
+                .push_bind(published_before);
+        }
+        builder
+            .push(" ORDER BY e.created_at DESC, e.id LIMIT ")
+            .push_bind(query.limit());
+
+        builder
+            .build_query_as::<PublishedEvent>()
+            .fetch_all(&self.pool)
             .await
-            .map_err(err_to_boxed)
-            .map(|response| UpdateDataResponse::Postgres(response.rows_affected()))
+            .map_err(VentrixError::from)
     }
 
-    async fn get_failed_events(&self) -> Result<Vec<VentrixEvent>, Box<dyn Error + Sync + Send>> {
-        sqlx::query_as::<_, FailedEventRow>(
-            r#"SELECT e.id, e.event_type, e.payload, f.retry_time, f.retries FROM events_published AS e INNER JOIN failed_events as f ON e.id = f.event_id WHERE f.retries < 3 AND f.retry_time < NOW()"#
+    async fn get_event(&self, event_id: Uuid) -> Result<PublishedEvent, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "get_event");
+        sqlx::query_as::<_, PublishedEvent>(
+            "SELECT id, event_type, payload, created_at AS published_at, dispatched_at, fulfilled_at FROM events_published WHERE id = $1",
+        )
+        .bind(event_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| EventNotFoundError::new(&event_id.to_string()).into())
+    }
+
+    async fn get_delivery_attempts(
+        &self,
+        event_id: Uuid,
+    ) -> Result<Vec<DeliveryAttempt>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "get_delivery_attempts");
+        sqlx::query_as::<_, DeliveryAttempt>(
+            "SELECT id, event_id, service_name, endpoint, attempt, status_code, succeeded, latency_ms, error, attempted_at, replay_id, filtered FROM delivery_attempts WHERE event_id = $1 ORDER BY attempted_at, attempt",
         )
+        .bind(event_id)
         .fetch_all(&self.pool)
         .await
-        .map_err(err_to_boxed_send_sync)
-        .map(|failed_events| failed_events.into_iter().map(VentrixEvent::from_failed_event).collect())
+        .map_err(VentrixError::from)
+    }
+
+    async fn get_delivery_attempts_for_events(
+        &self,
+        event_ids: &[Uuid],
+    ) -> Result<Vec<DeliveryAttempt>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "get_delivery_attempts_for_events");
+        sqlx::query_as::<_, DeliveryAttempt>(
+            "SELECT id, event_id, service_name, endpoint, attempt, status_code, succeeded, latency_ms, error, attempted_at, replay_id, filtered FROM delivery_attempts WHERE event_id = ANY($1) ORDER BY attempted_at, attempt",
+        )
+        .bind(event_ids)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(VentrixError::from)
+    }
+
+    async fn create_replay_job(&self, job: &ReplayJob) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "create_replay_job");
+        sqlx::query(&format!(
+            "INSERT INTO replay_jobs ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19)",
+            REPLAY_JOB_COLUMNS
+        ))
+        .bind(job.id)
+        .bind(&job.service_name)
+        .bind(&job.event_type)
+        .bind(job.published_after)
+        .bind(job.published_before)
+        .bind(&job.event_ids)
+        .bind(job.rate_per_second)
+        .bind(job.status)
+        .bind(job.total)
+        .bind(job.delivered)
+        .bind(job.failed)
+        .bind(job.skipped)
+        .bind(&job.error)
+        .bind(job.created_at)
+        .bind(job.finished_at)
+        .bind(job.cursor_published_at)
+        .bind(job.cursor_event_id)
+        .bind(&job.lease_owner)
+        .bind(job.lease_expires_at)
+        .execute(&self.pool)
+        .await
+        .map_err(VentrixError::from)
+        .map(|response| InsertDataResponse::Postgres(response.rows_affected()))
+    }
+
+    async fn get_replay_job(&self, replay_id: Uuid) -> Result<ReplayJob, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "get_replay_job");
+        sqlx::query_as::<_, ReplayJob>(&format!(
+            "SELECT {} FROM replay_jobs WHERE id = $1",
+            REPLAY_JOB_COLUMNS
+        ))
+        .bind(replay_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| ReplayNotFoundError::new(&replay_id.to_string()).into())
+    }
+
+    async fn update_replay_job(&self, job: &ReplayJob) -> Result<UpdateDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "update_replay_job");
+        let response = sqlx::query(
+            "UPDATE replay_jobs SET status = $1, total = $2, delivered = $3, failed = $4, skipped = $5, error = $6, finished_at = $7, cursor_published_at = $8, cursor_event_id = $9, lease_expires_at = $10 WHERE id = $11 AND status = $12 AND lease_owner IS NOT DISTINCT FROM $13",
+        )
+        .bind(job.status)
+        .bind(job.total)
+        .bind(job.delivered)
+        .bind(job.failed)
+        .bind(job.skipped)
+        .bind(&job.error)
+        .bind(job.finished_at)
+        .bind(job.cursor_published_at)
+        .bind(job.cursor_event_id)
+        .bind(job.lease_expires_at)
+        .bind(job.id)
+        .bind(ReplayStatus::Running)
+        .bind(&job.lease_owner)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(UpdateDataResponse::Postgres(response.rows_affected()))
+    }
+
+    async fn claim_replay_jobs(
+        &self,
+        lease_owner: &str,
+        lease_duration: Duration,
+    ) -> Result<Vec<ReplayJob>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "claim_replay_jobs");
+        let now = self.clock.now();
+        let returned_columns = REPLAY_JOB_COLUMNS
+            .split(", ")
+            .map(|column| format!("r.{}", column))
+            .collect::<Vec<_>>()
+            .join(", ");
+        sqlx::query_as::<_, ReplayJob>(&format!(
+            "WITH abandoned AS (SELECT id FROM replay_jobs WHERE status = $1 AND (lease_expires_at IS NULL OR lease_expires_at < $2) FOR UPDATE SKIP LOCKED) UPDATE replay_jobs AS r SET lease_owner = $3, lease_expires_at = $4 FROM abandoned WHERE r.id = abandoned.id RETURNING {}",
+            returned_columns
+        ))
+        .bind(ReplayStatus::Running)
+        .bind(now)
+        .bind(lease_owner)
+        .bind(now + lease_duration)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(VentrixError::from)
+    }
+
+    async fn cancel_replay_job(&self, replay_id: Uuid) -> Result<ReplayJob, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "cancel_replay_job");
+        sqlx::query(
+            "UPDATE replay_jobs SET status = $1, finished_at = $2 WHERE id = $3 AND status = $4",
+        )
+        .bind(ReplayStatus::Cancelled)
+        .bind(self.clock.now())
+        .bind(replay_id)
+        .bind(ReplayStatus::Running)
+        .execute(&self.pool)
+        .await?;
+
+        self.get_replay_job(replay_id).await
+    }
+
+    async fn count_events_to_replay(&self, job: &ReplayJob) -> Result<i64, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "count_events_to_replay");
+        let mut builder =
+            QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM events_published AS e WHERE TRUE");
+        push_replay_selection(&mut builder, job);
+
+        builder
+            .build_query_scalar::<i64>()
+            .fetch_one(&self.pool)
+            .await
+            .map_err(VentrixError::from)
+    }
+
+    async fn get_events_to_replay(
+        &self,
+        job: &ReplayJob,
+        limit: i64,
+    ) -> Result<Vec<(DateTime<Utc>, VentrixEvent)>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "get_events_to_replay");
+        let mut builder = QueryBuilder::<Postgres>::new(
+            "SELECT e.created_at, e.id, e.event_type, e.payload, e.traceparent, e.tracestate FROM events_published AS e WHERE TRUE",
+        );
+        push_replay_selection(&mut builder, job);
+        if let (Some(cursor_published_at), Some(cursor_event_id)) =
+            (job.cursor_published_at, job.cursor_event_id)
+        {
+            builder
+                .push(" AND (e.created_at, e.id) > (")
+                .push_bind(cursor_published_at)
+                .push(", ")
+                .push_bind(cursor_event_id)
+                .push(")");
+        }
+        builder
+            .push(" ORDER BY e.created_at, e.id LIMIT ")
+            .push_bind(limit);
+
+        builder
+            .build_query_as::<(
+                DateTime<Utc>,
+                Uuid,
+                String,
+                String,
+                Option<String>,
+                Option<String>,
+            )>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(VentrixError::from)
+            .map(|events| {
+                events
+                    .into_iter()
+                    .map(
+                        |(published_at, id, event_type, payload, traceparent, tracestate)| {
+                            let event = VentrixEvent {
+                                id,
+                                event_type,
+                                payload,
+                                retry_details: None,
+                                trace_context: TraceContext {
+                                    traceparent,
+                                    tracestate,
+                                },
+                            };
+                            (published_at, event)
+                        },
+                    )
+                    .collect()
+            })
+    }
+
+    async fn try_lock_pruning(&self) -> Result<Option<PruningLock>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "try_lock_pruning");
+        let mut connection = self.pool.acquire().await?;
+        let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+            .bind(PRUNING_LOCK_KEY)
+            .fetch_one(&mut *connection)
+            .await?;
+
+        Ok(locked.then(|| {
+            PruningLock::Postgres(AdvisoryLock {
+                key: PRUNING_LOCK_KEY,
+                connection: Some(Box::new(connection)),
+            })
+        }))
+    }
+
+    async fn get_events_to_prune(
+        &self,
+        criteria: &PruneCriteria,
+        limit: i64,
+    ) -> Result<Vec<PublishedEvent>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "get_events_to_prune");
+        let mut builder = QueryBuilder::<Postgres>::new(
+            "SELECT e.id, e.event_type, e.payload, e.created_at AS published_at, e.dispatched_at, e.fulfilled_at FROM events_published AS e WHERE TRUE",
+        );
+        match criteria.kind {
+            RetainedEventKind::Fulfilled => builder
+                .push(" AND e.fulfilled_at < ")
+                .push_bind(criteria.before)
+                .push(" AND NOT EXISTS (SELECT 1 FROM failed_events AS f WHERE f.event_id = e.id AND f.resolved_at IS NULL)"),
+            RetainedEventKind::DeadLetter => builder
+                .push(" AND EXISTS (SELECT 1 FROM failed_events AS f WHERE f.event_id = e.id AND f.resolved_at IS NULL AND f.retries >= ")
+                .push_bind(MAX_RETRIES)
+                .push(" AND f.dead_lettered_at < ")
+                .push_bind(criteria.before)
+                .push(")"),
+            RetainedEventKind::Unfulfilled => builder
+                .push(" AND e.fulfilled_at IS NULL AND e.created_at < ")
+                .push_bind(criteria.before)
+                .push(" AND NOT EXISTS (SELECT 1 FROM failed_events AS f WHERE f.event_id = e.id AND f.resolved_at IS NULL)"),
+        };
+        if let Some(event_type) = &criteria.event_type {
+            builder.push(" AND e.event_type = ").push_bind(event_type);
+        }
+        if !criteria.excluded_event_types.is_empty() {
+            builder
+                .push(" AND e.event_type <> ALL(")
+                .push_bind(&criteria.excluded_event_types)
+                .push(")");
+        }
+        builder
+            .push(" ORDER BY e.created_at, e.id LIMIT ")
+            .push_bind(limit);
+
+        builder
+            .build_query_as::<PublishedEvent>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(VentrixError::from)
+    }
+
+    async fn delete_events(&self, event_ids: &[Uuid]) -> Result<DeleteDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "delete_events");
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("DELETE FROM failed_events WHERE event_id = ANY($1)")
+            .bind(event_ids)
+            .execute(&mut *transaction)
+            .await?;
+        let response = sqlx::query("DELETE FROM events_published WHERE id = ANY($1)")
+            .bind(event_ids)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+
+        Ok(DeleteDataResponse::Postgres(response.rows_affected()))
+    }
+
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "create_api_key");
+        sqlx::query(&format!(
+            "INSERT INTO api_keys ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            API_KEY_COLUMNS
+        ))
+        .bind(api_key.id)
+        .bind(&api_key.name)
+        .bind(api_key.role)
+        .bind(&api_key.event_types)
+        .bind(&api_key.service_name)
+        .bind(&api_key.key_hash)
+        .bind(api_key.created_at)
+        .bind(api_key.revoked_at)
+        .execute(&self.pool)
+        .await
+        .map_err(
+            |err| match (is_foreign_key_violation(&err), &api_key.service_name) {
+                (true, Some(service_name)) => {
+                    ReferencedEntityMissingError::new("service", service_name).into()
+                }
+                _ => VentrixError::from(err),
+            },
+        )
+        .map(|response| InsertDataResponse::Postgres(response.rows_affected()))
+    }
+
+    async fn find_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "find_api_key");
+        sqlx::query_as::<_, ApiKey>(&format!(
+            "SELECT {} FROM api_keys WHERE key_hash = $1 AND revoked_at IS NULL",
+            API_KEY_COLUMNS
+        ))
+        .bind(key_hash)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(VentrixError::from)
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "list_api_keys");
+        sqlx::query_as::<_, ApiKey>(&format!(
+            "SELECT {} FROM api_keys ORDER BY created_at, id",
+            API_KEY_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(VentrixError::from)
+    }
+
+    async fn revoke_api_key(&self, key_id: Uuid) -> Result<ApiKey, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "revoke_api_key");
+        sqlx::query_as::<_, ApiKey>(&format!(
+            "UPDATE api_keys SET revoked_at = COALESCE(revoked_at, $1) WHERE id = $2 RETURNING {}",
+            API_KEY_COLUMNS
+        ))
+        .bind(self.clock.now())
+        .bind(key_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| ApiKeyNotFoundError::new(&key_id.to_string()).into())
+    }
+
+    async fn record_publish_denial(
+        &self,
+        denial: &PublishDenial,
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "record_publish_denial");
+        sqlx::query(&format!(
+            "INSERT INTO publish_denials ({}) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            PUBLISH_DENIAL_COLUMNS
+        ))
+        .bind(denial.id)
+        .bind(&denial.event_type)
+        .bind(denial.api_key_id)
+        .bind(&denial.api_key_name)
+        .bind(&denial.service_name)
+        .bind(&denial.reason)
+        .bind(denial.attempted_at)
+        .execute(&self.pool)
+        .await
+        .map_err(VentrixError::from)
+        .map(|response| InsertDataResponse::Postgres(response.rows_affected()))
+    }
+
+    async fn list_publish_denials(&self, limit: i64) -> Result<Vec<PublishDenial>, VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "list_publish_denials");
+        sqlx::query_as::<_, PublishDenial>(&format!(
+            "SELECT {} FROM publish_denials ORDER BY attempted_at DESC, id LIMIT $1",
+            PUBLISH_DENIAL_COLUMNS
+        ))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(VentrixError::from)
+    }
+
+    async fn check_health(&self) -> Result<(), VentrixError> {
+        let _timer = METRICS.db_query_timer("postgres", "check_health");
+        let applied: Vec<i64> =
+            sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+                .fetch_all(&self.pool)
+                .await?;
+        let pending: Vec<i64> = MIGRATOR
+            .iter()
+            .filter(|migration| !migration.migration_type.is_down_migration())
+            .map(|migration| migration.version)
+            .filter(|version| !applied.contains(version))
+            .collect();
+
+        match pending.is_empty() {
+            true => Ok(()),
+            false => Err(PendingMigrationsError::new(&pending).into()),
+        }
     }
 }
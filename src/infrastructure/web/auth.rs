@@ -0,0 +1,102 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::AUTHORIZATION,
+    Error, FromRequest, HttpMessage, HttpRequest,
+};
+
+use crate::{
+    common::errors::{UnauthorizedError, VentrixError},
+    infrastructure::authentication::{Authenticator, Caller},
+};
+
+impl FromRequest for Caller {
+    type Error = VentrixError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Caller>()
+                .cloned()
+                .ok_or_else(|| UnauthorizedError::new("The request is not authenticated").into()),
+        )
+    }
+}
+
+// Rejects requests without a valid `Authorization: Bearer <key>` header with 401 and hands the
+// key of the others to the handlers as their Caller
+#[derive(Clone)]
+pub struct ApiKeyAuthentication {
+    authenticator: Authenticator,
+}
+
+impl ApiKeyAuthentication {
+    pub fn new(authenticator: Authenticator) -> Self {
+        Self { authenticator }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ApiKeyAuthentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ApiKeyAuthenticationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ApiKeyAuthenticationMiddleware {
+            service: Rc::new(service),
+            authenticator: self.authenticator.clone(),
+        }))
+    }
+}
+
+pub struct ApiKeyAuthenticationMiddleware<S> {
+    service: Rc<S>,
+    authenticator: Authenticator,
+}
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+impl<S, B> Service<ServiceRequest> for ApiKeyAuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let authenticator = self.authenticator.clone();
+        Box::pin(async move {
+            let authorization = req
+                .headers()
+                .get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            match authenticator.authenticate(authorization.as_deref()).await {
+                Ok(caller) => {
+                    req.extensions_mut().insert(caller);
+                    let response = service.call(req).await?;
+                    Ok(response.map_into_left_body())
+                }
+                Err(err) => Ok(req.error_response(err).map_into_right_body()),
+            }
+        })
+    }
+}
@@ -1,2 +1,4 @@
+pub mod auth;
 pub mod routes;
 pub mod startup;
+pub mod tls;
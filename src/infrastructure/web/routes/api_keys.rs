@@ -0,0 +1,55 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        api_keys::new_api_key,
+        clock::Clock,
+        errors::VentrixError,
+        types::{CreateApiKeyRequest, CreatedApiKey},
+    },
+    infrastructure::{authentication::Caller, persistence::Database},
+};
+
+// Stores a new API key and returns it along with the key, which is not stored
+#[tracing::instrument(
+    name = "Creating an API key",
+    skip(create_request, database, clock, caller),
+    fields(name = %create_request.name, role = ?create_request.role)
+)]
+pub async fn create_api_key(
+    create_request: web::Json<CreateApiKeyRequest>,
+    database: web::Data<dyn Database>,
+    clock: web::Data<dyn Clock>,
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    caller.require_admin()?;
+    let (api_key, key) = new_api_key(create_request.into_inner(), clock.now())?;
+    database.get_ref().create_api_key(&api_key).await?;
+
+    Ok(HttpResponse::Created().json(CreatedApiKey { api_key, key }))
+}
+
+#[tracing::instrument(name = "Listing API keys", skip(database, caller))]
+pub async fn list_api_keys(
+    database: web::Data<dyn Database>,
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    caller.require_admin()?;
+    let api_keys = database.get_ref().list_api_keys().await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "api_keys": api_keys })))
+}
+
+#[tracing::instrument(name = "Revoking an API key", skip(database, caller))]
+pub async fn revoke_api_key(
+    key_id: web::Path<Uuid>,
+    database: web::Data<dyn Database>,
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    caller.require_admin()?;
+    let api_key = database.get_ref().revoke_api_key(*key_id).await?;
+
+    Ok(HttpResponse::Ok().json(api_key))
+}
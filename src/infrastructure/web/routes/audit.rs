@@ -0,0 +1,22 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+
+use crate::{
+    common::{errors::VentrixError, types::PublishDenialQuery},
+    infrastructure::{authentication::Caller, persistence::Database},
+};
+
+#[tracing::instrument(name = "Listing refused publishes", skip(database, caller))]
+pub async fn list_publish_denials(
+    query: web::Query<PublishDenialQuery>,
+    database: web::Data<dyn Database>,
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    caller.require_admin()?;
+    let denials = database
+        .get_ref()
+        .list_publish_denials(query.limit())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "publish_denials": denials })))
+}
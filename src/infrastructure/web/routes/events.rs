@@ -1,15 +1,16 @@
 use crate::application::queue_service::ventrix_queue::VentrixQueue;
-use crate::common::errors::InvalidPropertyDef;
-use crate::common::schema_validator::is_valid_property_def;
+use crate::common::errors::{InvalidPropertyDef, VentrixError};
+use crate::common::metrics::METRICS;
+use crate::common::schema_validator::{is_valid_property_def, validate_payload};
 use crate::common::types::{
-    FeatureFlagConfig, ListenToEventReq, ListenToEventResponse, NewEventTypeRequest,
-    PublishEventRequest, VentrixEvent,
+    EventHistory, EventQuery, FeatureFlagConfig, ListenToEventReq, ListenToEventResponse,
+    NewEventTypeRequest, PublishEventRequest, TraceContext, VentrixEvent,
 };
+use crate::infrastructure::authentication::Caller;
 use crate::infrastructure::persistence::Database;
 use actix_web::{web, HttpResponse};
 use serde_json::{json, Value};
 use uuid::Uuid;
-use valico::json_schema::Scope;
 
 fn set_payload(
     feature_flags: web::Data<FeatureFlagConfig>,
@@ -27,6 +28,7 @@ fn set_payload(
 
 #[tracing::instrument(
     name = "Registering new event type",
+    skip(caller),
     fields (
         %event_type_to_register.name,
         %event_type_to_register.description,
@@ -37,163 +39,126 @@ pub async fn register_new_event_type(
     event_type_to_register: web::Json<NewEventTypeRequest>,
     database: web::Data<dyn Database>,
     feature_flags: web::Data<FeatureFlagConfig>,
-) -> HttpResponse {
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    caller.require_admin()?;
     let database = database.get_ref();
 
-    match set_payload(
+    set_payload(
         feature_flags,
         event_type_to_register.payload_definition.clone(),
-    ) {
-        Ok(_) => {
-            let database_response = database.register_event_type(&event_type_to_register).await;
-
-            match database_response {
-                Ok(_) => {
-                    let json_response = json!(
-                        {
-                            "name": event_type_to_register.name,
-                            "description" : event_type_to_register.description,
-                            "payload_description": event_type_to_register.payload_definition
-                        }
-                    );
-                    HttpResponse::Created().json(json_response)
-                }
-                Err(err) => HttpResponse::BadRequest().json(err.to_string()),
-            }
-        }
-        Err(err) => {
-            let response = json!({
-                "message": "Issue validating payload",
-                "error": err.to_string()
-            });
-            HttpResponse::BadRequest().json(response)
+    )?;
+
+    database
+        .register_event_type(&event_type_to_register)
+        .await?;
+
+    let json_response = json!(
+        {
+            "name": event_type_to_register.name,
+            "description" : event_type_to_register.description,
+            "payload_description": event_type_to_register.payload_definition,
+            "publishers": event_type_to_register.publishers
         }
-    }
+    );
+    Ok(HttpResponse::Created().json(json_response))
 }
 
-#[tracing::instrument(name = "Listening to event type", fields())]
+#[tracing::instrument(name = "Listening to event type", skip(caller), fields())]
 pub async fn listen_to_event(
     listen_request: web::Json<ListenToEventReq>,
-    database: web::Data<dyn Database>,
-) -> HttpResponse {
-    match database
-        .get_ref()
-        .register_service_for_event_type(&listen_request)
-        .await
-    {
-        Ok(_) => HttpResponse::Created().json(ListenToEventResponse {
-            message: format!(
-                "Service {} successfully registered to listen to event type {}",
-                &listen_request.service_name, &listen_request.event_type
-            ),
-        }),
-        Err(err) => {
-            let err_string = err.to_string();
-            match err.downcast::<sqlx::Error>().is_ok_and(|sqlx_err| {
-                if let sqlx::Error::RowNotFound = *sqlx_err {
-                    return true;
-                }
-                false
-            }) {
-                true => {
-                    let response = json!({
-                        "message": "RowNotFound error returned from server. Is the event_type in your request spelt correctly?"
-                    });
-                    HttpResponse::BadRequest().json(response)
-                }
-                false => HttpResponse::InternalServerError().json(err_string),
-            }
-        }
-    }
+    queue: web::Data<VentrixQueue>,
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    caller.require_service(&listen_request.service_name)?;
+    let listen_request = listen_request.into_inner();
+    let message = format!(
+        "Service {} successfully registered to listen to event type {}",
+        &listen_request.service_name, &listen_request.event_type
+    );
+    queue.listen_to_event(listen_request).await?;
+
+    Ok(HttpResponse::Created().json(ListenToEventResponse { message }))
 }
 
-#[tracing::instrument(name = "Publishing event")]
+#[tracing::instrument(name = "Publishing event", skip(caller))]
 pub async fn publish_event(
     publish_event_req: web::Json<PublishEventRequest>,
     queue: web::Data<VentrixQueue>,
     database: web::Data<dyn Database>,
-) -> HttpResponse {
-    let queue = queue.get_ref();
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    let publish_event_req = publish_event_req.into_inner();
+    let event_type = publish_event_req.event_type.clone();
 
+    // The caller is authorized before the schema is looked up, so a refused caller is told so
+    // whether or not the event type is registered. The schema is still looked up so only
+    // registered event types become metric labels.
+    let authorized = queue.authorize_publish(&caller.0, &event_type).await;
+    let schema = database.get_schema_for_event_type(&event_type).await;
+    let registered_event_type = schema.is_ok().then_some(event_type.as_str());
+    let result = match (authorized, schema) {
+        (Ok(()), Ok(schema)) => {
+            publish(
+                publish_event_req,
+                schema.payload_definition,
+                queue.get_ref(),
+                database.get_ref(),
+            )
+            .await
+        }
+        (Err(err), _) | (Ok(()), Err(err)) => Err(err),
+    };
+    METRICS.record_publish(registered_event_type, &result);
+    result?;
+
+    Ok(HttpResponse::Created().finish())
+}
+
+async fn publish(
+    publish_event_req: PublishEventRequest,
+    schema: String,
+    queue: &VentrixQueue,
+    database: &dyn Database,
+) -> Result<(), VentrixError> {
     let event = VentrixEvent {
         id: Uuid::new_v4(),
-        event_type: publish_event_req.event_type.clone(),
-        payload: publish_event_req.payload.clone(),
+        event_type: publish_event_req.event_type,
+        payload: publish_event_req.payload,
         retry_details: None,
+        trace_context: TraceContext::current(),
     };
 
-    if let Err(err) = database.save_published_event(&event).await {
-        let response = json!({
-            "message": err.to_string()
-        });
-        return HttpResponse::InternalServerError().json(response);
-    };
+    validate_payload(&event.payload, &schema)
+        .inspect_err(|_| METRICS.record_schema_validation_failure(&event.event_type))?;
 
-    let schema = match database
-        .get_schema_for_event_type(&publish_event_req.event_type)
-        .await
-    {
-        Ok(schema_obj) => schema_obj.payload_definition,
-        Err(err) => {
-            let response = json!({
-                "message": "Unable to get schema string for event type",
-                "err": err.to_string()
-            });
-            return HttpResponse::BadRequest().json(response);
-        }
-    };
+    database.save_published_event(&event).await?;
 
-    let schema_value: Value = match serde_json::from_str(&schema) {
-        Ok(schema_as_value) => schema_as_value,
-        Err(err) => {
-            let response = json!({
-                "message": "Couldn't parse Value from schema string",
-                "err": err.to_string(),
-            });
-            return HttpResponse::BadRequest().json(response);
-        }
-    };
+    queue.publish_event(event).await
+}
 
-    let mut scope = Scope::new();
-    let scoped_schema = match scope.compile_and_return(schema_value.clone(), false) {
-        Ok(scoped_schema) => scoped_schema,
-        Err(err) => {
-            let response = json!({
-                "message": "Couldn't compile scoped schema from schema value",
-                "err": err.to_string()
-            });
-            return HttpResponse::BadRequest().json(response);
-        }
-    };
+#[tracing::instrument(name = "Listing events", skip(database, caller))]
+pub async fn list_events(
+    query: web::Query<EventQuery>,
+    database: web::Data<dyn Database>,
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    caller.require_admin()?;
+    let events = database.get_ref().list_events(&query).await?;
 
-    let payload_value: Value = match serde_json::from_str(&event.payload) {
-        Ok(payload_as_value) => payload_as_value,
-        Err(err) => {
-            let response = json!({
-                "message": "Couldn't parse Value from payload string",
-                "err": err.to_string()
-            });
-            return HttpResponse::BadRequest().json(response);
-        }
-    };
+    Ok(HttpResponse::Ok().json(json!({ "events": events })))
+}
 
-    let validation = scoped_schema.validate(&payload_value);
-
-    if !validation.is_strictly_valid() {
-        let response = json!({
-            "message": "Payload did not match the event type payload definition",
-            "expected_payload_schema": schema_value
-        });
-        HttpResponse::BadRequest().json(response)
-    } else {
-        match queue.publish_event(event).await {
-            Ok(_) => HttpResponse::Created().finish(),
-            Err(err) => {
-                let response = json!({
-                    "message": err.to_string()
-                });
-                HttpResponse::InternalServerError().json(response)
-            }
-        }
-    }
+#[tracing::instrument(name = "Getting event history", skip(database, caller))]
+pub async fn get_event(
+    event_id: web::Path<Uuid>,
+    database: web::Data<dyn Database>,
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    caller.require_admin()?;
+    let database = database.get_ref();
+    let event = database.get_event(*event_id).await?;
+    let deliveries = database.get_delivery_attempts(*event_id).await?;
+
+    Ok(HttpResponse::Ok().json(EventHistory { event, deliveries }))
 }
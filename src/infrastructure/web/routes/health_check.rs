@@ -1,5 +1,78 @@
-use actix_web::HttpResponse;
+use std::time::Duration;
 
-pub async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().finish()
+use actix_web::{web, HttpResponse};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    application::queue_service::ventrix_queue::VentrixQueue, infrastructure::persistence::Database,
+};
+
+// A database that takes longer than this to answer is treated as down
+const DATABASE_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub async fn health_check(ventrix_queue: web::Data<VentrixQueue>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "queue": {
+            "depth": ventrix_queue.depth(),
+            "capacity": ventrix_queue.capacity(),
+        }
+    }))
+}
+
+// The process is up and serving requests
+pub async fn liveness() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+// Whether this instance should receive traffic, with the state of every component it needs
+#[tracing::instrument(name = "Checking readiness", skip_all)]
+pub async fn readiness(
+    database: web::Data<dyn Database>,
+    ventrix_queue: web::Data<VentrixQueue>,
+) -> HttpResponse {
+    let mut components = Map::new();
+
+    let database_health =
+        match tokio::time::timeout(DATABASE_CHECK_TIMEOUT, database.check_health()).await {
+            Ok(Ok(_)) => component(true),
+            Ok(Err(err)) => component_error(err.to_string()),
+            Err(_) => component_error("Timed out waiting for the database".to_string()),
+        };
+    components.insert("database".to_string(), database_health);
+
+    for task in ventrix_queue.background_tasks() {
+        let mut task_health = component(task.is_running());
+        task_health["restarts"] = json!(task.restarts());
+        components.insert(task.name().to_string(), task_health);
+    }
+
+    let mut queue_health = component(!ventrix_queue.is_backlogged());
+    queue_health["depth"] = json!(ventrix_queue.depth());
+    queue_health["threshold"] = json!(ventrix_queue.readiness_depth_threshold());
+    components.insert("queue".to_string(), queue_health);
+
+    let ready = components
+        .values()
+        .all(|component| component["status"] == "up");
+    let body = json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "components": components,
+    });
+
+    match ready {
+        true => HttpResponse::Ok().json(body),
+        false => {
+            tracing::warn!("Instance is not ready: {}", body);
+            HttpResponse::ServiceUnavailable().json(body)
+        }
+    }
+}
+
+fn component(up: bool) -> Value {
+    json!({ "status": if up { "up" } else { "down" } })
+}
+
+fn component_error(error: String) -> Value {
+    json!({ "status": "down", "error": error })
 }
@@ -0,0 +1,12 @@
+use actix_web::{web, HttpResponse};
+use prometheus::TEXT_FORMAT;
+
+use crate::{application::queue_service::ventrix_queue::VentrixQueue, common::metrics::METRICS};
+
+pub async fn metrics(ventrix_queue: web::Data<VentrixQueue>) -> HttpResponse {
+    METRICS.set_queue_depth(ventrix_queue.depth());
+
+    HttpResponse::Ok()
+        .content_type(TEXT_FORMAT)
+        .body(METRICS.render())
+}
@@ -1,6 +1,10 @@
+pub mod api_keys;
+pub mod audit;
 pub mod events;
 pub mod health_check;
+pub mod metrics;
 pub mod queue;
+pub mod replays;
 pub mod services;
 
 pub use health_check::*;
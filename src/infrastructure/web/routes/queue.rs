@@ -1,7 +1,8 @@
 use actix_web::{web, HttpResponse};
 
 use crate::{
-    application::queue_service::ventrix_queue::VentrixQueue, common::types::VentrixEvent,
+    application::queue_service::ventrix_queue::VentrixQueue,
+    common::{errors::VentrixError, types::VentrixEvent},
     infrastructure::persistence::Database,
 };
 
@@ -16,18 +17,12 @@ pub async fn enqueue_event(
     event: web::Json<VentrixEvent>,
     ventrix_queue: web::Data<VentrixQueue>,
     database: web::Data<dyn Database>,
-) -> HttpResponse {
+) -> Result<HttpResponse, VentrixError> {
     tracing::info!("Adding event to the queue: {:?}", event);
-    // TODO: Keep this logic encapsulated within the Ventrix queue
-    match ventrix_queue
+    ventrix_queue
         .get_ref()
-        .sender
-        .send(event.into_inner())
-        .await
-    {
-        Ok(_) => HttpResponse::Created().finish(),
-        Err(_) => HttpResponse::InternalServerError()
-            .reason("Unable to add event to queue")
-            .finish(),
-    }
+        .publish_event(event.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Created().finish())
 }
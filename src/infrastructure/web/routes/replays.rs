@@ -0,0 +1,46 @@
+use actix_web::{web, HttpResponse};
+use uuid::Uuid;
+
+use crate::{
+    application::queue_service::ventrix_queue::VentrixQueue,
+    common::{errors::VentrixError, types::ReplayRequest},
+    infrastructure::{authentication::Caller, persistence::Database},
+};
+
+#[tracing::instrument(name = "Starting a replay", skip(queue, caller))]
+pub async fn start_replay(
+    replay_request: web::Json<ReplayRequest>,
+    queue: web::Data<VentrixQueue>,
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    caller.require_service(&replay_request.service_name)?;
+    let job = queue.start_replay(replay_request.into_inner()).await?;
+
+    Ok(HttpResponse::Accepted().json(job))
+}
+
+#[tracing::instrument(name = "Getting a replay", skip(database, caller))]
+pub async fn get_replay(
+    replay_id: web::Path<Uuid>,
+    database: web::Data<dyn Database>,
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    let job = database.get_ref().get_replay_job(*replay_id).await?;
+    caller.require_service(&job.service_name)?;
+
+    Ok(HttpResponse::Ok().json(job))
+}
+
+#[tracing::instrument(name = "Cancelling a replay", skip(database, caller))]
+pub async fn cancel_replay(
+    replay_id: web::Path<Uuid>,
+    database: web::Data<dyn Database>,
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    let database = database.get_ref();
+    let job = database.get_replay_job(*replay_id).await?;
+    caller.require_service(&job.service_name)?;
+    let job = database.cancel_replay_job(*replay_id).await?;
+
+    Ok(HttpResponse::Ok().json(job))
+}
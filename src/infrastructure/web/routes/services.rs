@@ -2,14 +2,17 @@ use actix_web::{web, HttpResponse};
 use serde_json::json;
 
 use crate::{
-    common::types::ServiceDetails, domain::models::service::RegisterServiceRequest,
-    infrastructure::persistence::Database,
+    application::queue_service::ventrix_queue::VentrixQueue,
+    common::{errors::VentrixError, types::ServiceDetails},
+    domain::models::service::RegisterServiceRequest,
+    infrastructure::{authentication::Caller, persistence::Database},
 };
 
 use super::DeleteServiceRequest;
 
 #[tracing::instrument(
     name = "Registering a new service",
+    skip(caller),
     fields(
         name = %reg_service_req.name,
         url = %reg_service_req.url
@@ -17,29 +20,25 @@ use super::DeleteServiceRequest;
 )]
 pub async fn register_service(
     reg_service_req: web::Json<RegisterServiceRequest>,
-    database: web::Data<dyn Database>,
-) -> HttpResponse {
+    queue: web::Data<VentrixQueue>,
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    caller.require_admin()?;
     let reg_service_req = reg_service_req.into_inner();
-    tracing::info!("Getting reference to database...");
-    let database = database.get_ref();
-    tracing::info!("Reference to database received!");
-    match database.register_service(&reg_service_req).await {
-        Ok(_) => {
-            let response = json!({
-                "name": reg_service_req.name,
-                "service_details": ServiceDetails {
-                    endpoint: reg_service_req.url
-                }
-            })
-            .to_string();
-            HttpResponse::Created().json(response)
+    let response = json!({
+        "name": reg_service_req.name,
+        "service_details": ServiceDetails {
+            endpoint: reg_service_req.url.clone()
         }
-        Err(err) => HttpResponse::BadRequest().json(err.to_string()),
-    }
+    });
+    queue.register_service(reg_service_req).await?;
+
+    Ok(HttpResponse::Created().json(response))
 }
 
 #[tracing::instrument(
     name = "Removing a service",
+    skip(caller),
     fields(
         name = %delete_service_req.name,
     )
@@ -47,19 +46,28 @@ pub async fn register_service(
 pub async fn remove_service(
     delete_service_req: web::Json<DeleteServiceRequest>,
     database: web::Data<dyn Database>,
-) -> HttpResponse {
-    match database
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    caller.require_admin()?;
+    database
         .get_ref()
         .remove_service(&delete_service_req.name)
-        .await
-    {
-        Ok(_) => {
-            let response = json!({
-                "message": format!("Record successfully deleted for service: {}", delete_service_req.name)
-            })
-            .to_string();
-            HttpResponse::NoContent().json(response)
-        }
-        Err(err) => HttpResponse::InternalServerError().json(err.to_string()),
-    }
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[tracing::instrument(
+    name = "Getting the subscriptions of a service",
+    skip(database, caller)
+)]
+pub async fn get_subscriptions(
+    service_name: web::Path<String>,
+    database: web::Data<dyn Database>,
+    caller: Caller,
+) -> Result<HttpResponse, VentrixError> {
+    caller.require_service(&service_name)?;
+    let subscriptions = database.get_ref().get_subscriptions(&service_name).await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "subscriptions": subscriptions })))
 }
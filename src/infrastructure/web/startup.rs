@@ -1,51 +1,112 @@
-use std::net::TcpListener;
+use std::{net::TcpListener, sync::Arc};
 
 use actix_web::{
-    dev::Server,
+    dev::{Server, Service},
     web::{self, Data},
     App, HttpServer,
 };
 use tracing_actix_web::TracingLogger;
 
 use crate::{
-    application::queue_service::ventrix_queue::VentrixQueue, common::types::FeatureFlagConfig,
-    infrastructure::persistence::Database,
+    application::queue_service::ventrix_queue::VentrixQueue,
+    common::{clock::Clock, configuration::ApiTlsSettings, types::FeatureFlagConfig},
+    infrastructure::{authentication::Authenticator, persistence::Database},
 };
 
-use super::routes::{events, health_check, services};
+use super::{
+    auth::ApiKeyAuthentication,
+    routes::{
+        api_keys, audit, events, health_check, liveness, metrics, readiness, replays, services,
+    },
+    tls,
+};
 
 pub async fn run(
     listener: TcpListener,
     database: web::Data<dyn Database>,
+    ventrix_queue: web::Data<VentrixQueue>,
+    clock: Arc<dyn Clock>,
     feature_flags: FeatureFlagConfig,
+    tls_settings: Option<&ApiTlsSettings>,
+    authenticator: Authenticator,
 ) -> Result<Server, std::io::Error> {
-    let ventrix_queue = VentrixQueue::new(database.clone()).await;
-    let ventrix_queue = web::Data::new(ventrix_queue);
     let feature_flags = web::Data::new(feature_flags);
+    let clock: web::Data<dyn Clock> = web::Data::from(clock);
+    let requires_client_certificate =
+        tls_settings.is_some_and(|tls_settings| tls_settings.client_ca_path.is_some());
 
     let server = HttpServer::new(move || {
         App::new()
             .wrap(TracingLogger::default())
             .route("/health_check", web::get().to(health_check))
+            .service(
+                web::scope("/health")
+                    .route("/live", web::get().to(liveness))
+                    .route("/ready", web::get().to(readiness)),
+            )
+            .route("/metrics", web::get().to(metrics::metrics))
             .service(
                 web::scope("/api")
+                    .wrap(ApiKeyAuthentication::new(authenticator.clone()))
                     .service(
                         web::scope("/service")
                             .route("/register", web::post().to(services::register_service))
-                            .route("/remove", web::post().to(services::remove_service)),
+                            .route("/remove", web::post().to(services::remove_service))
+                            .route(
+                                "/{service_name}/subscriptions",
+                                web::get().to(services::get_subscriptions),
+                            ),
                     )
                     .service(
                         web::scope("/events")
+                            .route("", web::get().to(events::list_events))
                             .route("/register", web::post().to(events::register_new_event_type))
-                            .route("/publish", web::post().to(events::publish_event))
-                            .route("/listen", web::post().to(events::listen_to_event)),
-                    ),
+                            .service(
+                                web::resource("/publish")
+                                    .wrap_fn(move |req, srv| {
+                                        let response = tls::check_client_certificate(
+                                            &req,
+                                            requires_client_certificate,
+                                        )
+                                        .map(|()| srv.call(req));
+                                        async move { response?.await }
+                                    })
+                                    .route(web::post().to(events::publish_event)),
+                            )
+                            .route("/listen", web::post().to(events::listen_to_event))
+                            .route("/{event_id}", web::get().to(events::get_event)),
+                    )
+                    .service(
+                        web::scope("/replays")
+                            .route("", web::post().to(replays::start_replay))
+                            .route("/{replay_id}", web::get().to(replays::get_replay))
+                            .route(
+                                "/{replay_id}/cancel",
+                                web::post().to(replays::cancel_replay),
+                            ),
+                    )
+                    .service(
+                        web::scope("/keys")
+                            .route("", web::post().to(api_keys::create_api_key))
+                            .route("", web::get().to(api_keys::list_api_keys))
+                            .route("/{key_id}/revoke", web::post().to(api_keys::revoke_api_key)),
+                    )
+                    .service(web::scope("/audit").route(
+                        "/publish_denials",
+                        web::get().to(audit::list_publish_denials),
+                    )),
             )
             .app_data(database.clone())
             .app_data(Data::clone(&ventrix_queue))
+            .app_data(Data::clone(&clock))
             .app_data(Data::clone(&feature_flags))
     })
-    .listen(listener)?
+    .on_connect(tls::on_connect);
+    // Over TLS, clients that offer HTTP/2 through ALPN are served with it
+    let server = match tls_settings {
+        Some(tls_settings) => server.listen_rustls(listener, tls::server_config(tls_settings)?)?,
+        None => server.listen(listener)?,
+    }
     .run();
 
     Ok(server)
@@ -0,0 +1,176 @@
+use std::{
+    any::Any,
+    fs::File,
+    io::{self, BufReader},
+    sync::{Arc, RwLock, Weak},
+    time::{Duration, SystemTime},
+};
+
+use actix_tls::accept::rustls_0_20::TlsStream;
+use actix_web::{
+    dev::{Extensions, ServiceRequest},
+    rt::net::TcpStream,
+};
+use rustls::{
+    server::{AllowAnyAnonymousOrAuthenticatedClient, ClientHello, ResolvesServerCert},
+    sign::{self, CertifiedKey},
+    Certificate, PrivateKey, RootCertStore, ServerConfig,
+};
+use rustls_pemfile::Item;
+
+use crate::common::{
+    configuration::ApiTlsSettings,
+    errors::{ForbiddenError, VentrixError},
+};
+
+// Builds the TLS configuration of the API listener. The certificate is reloaded whenever its
+// files change, so renewing it does not need a restart. With a client CA, clients may still
+// connect without a certificate; only publishing requires one, see ClientCertificate.
+pub fn server_config(settings: &ApiTlsSettings) -> io::Result<ServerConfig> {
+    let resolver = Arc::new(ReloadingCertificate::load(settings)?);
+    tokio::spawn(watch_certificate(
+        Arc::downgrade(&resolver),
+        settings.clone(),
+    ));
+
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let config = match &settings.client_ca_path {
+        Some(client_ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in read_certificates(client_ca_path)? {
+                roots.add(&certificate).map_err(|err| {
+                    invalid_data(format!("Invalid client CA in {}: {}", client_ca_path, err))
+                })?;
+            }
+            builder
+                .with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots))
+                .with_cert_resolver(resolver)
+        }
+        None => builder.with_no_client_auth().with_cert_resolver(resolver),
+    };
+    Ok(config)
+}
+
+// Present in the connection data of requests whose client presented a certificate. The
+// handshake fails for certificates the client CA did not issue, so any one here is trusted.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientCertificate;
+
+pub fn on_connect(connection: &dyn Any, data: &mut Extensions) {
+    let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+    let (_, session) = stream.get_ref();
+    if session
+        .peer_certificates()
+        .is_some_and(|certificates| !certificates.is_empty())
+    {
+        data.insert(ClientCertificate);
+    }
+}
+
+// Refuses the request when a certificate is required but the client did not present one
+pub fn check_client_certificate(req: &ServiceRequest, required: bool) -> Result<(), VentrixError> {
+    match required && req.conn_data::<ClientCertificate>().is_none() {
+        true => Err(ForbiddenError::new("Publishing requires a client certificate").into()),
+        false => Ok(()),
+    }
+}
+
+struct ReloadingCertificate {
+    certified_key: RwLock<Arc<CertifiedKey>>,
+    modified: RwLock<Option<SystemTime>>,
+}
+
+impl ReloadingCertificate {
+    fn load(settings: &ApiTlsSettings) -> io::Result<Self> {
+        Ok(Self {
+            certified_key: RwLock::new(Arc::new(certified_key(settings)?)),
+            modified: RwLock::new(last_modified(settings)),
+        })
+    }
+
+    // Reloads the certificate when its files changed since the last load. A certificate that
+    // cannot be loaded is logged and the current one kept.
+    fn reload_if_changed(&self, settings: &ApiTlsSettings) {
+        let modified = last_modified(settings);
+        if *self.modified.read().unwrap() == modified {
+            return;
+        }
+        match certified_key(settings) {
+            Ok(certified_key) => {
+                tracing::info!(
+                    "Reloaded the API certificate from {}",
+                    settings.certificate_path
+                );
+                *self.certified_key.write().unwrap() = Arc::new(certified_key);
+                *self.modified.write().unwrap() = modified;
+            }
+            Err(err) => tracing::error!("Could not reload the API certificate: {}", err),
+        }
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertificate {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(&self.certified_key.read().unwrap()))
+    }
+}
+
+// Stops once the server holding the certificate is gone
+async fn watch_certificate(certificate: Weak<ReloadingCertificate>, settings: ApiTlsSettings) {
+    let mut interval =
+        tokio::time::interval(Duration::from_millis(settings.reload_interval_milliseconds));
+    loop {
+        interval.tick().await;
+        let Some(certificate) = certificate.upgrade() else {
+            return;
+        };
+        certificate.reload_if_changed(&settings);
+    }
+}
+
+fn certified_key(settings: &ApiTlsSettings) -> io::Result<CertifiedKey> {
+    let certificates = read_certificates(&settings.certificate_path)?;
+    let key = read_private_key(&settings.key_path)?;
+    let key = sign::any_supported_type(&key).map_err(|err| {
+        invalid_data(format!("Unsupported key in {}: {}", settings.key_path, err))
+    })?;
+    Ok(CertifiedKey::new(certificates, key))
+}
+
+fn read_certificates(path: &str) -> io::Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certificates: Vec<Certificate> = rustls_pemfile::certs(&mut reader)?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    if certificates.is_empty() {
+        return Err(invalid_data(format!("No certificate found in {}", path)));
+    }
+    Ok(certificates)
+}
+
+fn read_private_key(path: &str) -> io::Result<PrivateKey> {
+    let mut reader = BufReader::new(File::open(path)?);
+    rustls_pemfile::read_all(&mut reader)?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| invalid_data(format!("No private key found in {}", path)))
+}
+
+fn last_modified(settings: &ApiTlsSettings) -> Option<SystemTime> {
+    let modified = |path: &str| {
+        std::fs::metadata(path)
+            .and_then(|file| file.modified())
+            .ok()
+    };
+    modified(&settings.certificate_path).max(modified(&settings.key_path))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -1,10 +1,5 @@
-pub mod common;
-
 use actix_web::web;
-use common::configuration::get_configuration;
-use common::telemetry::{get_subscriber, init_tracing_subscriber};
-use common::types::FeatureFlagConfig;
-use secrecy::ExposeSecret;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::collections::HashMap;
@@ -12,14 +7,25 @@ use std::net::TcpListener;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::sleep;
+use ventrix::application::queue_service::ventrix_queue::VentrixQueue;
+use ventrix::common::clock::{Clock, SystemClock};
+use ventrix::common::configuration::{get_configuration, DispatchMode};
+use ventrix::common::telemetry::{get_subscriber, get_tracer, init_tracing_subscriber};
+use ventrix::common::types::FeatureFlagConfig;
+use ventrix::infrastructure::authentication::Authenticator;
+use ventrix::infrastructure::grpc::startup::run_grpc;
 use ventrix::infrastructure::persistence::inmemory::InMemoryDatabase;
-use ventrix::infrastructure::persistence::postgres::PostgresDatabase;
+use ventrix::infrastructure::persistence::notifier::PostgresNotifier;
+use ventrix::infrastructure::persistence::postgres::{PostgresDatabase, MIGRATOR};
 use ventrix::infrastructure::persistence::Database;
 use ventrix::infrastructure::web::startup::run;
 
 #[tokio::main]
 async fn main() -> Result<(), std::io::Error> {
-    let subscriber = get_subscriber("ventrix".into(), "info".into(), std::io::stdout);
+    let configuration = get_configuration().expect("Failed to read configuration");
+
+    let tracer = get_tracer("ventrix".into(), &configuration.telemetry);
+    let subscriber = get_subscriber("ventrix".into(), "info".into(), std::io::stdout, tracer);
     init_tracing_subscriber(subscriber);
 
     let feature_flags: FeatureFlagConfig = HashMap::from([
@@ -27,8 +33,9 @@ async fn main() -> Result<(), std::io::Error> {
         (String::from("validate_event_def"), true),
     ]);
 
-    let configuration = get_configuration().expect("Failed to read configuration");
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
 
+    let mut postgres_pool = None;
     let database: web::Data<dyn Database> = match feature_flags.get("persistence") {
         Some(persistence_true) => {
             if *persistence_true {
@@ -43,28 +50,112 @@ async fn main() -> Result<(), std::io::Error> {
                     panic!("Received error: {}", err)
                 }
 
-                if let Err(err) = sqlx::migrate!("./migrations").run(&pool).await {
+                if let Err(err) = MIGRATOR.run(&pool).await {
                     panic!("{}", err.to_string())
                 }
-                let db_arc: Arc<dyn Database> = Arc::new(PostgresDatabase::new(pool));
+                postgres_pool = Some(pool.clone());
+                let db_arc: Arc<dyn Database> =
+                    Arc::new(PostgresDatabase::new(pool, clock.clone()));
                 web::Data::from(db_arc)
             } else {
-                let db_arc: Arc<dyn Database> = Arc::new(InMemoryDatabase::default());
+                let db_arc: Arc<dyn Database> = Arc::new(InMemoryDatabase::new(clock.clone()));
                 web::Data::from(db_arc)
             }
         }
         None => {
-            let db_arc: Arc<dyn Database> = Arc::new(InMemoryDatabase::default());
+            let db_arc: Arc<dyn Database> = Arc::new(InMemoryDatabase::new(clock.clone()));
             web::Data::from(db_arc)
         }
     };
 
+    ensure_api_keys_exist(
+        &database,
+        configuration.application.bootstrap_admin_key.as_ref(),
+    )
+    .await?;
+
+    let ventrix_queue = match configuration.queue.dispatch_mode {
+        DispatchMode::InProcess => {
+            VentrixQueue::new(database.clone(), configuration.queue.clone(), clock.clone()).await
+        }
+        DispatchMode::Postgres => {
+            let pool = postgres_pool.expect("Postgres dispatch requires the persistence feature");
+            VentrixQueue::with_postgres_dispatch(
+                database.clone(),
+                configuration.queue.clone(),
+                clock.clone(),
+                PostgresNotifier::new(pool),
+            )
+            .await
+        }
+    };
+    let ventrix_queue = web::Data::new(
+        ventrix_queue
+            .map_err(|err| std::io::Error::other(format!("Invalid queue settings: {}", err)))?,
+    );
+
     let address = format!(
         "{}:{}",
         configuration.application.host, configuration.application.port
     );
     let listener = TcpListener::bind(address)?;
-    run(listener, database, feature_flags).await?.await
+
+    let grpc_address = format!(
+        "{}:{}",
+        configuration.application.host, configuration.application.grpc_port
+    );
+    let grpc_listener = TcpListener::bind(grpc_address)?;
+
+    // Both APIs accept the same keys
+    let authenticator = Authenticator::new(
+        database.clone(),
+        configuration.application.bootstrap_admin_key.as_ref(),
+    );
+
+    let grpc_server = run_grpc(
+        grpc_listener,
+        database.clone(),
+        ventrix_queue.clone(),
+        feature_flags.clone(),
+        configuration.application.tls.as_ref(),
+        authenticator.clone(),
+    )?;
+    let http_server = run(
+        listener,
+        database,
+        ventrix_queue,
+        clock,
+        feature_flags,
+        configuration.application.tls.as_ref(),
+        authenticator,
+    )
+    .await?;
+
+    tokio::select! {
+        result = http_server => result,
+        result = grpc_server => result.map_err(std::io::Error::other),
+    }
+}
+
+// Without a bootstrap admin key or a stored key nobody could use the API, not even to create a
+// key, so the instance refuses to start
+async fn ensure_api_keys_exist(
+    database: &web::Data<dyn Database>,
+    bootstrap_admin_key: Option<&Secret<String>>,
+) -> Result<(), std::io::Error> {
+    if bootstrap_admin_key.is_some_and(|key| !key.expose_secret().trim().is_empty()) {
+        return Ok(());
+    }
+    let api_keys = database
+        .list_api_keys()
+        .await
+        .map_err(|err| std::io::Error::other(err.to_string()))?;
+    match api_keys.iter().any(|api_key| api_key.revoked_at.is_none()) {
+        true => Ok(()),
+        false => Err(std::io::Error::other(
+            "No API keys exist: set APP_APPLICATION__BOOTSTRAP_ADMIN_KEY to create the first",
+        )),
+    }
 }
 
 async fn wait_for_db(connection_string: &str) -> Result<(), sqlx::Error> {
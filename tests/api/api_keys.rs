@@ -0,0 +1,238 @@
+use serde_json::{json, Value};
+
+use crate::{
+    helpers::{spawn_app, spawn_app_with_postgres, TestApp, TEST_EVENT_TYPE},
+    mock_subscriber::MockSubscriber,
+};
+
+// Creates a key with the app's admin key and returns the key and its id
+async fn created_key(app: &TestApp, body: Value) -> (String, String) {
+    let response = app.create_api_key(body).await;
+    assert_eq!(201, response.status());
+    let created: Value = response.json().await.expect("Failed to parse body");
+    (
+        created["key"].as_str().unwrap().to_string(),
+        created["id"].as_str().unwrap().to_string(),
+    )
+}
+
+async fn revoke(app: &TestApp, key_id: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/api/keys/{}/revoke", &app.address, key_id))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn list_keys(app: &TestApp) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/api/keys", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn admin_keys_are_created_used_and_revoked(app: TestApp) {
+    let response = app
+        .create_api_key(json!({ "name": "operator", "role": "admin" }))
+        .await;
+    assert_eq!(201, response.status());
+    let created: Value = response.json().await.expect("Failed to parse body");
+    assert_eq!("operator", created["name"]);
+    assert_eq!("admin", created["role"]);
+    assert!(created["created_at"].is_string());
+    assert!(created["revoked_at"].is_null());
+    assert!(created.get("key_hash").is_none());
+    let key = created["key"].as_str().unwrap();
+    let key_id = created["id"].as_str().unwrap();
+
+    let operator = app.with_api_key(key);
+    assert_eq!(
+        201,
+        operator.register_event_type(TEST_EVENT_TYPE).await.status()
+    );
+
+    // The key is only shown when it is created
+    let response = list_keys(&app).await;
+    assert_eq!(200, response.status());
+    let listed = response.text().await.unwrap();
+    assert!(listed.contains(key_id));
+    assert!(!listed.contains(key));
+    assert!(!listed.contains("key_hash"));
+
+    let response = revoke(&app, key_id).await;
+    assert_eq!(200, response.status());
+    let revoked: Value = response.json().await.expect("Failed to parse body");
+    assert!(revoked["revoked_at"].is_string());
+
+    assert_eq!(
+        401,
+        operator.register_event_type("other_event").await.status()
+    );
+}
+
+#[tokio::test]
+async fn admin_keys_are_created_used_and_revoked_in_memory() {
+    admin_keys_are_created_used_and_revoked(spawn_app().await).await;
+}
+
+#[tokio::test]
+async fn admin_keys_are_created_used_and_revoked_in_postgres() {
+    let Some(app) = spawn_app_with_postgres().await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    admin_keys_are_created_used_and_revoked(app).await;
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_key_return_401() {
+    let app = spawn_app().await;
+    let anonymous = reqwest::Client::new();
+
+    let response = anonymous
+        .post(format!("{}/api/service/remove", &app.address))
+        .json(&json!({ "name": "vinnie" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(401, response.status());
+    assert_eq!("Bearer", response.headers()["WWW-Authenticate"]);
+
+    let response = app
+        .with_api_key("vtx_unknown")
+        .register_event_type(TEST_EVENT_TYPE)
+        .await;
+    assert_eq!(401, response.status());
+    let body: Value = response.json().await.expect("Failed to parse body");
+    assert_eq!("urn:ventrix:error:unauthorized", body["type"]);
+
+    // Health checks stay open
+    let response = anonymous
+        .get(format!("{}/health_check", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status());
+}
+
+#[tokio::test]
+async fn publisher_keys_may_only_publish_their_event_types() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+    assert_eq!(
+        201,
+        app.register_event_type("payments.settled").await.status()
+    );
+    let (key, _) = created_key(
+        &app,
+        json!({ "name": "tests", "role": "publisher", "event_types": [TEST_EVENT_TYPE] }),
+    )
+    .await;
+    let publisher = app.with_api_key(&key);
+
+    let response = publisher
+        .publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    assert_eq!(201, response.status());
+    subscriber.wait_for_deliveries(1).await;
+
+    let response = publisher
+        .publish_event("payments.settled", json!({ "name": "John Rustsworth" }))
+        .await;
+    assert_eq!(403, response.status());
+    let body: Value = response.json().await.expect("Failed to parse body");
+    assert_eq!("urn:ventrix:error:forbidden", body["type"]);
+
+    assert_eq!(
+        403,
+        publisher
+            .register_event_type("orders.created")
+            .await
+            .status()
+    );
+    assert_eq!(403, publisher.get_subscriptions("vinnie").await.status());
+    assert_eq!(
+        403,
+        publisher
+            .create_api_key(json!({ "name": "escalated", "role": "admin" }))
+            .await
+            .status()
+    );
+}
+
+#[tokio::test]
+async fn subscriber_keys_may_only_act_for_their_service() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    assert_eq!(
+        201,
+        app.register_service("vinnie", &subscriber.url)
+            .await
+            .status()
+    );
+    assert_eq!(
+        201,
+        app.register_service("paulie", &subscriber.url)
+            .await
+            .status()
+    );
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    let (key, _) = created_key(
+        &app,
+        json!({ "name": "vinnie", "role": "subscriber", "service_name": "vinnie" }),
+    )
+    .await;
+    let vinnie = app.with_api_key(&key);
+
+    let response = vinnie
+        .listen_to_event("vinnie", TEST_EVENT_TYPE, "/events")
+        .await;
+    assert_eq!(201, response.status());
+    assert_eq!(200, vinnie.get_subscriptions("vinnie").await.status());
+
+    let response = vinnie
+        .listen_to_event("paulie", TEST_EVENT_TYPE, "/events")
+        .await;
+    assert_eq!(403, response.status());
+    assert_eq!(403, vinnie.get_subscriptions("paulie").await.status());
+    assert_eq!(
+        403,
+        vinnie
+            .publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+            .await
+            .status()
+    );
+    assert_eq!(
+        403,
+        vinnie
+            .register_service("tony", &subscriber.url)
+            .await
+            .status()
+    );
+}
+
+#[tokio::test]
+async fn keys_that_do_not_fit_their_role_return_400() {
+    let app = spawn_app().await;
+
+    for body in [
+        json!({ "name": "", "role": "admin" }),
+        json!({ "name": "operator", "role": "admin", "service_name": "vinnie" }),
+        json!({ "name": "orders", "role": "publisher" }),
+        json!({ "name": "orders", "role": "publisher", "event_types": ["orders.cre*"] }),
+        json!({ "name": "vinnie", "role": "subscriber" }),
+    ] {
+        let response = app.create_api_key(body.clone()).await;
+        assert_eq!(400, response.status(), "{}", body);
+    }
+
+    let response = app
+        .create_api_key(json!({ "name": "ghost", "role": "subscriber", "service_name": "ghost" }))
+        .await;
+    assert_eq!(422, response.status());
+
+    let response = revoke(&app, &uuid::Uuid::new_v4().to_string()).await;
+    assert_eq!(404, response.status());
+}
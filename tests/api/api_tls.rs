@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use reqwest::{Certificate, Client, Identity, Version};
+use serde_json::{json, Value};
+use ventrix::common::configuration::ApiTlsSettings;
+
+use crate::{
+    helpers::{client_with_key, spawn_app_with_tls, TEST_ADMIN_KEY, TEST_EVENT_TYPE},
+    test_certificates::TestCertificates,
+};
+
+fn tls_settings(certificates: &TestCertificates) -> ApiTlsSettings {
+    ApiTlsSettings {
+        certificate_path: TestCertificates::write(&certificates.server.certificate_pem()),
+        key_path: TestCertificates::write(&certificates.server.key_pem()),
+        client_ca_path: None,
+        reload_interval_milliseconds: 50,
+    }
+}
+
+fn client_trusting(certificates: &TestCertificates) -> Client {
+    Client::builder()
+        .add_root_certificate(ca(certificates))
+        .build()
+        .unwrap()
+}
+
+fn ca(certificates: &TestCertificates) -> Certificate {
+    Certificate::from_pem(certificates.ca.certificate_pem().as_bytes()).unwrap()
+}
+
+#[tokio::test]
+async fn api_is_served_over_tls_with_http2() {
+    let certificates = TestCertificates::generate();
+    let app = spawn_app_with_tls(tls_settings(&certificates), client_trusting(&certificates)).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/health_check", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    assert_eq!(Version::HTTP_2, response.version());
+
+    // Clients without HTTP/2 are still served
+    let response = Client::builder()
+        .add_root_certificate(ca(&certificates))
+        .http1_only()
+        .build()
+        .unwrap()
+        .get(format!("{}/health_check", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    assert_eq!(Version::HTTP_11, response.version());
+}
+
+#[tokio::test]
+async fn certificate_is_reloaded_when_its_files_change() {
+    let certificates = TestCertificates::generate();
+    let settings = tls_settings(&certificates);
+    let app = spawn_app_with_tls(settings.clone(), client_trusting(&certificates)).await;
+    let renewed = TestCertificates::generate();
+    let renewed_client = client_trusting(&renewed);
+    let health_check =
+        |client: &Client| client.get(format!("{}/health_check", &app.address)).send();
+    assert!(health_check(&renewed_client).await.is_err());
+
+    std::fs::write(&settings.key_path, renewed.server.key_pem()).unwrap();
+    std::fs::write(&settings.certificate_path, renewed.server.certificate_pem()).unwrap();
+
+    for _ in 0..100 {
+        if health_check(&renewed_client).await.is_ok() {
+            assert!(health_check(&app.api_client).await.is_err());
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("The renewed certificate was not served");
+}
+
+#[tokio::test]
+async fn publishers_must_present_a_client_certificate_from_the_configured_ca() {
+    let certificates = TestCertificates::generate();
+    let settings = ApiTlsSettings {
+        client_ca_path: Some(TestCertificates::write(&certificates.ca.certificate_pem())),
+        ..tls_settings(&certificates)
+    };
+    let identity = Identity::from_pkcs8_pem(
+        certificates.client.certificate_pem().as_bytes(),
+        certificates.client.key_pem().as_bytes(),
+    )
+    .unwrap();
+    let api_client = client_with_key(
+        Client::builder()
+            .add_root_certificate(ca(&certificates))
+            .identity(identity),
+        TEST_ADMIN_KEY,
+    );
+    let app = spawn_app_with_tls(settings, api_client).await;
+
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    let payload = json!({ "name": "John Rustsworth" });
+    assert_eq!(
+        201,
+        app.publish_event(TEST_EVENT_TYPE, payload.clone())
+            .await
+            .status()
+    );
+
+    // Clients without a certificate may do anything but publish
+    let anonymous = client_with_key(
+        Client::builder().add_root_certificate(ca(&certificates)),
+        TEST_ADMIN_KEY,
+    );
+    let response = anonymous
+        .get(format!("{}/health_check", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    let response = anonymous
+        .get(format!("{}/api/events", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status());
+    let response = anonymous
+        .post(format!("{}/api/events/publish", &app.address))
+        .json(&json!({ "event_type": TEST_EVENT_TYPE, "payload": payload }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(403, response.status());
+    let body: Value = response.json().await.expect("Failed to parse body");
+    assert_eq!("urn:ventrix:error:forbidden", body["type"]);
+
+    // A certificate from another CA is refused as well
+    let stranger = TestCertificates::generate();
+    let identity = Identity::from_pkcs8_pem(
+        stranger.client.certificate_pem().as_bytes(),
+        stranger.client.key_pem().as_bytes(),
+    )
+    .unwrap();
+    let response = Client::builder()
+        .add_root_certificate(ca(&certificates))
+        .identity(identity)
+        .build()
+        .unwrap()
+        .get(format!("{}/health_check", &app.address))
+        .send()
+        .await;
+    assert!(response.is_err());
+}
@@ -0,0 +1,131 @@
+use chrono::Duration as ChronoDuration;
+use serde_json::json;
+use std::{collections::HashSet, time::Duration};
+use uuid::Uuid;
+use ventrix::common::configuration::{BackpressurePolicy, QueueSettings};
+
+use crate::{
+    helpers::{spawn_app_with_queue_settings, test_queue_settings, TestApp, TEST_EVENT_TYPE},
+    mock_subscriber::{MockResponse, MockSubscriber, ReceivedDelivery},
+    recording_database::DatabaseCall,
+};
+
+fn event_id(delivery: &ReceivedDelivery) -> Uuid {
+    delivery.event["id"]
+        .as_str()
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .expect("Delivery did not contain an event id")
+}
+
+fn single_slot_queue(backpressure: BackpressurePolicy) -> QueueSettings {
+    QueueSettings {
+        capacity: 1,
+        backpressure,
+        backpressure_timeout_milliseconds: 200,
+        ..test_queue_settings()
+    }
+}
+
+// Occupies the event processor with a hanging delivery, fills the single queue slot and
+// returns the responses to three publishes
+async fn publish_while_queue_is_full(app: &TestApp) -> Vec<reqwest::Response> {
+    let mut responses = vec![];
+    for _ in 0..3 {
+        responses.push(
+            app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+                .await,
+        );
+    }
+    responses
+}
+
+#[tokio::test]
+async fn reject_policy_returns_429_with_retry_after_when_queue_is_full() {
+    let app = spawn_app_with_queue_settings(single_slot_queue(BackpressurePolicy::Reject)).await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.respond_with([MockResponse::Hang]);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    let responses = publish_while_queue_is_full(&app).await;
+
+    assert_eq!(201, responses[0].status());
+    assert_eq!(429, responses[2].status());
+    assert_eq!("1", responses[2].headers()["retry-after"]);
+}
+
+#[tokio::test]
+async fn block_policy_returns_429_once_the_timeout_elapses() {
+    let app = spawn_app_with_queue_settings(single_slot_queue(BackpressurePolicy::Block)).await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.respond_with([MockResponse::Hang]);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    let responses = publish_while_queue_is_full(&app).await;
+
+    assert_eq!(201, responses[0].status());
+    assert_eq!(201, responses[1].status());
+    assert_eq!(429, responses[2].status());
+}
+
+#[tokio::test]
+async fn spill_policy_accepts_the_event_and_delivers_it_later() {
+    let app = spawn_app_with_queue_settings(single_slot_queue(BackpressurePolicy::Spill)).await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.respond_with([MockResponse::Hang]);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    let responses = publish_while_queue_is_full(&app).await;
+    assert!(responses.iter().all(|response| response.status() == 201));
+
+    let deliveries = subscriber.wait_for_deliveries(3).await;
+    let delivered: HashSet<Uuid> = deliveries.iter().map(event_id).collect();
+    assert_eq!(3, delivered.len());
+}
+
+#[tokio::test]
+async fn events_waiting_in_the_queue_past_the_lease_are_delivered_once() {
+    let app = spawn_app_with_queue_settings(single_slot_queue(BackpressurePolicy::Spill)).await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.respond_with([MockResponse::Hang]);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    let responses = publish_while_queue_is_full(&app).await;
+    assert!(responses.iter().all(|response| response.status() == 201));
+    // The queued and spilled events wait behind the hanging delivery for longer than a lease
+    app.clock.advance(ChronoDuration::hours(1));
+
+    let deliveries = subscriber.wait_for_deliveries(3).await;
+    for delivery in &deliveries {
+        let id = event_id(delivery);
+        app.database
+            .wait_for(|call| *call == DatabaseCall::Dispatched(id))
+            .await;
+    }
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let delivered: Vec<Uuid> = subscriber.deliveries().iter().map(event_id).collect();
+    let unique: HashSet<&Uuid> = delivered.iter().collect();
+    assert_eq!(3, delivered.len(), "Deliveries: {:?}", delivered);
+    assert_eq!(3, unique.len());
+}
+
+#[tokio::test]
+async fn health_check_reports_queue_depth() {
+    let app = spawn_app_with_queue_settings(single_slot_queue(BackpressurePolicy::Reject)).await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.respond_with([MockResponse::Hang]);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    publish_while_queue_is_full(&app).await;
+
+    let health: serde_json::Value = app
+        .api_client
+        .get(format!("{}/health_check", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse body");
+    assert_eq!(1, health["queue"]["depth"]);
+    assert_eq!(1, health["queue"]["capacity"]);
+}
@@ -0,0 +1,101 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+// Stands in for an OTLP/HTTP collector. It outlives any single test runtime, so it serves
+// from its own thread and keeps the raw protobuf body of every export it receives.
+pub struct CollectorStub {
+    pub endpoint: String,
+    exports: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl CollectorStub {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind collector stub");
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let exports = Arc::new(Mutex::new(vec![]));
+
+        let accept_exports = Arc::clone(&exports);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let exports = Arc::clone(&accept_exports);
+                std::thread::spawn(move || handle_export(stream, exports));
+            }
+        });
+
+        Self { endpoint, exports }
+    }
+
+    // Waits for an exported span with the given name in the given trace. Protobuf stores the
+    // trace id as raw bytes and the name as plain UTF-8, so both can be found in the body.
+    pub async fn wait_for_span(&self, trace_id: &str, span_name: &str) {
+        let trace_id = decode_hex(trace_id);
+        for _ in 0..100 {
+            let found = self
+                .exports
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|body| contains(body, &trace_id) && contains(body, span_name.as_bytes()));
+            if found {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("Span {} was not exported for its trace", span_name);
+    }
+}
+
+fn handle_export(mut stream: TcpStream, exports: Arc<Mutex<Vec<Vec<u8>>>>) {
+    let mut buffer = vec![];
+    let mut chunk = [0u8; 4096];
+
+    let header_end = loop {
+        match stream.read(&mut chunk) {
+            Ok(0) | Err(_) => return,
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+        }
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let content_length = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-length")
+                .then(|| value.trim().parse::<usize>().ok())?
+        })
+        .unwrap_or(0);
+
+    while buffer.len() < header_end + content_length {
+        match stream.read(&mut chunk) {
+            Ok(0) | Err(_) => return,
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+        }
+    }
+
+    exports
+        .lock()
+        .unwrap()
+        .push(buffer[header_end..header_end + content_length].to_vec());
+    let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+}
+
+fn decode_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).expect("Invalid hex"))
+        .collect()
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
@@ -0,0 +1,340 @@
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::Duration;
+use uuid::Uuid;
+use ventrix::common::clock::Clock;
+use ventrix::common::configuration::DispatchMode;
+use ventrix::common::types::{TraceContext, VentrixEvent};
+use ventrix::infrastructure::persistence::{Database, MAX_RETRIES};
+
+use crate::{
+    helpers::{
+        spawn_app, spawn_app_with_postgres, spawn_app_with_postgres_dispatch_mode, spawn_replica,
+        TEST_EVENT_TYPE,
+    },
+    mock_subscriber::{MockResponse, MockSubscriber, ReceivedDelivery},
+    recording_database::DatabaseCall,
+};
+
+fn event_id(delivery: &ReceivedDelivery) -> Uuid {
+    delivery.event["id"]
+        .as_str()
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .expect("Delivery did not contain an event id")
+}
+
+#[tokio::test]
+async fn published_event_is_delivered_to_listening_service() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    let response = app
+        .publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    assert_eq!(201, response.status());
+
+    let deliveries = subscriber.wait_for_deliveries(1).await;
+    assert_eq!("/events", deliveries[0].path);
+    assert_eq!(TEST_EVENT_TYPE, deliveries[0].event["event_type"]);
+    assert_eq!(
+        json!({ "name": "John Rustsworth" }).to_string(),
+        deliveries[0].event["payload"]
+    );
+    assert!(deliveries[0].event["retry_details"].is_null());
+}
+
+#[tokio::test]
+async fn published_event_is_delivered_to_every_listening_service() {
+    let app = spawn_app().await;
+    let vinnie = MockSubscriber::start().await;
+    let viktor = MockSubscriber::start().await;
+    app.subscribe("vinnie", &vinnie.url).await;
+    app.subscribe("viktor", &viktor.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+
+    let vinnie_deliveries = vinnie.wait_for_deliveries(1).await;
+    let viktor_deliveries = viktor.wait_for_deliveries(1).await;
+    assert_eq!(
+        event_id(&vinnie_deliveries[0]),
+        event_id(&viktor_deliveries[0])
+    );
+}
+
+#[tokio::test]
+async fn invalid_payload_is_not_delivered() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    let response = app
+        .publish_event(TEST_EVENT_TYPE, json!({ "age": 42 }))
+        .await;
+    assert_eq!(400, response.status());
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(subscriber.deliveries().is_empty());
+}
+
+#[tokio::test]
+async fn successful_delivery_fulfils_the_event() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+
+    let deliveries = subscriber.wait_for_deliveries(1).await;
+    let id = event_id(&deliveries[0]);
+    app.database
+        .wait_for(|call| *call == DatabaseCall::Fulfilled(id))
+        .await;
+}
+
+#[tokio::test]
+async fn successful_delivery_sets_fulfilled_at_in_postgres() {
+    let Some(app) = spawn_app_with_postgres().await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+
+    let deliveries = subscriber.wait_for_deliveries(1).await;
+    let id = event_id(&deliveries[0]);
+    app.database
+        .wait_for(|call| *call == DatabaseCall::Fulfilled(id))
+        .await;
+
+    let fulfilled: bool =
+        sqlx::query_scalar("SELECT fulfilled_at IS NOT NULL FROM events_published WHERE id = $1")
+            .bind(id)
+            .fetch_one(app.db_pool.as_ref().unwrap())
+            .await
+            .expect("Failed to fetch published event");
+    assert!(fulfilled);
+}
+
+#[tokio::test]
+async fn failed_delivery_is_retried_until_it_succeeds() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.fail_times(2);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    let id = event_id(&subscriber.wait_for_deliveries(1).await[0]);
+
+    app.advance_past_retry(id, 0).await;
+    subscriber.wait_for_deliveries(2).await;
+    app.advance_past_retry(id, 1).await;
+    let deliveries = subscriber.wait_for_deliveries(3).await;
+
+    assert!(deliveries.iter().all(|delivery| event_id(delivery) == id));
+    assert_eq!(0, deliveries[1].event["retry_details"]["retry_count"]);
+    assert_eq!(1, deliveries[2].event["retry_details"]["retry_count"]);
+    app.database
+        .wait_for(|call| *call == DatabaseCall::FailedEventResolved(id))
+        .await;
+    app.database
+        .wait_for(|call| *call == DatabaseCall::Fulfilled(id))
+        .await;
+}
+
+#[tokio::test]
+async fn failed_delivery_is_not_retried_before_its_retry_time() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.fail_times(1);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    let id = event_id(&subscriber.wait_for_deliveries(1).await[0]);
+    app.database
+        .wait_for(|call| *call == DatabaseCall::FailedEventAdded(id))
+        .await;
+
+    // Several polls pass while the clock stands still
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(1, subscriber.deliveries().len());
+
+    app.advance_past_retry(id, 0).await;
+    subscriber.wait_for_deliveries(2).await;
+}
+
+#[tokio::test]
+async fn closed_connection_is_recorded_as_a_failure_and_retried() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.respond_with([MockResponse::CloseConnection]);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    let id = event_id(&subscriber.wait_for_deliveries(1).await[0]);
+
+    app.advance_past_retry(id, 0).await;
+    subscriber.wait_for_deliveries(2).await;
+    app.database
+        .wait_for(|call| *call == DatabaseCall::Fulfilled(id))
+        .await;
+}
+
+#[tokio::test]
+async fn hanging_subscriber_times_out_and_is_retried() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.respond_with([MockResponse::Hang]);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    let id = event_id(&subscriber.wait_for_deliveries(1).await[0]);
+
+    app.advance_past_retry(id, 0).await;
+    subscriber.wait_for_deliveries(2).await;
+    app.database
+        .wait_for(|call| *call == DatabaseCall::Fulfilled(id))
+        .await;
+}
+
+#[tokio::test]
+async fn event_is_dead_lettered_after_max_retries() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.fail_times(MAX_RETRIES as usize + 2);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    let id = event_id(&subscriber.wait_for_deliveries(1).await[0]);
+
+    for retries in 0..MAX_RETRIES {
+        app.advance_past_retry(id, retries).await;
+        subscriber.wait_for_deliveries(retries as usize + 2).await;
+    }
+    app.advance_past_retry(id, MAX_RETRIES).await;
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(MAX_RETRIES as usize + 1, subscriber.deliveries().len());
+    assert!(!app.database.calls().contains(&DatabaseCall::Fulfilled(id)));
+}
+
+#[tokio::test]
+async fn instances_sharing_a_database_retry_each_failure_once() {
+    let Some(app) = spawn_app_with_postgres().await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    let _replica = spawn_replica(&app).await;
+    let subscriber = MockSubscriber::start().await;
+    let event_count = 10;
+    subscriber.fail_times(event_count);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    for _ in 0..event_count {
+        app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+            .await;
+    }
+    let deliveries = subscriber.wait_for_deliveries(event_count).await;
+    for delivery in &deliveries {
+        let id = event_id(delivery);
+        app.database
+            .wait_for(|call| *call == DatabaseCall::FailedEventAdded(id))
+            .await;
+    }
+
+    // Both instances now find every failure due
+    app.clock.advance(chrono::Duration::minutes(10));
+    subscriber.wait_for_deliveries(event_count * 2).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut deliveries_per_event: HashMap<Uuid, usize> = HashMap::new();
+    for delivery in subscriber.deliveries() {
+        *deliveries_per_event.entry(event_id(&delivery)).or_default() += 1;
+    }
+    assert_eq!(event_count, deliveries_per_event.len());
+    assert!(deliveries_per_event.values().all(|count| *count == 2));
+}
+
+#[tokio::test]
+async fn instances_in_postgres_dispatch_mode_deliver_each_event_once() {
+    let Some(app) = spawn_app_with_postgres_dispatch_mode(DispatchMode::Postgres).await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    let replica = spawn_replica(&app).await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    let event_count = 20;
+    for i in 0..event_count {
+        // Publish through both instances, as a load balancer would
+        let instance = if i % 2 == 0 { &app } else { &replica };
+        let response = instance
+            .publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+            .await;
+        assert_eq!(201, response.status());
+    }
+    subscriber.wait_for_deliveries(event_count).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut deliveries_per_event: HashMap<Uuid, usize> = HashMap::new();
+    for delivery in subscriber.deliveries() {
+        *deliveries_per_event.entry(event_id(&delivery)).or_default() += 1;
+    }
+    assert_eq!(event_count, deliveries_per_event.len());
+    assert!(deliveries_per_event.values().all(|count| *count == 1));
+}
+
+#[tokio::test]
+async fn event_claimed_by_a_dead_instance_is_delivered_once_its_lease_expires() {
+    let Some(app) = spawn_app_with_postgres_dispatch_mode(DispatchMode::Postgres).await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    // An instance that claims an event and dies before delivering it
+    let event = VentrixEvent {
+        id: Uuid::new_v4(),
+        event_type: TEST_EVENT_TYPE.to_string(),
+        payload: json!({ "name": "John Rustsworth" }).to_string(),
+        retry_details: None,
+        trace_context: TraceContext::default(),
+    };
+    app.database
+        .save_published_event(&event)
+        .await
+        .expect("Failed to save event");
+    let claimed = app
+        .database
+        .claim_pending_events(
+            "dead-instance",
+            chrono::Duration::minutes(5),
+            app.clock.now(),
+            10,
+        )
+        .await
+        .expect("Failed to claim pending events");
+    assert_eq!(1, claimed.len());
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(subscriber.deliveries().is_empty());
+
+    app.clock.advance(chrono::Duration::minutes(10));
+    let deliveries = subscriber.wait_for_deliveries(1).await;
+    assert_eq!(event.id, event_id(&deliveries[0]));
+    app.database
+        .wait_for(|call| *call == DatabaseCall::Dispatched(event.id))
+        .await;
+}
@@ -0,0 +1,415 @@
+use actix_web::web;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Duration;
+use secrecy::Secret;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use ventrix::{
+    application::queue_service::ventrix_queue::VentrixQueue,
+    common::clock::MockClock,
+    infrastructure::persistence::{inmemory::InMemoryDatabase, Database},
+};
+
+use crate::{
+    helpers::{
+        spawn_app, spawn_app_with_postgres, spawn_app_with_queue_settings, test_queue_settings,
+        TestApp, TEST_EVENT_TYPE,
+    },
+    mock_subscriber::{MockResponse, MockSubscriber},
+    recording_database::DatabaseCall,
+};
+
+fn oauth2_delivery(token_url: &str) -> Value {
+    oauth2_delivery_with_secret(token_url, "oauth-client-secret")
+}
+
+fn oauth2_delivery_with_secret(token_url: &str, client_secret: &str) -> Value {
+    json!({
+        "auth": {
+            "type": "oauth2_client_credentials",
+            "token_url": format!("{}/token", token_url),
+            "client_id": "ventrix",
+            "client_secret": client_secret,
+            "scope": "events:write"
+        }
+    })
+}
+
+async fn deliveries_carry_the_configured_headers_and_basic_auth(app: TestApp) {
+    let subscriber = MockSubscriber::start().await;
+    assert_eq!(
+        201,
+        app.register_service_with_delivery(
+            "vinnie",
+            &subscriber.url,
+            json!({ "headers": { "X-Tenant": "acme", "X-Api-Key": "service-key" } }),
+        )
+        .await
+        .status()
+    );
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    assert_eq!(
+        201,
+        app.listen_to_event_with_delivery(
+            "vinnie",
+            TEST_EVENT_TYPE,
+            json!({
+                "headers": { "x-api-key": "subscription-key" },
+                "auth": { "type": "basic", "username": "ventrix", "password": "basic-password" }
+            }),
+        )
+        .await
+        .status()
+    );
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+
+    let deliveries = subscriber.wait_for_deliveries(1).await;
+    let headers = &deliveries[0].headers;
+    assert_eq!(Some("acme"), headers.get("x-tenant").map(String::as_str));
+    assert_eq!(
+        Some("subscription-key"),
+        headers.get("x-api-key").map(String::as_str)
+    );
+    assert_eq!(
+        Some(format!(
+            "Basic {}",
+            STANDARD.encode("ventrix:basic-password")
+        )),
+        headers.get("authorization").cloned()
+    );
+}
+
+#[tokio::test]
+async fn deliveries_carry_the_configured_headers_and_basic_auth_in_memory() {
+    deliveries_carry_the_configured_headers_and_basic_auth(spawn_app().await).await;
+}
+
+#[tokio::test]
+async fn deliveries_carry_the_configured_headers_and_basic_auth_in_postgres() {
+    let Some(app) = spawn_app_with_postgres().await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    deliveries_carry_the_configured_headers_and_basic_auth(app).await;
+}
+
+#[tokio::test]
+async fn oauth2_tokens_are_cached_until_they_are_about_to_expire() {
+    let app = spawn_app().await;
+    let token_server = MockSubscriber::start().await;
+    token_server.respond_with([
+        MockResponse::Json(r#"{"access_token":"token-1","token_type":"Bearer","expires_in":3600}"#),
+        MockResponse::Json(r#"{"access_token":"token-2","token_type":"Bearer","expires_in":3600}"#),
+    ]);
+    let subscriber = MockSubscriber::start().await;
+    assert_eq!(
+        201,
+        app.register_service_with_delivery(
+            "vinnie",
+            &subscriber.url,
+            oauth2_delivery(&token_server.url)
+        )
+        .await
+        .status()
+    );
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    assert_eq!(
+        201,
+        app.listen_to_event("vinnie", TEST_EVENT_TYPE, "/events")
+            .await
+            .status()
+    );
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    subscriber.wait_for_deliveries(2).await;
+    // Within the refresh margin of the token's expiry
+    app.clock.advance(Duration::seconds(3590));
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+
+    let deliveries = subscriber.wait_for_deliveries(3).await;
+    let authorizations: Vec<_> = deliveries
+        .iter()
+        .map(|delivery| delivery.headers.get("authorization").cloned())
+        .collect();
+    assert_eq!(
+        vec![
+            Some("Bearer token-1".to_string()),
+            Some("Bearer token-1".to_string()),
+            Some("Bearer token-2".to_string()),
+        ],
+        authorizations
+    );
+    let token_requests = token_server.deliveries();
+    assert_eq!(2, token_requests.len());
+    assert_eq!("/token", token_requests[0].path);
+    for field in [
+        "grant_type=client_credentials",
+        "client_id=ventrix",
+        "client_secret=oauth-client-secret",
+        "scope=events%3Awrite",
+    ] {
+        assert!(
+            token_requests[0].body.contains(field),
+            "The token request {:?} did not contain {}",
+            token_requests[0].body,
+            field
+        );
+    }
+}
+
+#[tokio::test]
+async fn oauth2_tokens_are_not_shared_between_different_client_secrets() {
+    let app = spawn_app().await;
+    let token_server = MockSubscriber::start().await;
+    token_server.respond_with([
+        MockResponse::Json(r#"{"access_token":"token-1","expires_in":3600}"#),
+        MockResponse::Json(r#"{"access_token":"token-2","expires_in":3600}"#),
+    ]);
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    let mut subscribers = Vec::new();
+    for service_name in ["vinnie", "vera"] {
+        let subscriber = MockSubscriber::start().await;
+        let secret = format!("{}-client-secret", service_name);
+        assert_eq!(
+            201,
+            app.register_service_with_delivery(
+                service_name,
+                &subscriber.url,
+                oauth2_delivery_with_secret(&token_server.url, &secret)
+            )
+            .await
+            .status()
+        );
+        assert_eq!(
+            201,
+            app.listen_to_event(service_name, TEST_EVENT_TYPE, "/events")
+                .await
+                .status()
+        );
+        subscribers.push(subscriber);
+    }
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+
+    let mut authorizations = Vec::new();
+    for subscriber in &subscribers {
+        let deliveries = subscriber.wait_for_deliveries(1).await;
+        authorizations.push(deliveries[0].headers.get("authorization").cloned());
+    }
+    authorizations.sort();
+    assert_eq!(
+        vec![
+            Some("Bearer token-1".to_string()),
+            Some("Bearer token-2".to_string()),
+        ],
+        authorizations
+    );
+    let token_requests = token_server.deliveries();
+    assert_eq!(2, token_requests.len());
+    for secret in ["vinnie-client-secret", "vera-client-secret"] {
+        assert!(
+            token_requests
+                .iter()
+                .any(|request| request.body.contains(&format!("client_secret={}", secret))),
+            "No token was fetched with {}",
+            secret
+        );
+    }
+}
+
+#[tokio::test]
+async fn deliveries_are_retried_when_no_token_can_be_fetched() {
+    let app = spawn_app().await;
+    let token_server = MockSubscriber::start().await;
+    token_server.respond_with([
+        MockResponse::Status(503),
+        MockResponse::Json(r#"{"access_token":"token-1","expires_in":3600}"#),
+    ]);
+    let subscriber = MockSubscriber::start().await;
+    assert_eq!(
+        201,
+        app.register_service_with_delivery(
+            "vinnie",
+            &subscriber.url,
+            oauth2_delivery(&token_server.url)
+        )
+        .await
+        .status()
+    );
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    assert_eq!(
+        201,
+        app.listen_to_event("vinnie", TEST_EVENT_TYPE, "/events")
+            .await
+            .status()
+    );
+
+    let response = app
+        .publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    assert_eq!(201, response.status());
+    let DatabaseCall::FailedEventAdded(event_id) = app
+        .database
+        .wait_for(|call| matches!(call, DatabaseCall::FailedEventAdded(_)))
+        .await
+    else {
+        unreachable!()
+    };
+    assert!(subscriber.deliveries().is_empty());
+    app.advance_past_retry(event_id, 0).await;
+
+    let deliveries = subscriber.wait_for_deliveries(1).await;
+    assert_eq!(
+        Some("Bearer token-1"),
+        deliveries[0]
+            .headers
+            .get("authorization")
+            .map(String::as_str)
+    );
+    assert!(app.metrics().await.contains(r#"status_class="auth_error""#));
+}
+
+#[tokio::test]
+async fn delivery_secrets_are_never_returned_by_the_read_api() {
+    let app = spawn_app().await;
+    assert_eq!(
+        201,
+        app.register_service_with_delivery(
+            "vinnie",
+            "http://localhost:8081",
+            json!({ "headers": { "X-Api-Key": "service-header-secret" } }),
+        )
+        .await
+        .status()
+    );
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    assert_eq!(
+        201,
+        app.listen_to_event_with_delivery(
+            "vinnie",
+            TEST_EVENT_TYPE,
+            oauth2_delivery("http://localhost:8082"),
+        )
+        .await
+        .status()
+    );
+
+    let response = app.get_subscriptions("vinnie").await;
+    assert_eq!(200, response.status());
+    let body = response.text().await.expect("Failed to read body");
+    assert!(body.contains(TEST_EVENT_TYPE));
+    for secret in ["service-header-secret", "oauth-client-secret"] {
+        assert!(
+            !body.contains(secret),
+            "{} was returned in {}",
+            secret,
+            body
+        );
+    }
+}
+
+#[tokio::test]
+async fn invalid_delivery_settings_return_400() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            json!({ "auth": { "type": "digest", "username": "ventrix" } }),
+            "the auth type is unknown",
+        ),
+        (
+            json!({ "headers": { "Content-Type": "text/plain" } }),
+            "a header Ventrix sets is configured",
+        ),
+        (
+            json!({ "auth": { "type": "basic", "username": "", "password": "secret" } }),
+            "basic auth has no username",
+        ),
+        (oauth2_delivery("not a url"), "the token URL is not a URL"),
+    ];
+
+    for (delivery, description) in test_cases {
+        let response = app
+            .register_service_with_delivery("vinnie", "http://localhost:8081", delivery)
+            .await;
+
+        assert_eq!(
+            400,
+            response.status(),
+            "The API did not fail with 400 Bad Request when {}.",
+            description
+        );
+    }
+
+    // Settings that parse but are not valid are reported as such
+    let response = app
+        .register_service_with_delivery(
+            "vinnie",
+            "http://localhost:8081",
+            json!({ "headers": { "Content-Type": "text/plain" } }),
+        )
+        .await;
+    let body: Value = response.json().await.expect("Failed to parse body");
+    assert_eq!("urn:ventrix:error:invalid-delivery-settings", body["type"]);
+}
+
+#[tokio::test]
+async fn queue_refuses_to_start_with_an_invalid_encryption_key() {
+    let clock = Arc::new(MockClock::default());
+    let db_arc: Arc<dyn Database> = Arc::new(InMemoryDatabase::new(clock.clone()));
+    let database = web::Data::from(db_arc);
+
+    for (encryption_key, description) in [
+        ("not base64!", "the key is not base64"),
+        ("c2hvcnQ=", "the key is too short"),
+    ] {
+        let mut settings = test_queue_settings();
+        settings.delivery_auth.encryption_key = Some(Secret::new(encryption_key.into()));
+
+        let queue = VentrixQueue::new(database.clone(), settings, clock.clone()).await;
+
+        assert!(queue.is_err(), "The queue started when {}.", description);
+    }
+}
+
+#[tokio::test]
+async fn delivery_settings_are_refused_without_an_encryption_key() {
+    let mut settings = test_queue_settings();
+    settings.delivery_auth.encryption_key = None;
+    let app = spawn_app_with_queue_settings(settings).await;
+    let delivery = json!({ "headers": { "X-Tenant": "acme" } });
+
+    let response = app
+        .register_service_with_delivery("vinnie", "http://localhost:8081", delivery.clone())
+        .await;
+    assert_eq!(400, response.status());
+    let body: Value = response.json().await.expect("Failed to parse body");
+    assert_eq!("urn:ventrix:error:invalid-delivery-settings", body["type"]);
+
+    // Registrations without delivery settings work as before
+    assert_eq!(
+        201,
+        app.register_service("vinnie", "http://localhost:8081")
+            .await
+            .status()
+    );
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    assert_eq!(
+        400,
+        app.listen_to_event_with_delivery("vinnie", TEST_EVENT_TYPE, delivery)
+            .await
+            .status()
+    );
+    assert_eq!(
+        201,
+        app.listen_to_event("vinnie", TEST_EVENT_TYPE, "/events")
+            .await
+            .status()
+    );
+}
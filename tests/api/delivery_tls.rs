@@ -0,0 +1,203 @@
+use serde_json::json;
+
+use crate::{
+    helpers::{spawn_app, spawn_app_with_queue_settings, test_queue_settings, TEST_EVENT_TYPE},
+    mock_subscriber::{MockResponse, MockSubscriber},
+    recording_database::DatabaseCall,
+    test_certificates::TestCertificates,
+    tls_subscriber::TlsSubscriber,
+};
+
+#[tokio::test]
+async fn deliveries_use_the_configured_client_certificate_and_ca() {
+    let certificates = TestCertificates::generate();
+    let mut settings = test_queue_settings();
+    settings.delivery_tls.ca_bundle_paths =
+        vec![TestCertificates::write(&certificates.ca.certificate_pem())];
+    settings.delivery_tls.client_certificate_path = Some(TestCertificates::write(
+        &certificates.client.certificate_pem(),
+    ));
+    settings.delivery_tls.client_key_path =
+        Some(TestCertificates::write(&certificates.client.key_pem()));
+    let app = spawn_app_with_queue_settings(settings).await;
+    let subscriber = TlsSubscriber::start(&certificates).await;
+    assert_eq!(
+        201,
+        app.register_service("vinnie", &subscriber.url)
+            .await
+            .status()
+    );
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    assert_eq!(
+        201,
+        app.listen_to_event("vinnie", TEST_EVENT_TYPE, "/events")
+            .await
+            .status()
+    );
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+
+    let deliveries = subscriber.subscriber.wait_for_deliveries(1).await;
+    assert_eq!("/events", deliveries[0].path);
+    assert_eq!(TEST_EVENT_TYPE, deliveries[0].event["event_type"]);
+}
+
+#[tokio::test]
+async fn services_can_bring_their_own_client_certificate_and_ca() {
+    let certificates = TestCertificates::generate();
+    let app = spawn_app().await;
+    let subscriber = TlsSubscriber::start(&certificates).await;
+    assert_eq!(
+        201,
+        app.register_service_with_delivery(
+            "vinnie",
+            &subscriber.url,
+            json!({
+                "tls": {
+                    "ca_certificates": certificates.ca.certificate_pem(),
+                    "client_certificate": certificates.client.certificate_pem(),
+                    "client_key": certificates.client.key_pem()
+                }
+            }),
+        )
+        .await
+        .status()
+    );
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    assert_eq!(
+        201,
+        app.listen_to_event("vinnie", TEST_EVENT_TYPE, "/events")
+            .await
+            .status()
+    );
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+
+    subscriber.subscriber.wait_for_deliveries(1).await;
+}
+
+#[tokio::test]
+async fn oauth2_tokens_are_fetched_with_the_services_client_certificate() {
+    let certificates = TestCertificates::generate();
+    let app = spawn_app().await;
+    let token_server = TlsSubscriber::start(&certificates).await;
+    token_server.subscriber.respond_with([MockResponse::Json(
+        r#"{"access_token":"token-1","expires_in":3600}"#,
+    )]);
+    let subscriber = MockSubscriber::start().await;
+    assert_eq!(
+        201,
+        app.register_service_with_delivery(
+            "vinnie",
+            &subscriber.url,
+            json!({
+                "tls": {
+                    "ca_certificates": certificates.ca.certificate_pem(),
+                    "client_certificate": certificates.client.certificate_pem(),
+                    "client_key": certificates.client.key_pem()
+                },
+                "auth": {
+                    "type": "oauth2_client_credentials",
+                    "token_url": format!("{}/token", token_server.url),
+                    "client_id": "ventrix",
+                    "client_secret": "oauth-client-secret"
+                }
+            }),
+        )
+        .await
+        .status()
+    );
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    assert_eq!(
+        201,
+        app.listen_to_event("vinnie", TEST_EVENT_TYPE, "/events")
+            .await
+            .status()
+    );
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+
+    let deliveries = subscriber.wait_for_deliveries(1).await;
+    assert_eq!(
+        Some("Bearer token-1"),
+        deliveries[0]
+            .headers
+            .get("authorization")
+            .map(String::as_str)
+    );
+    assert_eq!(1, token_server.subscriber.deliveries().len());
+}
+
+#[tokio::test]
+async fn deliveries_without_a_client_certificate_fail_and_are_retried() {
+    let certificates = TestCertificates::generate();
+    let app = spawn_app().await;
+    let subscriber = TlsSubscriber::start(&certificates).await;
+    assert_eq!(
+        201,
+        app.register_service("vinnie", &subscriber.url)
+            .await
+            .status()
+    );
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    assert_eq!(
+        201,
+        app.listen_to_event_with_delivery(
+            "vinnie",
+            TEST_EVENT_TYPE,
+            json!({ "tls": { "ca_certificates": certificates.ca.certificate_pem() } }),
+        )
+        .await
+        .status()
+    );
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+
+    subscriber.wait_for_rejected_handshake().await;
+    app.database
+        .wait_for(|call| matches!(call, DatabaseCall::FailedEventAdded(_)))
+        .await;
+    assert!(subscriber.subscriber.deliveries().is_empty());
+}
+
+#[tokio::test]
+async fn invalid_tls_settings_return_400() {
+    let certificates = TestCertificates::generate();
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            json!({ "tls": { "ca_certificates": "not a certificate" } }),
+            "the CA bundle holds no certificate",
+        ),
+        (
+            json!({ "tls": { "client_certificate": certificates.client.certificate_pem() } }),
+            "the client certificate has no key",
+        ),
+        (
+            json!({
+                "tls": {
+                    "client_certificate": certificates.client.certificate_pem(),
+                    "client_key": "not a key"
+                }
+            }),
+            "the client key is not a PEM key",
+        ),
+    ];
+
+    for (delivery, description) in test_cases {
+        let response = app
+            .register_service_with_delivery("vinnie", "https://localhost:8081", delivery)
+            .await;
+
+        assert_eq!(
+            400,
+            response.status(),
+            "The API did not fail with 400 Bad Request when {}.",
+            description
+        );
+    }
+}
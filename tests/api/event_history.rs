@@ -0,0 +1,185 @@
+use chrono::Duration as ChronoDuration;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{
+    helpers::{spawn_app, spawn_app_with_postgres, TestApp, TEST_EVENT_TYPE},
+    mock_subscriber::{MockResponse, MockSubscriber},
+    recording_database::DatabaseCall,
+};
+
+async fn listed_event_ids(app: &TestApp, query: &[(&str, &str)]) -> Vec<String> {
+    let response = app.list_events(query).await;
+    assert_eq!(200, response.status());
+    let body: Value = response.json().await.expect("Failed to parse body");
+    body["events"]
+        .as_array()
+        .expect("Events should be a list")
+        .iter()
+        .map(|event| event["id"].as_str().unwrap().to_string())
+        .collect()
+}
+
+async fn delivered_event_id(subscriber: &MockSubscriber, delivery: usize) -> Uuid {
+    let deliveries = subscriber.wait_for_deliveries(delivery + 1).await;
+    Uuid::parse_str(deliveries[delivery].event["id"].as_str().unwrap()).unwrap()
+}
+
+async fn event_history_lists_every_delivery_attempt(app: TestApp) {
+    let subscriber = MockSubscriber::start().await;
+    subscriber.fail_times(1);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    let event_id = delivered_event_id(&subscriber, 0).await;
+    app.advance_past_retry(event_id, 0).await;
+    app.database
+        .wait_for(|call| *call == DatabaseCall::FailedEventResolved(event_id))
+        .await;
+
+    let response = app.get_event(&event_id.to_string()).await;
+    assert_eq!(200, response.status());
+    let history: Value = response.json().await.expect("Failed to parse body");
+    assert_eq!(event_id.to_string(), history["id"]);
+    assert_eq!(TEST_EVENT_TYPE, history["event_type"]);
+    assert_eq!(
+        json!({ "name": "John Rustsworth" }).to_string(),
+        history["payload"]
+    );
+    assert!(history["fulfilled_at"].is_string());
+
+    let deliveries = history["deliveries"].as_array().unwrap();
+    assert_eq!(2, deliveries.len());
+    for (delivery, attempt) in deliveries.iter().zip(0..) {
+        assert_eq!("vinnie", delivery["service_name"]);
+        assert_eq!("/events", delivery["endpoint"]);
+        assert_eq!(attempt, delivery["attempt"]);
+        assert!(delivery["latency_ms"].is_i64());
+        assert!(delivery["attempted_at"].is_string());
+    }
+    assert_eq!(500, deliveries[0]["status_code"]);
+    assert_eq!(false, deliveries[0]["succeeded"]);
+    assert!(deliveries[0]["error"]
+        .as_str()
+        .unwrap()
+        .contains("500 Internal Server Error"));
+    assert_eq!(200, deliveries[1]["status_code"]);
+    assert_eq!(true, deliveries[1]["succeeded"]);
+    assert!(deliveries[1]["error"].is_null());
+}
+
+#[tokio::test]
+async fn event_history_lists_every_delivery_attempt_in_memory() {
+    event_history_lists_every_delivery_attempt(spawn_app().await).await;
+}
+
+#[tokio::test]
+async fn event_history_lists_every_delivery_attempt_in_postgres() {
+    let Some(app) = spawn_app_with_postgres().await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    event_history_lists_every_delivery_attempt(app).await;
+}
+
+#[tokio::test]
+async fn dropped_connection_is_recorded_without_a_status_code() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.respond_with([MockResponse::CloseConnection]);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    let call = app
+        .database
+        .wait_for(|call| matches!(call, DatabaseCall::FailedEventAdded(_)))
+        .await;
+    let DatabaseCall::FailedEventAdded(event_id) = call else {
+        unreachable!()
+    };
+
+    let history: Value = app
+        .get_event(&event_id.to_string())
+        .await
+        .json()
+        .await
+        .expect("Failed to parse body");
+    let deliveries = history["deliveries"].as_array().unwrap();
+    assert_eq!(1, deliveries.len());
+    assert!(deliveries[0]["status_code"].is_null());
+    assert_eq!(false, deliveries[0]["succeeded"]);
+    assert!(deliveries[0]["error"].is_string());
+    assert!(history["fulfilled_at"].is_null());
+}
+
+#[tokio::test]
+async fn events_can_be_listed_by_type_status_and_service() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+    app.register_event_type("order_shipped").await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    let delivered = delivered_event_id(&subscriber, 0).await;
+    app.database
+        .wait_for(|call| *call == DatabaseCall::Fulfilled(delivered))
+        .await;
+    app.clock.advance(ChronoDuration::seconds(1));
+    app.publish_event("order_shipped", json!({ "name": "John Rustsworth" }))
+        .await;
+
+    let all = listed_event_ids(&app, &[]).await;
+    assert_eq!(2, all.len());
+    let undelivered = all[0].clone();
+    assert_ne!(delivered.to_string(), undelivered);
+
+    let delivered = vec![delivered.to_string()];
+    assert_eq!(
+        delivered,
+        listed_event_ids(&app, &[("event_type", TEST_EVENT_TYPE)]).await
+    );
+    assert_eq!(
+        delivered,
+        listed_event_ids(&app, &[("status", "fulfilled")]).await
+    );
+    assert_eq!(
+        vec![undelivered],
+        listed_event_ids(&app, &[("status", "pending")]).await
+    );
+    assert_eq!(
+        delivered,
+        listed_event_ids(&app, &[("service", "vinnie")]).await
+    );
+    assert!(
+        listed_event_ids(&app, &[("published_after", "2999-01-01T00:00:00Z")])
+            .await
+            .is_empty()
+    );
+    assert_eq!(1, listed_event_ids(&app, &[("limit", "1")]).await.len());
+}
+
+#[tokio::test]
+async fn listing_events_with_an_invalid_filter_returns_400() {
+    let app = spawn_app().await;
+
+    for query in [
+        ("status", "unknown"),
+        ("published_after", "yesterday"),
+        ("limit", "many"),
+    ] {
+        let response = app.list_events(&[query]).await;
+        assert_eq!(400, response.status(), "{:?}", query);
+    }
+}
+
+#[tokio::test]
+async fn getting_an_unknown_event_returns_404() {
+    let app = spawn_app().await;
+
+    let response = app.get_event(&Uuid::new_v4().to_string()).await;
+
+    assert_eq!(404, response.status());
+}
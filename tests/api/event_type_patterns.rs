@@ -0,0 +1,95 @@
+use serde_json::{json, Value};
+
+use crate::{
+    helpers::{spawn_app, spawn_app_with_postgres, TestApp},
+    mock_subscriber::MockSubscriber,
+};
+
+async fn pattern_subscriptions_cover_event_types_registered_later(app: TestApp) {
+    let subscriber = MockSubscriber::start().await;
+    assert_eq!(
+        201,
+        app.register_service("vinnie", &subscriber.url)
+            .await
+            .status()
+    );
+    assert_eq!(
+        201,
+        app.listen_to_event("vinnie", "orders.#", "/orders")
+            .await
+            .status()
+    );
+    for event_type in ["orders.created", "orders.items.added", "invoices.created"] {
+        assert_eq!(201, app.register_event_type(event_type).await.status());
+    }
+
+    for event_type in ["invoices.created", "orders.created", "orders.items.added"] {
+        app.publish_event(event_type, json!({ "name": "John Rustsworth" }))
+            .await;
+    }
+
+    let deliveries = subscriber.wait_for_deliveries(2).await;
+    assert_eq!(
+        vec!["orders.created", "orders.items.added"],
+        deliveries
+            .iter()
+            .map(|delivery| delivery.event["event_type"].as_str().unwrap())
+            .collect::<Vec<_>>()
+    );
+    assert!(deliveries.iter().all(|delivery| delivery.path == "/orders"));
+
+    let response = app.get_subscriptions("vinnie").await;
+    assert_eq!(200, response.status());
+    let body: Value = response.json().await.expect("Failed to parse body");
+    assert_eq!(
+        json!([{
+            "event_type": "orders.#",
+            "endpoint": "/orders",
+            "filter": null,
+            "resolved_event_types": ["orders.created", "orders.items.added"]
+        }]),
+        body["subscriptions"]
+    );
+}
+
+#[tokio::test]
+async fn pattern_subscriptions_cover_event_types_registered_later_in_memory() {
+    pattern_subscriptions_cover_event_types_registered_later(spawn_app().await).await;
+}
+
+#[tokio::test]
+async fn pattern_subscriptions_cover_event_types_registered_later_in_postgres() {
+    let Some(app) = spawn_app_with_postgres().await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    pattern_subscriptions_cover_event_types_registered_later(app).await;
+}
+
+#[tokio::test]
+async fn listening_to_an_invalid_pattern_returns_400() {
+    let app = spawn_app().await;
+    assert_eq!(
+        201,
+        app.register_service("vinnie", "http://localhost:8081")
+            .await
+            .status()
+    );
+
+    let response = app
+        .listen_to_event("vinnie", "orders.cre*", "/orders")
+        .await;
+
+    assert_eq!(400, response.status());
+    let body: Value = response.json().await.expect("Failed to parse body");
+    assert_eq!("urn:ventrix:error:invalid-event-type-pattern", body["type"]);
+}
+
+#[tokio::test]
+async fn subscriptions_of_an_unknown_service_return_404() {
+    let app = spawn_app().await;
+
+    let response = app.get_subscriptions("unknown").await;
+
+    assert_eq!(404, response.status());
+}
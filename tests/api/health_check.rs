@@ -0,0 +1,200 @@
+use serde_json::{json, Value};
+use ventrix::common::configuration::QueueSettings;
+
+use crate::{
+    helpers::{
+        spawn_app, spawn_app_with_postgres, spawn_app_with_queue_settings, test_queue_settings,
+        TestApp, TEST_EVENT_TYPE,
+    },
+    mock_subscriber::{MockResponse, MockSubscriber},
+};
+
+async fn readiness(app: &TestApp) -> (u16, Value) {
+    let response = app
+        .api_client
+        .get(format!("{}/health/ready", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let status = response.status().as_u16();
+    let body = response.json().await.expect("Failed to parse body");
+    (status, body)
+}
+
+#[tokio::test]
+async fn health_check_works() {
+    let test_app = spawn_app().await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/health_check", &test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(response.status().is_success());
+    let health: serde_json::Value = response.json().await.expect("Failed to parse body");
+    assert_eq!("ok", health["status"]);
+    assert_eq!(0, health["queue"]["depth"]);
+    assert_eq!(50, health["queue"]["capacity"]);
+}
+
+#[tokio::test]
+async fn liveness_returns_200() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/health/live", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, response.status());
+}
+
+#[tokio::test]
+async fn readiness_reports_every_component_up() {
+    let app = spawn_app().await;
+
+    let (status, body) = readiness(&app).await;
+
+    assert_eq!(200, status);
+    assert_eq!("ready", body["status"]);
+    for component in ["database", "event_processor", "retry_processor", "queue"] {
+        assert_eq!(
+            "up", body["components"][component]["status"],
+            "{}",
+            component
+        );
+    }
+    assert_eq!(0, body["components"]["queue"]["depth"]);
+    assert_eq!(40, body["components"]["queue"]["threshold"]);
+}
+
+#[tokio::test]
+async fn readiness_fails_once_the_queue_backlog_reaches_the_threshold() {
+    let app = spawn_app_with_queue_settings(QueueSettings {
+        readiness_depth_threshold: 1,
+        ..test_queue_settings()
+    })
+    .await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.respond_with([MockResponse::Hang]);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    // The first event occupies the event processor, the second waits in the queue
+    for _ in 0..2 {
+        app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+            .await;
+    }
+    subscriber.wait_for_deliveries(1).await;
+
+    let (status, body) = readiness(&app).await;
+    assert_eq!(503, status);
+    assert_eq!("not_ready", body["status"]);
+    assert_eq!("down", body["components"]["queue"]["status"]);
+    assert_eq!(1, body["components"]["queue"]["depth"]);
+    assert_eq!("up", body["components"]["event_processor"]["status"]);
+}
+
+#[tokio::test]
+async fn readiness_checks_the_database_is_fully_migrated() {
+    let Some(app) = spawn_app_with_postgres().await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    let pool = app.db_pool.clone().unwrap();
+    assert_eq!(200, readiness(&app).await.0);
+
+    sqlx::query(
+        "DELETE FROM _sqlx_migrations WHERE version = (SELECT MAX(version) FROM _sqlx_migrations)",
+    )
+    .execute(&pool)
+    .await
+    .expect("Failed to forget the latest migration");
+
+    let (status, body) = readiness(&app).await;
+    assert_eq!(503, status);
+    assert_eq!("down", body["components"]["database"]["status"]);
+    assert!(body["components"]["database"]["error"]
+        .as_str()
+        .unwrap()
+        .contains("migrations have not been applied"));
+}
+
+#[tokio::test]
+async fn readiness_fails_when_the_database_is_unreachable() {
+    let Some(app) = spawn_app_with_postgres().await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+
+    app.db_pool.as_ref().unwrap().close().await;
+
+    let (status, body) = readiness(&app).await;
+    assert_eq!(503, status);
+    assert_eq!("down", body["components"]["database"]["status"]);
+}
+
+#[tokio::test]
+async fn event_processor_is_restarted_after_a_panic() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+    app.database.panic_on_next_lookup();
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "Crashing Event" }))
+        .await;
+    let mut body = Value::Null;
+    for _ in 0..100 {
+        body = readiness(&app).await.1;
+        if body["components"]["event_processor"]["restarts"] == 1 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(1, body["components"]["event_processor"]["restarts"]);
+    assert_eq!("up", body["components"]["event_processor"]["status"]);
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    let deliveries = subscriber.wait_for_deliveries(1).await;
+    assert_eq!(
+        json!({ "name": "John Rustsworth" }).to_string(),
+        deliveries[0].event["payload"]
+    );
+    assert!(app
+        .metrics()
+        .await
+        .contains(r#"ventrix_background_task_restarts_total{task="event_processor"}"#));
+}
+
+#[tokio::test]
+#[ignore = "Here for reference"]
+async fn subscribe_returns_a_400_when_data_is_missing() {
+    let test_app = spawn_app().await;
+    let client = reqwest::Client::new();
+    let test_cases = vec![
+        ("name=cameron%20raw", "missing the email"),
+        ("email=cameron.raw89%40gmail.com", "missing the name"),
+        ("", "missing both the name and email"),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        let response = client
+            .post(format!("{}/subscriptions", &test_app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(invalid_body)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload was {}",
+            error_message
+        );
+    }
+}
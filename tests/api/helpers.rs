@@ -0,0 +1,549 @@
+use actix_web::web;
+use chrono::Duration as ChronoDuration;
+use once_cell::sync::Lazy;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use secrecy::{ExposeSecret, Secret};
+use serde_json::{json, Value};
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{collections::HashMap, net::TcpListener};
+use uuid::Uuid;
+use ventrix::application::queue_service::ventrix_queue::VentrixQueue;
+use ventrix::common::clock::MockClock;
+use ventrix::common::configuration::{
+    ApiTlsSettings, BackpressurePolicy, DeliveryAuthSettings, DeliveryTlsSettings, DispatchMode,
+    QueueSettings, RetentionSettings, TelemetrySettings,
+};
+use ventrix::common::types::FeatureFlagConfig;
+use ventrix::infrastructure::authentication::Authenticator;
+use ventrix::infrastructure::persistence::inmemory::InMemoryDatabase;
+use ventrix::infrastructure::persistence::notifier::PostgresNotifier;
+use ventrix::infrastructure::persistence::postgres::PostgresDatabase;
+use ventrix::infrastructure::persistence::Database;
+use ventrix::{
+    common::{
+        configuration::{get_configuration, DatabaseSettings},
+        telemetry::{get_subscriber, get_tracer, init_tracing_subscriber},
+    },
+    infrastructure::web::startup::run,
+};
+
+use crate::{
+    collector_stub::CollectorStub,
+    recording_database::{DatabaseCall, RecordingDatabase},
+};
+
+// Spans from every test app are exported to the same collector stub
+pub static COLLECTOR: Lazy<CollectorStub> = Lazy::new(CollectorStub::start);
+
+static TELEMETRY_RUNTIME: Lazy<tokio::runtime::Runtime> = Lazy::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .expect("Failed to build the telemetry runtime")
+});
+
+static TRACING: Lazy<()> = Lazy::new(|| {
+    let default_filter_level = "info".to_string();
+    let subscriber_name = "test".to_string();
+    let telemetry_settings = TelemetrySettings {
+        otlp_endpoint: Some(COLLECTOR.endpoint.clone()),
+        export_interval_milliseconds: 100,
+    };
+    // The exporter must outlive the runtime of the test that happens to install it
+    let _runtime = TELEMETRY_RUNTIME.enter();
+    let tracer = get_tracer(subscriber_name.clone(), &telemetry_settings);
+
+    if std::env::var("TEST_LOG").is_ok() {
+        let subscriber = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::stdout,
+            tracer,
+        );
+        init_tracing_subscriber(subscriber);
+    } else {
+        let subscriber =
+            get_subscriber(subscriber_name, default_filter_level, std::io::sink, tracer);
+        init_tracing_subscriber(subscriber);
+    };
+});
+
+pub const TEST_EVENT_TYPE: &str = "test_event";
+pub const TEST_ADMIN_KEY: &str = "test-bootstrap-admin-key";
+
+pub struct TestApp {
+    pub address: String,
+    pub database: Arc<RecordingDatabase>,
+    pub clock: Arc<MockClock>,
+    pub db_pool: Option<PgPool>,
+    pub dispatch_mode: DispatchMode,
+    pub api_client: reqwest::Client,
+}
+
+// Builds a client sending the given API key with every request
+pub fn client_with_key(builder: reqwest::ClientBuilder, key: &str) -> reqwest::Client {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {}", key)).unwrap(),
+    );
+    builder.default_headers(headers).build().unwrap()
+}
+
+pub fn test_queue_settings() -> QueueSettings {
+    QueueSettings {
+        retry_reconcile_interval_milliseconds: 100,
+        retry_release_per_second: 100,
+        delivery_timeout_milliseconds: 500,
+        dispatch_mode: DispatchMode::InProcess,
+        capacity: 50,
+        backpressure: BackpressurePolicy::Block,
+        backpressure_timeout_milliseconds: 5_000,
+        retry_after_seconds: 1,
+        readiness_depth_threshold: 40,
+        task_restart_backoff_milliseconds: 10,
+        task_restart_max_backoff_milliseconds: 1000,
+        replay_per_second: 1000,
+        stream_ack_timeout_milliseconds: 30_000,
+        retention: RetentionSettings {
+            prune_interval_milliseconds: 50,
+            batch_size: 2,
+            batch_pause_milliseconds: 1,
+            fulfilled_days: None,
+            dead_letter_days: None,
+            unfulfilled_days: None,
+            event_types: vec![],
+            archive_directory: None,
+        },
+        delivery_auth: DeliveryAuthSettings {
+            encryption_key: Some(Secret::new(
+                "dGVzdC1kZWxpdmVyeS1lbmNyeXB0aW9uLWtleS0zMmI=".to_string(),
+            )),
+            token_refresh_margin_seconds: 30,
+        },
+        delivery_tls: DeliveryTlsSettings {
+            ca_bundle_paths: vec![],
+            client_certificate_path: None,
+            client_key_path: None,
+        },
+    }
+}
+
+pub async fn spawn_app() -> TestApp {
+    let clock = Arc::new(MockClock::default());
+    let database: Arc<dyn Database> = Arc::new(InMemoryDatabase::new(clock.clone()));
+    spawn_app_with_database(database, clock, None, test_queue_settings(), None).await
+}
+
+pub async fn spawn_app_with_queue_settings(settings: QueueSettings) -> TestApp {
+    let clock = Arc::new(MockClock::default());
+    let database: Arc<dyn Database> = Arc::new(InMemoryDatabase::new(clock.clone()));
+    spawn_app_with_database(database, clock, None, settings, None).await
+}
+
+// Serves the API over TLS, calling it with the given client
+pub async fn spawn_app_with_tls(tls: ApiTlsSettings, api_client: reqwest::Client) -> TestApp {
+    let clock = Arc::new(MockClock::default());
+    let database: Arc<dyn Database> = Arc::new(InMemoryDatabase::new(clock.clone()));
+    let app =
+        spawn_app_with_database(database, clock, None, test_queue_settings(), Some(tls)).await;
+    TestApp { api_client, ..app }
+}
+
+// Returns None when no local Postgres is available
+pub async fn spawn_app_with_postgres() -> Option<TestApp> {
+    spawn_app_with_postgres_dispatch_mode(DispatchMode::InProcess).await
+}
+
+pub async fn spawn_app_with_postgres_dispatch_mode(dispatch_mode: DispatchMode) -> Option<TestApp> {
+    spawn_app_with_postgres_queue_settings(QueueSettings {
+        dispatch_mode,
+        ..test_queue_settings()
+    })
+    .await
+}
+
+pub async fn spawn_app_with_postgres_queue_settings(settings: QueueSettings) -> Option<TestApp> {
+    let mut configuration = get_configuration().expect("Failed to read configuration");
+    configuration.database.database_name = Uuid::new_v4().to_string();
+    let pool = configure_database(&configuration.database).await?;
+
+    let clock = Arc::new(MockClock::default());
+    let database: Arc<dyn Database> = Arc::new(PostgresDatabase::new(pool.clone(), clock.clone()));
+    Some(spawn_app_with_database(database, clock, Some(pool), settings, None).await)
+}
+
+// Starts a second instance sharing the given app's Postgres database, clock and dispatch mode
+pub async fn spawn_replica(app: &TestApp) -> TestApp {
+    let pool = app
+        .db_pool
+        .clone()
+        .expect("Replicas can only share a Postgres database");
+    let database: Arc<dyn Database> =
+        Arc::new(PostgresDatabase::new(pool.clone(), app.clock.clone()));
+    let settings = QueueSettings {
+        dispatch_mode: app.dispatch_mode,
+        ..test_queue_settings()
+    };
+    spawn_app_with_database(database, app.clock.clone(), Some(pool), settings, None).await
+}
+
+async fn spawn_app_with_database(
+    inner: Arc<dyn Database>,
+    clock: Arc<MockClock>,
+    db_pool: Option<PgPool>,
+    settings: QueueSettings,
+    tls: Option<ApiTlsSettings>,
+) -> TestApp {
+    Lazy::force(&TRACING);
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to random port");
+    let port = listener.local_addr().unwrap().port();
+    // rustls refuses IP addresses as server names, so TLS clients connect by name
+    let address = match tls {
+        Some(_) => format!("https://localhost:{}", port),
+        None => format!("http://127.0.0.1:{}", port),
+    };
+
+    let feature_flags: FeatureFlagConfig = HashMap::new();
+
+    let recording_database = Arc::new(RecordingDatabase::new(inner));
+    let db_arc: Arc<dyn Database> = recording_database.clone();
+    let database = web::Data::from(db_arc);
+    let dispatch_mode = settings.dispatch_mode;
+    let ventrix_queue = match dispatch_mode {
+        DispatchMode::InProcess => {
+            VentrixQueue::new(database.clone(), settings, clock.clone()).await
+        }
+        DispatchMode::Postgres => {
+            let pool = db_pool
+                .clone()
+                .expect("Postgres dispatch needs a Postgres database");
+            VentrixQueue::with_postgres_dispatch(
+                database.clone(),
+                settings,
+                clock.clone(),
+                PostgresNotifier::new(pool),
+            )
+            .await
+        }
+    };
+    let ventrix_queue = web::Data::new(ventrix_queue.expect("Invalid queue settings"));
+
+    let authenticator = Authenticator::new(
+        database.clone(),
+        Some(&Secret::new(TEST_ADMIN_KEY.to_string())),
+    );
+    let server = run(
+        listener,
+        database,
+        ventrix_queue,
+        clock.clone(),
+        feature_flags,
+        tls.as_ref(),
+        authenticator,
+    )
+    .await
+    .expect("Failed to bind address");
+    tokio::spawn(server);
+
+    TestApp {
+        address,
+        database: recording_database,
+        clock,
+        db_pool,
+        dispatch_mode,
+        api_client: client_with_key(reqwest::Client::builder(), TEST_ADMIN_KEY),
+    }
+}
+
+async fn configure_database(config: &DatabaseSettings) -> Option<PgPool> {
+    let mut connection = tokio::time::timeout(
+        Duration::from_secs(2),
+        PgConnection::connect(config.connection_string_without_db().expose_secret()),
+    )
+    .await
+    .ok()?
+    .ok()?;
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
+        .await
+        .expect("Failed to create database");
+
+    let connection_pool = PgPool::connect(config.connection_string().expose_secret())
+        .await
+        .expect("Failed to connect to Postgres");
+    sqlx::migrate!("./migrations")
+        .run(&connection_pool)
+        .await
+        .expect("Failed to migrate the database");
+
+    Some(connection_pool)
+}
+
+impl TestApp {
+    // The same app, called with another API key
+    pub fn with_api_key(&self, key: &str) -> TestApp {
+        TestApp {
+            address: self.address.clone(),
+            database: self.database.clone(),
+            clock: self.clock.clone(),
+            db_pool: self.db_pool.clone(),
+            dispatch_mode: self.dispatch_mode,
+            api_client: client_with_key(reqwest::Client::builder(), key),
+        }
+    }
+
+    pub async fn create_api_key(&self, body: Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/api/keys", &self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn metrics(&self) -> String {
+        self.api_client
+            .get(format!("{}/metrics", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .expect("Failed to read metrics")
+    }
+
+    pub async fn register_service(&self, name: &str, url: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/api/service/register", &self.address))
+            .json(&json!({ "name": name, "url": url }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn register_service_with_delivery(
+        &self,
+        name: &str,
+        url: &str,
+        delivery: Value,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/api/service/register", &self.address))
+            .json(&json!({ "name": name, "url": url, "delivery": delivery }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn register_event_type(&self, name: &str) -> reqwest::Response {
+        self.register_event_type_with_publishers(name, None).await
+    }
+
+    pub async fn register_event_type_with_publishers(
+        &self,
+        name: &str,
+        publishers: Option<&[&str]>,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/api/events/register", &self.address))
+            .json(&json!({
+                "name": name,
+                "publishers": publishers,
+                "description": "This is a test event",
+                "payload_definition": {
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "string"
+                        }
+                    },
+                    "required": ["name"]
+                }
+            }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn listen_to_event(
+        &self,
+        service_name: &str,
+        event_type: &str,
+        endpoint: &str,
+    ) -> reqwest::Response {
+        self.listen_to_event_with_filter(service_name, event_type, endpoint, None)
+            .await
+    }
+
+    pub async fn listen_to_event_with_filter(
+        &self,
+        service_name: &str,
+        event_type: &str,
+        endpoint: &str,
+        filter: Option<&str>,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/api/events/listen", &self.address))
+            .json(&json!({
+                "service_name": service_name,
+                "event_type": event_type,
+                "endpoint": endpoint,
+                "filter": filter
+            }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn listen_to_event_with_transform(
+        &self,
+        service_name: &str,
+        event_type: &str,
+        transform: Value,
+        target_schema: Option<Value>,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/api/events/listen", &self.address))
+            .json(&json!({
+                "service_name": service_name,
+                "event_type": event_type,
+                "endpoint": "/events",
+                "transform": transform,
+                "target_schema": target_schema
+            }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn listen_to_event_with_delivery(
+        &self,
+        service_name: &str,
+        event_type: &str,
+        delivery: Value,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/api/events/listen", &self.address))
+            .json(&json!({
+                "service_name": service_name,
+                "event_type": event_type,
+                "endpoint": "/events",
+                "delivery": delivery
+            }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn publish_event(&self, event_type: &str, payload: Value) -> reqwest::Response {
+        self.publish_event_with_headers(event_type, payload, &[])
+            .await
+    }
+
+    pub async fn publish_event_with_headers(
+        &self,
+        event_type: &str,
+        payload: Value,
+        headers: &[(&str, &str)],
+    ) -> reqwest::Response {
+        headers
+            .iter()
+            .fold(
+                self.api_client
+                    .post(format!("{}/api/events/publish", &self.address)),
+                |request, (name, value)| request.header(*name, *value),
+            )
+            .json(&json!({
+                "event_type": event_type,
+                "payload": payload.to_string()
+            }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn list_events(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/api/events", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_event(&self, event_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/api/events/{}", &self.address, event_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscriptions(&self, service_name: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/api/service/{}/subscriptions",
+                &self.address, service_name
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn start_replay(&self, body: Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/api/replays", &self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_replay(&self, replay_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/api/replays/{}", &self.address, replay_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn cancel_replay(&self, replay_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/api/replays/{}/cancel",
+                &self.address, replay_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Registers a service listening to TEST_EVENT_TYPE on the given subscriber url
+    pub async fn subscribe(&self, service_name: &str, url: &str) {
+        assert_eq!(201, self.register_service(service_name, url).await.status());
+        let _ = self.register_event_type(TEST_EVENT_TYPE).await;
+        assert_eq!(
+            201,
+            self.listen_to_event(service_name, TEST_EVENT_TYPE, "/events")
+                .await
+                .status()
+        );
+    }
+
+    // Waits for the queue to record a retry for the event, then moves the clock past the
+    // longest backoff so the next poll picks it up
+    pub async fn advance_past_retry(&self, event_id: Uuid, retries: i16) {
+        self.database
+            .wait_for(|call| match call {
+                DatabaseCall::FailedEventAdded(id) => *id == event_id && retries == 0,
+                DatabaseCall::RetryScheduled { id, retries: r } => *id == event_id && *r == retries,
+                _ => false,
+            })
+            .await;
+        self.clock.advance(ChronoDuration::minutes(10));
+    }
+}
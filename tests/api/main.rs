@@ -0,0 +1,22 @@
+mod api_keys;
+mod api_tls;
+mod backpressure;
+mod collector_stub;
+mod delivery;
+mod delivery_auth;
+mod delivery_tls;
+mod event_history;
+mod event_type_patterns;
+mod health_check;
+mod helpers;
+mod metrics;
+mod mock_subscriber;
+mod payload_transforms;
+mod publish_authorization;
+mod recording_database;
+mod replay;
+mod retention;
+mod subscription_filters;
+mod test_certificates;
+mod tls_subscriber;
+mod trace_propagation;
@@ -0,0 +1,170 @@
+use serde_json::json;
+use std::time::Duration;
+use uuid::Uuid;
+use ventrix::infrastructure::persistence::MAX_RETRIES;
+
+use crate::{
+    helpers::{spawn_app, TestApp, TEST_EVENT_TYPE},
+    mock_subscriber::MockSubscriber,
+    recording_database::DatabaseCall,
+};
+
+// Metrics are shared by every app in the test process, so tests use their own service
+// names and only assert on values they alone can move
+fn metric_value(metrics: &str, series: &str) -> Option<f64> {
+    metrics.lines().find_map(|line| {
+        line.strip_prefix(series)
+            .and_then(|value| value.trim().parse().ok())
+    })
+}
+
+async fn wait_for_metric(app: &TestApp, series: &str, expected: impl Fn(f64) -> bool) {
+    for _ in 0..100 {
+        if metric_value(&app.metrics().await, series).is_some_and(&expected) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("Timed out waiting for metric {}", series);
+}
+
+async fn delivered_event_id(subscriber: &MockSubscriber, deliveries: usize) -> Uuid {
+    subscriber.wait_for_deliveries(deliveries).await[0].event["id"]
+        .as_str()
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .expect("Delivery did not contain an event id")
+}
+
+#[tokio::test]
+async fn metrics_endpoint_exposes_prometheus_text() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(response.status().is_success());
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    let metrics = response.text().await.unwrap();
+    assert!(metrics.contains("# TYPE ventrix_queue_depth gauge"));
+}
+
+#[tokio::test]
+async fn publish_outcomes_and_schema_failures_are_counted() {
+    let app = spawn_app().await;
+    let event_type = "metrics_publish_event";
+    app.register_event_type(event_type).await;
+
+    app.publish_event(event_type, json!({ "name": "John Rustsworth" }))
+        .await;
+    app.publish_event(event_type, json!({ "age": 42 })).await;
+    app.publish_event("metrics_unknown_event", json!({})).await;
+
+    let metrics = app.metrics().await;
+    assert_eq!(
+        Some(1.0),
+        metric_value(
+            &metrics,
+            r#"ventrix_events_published_total{event_type="metrics_publish_event",outcome="accepted"}"#
+        )
+    );
+    assert_eq!(
+        Some(1.0),
+        metric_value(
+            &metrics,
+            r#"ventrix_events_published_total{event_type="metrics_publish_event",outcome="invalid-payload"}"#
+        )
+    );
+    // Event types that are not registered don't become labels; other tests count here too
+    assert!(metric_value(
+        &metrics,
+        r#"ventrix_events_published_total{event_type="unknown",outcome="event-type-not-found"}"#
+    )
+    .is_some_and(|count| count >= 1.0));
+    assert!(!metrics.contains("metrics_unknown_event"));
+    assert_eq!(
+        Some(1.0),
+        metric_value(
+            &metrics,
+            r#"ventrix_schema_validation_failures_total{event_type="metrics_publish_event"}"#
+        )
+    );
+    assert!(metric_value(
+        &metrics,
+        r#"ventrix_db_query_duration_seconds_count{backend="in_memory",operation="save_published_event"}"#
+    )
+    .is_some_and(|count| count >= 1.0));
+}
+
+#[tokio::test]
+async fn delivery_outcomes_are_counted_by_service_and_status_class() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.fail_times(1);
+    app.subscribe("metrics_vinnie", &subscriber.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    let id = delivered_event_id(&subscriber, 1).await;
+    app.advance_past_retry(id, 0).await;
+    subscriber.wait_for_deliveries(2).await;
+    app.database
+        .wait_for(|call| *call == DatabaseCall::Fulfilled(id))
+        .await;
+
+    let metrics = app.metrics().await;
+    for (series, expected) in [
+        (
+            r#"ventrix_delivery_attempts_total{service="metrics_vinnie",status_class="5xx"}"#,
+            1.0,
+        ),
+        (
+            r#"ventrix_delivery_failures_total{service="metrics_vinnie",status_class="5xx"}"#,
+            1.0,
+        ),
+        (
+            r#"ventrix_delivery_attempts_total{service="metrics_vinnie",status_class="2xx"}"#,
+            1.0,
+        ),
+        (
+            r#"ventrix_delivery_successes_total{service="metrics_vinnie",status_class="2xx"}"#,
+            1.0,
+        ),
+        (
+            r#"ventrix_delivery_duration_seconds_count{service="metrics_vinnie"}"#,
+            2.0,
+        ),
+    ] {
+        assert_eq!(Some(expected), metric_value(&metrics, series), "{}", series);
+    }
+    assert!(metric_value(&metrics, "ventrix_retries_total").is_some_and(|count| count >= 1.0));
+}
+
+#[tokio::test]
+async fn exhausted_retries_are_counted_as_dead_letters() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.fail_times(MAX_RETRIES as usize + 1);
+    app.subscribe("metrics_viktor", &subscriber.url).await;
+    let dead_letters_before =
+        metric_value(&app.metrics().await, "ventrix_dead_letters_total").unwrap_or_default();
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    let id = delivered_event_id(&subscriber, 1).await;
+    for retries in 0..MAX_RETRIES {
+        app.advance_past_retry(id, retries).await;
+        subscriber.wait_for_deliveries(retries as usize + 2).await;
+    }
+
+    wait_for_metric(&app, "ventrix_dead_letters_total", |dead_letters| {
+        dead_letters > dead_letters_before
+    })
+    .await;
+}
@@ -0,0 +1,179 @@
+use serde_json::Value;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum MockResponse {
+    Status(u16),
+    // 200 with a JSON body
+    Json(&'static str),
+    Hang,
+    CloseConnection,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReceivedDelivery {
+    pub path: String,
+    // Header names are lowercased
+    pub headers: HashMap<String, String>,
+    // Null when the body is not JSON, e.g. a form
+    pub event: Value,
+    pub body: String,
+}
+
+// In-process HTTP subscriber with a scripted list of responses. Once the script is
+// exhausted every request is answered with 200.
+pub struct MockSubscriber {
+    pub url: String,
+    script: Arc<Mutex<VecDeque<MockResponse>>>,
+    deliveries: Arc<Mutex<Vec<ReceivedDelivery>>>,
+}
+
+impl MockSubscriber {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind mock subscriber");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let script = Arc::new(Mutex::new(VecDeque::new()));
+        let deliveries = Arc::new(Mutex::new(vec![]));
+
+        let accept_script = Arc::clone(&script);
+        let accept_deliveries = Arc::clone(&deliveries);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let script = Arc::clone(&accept_script);
+                let deliveries = Arc::clone(&accept_deliveries);
+                tokio::spawn(handle_connection(stream, script, deliveries));
+            }
+        });
+
+        Self {
+            url,
+            script,
+            deliveries,
+        }
+    }
+
+    pub fn respond_with(&self, responses: impl IntoIterator<Item = MockResponse>) {
+        self.script.lock().unwrap().extend(responses);
+    }
+
+    pub fn fail_times(&self, times: usize) {
+        self.respond_with(std::iter::repeat_n(MockResponse::Status(500), times));
+    }
+
+    pub fn deliveries(&self) -> Vec<ReceivedDelivery> {
+        self.deliveries.lock().unwrap().clone()
+    }
+
+    pub async fn wait_for_deliveries(&self, count: usize) -> Vec<ReceivedDelivery> {
+        for _ in 0..100 {
+            let deliveries = self.deliveries();
+            if deliveries.len() >= count {
+                return deliveries;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!(
+            "Expected {} deliveries, received {}",
+            count,
+            self.deliveries().len()
+        );
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    script: Arc<Mutex<VecDeque<MockResponse>>>,
+    deliveries: Arc<Mutex<Vec<ReceivedDelivery>>>,
+) {
+    let Some(delivery) = read_request(&mut stream).await else {
+        return;
+    };
+    deliveries.lock().unwrap().push(delivery);
+
+    let response = script
+        .lock()
+        .unwrap()
+        .pop_front()
+        .unwrap_or(MockResponse::Status(200));
+
+    match response {
+        MockResponse::Status(status) => {
+            let response = format!(
+                "HTTP/1.1 {} Mock\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        }
+        MockResponse::Json(body) => {
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        }
+        MockResponse::Hang => {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        }
+        MockResponse::CloseConnection => drop(stream),
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<ReceivedDelivery> {
+    let mut buffer = vec![];
+    let mut chunk = [0u8; 1024];
+
+    let header_end = loop {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let path = head.split_whitespace().nth(1)?.to_string();
+    let headers: HashMap<String, String> = head
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.trim().to_lowercase(), value.trim().to_string()))
+        })
+        .collect();
+    let content_length = headers
+        .get("content-length")
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    while buffer.len() < header_end + content_length {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let body = &buffer[header_end..header_end + content_length];
+    Some(ReceivedDelivery {
+        path,
+        headers,
+        event: serde_json::from_slice(body).unwrap_or(Value::Null),
+        body: String::from_utf8_lossy(body).to_string(),
+    })
+}
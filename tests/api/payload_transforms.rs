@@ -0,0 +1,175 @@
+use serde_json::{json, Value};
+
+use crate::{
+    helpers::{spawn_app, spawn_app_with_postgres, TestApp, TEST_EVENT_TYPE},
+    mock_subscriber::MockSubscriber,
+};
+
+fn delivered_payload(event: &Value) -> Value {
+    serde_json::from_str(event["payload"].as_str().unwrap()).expect("Invalid delivered payload")
+}
+
+#[tokio::test]
+async fn listening_with_an_invalid_transform_returns_400() {
+    let app = spawn_app().await;
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    let target_schema = json!({
+        "type": "object",
+        "properties": { "customer": { "type": "integer" } },
+        "required": ["customer"]
+    });
+    let test_cases = vec![
+        (
+            json!(["payload.name"]),
+            None,
+            "the transform is not an object",
+        ),
+        (
+            json!({ "customer": "payload.surname" }),
+            None,
+            "the field is not in the schema",
+        ),
+        (
+            json!({ "customer": "payload.name" }),
+            Some(target_schema.clone()),
+            "the field's type does not match the target schema",
+        ),
+        (
+            json!({ "kind": "event_type" }),
+            Some(target_schema),
+            "a field the target schema requires is missing",
+        ),
+    ];
+
+    for (service_name, (transform, target_schema, description)) in
+        ["vinnie", "viktor", "vanessa", "valerie"]
+            .into_iter()
+            .zip(test_cases)
+    {
+        assert_eq!(
+            201,
+            app.register_service(service_name, "http://localhost:8081")
+                .await
+                .status()
+        );
+        let response = app
+            .listen_to_event_with_transform(service_name, TEST_EVENT_TYPE, transform, target_schema)
+            .await;
+
+        assert_eq!(
+            400,
+            response.status(),
+            "The API did not fail with 400 Bad Request when {}.",
+            description
+        );
+        let body: Value = response.json().await.expect("Failed to parse body");
+        assert_eq!("urn:ventrix:error:invalid-transform", body["type"]);
+    }
+}
+
+async fn subscribers_receive_the_payload_shaped_by_their_transform(app: TestApp) {
+    let transformed_subscriber = MockSubscriber::start().await;
+    let subscriber = MockSubscriber::start().await;
+    assert_eq!(
+        201,
+        app.register_service("vinnie", &transformed_subscriber.url)
+            .await
+            .status()
+    );
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    assert_eq!(
+        201,
+        app.listen_to_event_with_transform(
+            "vinnie",
+            TEST_EVENT_TYPE,
+            json!({ "customer": { "full_name": "payload.name" }, "kind": "event_type" }),
+            Some(json!({
+                "type": "object",
+                "properties": { "kind": { "type": "string" } },
+                "required": ["customer", "kind"]
+            })),
+        )
+        .await
+        .status()
+    );
+    app.subscribe("viktor", &subscriber.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+
+    let transformed = transformed_subscriber.wait_for_deliveries(1).await;
+    assert_eq!(
+        json!({ "customer": { "full_name": "John Rustsworth" }, "kind": TEST_EVENT_TYPE }),
+        delivered_payload(&transformed[0].event)
+    );
+    let deliveries = subscriber.wait_for_deliveries(1).await;
+    assert_eq!(
+        json!({ "name": "John Rustsworth" }),
+        delivered_payload(&deliveries[0].event)
+    );
+    assert_eq!(transformed[0].event["id"], deliveries[0].event["id"]);
+}
+
+#[tokio::test]
+async fn subscribers_receive_the_payload_shaped_by_their_transform_in_memory() {
+    subscribers_receive_the_payload_shaped_by_their_transform(spawn_app().await).await;
+}
+
+#[tokio::test]
+async fn subscribers_receive_the_payload_shaped_by_their_transform_in_postgres() {
+    let Some(app) = spawn_app_with_postgres().await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    subscribers_receive_the_payload_shaped_by_their_transform(app).await;
+}
+
+#[tokio::test]
+async fn payloads_not_matching_the_target_schema_are_not_sent() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    let other_subscriber = MockSubscriber::start().await;
+    assert_eq!(
+        201,
+        app.register_service("vinnie", &subscriber.url)
+            .await
+            .status()
+    );
+    // Pattern subscriptions cannot be checked against a source schema when listening
+    assert_eq!(
+        201,
+        app.listen_to_event_with_transform(
+            "vinnie",
+            "test_event.#",
+            json!({ "customer": "payload.surname" }),
+            Some(json!({ "type": "object", "required": ["customer"] })),
+        )
+        .await
+        .status()
+    );
+    app.subscribe("viktor", &other_subscriber.url).await;
+
+    // Events are dispatched in order, so once the second is delivered the first is done
+    for _ in 0..2 {
+        app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+            .await;
+    }
+    let deliveries = other_subscriber.wait_for_deliveries(2).await;
+
+    let event_id = deliveries[0].event["id"].as_str().unwrap();
+    let history: Value = app
+        .get_event(event_id)
+        .await
+        .json()
+        .await
+        .expect("Failed to parse body");
+    let attempt = history["deliveries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|delivery| delivery["service_name"] == "vinnie")
+        .expect("The rejected delivery should be recorded");
+    assert_eq!(false, attempt["succeeded"]);
+    assert!(attempt["error"].as_str().unwrap().contains("target schema"));
+    assert!(subscriber.deliveries().is_empty());
+}
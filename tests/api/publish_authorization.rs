@@ -0,0 +1,220 @@
+use chrono::Duration as ChronoDuration;
+use serde_json::{json, Value};
+
+use crate::{
+    helpers::{spawn_app, spawn_app_with_postgres, TestApp},
+    mock_subscriber::MockSubscriber,
+};
+
+// Returns a client for the app using a new publisher key
+async fn publisher(app: &TestApp, event_types: &[&str], service_name: Option<&str>) -> TestApp {
+    let response = app
+        .create_api_key(json!({
+            "name": service_name.unwrap_or("anonymous"),
+            "role": "publisher",
+            "event_types": event_types,
+            "service_name": service_name,
+        }))
+        .await;
+    assert_eq!(201, response.status());
+    let created: Value = response.json().await.expect("Failed to parse body");
+    app.with_api_key(created["key"].as_str().unwrap())
+}
+
+async fn publish_denials(app: &TestApp) -> Vec<Value> {
+    let response = app
+        .api_client
+        .get(format!("{}/api/audit/publish_denials", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status());
+    let body: Value = response.json().await.expect("Failed to parse body");
+    body["publish_denials"].as_array().unwrap().clone()
+}
+
+async fn only_allowed_services_publish_owned_event_types(app: TestApp) {
+    let subscriber = MockSubscriber::start().await;
+    for service in ["payments", "orders"] {
+        assert_eq!(
+            201,
+            app.register_service(service, &subscriber.url)
+                .await
+                .status()
+        );
+    }
+    let response = app
+        .register_event_type_with_publishers("payments.settled", Some(&["payments"]))
+        .await;
+    assert_eq!(201, response.status());
+    let registered: Value = response.json().await.expect("Failed to parse body");
+    assert_eq!(json!(["payments"]), registered["publishers"]);
+    let payload = json!({ "name": "John Rustsworth" });
+
+    let payments = publisher(&app, &["payments.*"], Some("payments")).await;
+    let response = payments
+        .publish_event("payments.settled", payload.clone())
+        .await;
+    assert_eq!(201, response.status());
+
+    let orders = publisher(&app, &["payments.*"], Some("orders")).await;
+    let response = orders
+        .publish_event("payments.settled", payload.clone())
+        .await;
+    assert_eq!(403, response.status());
+    let body: Value = response.json().await.expect("Failed to parse body");
+    assert_eq!("urn:ventrix:error:forbidden", body["type"]);
+
+    // A key publishing as no service is refused too, while admins are not limited
+    app.clock.advance(ChronoDuration::seconds(1));
+    let anonymous = publisher(&app, &["payments.*"], None).await;
+    let response = anonymous
+        .publish_event("payments.settled", payload.clone())
+        .await;
+    assert_eq!(403, response.status());
+    assert_eq!(
+        201,
+        app.publish_event("payments.settled", payload)
+            .await
+            .status()
+    );
+
+    let denials = publish_denials(&app).await;
+    assert_eq!(2, denials.len());
+    assert_eq!("anonymous", denials[0]["api_key_name"]);
+    assert!(denials[0]["service_name"].is_null());
+    assert_eq!("payments.settled", denials[1]["event_type"]);
+    assert_eq!("orders", denials[1]["api_key_name"]);
+    assert_eq!("orders", denials[1]["service_name"]);
+    assert!(denials[1]["api_key_id"].is_string());
+    assert!(denials[1]["attempted_at"].is_string());
+    assert!(denials[1]["reason"]
+        .as_str()
+        .unwrap()
+        .contains("not an allowed publisher"));
+}
+
+#[tokio::test]
+async fn only_allowed_services_publish_owned_event_types_in_memory() {
+    only_allowed_services_publish_owned_event_types(spawn_app().await).await;
+}
+
+#[tokio::test]
+async fn only_allowed_services_publish_owned_event_types_in_postgres() {
+    let Some(app) = spawn_app_with_postgres().await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    only_allowed_services_publish_owned_event_types(app).await;
+}
+
+#[tokio::test]
+async fn event_types_without_publishers_accept_any_publisher_key_for_them() {
+    let app = spawn_app().await;
+    assert_eq!(
+        201,
+        app.register_event_type("orders.created").await.status()
+    );
+    let orders = publisher(&app, &["orders.*"], None).await;
+
+    let response = orders
+        .publish_event("orders.created", json!({ "name": "John Rustsworth" }))
+        .await;
+
+    assert_eq!(201, response.status());
+    assert!(publish_denials(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn publishing_outside_the_key_scope_is_recorded() {
+    let app = spawn_app().await;
+    assert_eq!(
+        201,
+        app.register_event_type("payments.settled").await.status()
+    );
+    let orders = publisher(&app, &["orders.*"], None).await;
+
+    let response = orders
+        .publish_event("payments.settled", json!({ "name": "John Rustsworth" }))
+        .await;
+
+    assert_eq!(403, response.status());
+    let denials = publish_denials(&app).await;
+    assert_eq!(1, denials.len());
+    assert_eq!("payments.settled", denials[0]["event_type"]);
+    assert!(denials[0]["reason"]
+        .as_str()
+        .unwrap()
+        .contains("may not publish"));
+    assert!(app
+        .metrics()
+        .await
+        .contains(r#"event_type="payments.settled",outcome="forbidden""#));
+
+    // Refused whether or not the event type is registered, so keys can't probe for event types
+    let response = orders
+        .publish_event("payments.refunded", json!({ "name": "John Rustsworth" }))
+        .await;
+    assert_eq!(403, response.status());
+
+    // Only admins may read the audit trail
+    let response = orders
+        .api_client
+        .get(format!("{}/api/audit/publish_denials", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(403, response.status());
+}
+
+#[tokio::test]
+async fn removed_services_no_longer_publish_their_event_types() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    assert_eq!(
+        201,
+        app.register_service("payments", &subscriber.url)
+            .await
+            .status()
+    );
+    assert_eq!(
+        201,
+        app.register_event_type_with_publishers("payments.settled", Some(&["payments"]))
+            .await
+            .status()
+    );
+    assert_eq!(
+        204,
+        app.api_client
+            .post(format!("{}/api/service/remove", &app.address))
+            .json(&json!({ "name": "payments" }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .status()
+    );
+
+    // A new service under the same name does not inherit the right to publish
+    assert_eq!(
+        201,
+        app.register_service("payments", &subscriber.url)
+            .await
+            .status()
+    );
+    let payments = publisher(&app, &["payments.*"], Some("payments")).await;
+    let response = payments
+        .publish_event("payments.settled", json!({ "name": "John Rustsworth" }))
+        .await;
+    assert_eq!(403, response.status());
+}
+
+#[tokio::test]
+async fn event_types_with_an_unknown_publisher_return_422() {
+    let app = spawn_app().await;
+
+    let response = app
+        .register_event_type_with_publishers("payments.settled", Some(&["ghost"]))
+        .await;
+
+    assert_eq!(422, response.status());
+}
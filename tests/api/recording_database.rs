@@ -0,0 +1,363 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use uuid::Uuid;
+use ventrix::{
+    common::{
+        errors::VentrixError,
+        types::{
+            ApiKey, DeliveryAttempt, EventFulfillmentDetails, EventQuery, ListenToEventReq,
+            NewEventTypeRequest, PayloadSchema, PruneCriteria, PublishDenial, PublishedEvent,
+            ReplayJob, Subscription, VentrixEvent,
+        },
+    },
+    domain::models::service::{RegisterServiceRequest, Service},
+    infrastructure::persistence::{
+        Database, DeleteDataResponse, InsertDataResponse, PruningLock, UpdateDataResponse,
+    },
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DatabaseCall {
+    Fulfilled(Uuid),
+    Dispatched(Uuid),
+    FailedEventAdded(Uuid),
+    RetryScheduled { id: Uuid, retries: i16 },
+    FailedEventResolved(Uuid),
+    EventsDeleted(Vec<Uuid>),
+}
+
+// Delegates to a real backend while recording the writes the queue makes, so tests can
+// wait on delivery outcomes instead of sleeping
+#[derive(Debug)]
+pub struct RecordingDatabase {
+    inner: Arc<dyn Database>,
+    calls: Mutex<Vec<DatabaseCall>>,
+    panic_on_next_lookup: AtomicBool,
+}
+
+impl RecordingDatabase {
+    pub fn new(inner: Arc<dyn Database>) -> Self {
+        Self {
+            inner,
+            calls: Mutex::new(vec![]),
+            panic_on_next_lookup: AtomicBool::new(false),
+        }
+    }
+
+    // Makes the next subscriber lookup panic, crashing whichever task made it
+    pub fn panic_on_next_lookup(&self) {
+        self.panic_on_next_lookup.store(true, Ordering::SeqCst);
+    }
+
+    pub fn calls(&self) -> Vec<DatabaseCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    pub async fn wait_for(&self, predicate: impl Fn(&DatabaseCall) -> bool) -> DatabaseCall {
+        for _ in 0..100 {
+            if let Some(call) = self.calls().into_iter().find(|call| predicate(call)) {
+                return call;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!(
+            "Timed out waiting for database call. Calls: {:?}",
+            self.calls()
+        );
+    }
+
+    fn record(&self, call: DatabaseCall) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+#[async_trait]
+impl Database for RecordingDatabase {
+    async fn register_service(
+        &self,
+        service: &RegisterServiceRequest,
+    ) -> Result<InsertDataResponse, VentrixError> {
+        self.inner.register_service(service).await
+    }
+
+    async fn remove_service(&self, service_name: &str) -> Result<DeleteDataResponse, VentrixError> {
+        self.inner.remove_service(service_name).await
+    }
+
+    async fn register_event_type(
+        &self,
+        event_type: &NewEventTypeRequest,
+    ) -> Result<InsertDataResponse, VentrixError> {
+        self.inner.register_event_type(event_type).await
+    }
+
+    async fn get_service(&self, service_name: &str) -> Result<Service, VentrixError> {
+        self.inner.get_service(service_name).await
+    }
+
+    async fn save_published_event(
+        &self,
+        event: &VentrixEvent,
+    ) -> Result<InsertDataResponse, VentrixError> {
+        self.inner.save_published_event(event).await
+    }
+
+    async fn fulfil_event(&self, event: &VentrixEvent) -> Result<UpdateDataResponse, VentrixError> {
+        let response = self.inner.fulfil_event(event).await?;
+        self.record(DatabaseCall::Fulfilled(event.id));
+        Ok(response)
+    }
+
+    async fn register_service_for_event_type(
+        &self,
+        listen_to_event_req: &ListenToEventReq,
+    ) -> Result<InsertDataResponse, VentrixError> {
+        self.inner
+            .register_service_for_event_type(listen_to_event_req)
+            .await
+    }
+
+    async fn get_service_by_event_type(
+        &self,
+        event_type: &str,
+    ) -> Result<Vec<EventFulfillmentDetails>, VentrixError> {
+        if self.panic_on_next_lookup.swap(false, Ordering::SeqCst) {
+            panic!("Injected failure looking up subscribers");
+        }
+        self.inner.get_service_by_event_type(event_type).await
+    }
+
+    async fn get_subscriptions(
+        &self,
+        service_name: &str,
+    ) -> Result<Vec<Subscription>, VentrixError> {
+        self.inner.get_subscriptions(service_name).await
+    }
+
+    async fn get_schema_for_event_type(
+        &self,
+        event_type: &str,
+    ) -> Result<PayloadSchema, VentrixError> {
+        self.inner.get_schema_for_event_type(event_type).await
+    }
+
+    async fn get_publishers_for_event_type(
+        &self,
+        event_type: &str,
+    ) -> Result<Option<Vec<String>>, VentrixError> {
+        self.inner.get_publishers_for_event_type(event_type).await
+    }
+
+    async fn add_failed_event(
+        &self,
+        event: &VentrixEvent,
+    ) -> Result<InsertDataResponse, VentrixError> {
+        let response = self.inner.add_failed_event(event).await?;
+        self.record(DatabaseCall::FailedEventAdded(event.id));
+        Ok(response)
+    }
+
+    async fn resolve_failed_event(
+        &self,
+        event_id: Uuid,
+    ) -> Result<UpdateDataResponse, VentrixError> {
+        let response = self.inner.resolve_failed_event(event_id).await?;
+        self.record(DatabaseCall::FailedEventResolved(event_id));
+        Ok(response)
+    }
+
+    async fn update_retry_time(
+        &self,
+        event_id: Uuid,
+        new_retry_time: DateTime<Utc>,
+        retries: i16,
+    ) -> Result<UpdateDataResponse, VentrixError> {
+        let response = self
+            .inner
+            .update_retry_time(event_id, new_retry_time, retries)
+            .await?;
+        self.record(DatabaseCall::RetryScheduled {
+            id: event_id,
+            retries,
+        });
+        Ok(response)
+    }
+
+    async fn claim_failed_events(
+        &self,
+        lease_owner: &str,
+        lease_duration: ChronoDuration,
+        limit: i64,
+    ) -> Result<Vec<VentrixEvent>, VentrixError> {
+        self.inner
+            .claim_failed_events(lease_owner, lease_duration, limit)
+            .await
+    }
+
+    async fn get_next_retry_time(&self) -> Result<Option<DateTime<Utc>>, VentrixError> {
+        self.inner.get_next_retry_time().await
+    }
+
+    async fn claim_pending_events(
+        &self,
+        lease_owner: &str,
+        lease_duration: ChronoDuration,
+        published_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<VentrixEvent>, VentrixError> {
+        self.inner
+            .claim_pending_events(lease_owner, lease_duration, published_before, limit)
+            .await
+    }
+
+    async fn renew_pending_lease(
+        &self,
+        event_id: Uuid,
+        lease_owner: &str,
+        lease_duration: ChronoDuration,
+    ) -> Result<bool, VentrixError> {
+        self.inner
+            .renew_pending_lease(event_id, lease_owner, lease_duration)
+            .await
+    }
+
+    async fn mark_event_dispatched(
+        &self,
+        event_id: Uuid,
+    ) -> Result<UpdateDataResponse, VentrixError> {
+        let response = self.inner.mark_event_dispatched(event_id).await?;
+        self.record(DatabaseCall::Dispatched(event_id));
+        Ok(response)
+    }
+
+    async fn mark_event_spilled(&self, event_id: Uuid) -> Result<UpdateDataResponse, VentrixError> {
+        self.inner.mark_event_spilled(event_id).await
+    }
+
+    async fn take_spilled_events(&self, limit: i64) -> Result<Vec<VentrixEvent>, VentrixError> {
+        self.inner.take_spilled_events(limit).await
+    }
+
+    async fn record_delivery_attempt(
+        &self,
+        attempt: &DeliveryAttempt,
+    ) -> Result<InsertDataResponse, VentrixError> {
+        self.inner.record_delivery_attempt(attempt).await
+    }
+
+    async fn list_events(&self, query: &EventQuery) -> Result<Vec<PublishedEvent>, VentrixError> {
+        self.inner.list_events(query).await
+    }
+
+    async fn get_event(&self, event_id: Uuid) -> Result<PublishedEvent, VentrixError> {
+        self.inner.get_event(event_id).await
+    }
+
+    async fn get_delivery_attempts(
+        &self,
+        event_id: Uuid,
+    ) -> Result<Vec<DeliveryAttempt>, VentrixError> {
+        self.inner.get_delivery_attempts(event_id).await
+    }
+
+    async fn get_delivery_attempts_for_events(
+        &self,
+        event_ids: &[Uuid],
+    ) -> Result<Vec<DeliveryAttempt>, VentrixError> {
+        self.inner.get_delivery_attempts_for_events(event_ids).await
+    }
+
+    async fn create_replay_job(&self, job: &ReplayJob) -> Result<InsertDataResponse, VentrixError> {
+        self.inner.create_replay_job(job).await
+    }
+
+    async fn get_replay_job(&self, replay_id: Uuid) -> Result<ReplayJob, VentrixError> {
+        self.inner.get_replay_job(replay_id).await
+    }
+
+    async fn update_replay_job(&self, job: &ReplayJob) -> Result<UpdateDataResponse, VentrixError> {
+        self.inner.update_replay_job(job).await
+    }
+
+    async fn claim_replay_jobs(
+        &self,
+        lease_owner: &str,
+        lease_duration: ChronoDuration,
+    ) -> Result<Vec<ReplayJob>, VentrixError> {
+        self.inner
+            .claim_replay_jobs(lease_owner, lease_duration)
+            .await
+    }
+
+    async fn cancel_replay_job(&self, replay_id: Uuid) -> Result<ReplayJob, VentrixError> {
+        self.inner.cancel_replay_job(replay_id).await
+    }
+
+    async fn count_events_to_replay(&self, job: &ReplayJob) -> Result<i64, VentrixError> {
+        self.inner.count_events_to_replay(job).await
+    }
+
+    async fn get_events_to_replay(
+        &self,
+        job: &ReplayJob,
+        limit: i64,
+    ) -> Result<Vec<(DateTime<Utc>, VentrixEvent)>, VentrixError> {
+        self.inner.get_events_to_replay(job, limit).await
+    }
+
+    async fn try_lock_pruning(&self) -> Result<Option<PruningLock>, VentrixError> {
+        self.inner.try_lock_pruning().await
+    }
+
+    async fn get_events_to_prune(
+        &self,
+        criteria: &PruneCriteria,
+        limit: i64,
+    ) -> Result<Vec<PublishedEvent>, VentrixError> {
+        self.inner.get_events_to_prune(criteria, limit).await
+    }
+
+    async fn delete_events(&self, event_ids: &[Uuid]) -> Result<DeleteDataResponse, VentrixError> {
+        let response = self.inner.delete_events(event_ids).await?;
+        self.record(DatabaseCall::EventsDeleted(event_ids.to_vec()));
+        Ok(response)
+    }
+
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<InsertDataResponse, VentrixError> {
+        self.inner.create_api_key(api_key).await
+    }
+
+    async fn find_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, VentrixError> {
+        self.inner.find_api_key(key_hash).await
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>, VentrixError> {
+        self.inner.list_api_keys().await
+    }
+
+    async fn revoke_api_key(&self, key_id: Uuid) -> Result<ApiKey, VentrixError> {
+        self.inner.revoke_api_key(key_id).await
+    }
+
+    async fn record_publish_denial(
+        &self,
+        denial: &PublishDenial,
+    ) -> Result<InsertDataResponse, VentrixError> {
+        self.inner.record_publish_denial(denial).await
+    }
+
+    async fn list_publish_denials(&self, limit: i64) -> Result<Vec<PublishDenial>, VentrixError> {
+        self.inner.list_publish_denials(limit).await
+    }
+
+    async fn check_health(&self) -> Result<(), VentrixError> {
+        self.inner.check_health().await
+    }
+}
@@ -0,0 +1,342 @@
+use std::time::Duration;
+
+use chrono::Duration as ChronoDuration;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use ventrix::{
+    common::{
+        clock::Clock,
+        types::{ReplayJob, ReplayStatus},
+    },
+    infrastructure::persistence::Database,
+};
+
+use crate::{
+    helpers::{spawn_app, spawn_app_with_postgres, TestApp, TEST_EVENT_TYPE},
+    mock_subscriber::MockSubscriber,
+    recording_database::DatabaseCall,
+};
+
+const REPLAY_HEADER: &str = "x-ventrix-replay";
+
+// Publishes events a minute apart and waits until each has been delivered and fulfilled
+async fn publish_events(app: &TestApp, subscriber: &MockSubscriber, count: usize) -> Vec<String> {
+    let mut event_ids = vec![];
+    for index in 0..count {
+        app.publish_event(
+            TEST_EVENT_TYPE,
+            json!({ "name": format!("Event {}", index) }),
+        )
+        .await;
+        let deliveries = subscriber.wait_for_deliveries(event_ids.len() + 1).await;
+        let event_id = deliveries[event_ids.len()].event["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let id = Uuid::parse_str(&event_id).unwrap();
+        app.database
+            .wait_for(|call| *call == DatabaseCall::Fulfilled(id))
+            .await;
+        event_ids.push(event_id);
+        app.clock.advance(ChronoDuration::minutes(1));
+    }
+    event_ids
+}
+
+async fn start_replay(app: &TestApp, body: Value) -> Value {
+    let response = app.start_replay(body).await;
+    assert_eq!(202, response.status());
+    response.json().await.expect("Failed to parse body")
+}
+
+async fn wait_for_replay_status(app: &TestApp, replay_id: &str, status: &str) -> Value {
+    let mut replay = Value::Null;
+    for _ in 0..100 {
+        replay = app
+            .get_replay(replay_id)
+            .await
+            .json()
+            .await
+            .expect("Failed to parse body");
+        if replay["status"] == status {
+            return replay;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("Replay did not become {}: {}", status, replay);
+}
+
+fn replayed_event_ids(subscriber: &MockSubscriber, replay_id: &str) -> Vec<String> {
+    subscriber
+        .deliveries()
+        .iter()
+        .filter(|delivery| {
+            delivery.headers.get(REPLAY_HEADER).map(String::as_str) == Some(replay_id)
+        })
+        .map(|delivery| delivery.event["id"].as_str().unwrap().to_string())
+        .collect()
+}
+
+async fn replay_redelivers_a_time_window_to_one_service(app: TestApp) {
+    let subscriber = MockSubscriber::start().await;
+    let other_subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+    app.subscribe("other", &other_subscriber.url).await;
+    let start = app.clock.now();
+    let event_ids = publish_events(&app, &subscriber, 3).await;
+    other_subscriber.wait_for_deliveries(3).await;
+
+    let replay = start_replay(
+        &app,
+        json!({
+            "service_name": "vinnie",
+            "event_type": TEST_EVENT_TYPE,
+            "published_after": start.to_rfc3339(),
+            "published_before": app.clock.now().to_rfc3339(),
+        }),
+    )
+    .await;
+    assert_eq!("running", replay["status"]);
+    assert_eq!(3, replay["total"]);
+    let replay_id = replay["id"].as_str().unwrap();
+
+    let replay = wait_for_replay_status(&app, replay_id, "completed").await;
+    assert_eq!(3, replay["delivered"]);
+    assert_eq!(0, replay["failed"]);
+    assert!(replay["finished_at"].is_string());
+    assert_eq!(event_ids, replayed_event_ids(&subscriber, replay_id));
+    assert_eq!(3, other_subscriber.deliveries().len());
+
+    let history: Value = app
+        .get_event(&event_ids[0])
+        .await
+        .json()
+        .await
+        .expect("Failed to parse body");
+    let deliveries = history["deliveries"].as_array().unwrap();
+    assert_eq!(3, deliveries.len());
+    assert_eq!(
+        1,
+        deliveries
+            .iter()
+            .filter(|delivery| delivery["replay_id"] == replay_id)
+            .count()
+    );
+}
+
+#[tokio::test]
+async fn replay_redelivers_a_time_window_to_one_service_in_memory() {
+    replay_redelivers_a_time_window_to_one_service(spawn_app().await).await;
+}
+
+#[tokio::test]
+async fn replay_redelivers_a_time_window_to_one_service_in_postgres() {
+    let Some(app) = spawn_app_with_postgres().await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    replay_redelivers_a_time_window_to_one_service(app).await;
+}
+
+#[tokio::test]
+async fn replay_redelivers_only_the_listed_events() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+    let event_ids = publish_events(&app, &subscriber, 3).await;
+
+    let replay = start_replay(
+        &app,
+        json!({ "service_name": "vinnie", "event_ids": [event_ids[1]] }),
+    )
+    .await;
+    let replay_id = replay["id"].as_str().unwrap();
+
+    wait_for_replay_status(&app, replay_id, "completed").await;
+    assert_eq!(
+        vec![event_ids[1].clone()],
+        replayed_event_ids(&subscriber, replay_id)
+    );
+}
+
+#[tokio::test]
+async fn failed_replay_deliveries_are_counted_and_not_retried() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+    let event_ids = publish_events(&app, &subscriber, 2).await;
+    subscriber.fail_times(1);
+
+    let replay = start_replay(
+        &app,
+        json!({ "service_name": "vinnie", "event_ids": event_ids }),
+    )
+    .await;
+
+    let replay = wait_for_replay_status(&app, replay["id"].as_str().unwrap(), "completed").await;
+    assert_eq!(1, replay["delivered"]);
+    assert_eq!(1, replay["failed"]);
+    assert!(!app
+        .database
+        .calls()
+        .iter()
+        .any(|call| matches!(call, DatabaseCall::FailedEventAdded(_))));
+}
+
+#[tokio::test]
+async fn replay_can_be_cancelled() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+    let event_ids = publish_events(&app, &subscriber, 3).await;
+
+    let replay = start_replay(
+        &app,
+        json!({ "service_name": "vinnie", "event_ids": event_ids, "rate_per_second": 1 }),
+    )
+    .await;
+    let replay_id = replay["id"].as_str().unwrap();
+    subscriber.wait_for_deliveries(4).await;
+
+    let response = app.cancel_replay(replay_id).await;
+    assert_eq!(200, response.status());
+    let replay: Value = response.json().await.expect("Failed to parse body");
+    assert_eq!("cancelled", replay["status"]);
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let replay = wait_for_replay_status(&app, replay_id, "cancelled").await;
+    assert_eq!(1, replay["delivered"]);
+    assert_eq!(1, replayed_event_ids(&subscriber, replay_id).len());
+}
+
+#[tokio::test]
+async fn replays_left_running_by_a_stopped_instance_are_resumed_after_their_cursor() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+    let event_ids = publish_events(&app, &subscriber, 3).await;
+    let first_event = app
+        .database
+        .get_event(Uuid::parse_str(&event_ids[0]).unwrap())
+        .await
+        .expect("Failed to get event");
+
+    // An instance that replayed the first event and stopped
+    let job = ReplayJob {
+        id: Uuid::new_v4(),
+        service_name: String::from("vinnie"),
+        event_type: None,
+        published_after: None,
+        published_before: None,
+        event_ids: Some(
+            event_ids
+                .iter()
+                .map(|event_id| Uuid::parse_str(event_id).unwrap())
+                .collect(),
+        ),
+        rate_per_second: 10,
+        status: ReplayStatus::Running,
+        total: 3,
+        delivered: 1,
+        failed: 0,
+        skipped: 0,
+        error: None,
+        created_at: app.clock.now(),
+        finished_at: None,
+        cursor_published_at: Some(first_event.published_at),
+        cursor_event_id: Some(first_event.id),
+        lease_owner: Some(String::from("stopped-instance")),
+        lease_expires_at: Some(app.clock.now() + ChronoDuration::minutes(1)),
+    };
+    app.database
+        .create_replay_job(&job)
+        .await
+        .expect("Failed to create replay job");
+    let replay_id = job.id.to_string();
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(replayed_event_ids(&subscriber, &replay_id).is_empty());
+
+    app.clock.advance(ChronoDuration::minutes(2));
+    let replay = wait_for_replay_status(&app, &replay_id, "completed").await;
+    assert_eq!(3, replay["delivered"]);
+    assert_eq!(
+        event_ids[1..].to_vec(),
+        replayed_event_ids(&subscriber, &replay_id)
+    );
+}
+
+#[tokio::test]
+async fn invalid_replay_requests_are_rejected() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+    app.register_service("bystander", &subscriber.url).await;
+    let event_id = Uuid::new_v4().to_string();
+
+    let test_cases = [
+        (json!({ "service_name": "vinnie" }), "no selection"),
+        (
+            json!({ "service_name": "vinnie", "event_type": TEST_EVENT_TYPE }),
+            "no time window",
+        ),
+        (
+            json!({
+                "service_name": "vinnie",
+                "event_type": TEST_EVENT_TYPE,
+                "published_after": "2023-01-02T00:00:00Z",
+                "published_before": "2023-01-01T00:00:00Z",
+            }),
+            "an inverted time window",
+        ),
+        (
+            json!({
+                "service_name": "vinnie",
+                "event_type": TEST_EVENT_TYPE,
+                "event_ids": [event_id],
+            }),
+            "both selections",
+        ),
+        (
+            json!({ "service_name": "vinnie", "event_ids": [] }),
+            "no event ids",
+        ),
+        (
+            json!({ "service_name": "vinnie", "event_ids": [event_id], "rate_per_second": 0 }),
+            "a zero rate",
+        ),
+        (
+            json!({
+                "service_name": "bystander",
+                "event_type": TEST_EVENT_TYPE,
+                "published_after": "2023-01-01T00:00:00Z",
+            }),
+            "a service that does not listen to the event type",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.start_replay(body).await;
+        assert_eq!(400, response.status(), "Replay with {}", description);
+    }
+}
+
+#[tokio::test]
+async fn replaying_to_an_unknown_service_returns_404() {
+    let app = spawn_app().await;
+
+    let response = app
+        .start_replay(json!({ "service_name": "unknown", "event_ids": [Uuid::new_v4()] }))
+        .await;
+
+    assert_eq!(404, response.status());
+}
+
+#[tokio::test]
+async fn unknown_replays_return_404() {
+    let app = spawn_app().await;
+    let replay_id = Uuid::new_v4().to_string();
+
+    assert_eq!(404, app.get_replay(&replay_id).await.status());
+    assert_eq!(404, app.cancel_replay(&replay_id).await.status());
+}
@@ -0,0 +1,200 @@
+use std::{io::BufRead, path::PathBuf};
+
+use chrono::Duration as ChronoDuration;
+use flate2::read::GzDecoder;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use ventrix::common::configuration::{EventTypeRetention, QueueSettings, RetentionSettings};
+use ventrix::infrastructure::persistence::MAX_RETRIES;
+
+use crate::{
+    helpers::{
+        spawn_app_with_postgres_queue_settings, spawn_app_with_queue_settings, test_queue_settings,
+        TestApp, TEST_EVENT_TYPE,
+    },
+    mock_subscriber::MockSubscriber,
+    recording_database::DatabaseCall,
+};
+
+const AUDIT_EVENT_TYPE: &str = "audit_event";
+
+fn retention_settings(retention: RetentionSettings) -> QueueSettings {
+    QueueSettings {
+        retention,
+        ..test_queue_settings()
+    }
+}
+
+fn default_retention() -> RetentionSettings {
+    RetentionSettings {
+        fulfilled_days: Some(7),
+        dead_letter_days: Some(30),
+        ..test_queue_settings().retention
+    }
+}
+
+// Publishes an event and waits until it has been delivered and fulfilled
+async fn publish_fulfilled_event(
+    app: &TestApp,
+    subscriber: &MockSubscriber,
+    event_type: &str,
+) -> Uuid {
+    let delivered = subscriber.deliveries().len();
+    app.publish_event(event_type, json!({ "name": "John Rustsworth" }))
+        .await;
+    let deliveries = subscriber.wait_for_deliveries(delivered + 1).await;
+    let event_id = Uuid::parse_str(deliveries[delivered].event["id"].as_str().unwrap()).unwrap();
+    app.database
+        .wait_for(|call| *call == DatabaseCall::Fulfilled(event_id))
+        .await;
+    event_id
+}
+
+async fn wait_for_deletion(app: &TestApp, event_id: Uuid) {
+    app.database
+        .wait_for(
+            |call| matches!(call, DatabaseCall::EventsDeleted(ids) if ids.contains(&event_id)),
+        )
+        .await;
+}
+
+async fn fulfilled_events_are_pruned_once_past_their_retention(app: TestApp) {
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    let expired = publish_fulfilled_event(&app, &subscriber, TEST_EVENT_TYPE).await;
+    app.clock.advance(ChronoDuration::days(3));
+    let retained = publish_fulfilled_event(&app, &subscriber, TEST_EVENT_TYPE).await;
+    app.clock.advance(ChronoDuration::days(5));
+
+    wait_for_deletion(&app, expired).await;
+    assert_eq!(404, app.get_event(&expired.to_string()).await.status());
+    assert_eq!(200, app.get_event(&retained.to_string()).await.status());
+}
+
+#[tokio::test]
+async fn fulfilled_events_are_pruned_once_past_their_retention_in_memory() {
+    let app = spawn_app_with_queue_settings(retention_settings(default_retention())).await;
+    fulfilled_events_are_pruned_once_past_their_retention(app).await;
+}
+
+#[tokio::test]
+async fn fulfilled_events_are_pruned_once_past_their_retention_in_postgres() {
+    let Some(app) =
+        spawn_app_with_postgres_queue_settings(retention_settings(default_retention())).await
+    else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    fulfilled_events_are_pruned_once_past_their_retention(app).await;
+}
+
+#[tokio::test]
+async fn dead_letters_are_kept_for_their_own_retention_period() {
+    let app = spawn_app_with_queue_settings(retention_settings(default_retention())).await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.fail_times(MAX_RETRIES as usize + 1);
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+    let deliveries = subscriber.wait_for_deliveries(1).await;
+    let dead_letter = Uuid::parse_str(deliveries[0].event["id"].as_str().unwrap()).unwrap();
+    for retries in 0..MAX_RETRIES {
+        app.advance_past_retry(dead_letter, retries).await;
+        subscriber.wait_for_deliveries(retries as usize + 2).await;
+    }
+    app.database
+        .wait_for(|call| {
+            *call
+                == DatabaseCall::RetryScheduled {
+                    id: dead_letter,
+                    retries: MAX_RETRIES,
+                }
+        })
+        .await;
+    let fulfilled = publish_fulfilled_event(&app, &subscriber, TEST_EVENT_TYPE).await;
+
+    app.clock.advance(ChronoDuration::days(8));
+    wait_for_deletion(&app, fulfilled).await;
+    assert_eq!(200, app.get_event(&dead_letter.to_string()).await.status());
+
+    app.clock.advance(ChronoDuration::days(23));
+    wait_for_deletion(&app, dead_letter).await;
+    assert_eq!(404, app.get_event(&dead_letter.to_string()).await.status());
+}
+
+#[tokio::test]
+async fn event_types_can_be_kept_for_their_own_retention_period() {
+    let app = spawn_app_with_queue_settings(retention_settings(RetentionSettings {
+        event_types: vec![EventTypeRetention {
+            event_type: AUDIT_EVENT_TYPE.to_string(),
+            fulfilled_days: None,
+            dead_letter_days: None,
+            unfulfilled_days: None,
+        }],
+        ..default_retention()
+    }))
+    .await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+    assert_eq!(
+        201,
+        app.register_event_type(AUDIT_EVENT_TYPE).await.status()
+    );
+    assert_eq!(
+        201,
+        app.listen_to_event("vinnie", AUDIT_EVENT_TYPE, "/events")
+            .await
+            .status()
+    );
+
+    let audited = publish_fulfilled_event(&app, &subscriber, AUDIT_EVENT_TYPE).await;
+    let expired = publish_fulfilled_event(&app, &subscriber, TEST_EVENT_TYPE).await;
+    app.clock.advance(ChronoDuration::days(8));
+
+    wait_for_deletion(&app, expired).await;
+    assert_eq!(200, app.get_event(&audited.to_string()).await.status());
+}
+
+#[tokio::test]
+async fn pruned_events_are_archived_before_they_are_deleted() {
+    let archive_directory =
+        std::env::temp_dir().join(format!("ventrix-archive-{}", Uuid::new_v4()));
+    let app = spawn_app_with_queue_settings(retention_settings(RetentionSettings {
+        archive_directory: Some(archive_directory.to_string_lossy().to_string()),
+        ..default_retention()
+    }))
+    .await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    let event_id = publish_fulfilled_event(&app, &subscriber, TEST_EVENT_TYPE).await;
+    app.clock.advance(ChronoDuration::days(8));
+    wait_for_deletion(&app, event_id).await;
+
+    let archives: Vec<PathBuf> = std::fs::read_dir(&archive_directory)
+        .expect("Failed to read the archive directory")
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(1, archives.len());
+    assert!(archives[0].to_string_lossy().ends_with(".ndjson.gz"));
+    let archived: Vec<Value> =
+        std::io::BufReader::new(GzDecoder::new(std::fs::File::open(&archives[0]).unwrap()))
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).expect("Invalid archived event"))
+            .collect();
+    std::fs::remove_dir_all(&archive_directory).expect("Failed to remove the archive directory");
+
+    assert_eq!(1, archived.len());
+    assert_eq!(event_id.to_string(), archived[0]["id"]);
+    assert_eq!(TEST_EVENT_TYPE, archived[0]["event_type"]);
+    assert_eq!(
+        json!({ "name": "John Rustsworth" }).to_string(),
+        archived[0]["payload"]
+    );
+    assert!(archived[0]["fulfilled_at"].is_string());
+    let deliveries = archived[0]["deliveries"].as_array().unwrap();
+    assert_eq!(1, deliveries.len());
+    assert_eq!("vinnie", deliveries[0]["service_name"]);
+}
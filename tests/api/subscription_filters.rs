@@ -0,0 +1,137 @@
+use serde_json::{json, Value};
+
+use crate::{
+    helpers::{spawn_app, spawn_app_with_postgres, TestApp, TEST_EVENT_TYPE},
+    mock_subscriber::MockSubscriber,
+};
+
+const RUSTSWORTH_FILTER: &str =
+    r#"payload.name == "John Rustsworth" && event_type == "test_event""#;
+
+async fn listen_with_filter(app: &TestApp, service_name: &str, filter: &str) -> reqwest::Response {
+    assert_eq!(
+        201,
+        app.register_service(service_name, "http://localhost:8081")
+            .await
+            .status()
+    );
+    let _ = app.register_event_type(TEST_EVENT_TYPE).await;
+    app.listen_to_event_with_filter(service_name, TEST_EVENT_TYPE, "/events", Some(filter))
+        .await
+}
+
+#[tokio::test]
+async fn listening_with_an_invalid_filter_returns_400() {
+    let app = spawn_app().await;
+    let deeply_nested = format!(
+        "{}payload.name == \"John\"{}",
+        "(".repeat(1000),
+        ")".repeat(1000)
+    );
+    let test_cases = vec![
+        (r#"payload.name == "#, "the filter does not parse"),
+        (deeply_nested.as_str(), "the filter nests too deeply"),
+        (
+            r#"payload.country == "GB""#,
+            "the field is not in the schema",
+        ),
+        (
+            "payload.name > 100",
+            "the value does not match the field's type",
+        ),
+    ];
+
+    for (service_name, (filter, description)) in ["vinnie", "vera", "viktor", "vanessa"]
+        .into_iter()
+        .zip(test_cases)
+    {
+        let response = listen_with_filter(&app, service_name, filter).await;
+
+        assert_eq!(
+            400,
+            response.status(),
+            "The API did not fail with 400 Bad Request when {}.",
+            description
+        );
+        let body: Value = response.json().await.expect("Failed to parse body");
+        assert_eq!("urn:ventrix:error:invalid-filter", body["type"]);
+    }
+}
+
+async fn events_not_matching_a_filter_are_recorded_as_filtered(app: TestApp) {
+    let filtered_subscriber = MockSubscriber::start().await;
+    let subscriber = MockSubscriber::start().await;
+    assert_eq!(
+        201,
+        app.register_service("vinnie", &filtered_subscriber.url)
+            .await
+            .status()
+    );
+    assert_eq!(201, app.register_event_type(TEST_EVENT_TYPE).await.status());
+    assert_eq!(
+        201,
+        app.listen_to_event_with_filter(
+            "vinnie",
+            TEST_EVENT_TYPE,
+            "/events",
+            Some(RUSTSWORTH_FILTER)
+        )
+        .await
+        .status()
+    );
+    app.subscribe("viktor", &subscriber.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "Ferris Crabman" }))
+        .await;
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+
+    let deliveries = subscriber.wait_for_deliveries(2).await;
+    let filtered_deliveries = filtered_subscriber.wait_for_deliveries(1).await;
+    assert_eq!(1, filtered_subscriber.deliveries().len());
+    assert!(filtered_deliveries[0].event["payload"]
+        .as_str()
+        .unwrap()
+        .contains("John Rustsworth"));
+
+    let skipped_event = deliveries
+        .iter()
+        .find(|delivery| {
+            delivery.event["payload"]
+                .as_str()
+                .unwrap()
+                .contains("Ferris")
+        })
+        .expect("The unfiltered service should receive every event");
+    let response = app
+        .get_event(skipped_event.event["id"].as_str().unwrap())
+        .await;
+    assert_eq!(200, response.status());
+    let history: Value = response.json().await.expect("Failed to parse body");
+    let deliveries = history["deliveries"].as_array().unwrap();
+    let filtered = deliveries
+        .iter()
+        .find(|delivery| delivery["service_name"] == "vinnie")
+        .expect("The filtered service should have a delivery attempt");
+    assert_eq!(true, filtered["filtered"]);
+    assert!(filtered["status_code"].is_null());
+    let delivered = deliveries
+        .iter()
+        .find(|delivery| delivery["service_name"] == "viktor")
+        .expect("The unfiltered service should have a delivery attempt");
+    assert_eq!(false, delivered["filtered"]);
+}
+
+#[tokio::test]
+async fn events_not_matching_a_filter_are_recorded_as_filtered_in_memory() {
+    events_not_matching_a_filter_are_recorded_as_filtered(spawn_app().await).await;
+}
+
+#[tokio::test]
+async fn events_not_matching_a_filter_are_recorded_as_filtered_in_postgres() {
+    let Some(app) = spawn_app_with_postgres().await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    events_not_matching_a_filter_are_recorded_as_filtered(app).await;
+}
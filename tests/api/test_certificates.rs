@@ -0,0 +1,144 @@
+use openssl::{
+    asn1::Asn1Time,
+    bn::{BigNum, MsbOption},
+    ec::{EcGroup, EcKey},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    x509::{
+        extension::{BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName},
+        X509NameBuilder, X509,
+    },
+};
+
+pub struct KeyPair {
+    pub certificate: X509,
+    pub key: PKey<Private>,
+}
+
+impl KeyPair {
+    pub fn certificate_pem(&self) -> String {
+        String::from_utf8(self.certificate.to_pem().unwrap()).unwrap()
+    }
+
+    pub fn key_pem(&self) -> String {
+        String::from_utf8(self.key.private_key_to_pem_pkcs8().unwrap()).unwrap()
+    }
+}
+
+// A private CA with a server certificate for 127.0.0.1 and a client certificate, generated
+// fresh for every test
+pub struct TestCertificates {
+    pub ca: KeyPair,
+    pub server: KeyPair,
+    pub client: KeyPair,
+}
+
+impl TestCertificates {
+    pub fn generate() -> Self {
+        let ca = self_signed_ca("Ventrix Test CA");
+        let server = issue(&ca, "localhost", true);
+        let client = issue(&ca, "ventrix", false);
+        Self { ca, server, client }
+    }
+
+    // Writes the PEM to a temporary file and returns its path
+    pub fn write(pem: &str) -> String {
+        let path = std::env::temp_dir().join(format!("ventrix-tls-{}.pem", uuid::Uuid::new_v4()));
+        std::fs::write(&path, pem).expect("Failed to write the test certificate");
+        path.to_string_lossy().to_string()
+    }
+}
+
+fn self_signed_ca(common_name: &str) -> KeyPair {
+    let key = generate_key();
+    let mut builder = certificate_builder(common_name, &key);
+    builder.set_issuer_name(&name(common_name)).unwrap();
+    builder
+        .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+        .unwrap();
+    builder
+        .append_extension(
+            KeyUsage::new()
+                .critical()
+                .key_cert_sign()
+                .crl_sign()
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    KeyPair {
+        certificate: builder.build(),
+        key,
+    }
+}
+
+fn issue(ca: &KeyPair, common_name: &str, server: bool) -> KeyPair {
+    let key = generate_key();
+    let mut builder = certificate_builder(common_name, &key);
+    builder
+        .set_issuer_name(ca.certificate.subject_name())
+        .unwrap();
+    builder
+        .append_extension(BasicConstraints::new().build().unwrap())
+        .unwrap();
+    builder
+        .append_extension(
+            KeyUsage::new()
+                .critical()
+                .digital_signature()
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    let usage = if server {
+        ExtendedKeyUsage::new().server_auth().build().unwrap()
+    } else {
+        ExtendedKeyUsage::new().client_auth().build().unwrap()
+    };
+    builder.append_extension(usage).unwrap();
+    if server {
+        let alternative_names = SubjectAlternativeName::new()
+            .dns("localhost")
+            .ip("127.0.0.1")
+            .build(&builder.x509v3_context(Some(&ca.certificate), None))
+            .unwrap();
+        builder.append_extension(alternative_names).unwrap();
+    }
+    builder.sign(&ca.key, MessageDigest::sha256()).unwrap();
+    KeyPair {
+        certificate: builder.build(),
+        key,
+    }
+}
+
+fn certificate_builder(common_name: &str, key: &PKey<Private>) -> openssl::x509::X509Builder {
+    let mut serial = BigNum::new().unwrap();
+    serial.rand(64, MsbOption::MAYBE_ZERO, false).unwrap();
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder
+        .set_serial_number(&serial.to_asn1_integer().unwrap())
+        .unwrap();
+    builder.set_subject_name(&name(common_name)).unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    builder
+}
+
+fn name(common_name: &str) -> openssl::x509::X509Name {
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", common_name).unwrap();
+    name.build()
+}
+
+fn generate_key() -> PKey<Private> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+}
@@ -0,0 +1,134 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use openssl::{
+    ssl::{SslAcceptor, SslMethod, SslStream, SslVerifyMode},
+    x509::store::X509StoreBuilder,
+};
+
+use crate::{mock_subscriber::MockSubscriber, test_certificates::TestCertificates};
+
+// Terminates TLS in front of a MockSubscriber, requiring a client certificate issued by the
+// test CA. Requests are forwarded as they are, so the MockSubscriber records the deliveries.
+pub struct TlsSubscriber {
+    pub url: String,
+    pub subscriber: MockSubscriber,
+    rejected_handshakes: Arc<AtomicUsize>,
+}
+
+impl TlsSubscriber {
+    pub async fn start(certificates: &TestCertificates) -> Self {
+        let subscriber = MockSubscriber::start().await;
+        let upstream = subscriber.url.trim_start_matches("http://").to_string();
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server()).unwrap();
+        acceptor.set_private_key(&certificates.server.key).unwrap();
+        acceptor
+            .set_certificate(&certificates.server.certificate)
+            .unwrap();
+        acceptor
+            .add_extra_chain_cert(certificates.ca.certificate.clone())
+            .unwrap();
+        let mut client_roots = X509StoreBuilder::new().unwrap();
+        client_roots
+            .add_cert(certificates.ca.certificate.clone())
+            .unwrap();
+        acceptor
+            .set_verify_cert_store(client_roots.build())
+            .unwrap();
+        acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        let acceptor = Arc::new(acceptor.build());
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind TLS subscriber");
+        let url = format!("https://{}", listener.local_addr().unwrap());
+        let rejected_handshakes = Arc::new(AtomicUsize::new(0));
+
+        let accept_rejected = Arc::clone(&rejected_handshakes);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let acceptor = Arc::clone(&acceptor);
+                let upstream = upstream.clone();
+                let rejected = Arc::clone(&accept_rejected);
+                std::thread::spawn(move || match acceptor.accept(stream) {
+                    Ok(stream) => forward(stream, &upstream),
+                    Err(_) => {
+                        rejected.fetch_add(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+
+        Self {
+            url,
+            subscriber,
+            rejected_handshakes,
+        }
+    }
+
+    pub async fn wait_for_rejected_handshake(&self) {
+        for _ in 0..100 {
+            if self.rejected_handshakes.load(Ordering::SeqCst) > 0 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("No TLS handshake was rejected");
+    }
+}
+
+fn forward(mut stream: SslStream<TcpStream>, upstream: &str) {
+    let Some(request) = read_request(&mut stream) else {
+        return;
+    };
+    let Ok(mut upstream) = TcpStream::connect(upstream) else {
+        return;
+    };
+    if upstream.write_all(&request).is_err() {
+        return;
+    }
+    // The MockSubscriber closes every connection after its response
+    let mut response = vec![];
+    let _ = upstream.read_to_end(&mut response);
+    let _ = stream.write_all(&response);
+    let _ = stream.shutdown();
+}
+
+fn read_request(stream: &mut SslStream<TcpStream>) -> Option<Vec<u8>> {
+    let mut buffer = vec![];
+    let mut chunk = [0u8; 4096];
+
+    let header_end = loop {
+        match stream.read(&mut chunk) {
+            Ok(0) | Err(_) => return None,
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+        }
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let content_length = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-length")
+                .then(|| value.trim().parse::<usize>().ok())?
+        })
+        .unwrap_or(0);
+
+    while buffer.len() < header_end + content_length {
+        match stream.read(&mut chunk) {
+            Ok(0) | Err(_) => return None,
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+        }
+    }
+    Some(buffer)
+}
@@ -0,0 +1,142 @@
+use serde_json::json;
+use uuid::Uuid;
+use ventrix::common::configuration::DispatchMode;
+
+use crate::{
+    helpers::{spawn_app, spawn_app_with_postgres_dispatch_mode, COLLECTOR, TEST_EVENT_TYPE},
+    mock_subscriber::{MockSubscriber, ReceivedDelivery},
+};
+
+const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+fn new_trace_id() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+fn traceparent(trace_id: &str) -> String {
+    format!("00-{}-{}-01", trace_id, PARENT_SPAN_ID)
+}
+
+// Returns the trace id and parent span id of the traceparent a subscriber received
+fn received_trace(delivery: &ReceivedDelivery) -> (String, String) {
+    let traceparent = delivery
+        .headers
+        .get("traceparent")
+        .expect("Delivery did not carry a traceparent header");
+    let parts: Vec<&str> = traceparent.split('-').collect();
+    assert_eq!(4, parts.len(), "Malformed traceparent {}", traceparent);
+    (parts[1].to_string(), parts[2].to_string())
+}
+
+#[tokio::test]
+async fn incoming_trace_context_is_propagated_to_subscribers() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+    let trace_id = new_trace_id();
+
+    let response = app
+        .publish_event_with_headers(
+            TEST_EVENT_TYPE,
+            json!({ "name": "John Rustsworth" }),
+            &[
+                ("traceparent", &traceparent(&trace_id)),
+                ("tracestate", "vendor=value"),
+            ],
+        )
+        .await;
+    assert_eq!(201, response.status());
+
+    let deliveries = subscriber.wait_for_deliveries(1).await;
+    let (received_trace_id, parent_span_id) = received_trace(&deliveries[0]);
+    assert_eq!(trace_id, received_trace_id);
+    // The subscriber is parented to the delivery span, not to the publisher's span
+    assert_ne!(PARENT_SPAN_ID, parent_span_id);
+    assert_eq!(
+        Some(&"vendor=value".to_string()),
+        deliveries[0].headers.get("tracestate")
+    );
+}
+
+#[tokio::test]
+async fn delivery_span_is_exported_in_the_publish_trace() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+    let trace_id = new_trace_id();
+
+    app.publish_event_with_headers(
+        TEST_EVENT_TYPE,
+        json!({ "name": "John Rustsworth" }),
+        &[("traceparent", &traceparent(&trace_id))],
+    )
+    .await;
+    subscriber.wait_for_deliveries(1).await;
+
+    COLLECTOR.wait_for_span(&trace_id, "Delivering event").await;
+}
+
+#[tokio::test]
+async fn event_published_without_trace_context_starts_a_trace() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+
+    app.publish_event(TEST_EVENT_TYPE, json!({ "name": "John Rustsworth" }))
+        .await;
+
+    let deliveries = subscriber.wait_for_deliveries(1).await;
+    let (trace_id, _) = received_trace(&deliveries[0]);
+    assert_ne!("0".repeat(32), trace_id);
+}
+
+#[tokio::test]
+async fn retried_delivery_gets_its_own_span_in_the_publish_trace() {
+    let app = spawn_app().await;
+    let subscriber = MockSubscriber::start().await;
+    subscriber.fail_times(1);
+    app.subscribe("vinnie", &subscriber.url).await;
+    let trace_id = new_trace_id();
+
+    app.publish_event_with_headers(
+        TEST_EVENT_TYPE,
+        json!({ "name": "John Rustsworth" }),
+        &[("traceparent", &traceparent(&trace_id))],
+    )
+    .await;
+    let event_id = Uuid::parse_str(
+        subscriber.wait_for_deliveries(1).await[0].event["id"]
+            .as_str()
+            .unwrap(),
+    )
+    .unwrap();
+    app.advance_past_retry(event_id, 0).await;
+
+    let deliveries = subscriber.wait_for_deliveries(2).await;
+    let (first_trace_id, first_span_id) = received_trace(&deliveries[0]);
+    let (retry_trace_id, retry_span_id) = received_trace(&deliveries[1]);
+    assert_eq!(trace_id, first_trace_id);
+    assert_eq!(trace_id, retry_trace_id);
+    assert_ne!(first_span_id, retry_span_id);
+}
+
+#[tokio::test]
+async fn trace_context_survives_dispatch_through_postgres() {
+    let Some(app) = spawn_app_with_postgres_dispatch_mode(DispatchMode::Postgres).await else {
+        eprintln!("Skipping: Postgres unavailable");
+        return;
+    };
+    let subscriber = MockSubscriber::start().await;
+    app.subscribe("vinnie", &subscriber.url).await;
+    let trace_id = new_trace_id();
+
+    app.publish_event_with_headers(
+        TEST_EVENT_TYPE,
+        json!({ "name": "John Rustsworth" }),
+        &[("traceparent", &traceparent(&trace_id))],
+    )
+    .await;
+
+    let deliveries = subscriber.wait_for_deliveries(1).await;
+    assert_eq!(trace_id, received_trace(&deliveries[0]).0);
+}
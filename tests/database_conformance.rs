@@ -0,0 +1,2317 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use secrecy::{ExposeSecret, Secret};
+use serde_json::{json, Value};
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::{sync::Arc, time::Duration};
+use uuid::Uuid;
+use ventrix::{
+    common::{
+        api_keys::new_api_key,
+        clock::{Clock, MockClock},
+        configuration::get_configuration,
+        errors::VentrixError,
+        secrets::SecretCipher,
+        types::{
+            ApiKey, ApiKeyRole, CreateApiKeyRequest, DeliveryAttempt, EventQuery, FulfilmentStatus,
+            ListenToEventReq, NewEventTypeRequest, PruneCriteria, PublishDenial, PublishedEvent,
+            ReplayJob, ReplayStatus, RetainedEventKind, TraceContext, VentrixEvent,
+        },
+    },
+    domain::models::service::RegisterServiceRequest,
+    infrastructure::persistence::{
+        inmemory::InMemoryDatabase, postgres::PostgresDatabase, Database, MAX_RETRIES,
+    },
+};
+
+// Every test in the suite is generated once per backend. A backend factory returns
+// None when the backend is unavailable (e.g. no local Postgres), and the test is skipped.
+// Each backend is built on a mock clock; tests in the second list also get to move it.
+macro_rules! conformance_tests {
+    ($backend:ident => $factory:ident; [$($test:ident),* $(,)?]; [$($clock_test:ident),* $(,)?]) => {
+        mod $backend {
+            use std::sync::Arc;
+            use ventrix::common::clock::MockClock;
+
+            $(
+                #[tokio::test]
+                async fn $test() {
+                    let clock = Arc::new(MockClock::default());
+                    let Some(database) = super::$factory(clock).await else {
+                        eprintln!("Skipping {}::{}: backend unavailable", stringify!($backend), stringify!($test));
+                        return;
+                    };
+                    super::$test(database.as_ref()).await;
+                }
+            )*
+            $(
+                #[tokio::test]
+                async fn $clock_test() {
+                    let clock = Arc::new(MockClock::default());
+                    let Some(database) = super::$factory(clock.clone()).await else {
+                        eprintln!("Skipping {}::{}: backend unavailable", stringify!($backend), stringify!($clock_test));
+                        return;
+                    };
+                    super::$clock_test(database.as_ref(), clock.as_ref()).await;
+                }
+            )*
+        }
+    };
+}
+
+macro_rules! database_conformance_suite {
+    ($backend:ident => $factory:ident) => {
+        conformance_tests!(
+            $backend => $factory;
+            [
+            registered_service_can_be_retrieved,
+            removed_service_can_no_longer_be_retrieved,
+            registered_event_type_schema_can_be_retrieved,
+            listening_services_are_returned_for_event_type,
+            event_type_without_listeners_returns_no_services,
+            removed_service_is_no_longer_returned_as_listener,
+            listener_filters_are_returned_for_event_type,
+            listener_transforms_are_returned_for_event_type,
+            listener_delivery_settings_are_returned_sealed,
+            published_event_can_be_fulfilled,
+            failed_event_is_not_due_before_retry_time,
+            failed_event_is_due_after_retry_time,
+            failed_event_is_not_due_after_max_retries,
+            resolved_failed_event_is_not_due,
+            adding_a_failed_event_for_an_unknown_event_returns_not_found,
+            updating_retry_time_of_an_unknown_failed_event_returns_not_found,
+            resolving_an_unknown_failed_event_returns_not_found,
+            registering_a_duplicate_service_returns_already_exists,
+            registering_a_duplicate_event_type_returns_already_exists,
+            getting_an_unknown_service_returns_not_found,
+            removing_an_unknown_service_returns_not_found,
+            getting_the_schema_of_an_unknown_event_type_returns_not_found,
+            fulfilling_an_unknown_event_returns_not_found,
+            marking_an_unknown_event_dispatched_returns_not_found,
+            listening_to_an_unknown_event_type_returns_referenced_entity_missing,
+            listening_as_an_unknown_service_returns_referenced_entity_missing,
+            migrated_backend_passes_health_check,
+            published_event_shows_when_it_was_dispatched_and_fulfilled,
+            getting_an_unknown_event_returns_not_found,
+            recording_a_delivery_attempt_for_an_unknown_event_returns_not_found,
+            replay_progress_is_saved_until_the_replay_is_cancelled,
+            getting_an_unknown_replay_returns_not_found,
+            cancelling_an_unknown_replay_returns_not_found,
+            events_to_replay_can_be_selected_by_id,
+            deleted_events_are_removed_with_their_failures_and_attempts,
+            filtered_delivery_attempts_are_marked_as_filtered,
+            pattern_listeners_are_returned_for_matching_event_types,
+            subscriptions_show_the_event_types_a_pattern_resolves_to,
+            invalid_event_type_patterns_are_rejected,
+            api_keys_of_a_removed_service_are_removed,
+            api_key_for_an_unknown_service_returns_referenced_entity_missing,
+            revoking_an_unknown_api_key_returns_not_found,
+            event_type_publishers_are_returned_until_their_service_is_removed,
+            event_type_with_an_unknown_publisher_returns_referenced_entity_missing,
+            pruning_lock_is_held_by_one_caller_at_a_time,
+            ];
+            [
+            failed_event_becomes_due_once_the_clock_passes_its_retry_time,
+            rescheduled_failed_event_is_not_due_until_the_clock_reaches_it,
+            next_retry_time_is_the_earliest_pending_retry,
+            next_retry_time_ignores_resolved_and_exhausted_events,
+            claimed_failed_event_is_not_claimed_again_until_its_lease_expires,
+            rescheduling_a_claimed_failed_event_releases_its_lease,
+            concurrent_claims_do_not_overlap,
+            failed_events_are_claimed_earliest_retry_first_up_to_the_limit,
+            pending_event_is_not_claimed_again_until_its_lease_expires,
+            dispatched_event_is_no_longer_pending,
+            pending_lease_is_renewed_only_while_no_other_instance_holds_it,
+            concurrent_pending_claims_do_not_overlap,
+            pending_event_published_after_the_cutoff_is_not_claimed,
+            spilled_events_are_taken_once_oldest_first,
+            claimed_events_keep_their_trace_context,
+            delivery_attempts_are_returned_oldest_first,
+            delivery_attempts_of_several_events_are_returned_together,
+            events_are_listed_newest_first,
+            listed_events_can_be_filtered,
+            events_to_replay_are_selected_by_type_and_window_oldest_first,
+            replays_are_claimed_once_their_lease_expires,
+            fulfilled_events_past_the_cutoff_are_selected_for_pruning_oldest_first,
+            dead_letters_past_the_cutoff_are_selected_for_pruning,
+            unfulfilled_events_past_the_cutoff_are_selected_for_pruning,
+            stored_api_keys_can_be_found_until_they_are_revoked,
+            publish_denials_are_listed_newest_first,
+            ]
+        );
+    };
+}
+
+database_conformance_suite!(inmemory => in_memory_database);
+database_conformance_suite!(postgres => postgres_database);
+
+async fn in_memory_database(clock: Arc<MockClock>) -> Option<Arc<dyn Database>> {
+    Some(Arc::new(InMemoryDatabase::new(clock)))
+}
+
+async fn postgres_database(clock: Arc<MockClock>) -> Option<Arc<dyn Database>> {
+    let mut configuration = get_configuration().expect("Failed to read configuration");
+    configuration.database.database_name = Uuid::new_v4().to_string();
+
+    let mut connection = tokio::time::timeout(
+        Duration::from_secs(2),
+        PgConnection::connect(
+            configuration
+                .database
+                .connection_string_without_db()
+                .expose_secret(),
+        ),
+    )
+    .await
+    .ok()?
+    .ok()?;
+    connection
+        .execute(
+            format!(
+                r#"CREATE DATABASE "{}";"#,
+                configuration.database.database_name
+            )
+            .as_str(),
+        )
+        .await
+        .expect("Failed to create database");
+
+    let pool = PgPool::connect(configuration.database.connection_string().expose_secret())
+        .await
+        .expect("Failed to connect to Postgres");
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .expect("Failed to migrate the database");
+
+    Some(Arc::new(PostgresDatabase::new(pool, clock)))
+}
+
+fn service_request(name: &str) -> RegisterServiceRequest {
+    RegisterServiceRequest {
+        name: name.to_string(),
+        url: String::from("http://localhost:8081"),
+        delivery: None,
+        sealed_delivery: None,
+    }
+}
+
+fn event_type_request(name: &str) -> NewEventTypeRequest {
+    NewEventTypeRequest {
+        name: name.to_string(),
+        description: String::from("This is a test event"),
+        payload_definition: json!({
+            "type": "object",
+            "properties": {
+                "name": {
+                    "type": "string"
+                }
+            },
+            "required": []
+        }),
+        publishers: None,
+    }
+}
+
+fn listen_request(service_name: &str, event_type: &str, endpoint: &str) -> ListenToEventReq {
+    ListenToEventReq {
+        service_name: service_name.to_string(),
+        event_type: event_type.to_string(),
+        endpoint: endpoint.to_string(),
+        filter: None,
+        transform: None,
+        target_schema: None,
+        delivery: None,
+        sealed_delivery: None,
+    }
+}
+
+fn trace_context() -> TraceContext {
+    TraceContext {
+        traceparent: Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".to_string()),
+        tracestate: Some("vendor=value".to_string()),
+    }
+}
+
+async fn publish_event(database: &dyn Database, event_type: &str) -> VentrixEvent {
+    let event = VentrixEvent {
+        id: Uuid::new_v4(),
+        event_type: event_type.to_string(),
+        payload: json!({ "name": "John Rustsworth" }).to_string(),
+        retry_details: None,
+        trace_context: trace_context(),
+    };
+    database
+        .save_published_event(&event)
+        .await
+        .expect("Failed to save published event");
+    event
+}
+
+// Claims every due failed event, as the retry processor does
+async fn claim_due_failed_events(database: &dyn Database) -> Vec<VentrixEvent> {
+    database
+        .claim_failed_events("instance-a", ChronoDuration::minutes(1), 100)
+        .await
+        .expect("Failed to claim failed events")
+}
+
+fn delivery_attempt(
+    event: &VentrixEvent,
+    service_name: &str,
+    attempt: i16,
+    attempted_at: DateTime<Utc>,
+) -> DeliveryAttempt {
+    DeliveryAttempt {
+        id: Uuid::new_v4(),
+        event_id: event.id,
+        service_name: service_name.to_string(),
+        endpoint: String::from("/events"),
+        attempt,
+        status_code: Some(500),
+        succeeded: false,
+        latency_ms: 12,
+        error: Some(String::from("Internal Server Error")),
+        attempted_at,
+        replay_id: None,
+        filtered: false,
+    }
+}
+
+fn replay_job(service_name: &str) -> ReplayJob {
+    ReplayJob {
+        id: Uuid::new_v4(),
+        service_name: service_name.to_string(),
+        event_type: None,
+        published_after: None,
+        published_before: None,
+        event_ids: None,
+        rate_per_second: 10,
+        status: ReplayStatus::Running,
+        total: 0,
+        delivered: 0,
+        failed: 0,
+        skipped: 0,
+        error: None,
+        created_at: Utc::now(),
+        finished_at: None,
+        cursor_published_at: None,
+        cursor_event_id: None,
+        lease_owner: None,
+        lease_expires_at: None,
+    }
+}
+
+fn event_ids(events: &[VentrixEvent]) -> Vec<Uuid> {
+    events.iter().map(|event| event.id).collect()
+}
+
+fn replayed_ids(events: &[(DateTime<Utc>, VentrixEvent)]) -> Vec<Uuid> {
+    events.iter().map(|(_, event)| event.id).collect()
+}
+
+fn prune_criteria(kind: RetainedEventKind, before: DateTime<Utc>) -> PruneCriteria {
+    PruneCriteria {
+        kind,
+        event_type: None,
+        excluded_event_types: vec![],
+        before,
+    }
+}
+
+async fn events_to_prune(
+    database: &dyn Database,
+    criteria: &PruneCriteria,
+    limit: i64,
+) -> Vec<Uuid> {
+    database
+        .get_events_to_prune(criteria, limit)
+        .await
+        .expect("Failed to get events to prune")
+        .into_iter()
+        .map(|event| event.id)
+        .collect()
+}
+
+async fn dead_letter_event(database: &dyn Database, event_type: &str) -> VentrixEvent {
+    let event = publish_event(database, event_type).await;
+    database
+        .add_failed_event(&event)
+        .await
+        .expect("Failed to add failed event");
+    database
+        .update_retry_time(event.id, Utc::now(), MAX_RETRIES)
+        .await
+        .expect("Failed to update retry time");
+    event
+}
+
+fn listed_ids(events: &[PublishedEvent]) -> Vec<Uuid> {
+    events.iter().map(|event| event.id).collect()
+}
+
+async fn registered_service_can_be_retrieved(database: &dyn Database) {
+    database
+        .register_service(&service_request("vinnie"))
+        .await
+        .expect("Failed to register service");
+
+    let service = database
+        .get_service("vinnie")
+        .await
+        .expect("Failed to get service");
+
+    assert_eq!("vinnie", service.name);
+    assert_eq!("http://localhost:8081", service.url);
+}
+
+async fn removed_service_can_no_longer_be_retrieved(database: &dyn Database) {
+    database
+        .register_service(&service_request("vinnie"))
+        .await
+        .expect("Failed to register service");
+    database
+        .remove_service("vinnie")
+        .await
+        .expect("Failed to remove service");
+
+    let err = database
+        .get_service("vinnie")
+        .await
+        .expect_err("Getting a removed service should fail");
+
+    assert!(matches!(err, VentrixError::ServiceNotFound(_)));
+}
+
+async fn registered_event_type_schema_can_be_retrieved(database: &dyn Database) {
+    let event_type = event_type_request("test_event");
+    database
+        .register_event_type(&event_type)
+        .await
+        .expect("Failed to register event type");
+
+    let schema = database
+        .get_schema_for_event_type("test_event")
+        .await
+        .expect("Failed to get schema");
+    let payload_definition: Value =
+        serde_json::from_str(&schema.payload_definition).expect("Schema is not valid JSON");
+
+    assert_eq!(event_type.payload_definition, payload_definition);
+}
+
+async fn listening_services_are_returned_for_event_type(database: &dyn Database) {
+    database
+        .register_event_type(&event_type_request("test_event"))
+        .await
+        .expect("Failed to register event type");
+    for name in ["vinnie", "viktor"] {
+        database
+            .register_service(&service_request(name))
+            .await
+            .expect("Failed to register service");
+        database
+            .register_service_for_event_type(&listen_request(name, "test_event", "/events"))
+            .await
+            .expect("Failed to listen to event type");
+    }
+
+    let mut listeners = database
+        .get_service_by_event_type("test_event")
+        .await
+        .expect("Failed to get listening services");
+    listeners.sort_by(|a, b| a.name.cmp(&b.name));
+
+    assert_eq!(2, listeners.len());
+    assert_eq!("viktor", listeners[0].name);
+    assert_eq!("vinnie", listeners[1].name);
+    assert_eq!("http://localhost:8081", listeners[1].url);
+    assert_eq!("/events", listeners[1].endpoint);
+}
+
+async fn listener_filters_are_returned_for_event_type(database: &dyn Database) {
+    database
+        .register_event_type(&event_type_request("test_event"))
+        .await
+        .expect("Failed to register event type");
+    database
+        .register_service(&service_request("vinnie"))
+        .await
+        .expect("Failed to register service");
+    database
+        .register_service_for_event_type(&ListenToEventReq {
+            filter: Some(String::from(r#"payload.name == "John Rustsworth""#)),
+            ..listen_request("vinnie", "test_event", "/events")
+        })
+        .await
+        .expect("Failed to listen to event type");
+
+    let listeners = database
+        .get_service_by_event_type("test_event")
+        .await
+        .expect("Failed to get listening services");
+
+    assert_eq!(1, listeners.len());
+    assert_eq!(
+        Some(r#"payload.name == "John Rustsworth""#),
+        listeners[0].filter.as_deref()
+    );
+}
+
+async fn listener_delivery_settings_are_returned_sealed(database: &dyn Database) {
+    let cipher = SecretCipher::new(&Secret::new(STANDARD.encode([7; 32]))).unwrap();
+    let service_delivery = cipher.seal(r#"{"headers":{"X-Tenant":"acme"}}"#);
+    let delivery = cipher.seal(r#"{"auth":{"type":"basic","username":"u","password":"p"}}"#);
+    database
+        .register_event_type(&event_type_request("test_event"))
+        .await
+        .expect("Failed to register event type");
+    database
+        .register_service(&RegisterServiceRequest {
+            sealed_delivery: Some(service_delivery.clone()),
+            ..service_request("vinnie")
+        })
+        .await
+        .expect("Failed to register service");
+    database
+        .register_service(&service_request("django"))
+        .await
+        .expect("Failed to register service");
+    database
+        .register_service_for_event_type(&ListenToEventReq {
+            sealed_delivery: Some(delivery.clone()),
+            ..listen_request("vinnie", "test_event", "/events")
+        })
+        .await
+        .expect("Failed to listen to event type");
+    database
+        .register_service_for_event_type(&listen_request("django", "test_event", "/events"))
+        .await
+        .expect("Failed to listen to event type");
+
+    let mut listeners = database
+        .get_service_by_event_type("test_event")
+        .await
+        .expect("Failed to get listening services");
+    listeners.sort_by(|a, b| a.name.cmp(&b.name));
+
+    assert_eq!(2, listeners.len());
+    assert_eq!(None, listeners[0].service_delivery_settings);
+    assert_eq!(None, listeners[0].delivery_settings);
+    assert_eq!(
+        Some(service_delivery),
+        listeners[1].service_delivery_settings
+    );
+    assert_eq!(Some(delivery), listeners[1].delivery_settings);
+}
+
+async fn listener_transforms_are_returned_for_event_type(database: &dyn Database) {
+    database
+        .register_event_type(&event_type_request("test_event"))
+        .await
+        .expect("Failed to register event type");
+    database
+        .register_service(&service_request("vinnie"))
+        .await
+        .expect("Failed to register service");
+    let transform = json!({ "customer": "payload.name" });
+    let target_schema = json!({
+        "type": "object",
+        "properties": { "customer": { "type": "string" } }
+    });
+    database
+        .register_service_for_event_type(&ListenToEventReq {
+            transform: Some(transform.clone()),
+            target_schema: Some(target_schema.clone()),
+            ..listen_request("vinnie", "test_event", "/events")
+        })
+        .await
+        .expect("Failed to listen to event type");
+
+    let listeners = database
+        .get_service_by_event_type("test_event")
+        .await
+        .expect("Failed to get listening services");
+
+    assert_eq!(1, listeners.len());
+    let stored_json = |json: &Option<String>| {
+        serde_json::from_str::<Value>(json.as_deref().expect("Missing stored JSON")).unwrap()
+    };
+    assert_eq!(transform, stored_json(&listeners[0].transform));
+    assert_eq!(target_schema, stored_json(&listeners[0].target_schema));
+}
+
+async fn event_type_without_listeners_returns_no_services(database: &dyn Database) {
+    database
+        .register_event_type(&event_type_request("test_event"))
+        .await
+        .expect("Failed to register event type");
+
+    let listeners = database
+        .get_service_by_event_type("test_event")
+        .await
+        .expect("Failed to get listening services");
+
+    assert!(listeners.is_empty());
+}
+
+async fn removed_service_is_no_longer_returned_as_listener(database: &dyn Database) {
+    database
+        .register_event_type(&event_type_request("test_event"))
+        .await
+        .expect("Failed to register event type");
+    database
+        .register_service(&service_request("vinnie"))
+        .await
+        .expect("Failed to register service");
+    database
+        .register_service_for_event_type(&listen_request("vinnie", "test_event", "/events"))
+        .await
+        .expect("Failed to listen to event type");
+    database
+        .remove_service("vinnie")
+        .await
+        .expect("Failed to remove service");
+
+    let listeners = database
+        .get_service_by_event_type("test_event")
+        .await
+        .expect("Failed to get listening services");
+
+    assert!(listeners.is_empty());
+}
+
+async fn published_event_can_be_fulfilled(database: &dyn Database) {
+    let event = publish_event(database, "test_event").await;
+
+    database
+        .fulfil_event(&event)
+        .await
+        .expect("Failed to fulfil event");
+}
+
+async fn failed_event_is_not_due_before_retry_time(database: &dyn Database) {
+    let event = publish_event(database, "test_event").await;
+    database
+        .add_failed_event(&event)
+        .await
+        .expect("Failed to add failed event");
+
+    let failed_events = claim_due_failed_events(database).await;
+
+    assert!(failed_events.is_empty());
+}
+
+async fn failed_event_is_due_after_retry_time(database: &dyn Database) {
+    let event = publish_event(database, "test_event").await;
+    database
+        .add_failed_event(&event)
+        .await
+        .expect("Failed to add failed event");
+    database
+        .update_retry_time(event.id, Utc::now() - ChronoDuration::minutes(1), 1)
+        .await
+        .expect("Failed to update retry time");
+
+    let failed_events = claim_due_failed_events(database).await;
+
+    assert_eq!(1, failed_events.len());
+    assert_eq!(event.id, failed_events[0].id);
+    assert_eq!(event.event_type, failed_events[0].event_type);
+    assert_eq!(event.payload, failed_events[0].payload);
+    assert_eq!(
+        1,
+        failed_events[0]
+            .retry_details
+            .expect("Failed event should carry retry details")
+            .retry_count
+    );
+}
+
+async fn failed_event_becomes_due_once_the_clock_passes_its_retry_time(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let event = publish_event(database, "test_event").await;
+    database
+        .add_failed_event(&event)
+        .await
+        .expect("Failed to add failed event");
+    assert!(claim_due_failed_events(database).await.is_empty());
+
+    clock.advance(ChronoDuration::minutes(2));
+
+    let failed_events = claim_due_failed_events(database).await;
+    assert_eq!(1, failed_events.len());
+    assert_eq!(event.id, failed_events[0].id);
+}
+
+async fn rescheduled_failed_event_is_not_due_until_the_clock_reaches_it(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let event = publish_event(database, "test_event").await;
+    database
+        .add_failed_event(&event)
+        .await
+        .expect("Failed to add failed event");
+    database
+        .update_retry_time(event.id, clock.now() + ChronoDuration::minutes(5), 1)
+        .await
+        .expect("Failed to update retry time");
+
+    clock.advance(ChronoDuration::minutes(4));
+    assert!(claim_due_failed_events(database).await.is_empty());
+
+    clock.advance(ChronoDuration::minutes(2));
+    let failed_events = claim_due_failed_events(database).await;
+    assert_eq!(1, failed_events.len());
+}
+
+async fn next_retry_time_is_the_earliest_pending_retry(database: &dyn Database, clock: &MockClock) {
+    assert_eq!(
+        None,
+        database
+            .get_next_retry_time()
+            .await
+            .expect("Failed to get next retry time")
+    );
+
+    let later_event = publish_event(database, "test_event").await;
+    let sooner_event = publish_event(database, "test_event").await;
+    for event in [&later_event, &sooner_event] {
+        database
+            .add_failed_event(event)
+            .await
+            .expect("Failed to add failed event");
+    }
+    let sooner = clock.now() + ChronoDuration::minutes(3);
+    database
+        .update_retry_time(later_event.id, clock.now() + ChronoDuration::minutes(5), 1)
+        .await
+        .expect("Failed to update retry time");
+    database
+        .update_retry_time(sooner_event.id, sooner, 1)
+        .await
+        .expect("Failed to update retry time");
+
+    let next_retry_time = database
+        .get_next_retry_time()
+        .await
+        .expect("Failed to get next retry time")
+        .expect("Expected a pending retry");
+    assert_eq!(
+        sooner.timestamp_millis(),
+        next_retry_time.timestamp_millis()
+    );
+}
+
+async fn next_retry_time_ignores_resolved_and_exhausted_events(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let resolved_event = publish_event(database, "test_event").await;
+    let exhausted_event = publish_event(database, "test_event").await;
+    for event in [&resolved_event, &exhausted_event] {
+        database
+            .add_failed_event(event)
+            .await
+            .expect("Failed to add failed event");
+    }
+    database
+        .resolve_failed_event(resolved_event.id)
+        .await
+        .expect("Failed to resolve failed event");
+    database
+        .update_retry_time(exhausted_event.id, clock.now(), MAX_RETRIES)
+        .await
+        .expect("Failed to update retry time");
+
+    assert_eq!(
+        None,
+        database
+            .get_next_retry_time()
+            .await
+            .expect("Failed to get next retry time")
+    );
+}
+
+async fn claimed_failed_event_is_not_claimed_again_until_its_lease_expires(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let event = publish_event(database, "test_event").await;
+    database
+        .add_failed_event(&event)
+        .await
+        .expect("Failed to add failed event");
+    clock.advance(ChronoDuration::minutes(2));
+
+    let claimed = database
+        .claim_failed_events("instance-a", ChronoDuration::minutes(1), 10)
+        .await
+        .expect("Failed to claim failed events");
+    assert_eq!(1, claimed.len());
+    assert_eq!(event.id, claimed[0].id);
+
+    let claimed = database
+        .claim_failed_events("instance-b", ChronoDuration::minutes(1), 10)
+        .await
+        .expect("Failed to claim failed events");
+    assert!(claimed.is_empty());
+
+    clock.advance(ChronoDuration::minutes(2));
+    let claimed = database
+        .claim_failed_events("instance-b", ChronoDuration::minutes(1), 10)
+        .await
+        .expect("Failed to claim failed events");
+    assert_eq!(1, claimed.len());
+}
+
+async fn rescheduling_a_claimed_failed_event_releases_its_lease(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let event = publish_event(database, "test_event").await;
+    database
+        .add_failed_event(&event)
+        .await
+        .expect("Failed to add failed event");
+    clock.advance(ChronoDuration::minutes(2));
+    database
+        .claim_failed_events("instance-a", ChronoDuration::minutes(10), 10)
+        .await
+        .expect("Failed to claim failed events");
+
+    database
+        .update_retry_time(event.id, clock.now() + ChronoDuration::minutes(2), 1)
+        .await
+        .expect("Failed to update retry time");
+    clock.advance(ChronoDuration::minutes(3));
+
+    let claimed = database
+        .claim_failed_events("instance-b", ChronoDuration::minutes(10), 10)
+        .await
+        .expect("Failed to claim failed events");
+    assert_eq!(1, claimed.len());
+}
+
+async fn concurrent_claims_do_not_overlap(database: &dyn Database, clock: &MockClock) {
+    let mut event_ids = vec![];
+    for _ in 0..20 {
+        let event = publish_event(database, "test_event").await;
+        database
+            .add_failed_event(&event)
+            .await
+            .expect("Failed to add failed event");
+        event_ids.push(event.id);
+    }
+    clock.advance(ChronoDuration::minutes(2));
+
+    let (claimed_by_a, claimed_by_b) = tokio::join!(
+        database.claim_failed_events("instance-a", ChronoDuration::minutes(1), 20),
+        database.claim_failed_events("instance-b", ChronoDuration::minutes(1), 20),
+    );
+    let mut claimed: Vec<Uuid> = claimed_by_a
+        .expect("Failed to claim failed events")
+        .into_iter()
+        .chain(claimed_by_b.expect("Failed to claim failed events"))
+        .map(|event| event.id)
+        .collect();
+    claimed.sort();
+    event_ids.sort();
+    assert_eq!(event_ids, claimed);
+}
+
+async fn failed_events_are_claimed_earliest_retry_first_up_to_the_limit(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let mut events = vec![];
+    for minutes in [3, 1, 2] {
+        let event = publish_event(database, "test_event").await;
+        database
+            .add_failed_event(&event)
+            .await
+            .expect("Failed to add failed event");
+        database
+            .update_retry_time(event.id, clock.now() + ChronoDuration::minutes(minutes), 1)
+            .await
+            .expect("Failed to update retry time");
+        events.push(event);
+    }
+    clock.advance(ChronoDuration::minutes(5));
+
+    let claimed = database
+        .claim_failed_events("instance-a", ChronoDuration::minutes(1), 2)
+        .await
+        .expect("Failed to claim failed events");
+    assert_eq!(vec![events[1].id, events[2].id], event_ids(&claimed));
+
+    let claimed = database
+        .claim_failed_events("instance-a", ChronoDuration::minutes(1), 2)
+        .await
+        .expect("Failed to claim failed events");
+    assert_eq!(vec![events[0].id], event_ids(&claimed));
+}
+
+async fn pending_event_is_not_claimed_again_until_its_lease_expires(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let event = publish_event(database, "test_event").await;
+
+    let claimed = database
+        .claim_pending_events("instance-a", ChronoDuration::minutes(1), clock.now(), 10)
+        .await
+        .expect("Failed to claim pending events");
+    assert_eq!(1, claimed.len());
+    assert_eq!(event.id, claimed[0].id);
+    assert_eq!(event.payload, claimed[0].payload);
+    assert!(claimed[0].retry_details.is_none());
+
+    let claimed = database
+        .claim_pending_events("instance-b", ChronoDuration::minutes(1), clock.now(), 10)
+        .await
+        .expect("Failed to claim pending events");
+    assert!(claimed.is_empty());
+
+    clock.advance(ChronoDuration::minutes(2));
+    let claimed = database
+        .claim_pending_events("instance-b", ChronoDuration::minutes(1), clock.now(), 10)
+        .await
+        .expect("Failed to claim pending events");
+    assert_eq!(1, claimed.len());
+}
+
+async fn pending_lease_is_renewed_only_while_no_other_instance_holds_it(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let event = publish_event(database, "test_event").await;
+    database
+        .claim_pending_events("instance-a", ChronoDuration::minutes(1), clock.now(), 10)
+        .await
+        .expect("Failed to claim pending events");
+
+    // The holder keeps the event past the lease it was claimed with
+    clock.advance(ChronoDuration::seconds(50));
+    let renew = |lease_owner| {
+        database.renew_pending_lease(event.id, lease_owner, ChronoDuration::minutes(1))
+    };
+    assert!(renew("instance-a").await.expect("Failed to renew lease"));
+    assert!(!renew("instance-b").await.expect("Failed to renew lease"));
+    clock.advance(ChronoDuration::seconds(50));
+    let claimed = database
+        .claim_pending_events("instance-b", ChronoDuration::minutes(1), clock.now(), 10)
+        .await
+        .expect("Failed to claim pending events");
+    assert!(claimed.is_empty());
+
+    // Once another instance claims the expired event, the first one may not dispatch it
+    clock.advance(ChronoDuration::minutes(2));
+    let claimed = database
+        .claim_pending_events("instance-b", ChronoDuration::minutes(1), clock.now(), 10)
+        .await
+        .expect("Failed to claim pending events");
+    assert_eq!(1, claimed.len());
+    assert!(!renew("instance-a").await.expect("Failed to renew lease"));
+
+    database
+        .mark_event_dispatched(event.id)
+        .await
+        .expect("Failed to mark event dispatched");
+    assert!(!renew("instance-b").await.expect("Failed to renew lease"));
+}
+
+async fn dispatched_event_is_no_longer_pending(database: &dyn Database, clock: &MockClock) {
+    let event = publish_event(database, "test_event").await;
+    database
+        .claim_pending_events("instance-a", ChronoDuration::minutes(1), clock.now(), 10)
+        .await
+        .expect("Failed to claim pending events");
+    database
+        .mark_event_dispatched(event.id)
+        .await
+        .expect("Failed to mark event dispatched");
+
+    clock.advance(ChronoDuration::minutes(2));
+    let claimed = database
+        .claim_pending_events("instance-b", ChronoDuration::minutes(1), clock.now(), 10)
+        .await
+        .expect("Failed to claim pending events");
+    assert!(claimed.is_empty());
+}
+
+async fn concurrent_pending_claims_do_not_overlap(database: &dyn Database, clock: &MockClock) {
+    let mut event_ids = vec![];
+    for _ in 0..20 {
+        event_ids.push(publish_event(database, "test_event").await.id);
+    }
+
+    let (claimed_by_a, claimed_by_b) = tokio::join!(
+        database.claim_pending_events("instance-a", ChronoDuration::minutes(1), clock.now(), 15),
+        database.claim_pending_events("instance-b", ChronoDuration::minutes(1), clock.now(), 15),
+    );
+    let mut claimed: Vec<Uuid> = claimed_by_a
+        .expect("Failed to claim pending events")
+        .into_iter()
+        .chain(claimed_by_b.expect("Failed to claim pending events"))
+        .map(|event| event.id)
+        .collect();
+    claimed.sort();
+    event_ids.sort();
+    assert_eq!(event_ids, claimed);
+}
+
+async fn pending_event_published_after_the_cutoff_is_not_claimed(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let cutoff = clock.now();
+    clock.advance(ChronoDuration::seconds(30));
+    publish_event(database, "test_event").await;
+
+    let claimed = database
+        .claim_pending_events("instance-a", ChronoDuration::minutes(1), cutoff, 10)
+        .await
+        .expect("Failed to claim pending events");
+    assert!(claimed.is_empty());
+
+    let claimed = database
+        .claim_pending_events("instance-a", ChronoDuration::minutes(1), clock.now(), 10)
+        .await
+        .expect("Failed to claim pending events");
+    assert_eq!(1, claimed.len());
+}
+
+async fn spilled_events_are_taken_once_oldest_first(database: &dyn Database, clock: &MockClock) {
+    let queued = publish_event(database, "test_event").await;
+    let mut spilled = vec![];
+    for _ in 0..3 {
+        let event = publish_event(database, "test_event").await;
+        database
+            .mark_event_spilled(event.id)
+            .await
+            .expect("Failed to mark event spilled");
+        spilled.push(event);
+        clock.advance(ChronoDuration::seconds(1));
+    }
+    database
+        .mark_event_dispatched(spilled[2].id)
+        .await
+        .expect("Failed to mark event dispatched");
+
+    let taken = database
+        .take_spilled_events(10)
+        .await
+        .expect("Failed to take spilled events");
+    let mut taken = event_ids(&taken);
+    taken.sort();
+    let mut expected = vec![spilled[0].id, spilled[1].id];
+    expected.sort();
+    assert_eq!(expected, taken);
+    assert!(!taken.contains(&queued.id));
+
+    // Taken events wait in the queue however long it takes, so they are not taken again
+    clock.advance(ChronoDuration::hours(1));
+    let taken = database
+        .take_spilled_events(10)
+        .await
+        .expect("Failed to take spilled events");
+    assert!(taken.is_empty());
+}
+
+async fn marking_an_unknown_event_dispatched_returns_not_found(database: &dyn Database) {
+    let result = database.mark_event_dispatched(Uuid::new_v4()).await;
+
+    assert!(matches!(result, Err(VentrixError::EventNotFound(_))));
+}
+
+async fn failed_event_is_not_due_after_max_retries(database: &dyn Database) {
+    let event = publish_event(database, "test_event").await;
+    database
+        .add_failed_event(&event)
+        .await
+        .expect("Failed to add failed event");
+    database
+        .update_retry_time(
+            event.id,
+            Utc::now() - ChronoDuration::minutes(1),
+            MAX_RETRIES,
+        )
+        .await
+        .expect("Failed to update retry time");
+
+    let failed_events = claim_due_failed_events(database).await;
+
+    assert!(failed_events.is_empty());
+}
+
+async fn resolved_failed_event_is_not_due(database: &dyn Database) {
+    let event = publish_event(database, "test_event").await;
+    database
+        .add_failed_event(&event)
+        .await
+        .expect("Failed to add failed event");
+    database
+        .update_retry_time(event.id, Utc::now() - ChronoDuration::minutes(1), 1)
+        .await
+        .expect("Failed to update retry time");
+    database
+        .resolve_failed_event(event.id)
+        .await
+        .expect("Failed to resolve failed event");
+
+    let failed_events = claim_due_failed_events(database).await;
+
+    assert!(failed_events.is_empty());
+}
+
+async fn adding_a_failed_event_for_an_unknown_event_returns_not_found(database: &dyn Database) {
+    let event = VentrixEvent {
+        id: Uuid::new_v4(),
+        event_type: String::from("test_event"),
+        payload: String::from("{}"),
+        retry_details: None,
+        trace_context: TraceContext::default(),
+    };
+
+    let err = database
+        .add_failed_event(&event)
+        .await
+        .expect_err("Adding a failed event for an unknown event should fail");
+
+    assert!(matches!(err, VentrixError::EventNotFound(_)));
+}
+
+async fn updating_retry_time_of_an_unknown_failed_event_returns_not_found(database: &dyn Database) {
+    let err = database
+        .update_retry_time(Uuid::new_v4(), Utc::now(), 1)
+        .await
+        .expect_err("Updating an unknown failed event should fail");
+
+    assert!(matches!(err, VentrixError::EventNotFound(_)));
+}
+
+async fn resolving_an_unknown_failed_event_returns_not_found(database: &dyn Database) {
+    let err = database
+        .resolve_failed_event(Uuid::new_v4())
+        .await
+        .expect_err("Resolving an unknown failed event should fail");
+
+    assert!(matches!(err, VentrixError::EventNotFound(_)));
+}
+
+async fn registering_a_duplicate_service_returns_already_exists(database: &dyn Database) {
+    database
+        .register_service(&service_request("vinnie"))
+        .await
+        .expect("Failed to register service");
+
+    let err = database
+        .register_service(&service_request("vinnie"))
+        .await
+        .expect_err("Registering a duplicate service should fail");
+
+    assert!(matches!(err, VentrixError::ServiceAlreadyExists(_)));
+}
+
+async fn registering_a_duplicate_event_type_returns_already_exists(database: &dyn Database) {
+    database
+        .register_event_type(&event_type_request("test_event"))
+        .await
+        .expect("Failed to register event type");
+
+    let err = database
+        .register_event_type(&event_type_request("test_event"))
+        .await
+        .expect_err("Registering a duplicate event type should fail");
+
+    assert!(matches!(err, VentrixError::EventTypeAlreadyExists(_)));
+}
+
+async fn getting_an_unknown_service_returns_not_found(database: &dyn Database) {
+    let err = database
+        .get_service("unknown")
+        .await
+        .expect_err("Getting an unknown service should fail");
+
+    assert!(matches!(err, VentrixError::ServiceNotFound(_)));
+}
+
+async fn removing_an_unknown_service_returns_not_found(database: &dyn Database) {
+    let err = database
+        .remove_service("unknown")
+        .await
+        .expect_err("Removing an unknown service should fail");
+
+    assert!(matches!(err, VentrixError::ServiceNotFound(_)));
+}
+
+async fn getting_the_schema_of_an_unknown_event_type_returns_not_found(database: &dyn Database) {
+    let err = database
+        .get_schema_for_event_type("unknown")
+        .await
+        .expect_err("Getting the schema of an unknown event type should fail");
+
+    assert!(matches!(err, VentrixError::EventTypeNotFound(_)));
+}
+
+async fn fulfilling_an_unknown_event_returns_not_found(database: &dyn Database) {
+    let event = VentrixEvent {
+        id: Uuid::new_v4(),
+        event_type: String::from("test_event"),
+        payload: String::from("{}"),
+        retry_details: None,
+        trace_context: TraceContext::default(),
+    };
+
+    let err = database
+        .fulfil_event(&event)
+        .await
+        .expect_err("Fulfilling an unknown event should fail");
+
+    assert!(matches!(err, VentrixError::EventNotFound(_)));
+}
+
+async fn listening_to_an_unknown_event_type_returns_referenced_entity_missing(
+    database: &dyn Database,
+) {
+    database
+        .register_service(&service_request("vinnie"))
+        .await
+        .expect("Failed to register service");
+
+    let err = database
+        .register_service_for_event_type(&ListenToEventReq {
+            service_name: String::from("vinnie"),
+            event_type: String::from("unknown"),
+            endpoint: String::from("/events"),
+            filter: None,
+            transform: None,
+            target_schema: None,
+            delivery: None,
+            sealed_delivery: None,
+        })
+        .await
+        .expect_err("Listening to an unknown event type should fail");
+
+    assert!(matches!(err, VentrixError::ReferencedEntityMissing(_)));
+}
+
+async fn listening_as_an_unknown_service_returns_referenced_entity_missing(
+    database: &dyn Database,
+) {
+    database
+        .register_event_type(&event_type_request("test_event"))
+        .await
+        .expect("Failed to register event type");
+
+    let err = database
+        .register_service_for_event_type(&ListenToEventReq {
+            service_name: String::from("unknown"),
+            event_type: String::from("test_event"),
+            endpoint: String::from("/events"),
+            filter: None,
+            transform: None,
+            target_schema: None,
+            delivery: None,
+            sealed_delivery: None,
+        })
+        .await
+        .expect_err("Listening as an unknown service should fail");
+
+    assert!(matches!(err, VentrixError::ReferencedEntityMissing(_)));
+}
+
+async fn claimed_events_keep_their_trace_context(database: &dyn Database, clock: &MockClock) {
+    let event = publish_event(database, "test_event").await;
+
+    let claimed = database
+        .claim_pending_events("instance-a", ChronoDuration::minutes(1), clock.now(), 10)
+        .await
+        .expect("Failed to claim pending events");
+    assert_eq!(trace_context(), claimed[0].trace_context);
+
+    database
+        .add_failed_event(&event)
+        .await
+        .expect("Failed to add failed event");
+    clock.advance(ChronoDuration::minutes(2));
+    let claimed = database
+        .claim_failed_events("instance-a", ChronoDuration::minutes(1), 10)
+        .await
+        .expect("Failed to claim failed events");
+    assert_eq!(trace_context(), claimed[0].trace_context);
+}
+
+async fn migrated_backend_passes_health_check(database: &dyn Database) {
+    database
+        .check_health()
+        .await
+        .expect("Health check failed on a migrated backend");
+}
+
+async fn published_event_shows_when_it_was_dispatched_and_fulfilled(database: &dyn Database) {
+    let event = publish_event(database, "test_event").await;
+
+    let published = database
+        .get_event(event.id)
+        .await
+        .expect("Failed to get event");
+    assert_eq!(event.event_type, published.event_type);
+    assert_eq!(event.payload, published.payload);
+    assert!(published.dispatched_at.is_none());
+    assert!(published.fulfilled_at.is_none());
+
+    database
+        .mark_event_dispatched(event.id)
+        .await
+        .expect("Failed to mark event dispatched");
+    database
+        .fulfil_event(&event)
+        .await
+        .expect("Failed to fulfil event");
+
+    let published = database
+        .get_event(event.id)
+        .await
+        .expect("Failed to get event");
+    assert!(published.dispatched_at.is_some());
+    assert!(published.fulfilled_at.is_some());
+}
+
+async fn getting_an_unknown_event_returns_not_found(database: &dyn Database) {
+    let err = database
+        .get_event(Uuid::new_v4())
+        .await
+        .expect_err("Getting an unknown event should fail");
+
+    assert!(matches!(err, VentrixError::EventNotFound(_)));
+}
+
+async fn recording_a_delivery_attempt_for_an_unknown_event_returns_not_found(
+    database: &dyn Database,
+) {
+    let event = VentrixEvent {
+        id: Uuid::new_v4(),
+        event_type: String::from("test_event"),
+        payload: String::from("{}"),
+        retry_details: None,
+        trace_context: TraceContext::default(),
+    };
+
+    let err = database
+        .record_delivery_attempt(&delivery_attempt(&event, "vinnie", 0, Utc::now()))
+        .await
+        .expect_err("Recording an attempt for an unknown event should fail");
+
+    assert!(matches!(err, VentrixError::EventNotFound(_)));
+}
+
+async fn delivery_attempts_of_several_events_are_returned_together(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let event = publish_event(database, "test_event").await;
+    let other_event = publish_event(database, "test_event").await;
+    let unselected_event = publish_event(database, "test_event").await;
+    let retry = delivery_attempt(
+        &event,
+        "vinnie",
+        1,
+        clock.now() + ChronoDuration::minutes(2),
+    );
+    let other = delivery_attempt(
+        &other_event,
+        "vinnie",
+        0,
+        clock.now() + ChronoDuration::minutes(1),
+    );
+    let first = delivery_attempt(&event, "vinnie", 0, clock.now());
+
+    for attempt in [
+        &retry,
+        &other,
+        &first,
+        &delivery_attempt(&unselected_event, "vinnie", 0, clock.now()),
+    ] {
+        database
+            .record_delivery_attempt(attempt)
+            .await
+            .expect("Failed to record delivery attempt");
+    }
+
+    let attempts = database
+        .get_delivery_attempts_for_events(&[event.id, other_event.id, Uuid::new_v4()])
+        .await
+        .expect("Failed to get delivery attempts");
+    assert_eq!(
+        vec![first.id, other.id, retry.id],
+        attempts
+            .iter()
+            .map(|attempt| attempt.id)
+            .collect::<Vec<_>>()
+    );
+    assert!(database
+        .get_delivery_attempts_for_events(&[])
+        .await
+        .expect("Failed to get delivery attempts")
+        .is_empty());
+}
+
+async fn delivery_attempts_are_returned_oldest_first(database: &dyn Database, clock: &MockClock) {
+    let event = publish_event(database, "test_event").await;
+    let other_event = publish_event(database, "test_event").await;
+    let first = delivery_attempt(&event, "vinnie", 0, clock.now());
+    let retry = DeliveryAttempt {
+        status_code: Some(200),
+        succeeded: true,
+        error: None,
+        ..delivery_attempt(
+            &event,
+            "vinnie",
+            1,
+            clock.now() + ChronoDuration::minutes(1),
+        )
+    };
+
+    for attempt in [
+        &retry,
+        &first,
+        &delivery_attempt(&other_event, "vinnie", 0, clock.now()),
+    ] {
+        database
+            .record_delivery_attempt(attempt)
+            .await
+            .expect("Failed to record delivery attempt");
+    }
+
+    let attempts = database
+        .get_delivery_attempts(event.id)
+        .await
+        .expect("Failed to get delivery attempts");
+    assert_eq!(
+        vec![first.id, retry.id],
+        attempts
+            .iter()
+            .map(|attempt| attempt.id)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(Some(500), attempts[0].status_code);
+    assert_eq!(Some("Internal Server Error"), attempts[0].error.as_deref());
+    assert!(!attempts[0].succeeded);
+    assert_eq!(12, attempts[0].latency_ms);
+    assert_eq!(1, attempts[1].attempt);
+    assert!(attempts[1].succeeded);
+    assert!(database
+        .get_delivery_attempts(Uuid::new_v4())
+        .await
+        .expect("Failed to get delivery attempts")
+        .is_empty());
+}
+
+async fn events_are_listed_newest_first(database: &dyn Database, clock: &MockClock) {
+    let oldest = publish_event(database, "test_event").await;
+    clock.advance(ChronoDuration::seconds(1));
+    let middle = publish_event(database, "test_event").await;
+    clock.advance(ChronoDuration::seconds(1));
+    let newest = publish_event(database, "test_event").await;
+
+    let events = database
+        .list_events(&EventQuery::default())
+        .await
+        .expect("Failed to list events");
+    assert_eq!(vec![newest.id, middle.id, oldest.id], listed_ids(&events));
+
+    let events = database
+        .list_events(&EventQuery {
+            limit: Some(2),
+            ..EventQuery::default()
+        })
+        .await
+        .expect("Failed to list events");
+    assert_eq!(vec![newest.id, middle.id], listed_ids(&events));
+}
+
+async fn listed_events_can_be_filtered(database: &dyn Database, clock: &MockClock) {
+    let start = clock.now();
+    let fulfilled = publish_event(database, "order_created").await;
+    database
+        .fulfil_event(&fulfilled)
+        .await
+        .expect("Failed to fulfil event");
+    clock.advance(ChronoDuration::minutes(1));
+    let pending = publish_event(database, "order_created").await;
+    database
+        .record_delivery_attempt(&delivery_attempt(&pending, "vinnie", 0, clock.now()))
+        .await
+        .expect("Failed to record delivery attempt");
+    clock.advance(ChronoDuration::minutes(1));
+    let other_type = publish_event(database, "order_shipped").await;
+
+    let cases = [
+        (
+            EventQuery {
+                event_type: Some(String::from("order_created")),
+                ..EventQuery::default()
+            },
+            vec![pending.id, fulfilled.id],
+        ),
+        (
+            EventQuery {
+                status: Some(FulfilmentStatus::Fulfilled),
+                ..EventQuery::default()
+            },
+            vec![fulfilled.id],
+        ),
+        (
+            EventQuery {
+                status: Some(FulfilmentStatus::Pending),
+                ..EventQuery::default()
+            },
+            vec![other_type.id, pending.id],
+        ),
+        (
+            EventQuery {
+                service: Some(String::from("vinnie")),
+                ..EventQuery::default()
+            },
+            vec![pending.id],
+        ),
+        (
+            EventQuery {
+                published_after: Some(start + ChronoDuration::seconds(30)),
+                published_before: Some(start + ChronoDuration::seconds(90)),
+                ..EventQuery::default()
+            },
+            vec![pending.id],
+        ),
+    ];
+
+    for (query, expected) in cases {
+        let events = database
+            .list_events(&query)
+            .await
+            .expect("Failed to list events");
+        assert_eq!(expected, listed_ids(&events), "{:?}", query);
+    }
+}
+
+async fn replay_progress_is_saved_until_the_replay_is_cancelled(database: &dyn Database) {
+    let event = publish_event(database, "test_event").await;
+    let job = ReplayJob {
+        event_type: Some(String::from("test_event")),
+        published_after: Some(Utc::now() - ChronoDuration::days(1)),
+        published_before: Some(Utc::now()),
+        total: 2,
+        ..replay_job("vinnie")
+    };
+    database
+        .create_replay_job(&job)
+        .await
+        .expect("Failed to create replay job");
+
+    database
+        .update_replay_job(&ReplayJob {
+            delivered: 1,
+            ..job.clone()
+        })
+        .await
+        .expect("Failed to update replay job");
+    let saved = database
+        .get_replay_job(job.id)
+        .await
+        .expect("Failed to get replay job");
+    assert_eq!(ReplayStatus::Running, saved.status);
+    assert_eq!(Some(String::from("test_event")), saved.event_type);
+    assert_eq!(2, saved.total);
+    assert_eq!(1, saved.delivered);
+
+    let attempt = DeliveryAttempt {
+        replay_id: Some(job.id),
+        ..delivery_attempt(&event, "vinnie", 0, Utc::now())
+    };
+    database
+        .record_delivery_attempt(&attempt)
+        .await
+        .expect("Failed to record delivery attempt");
+    let attempts = database
+        .get_delivery_attempts(event.id)
+        .await
+        .expect("Failed to get delivery attempts");
+    assert_eq!(Some(job.id), attempts[0].replay_id);
+
+    let cancelled = database
+        .cancel_replay_job(job.id)
+        .await
+        .expect("Failed to cancel replay job");
+    assert_eq!(ReplayStatus::Cancelled, cancelled.status);
+    assert!(cancelled.finished_at.is_some());
+
+    // A runner that has not noticed the cancellation yet cannot undo it
+    database
+        .update_replay_job(&ReplayJob {
+            delivered: 2,
+            status: ReplayStatus::Completed,
+            ..job.clone()
+        })
+        .await
+        .expect("Failed to update replay job");
+    let saved = database
+        .get_replay_job(job.id)
+        .await
+        .expect("Failed to get replay job");
+    assert_eq!(ReplayStatus::Cancelled, saved.status);
+    assert_eq!(1, saved.delivered);
+}
+
+async fn getting_an_unknown_replay_returns_not_found(database: &dyn Database) {
+    let err = database
+        .get_replay_job(Uuid::new_v4())
+        .await
+        .expect_err("Getting an unknown replay should fail");
+
+    assert!(matches!(err, VentrixError::ReplayNotFound(_)));
+}
+
+async fn cancelling_an_unknown_replay_returns_not_found(database: &dyn Database) {
+    let err = database
+        .cancel_replay_job(Uuid::new_v4())
+        .await
+        .expect_err("Cancelling an unknown replay should fail");
+
+    assert!(matches!(err, VentrixError::ReplayNotFound(_)));
+}
+
+async fn events_to_replay_can_be_selected_by_id(database: &dyn Database) {
+    let first = publish_event(database, "test_event").await;
+    let second = publish_event(database, "other_event").await;
+    publish_event(database, "test_event").await;
+    let job = ReplayJob {
+        event_ids: Some(vec![first.id, second.id, Uuid::new_v4()]),
+        ..replay_job("vinnie")
+    };
+
+    assert_eq!(
+        2,
+        database
+            .count_events_to_replay(&job)
+            .await
+            .expect("Failed to count events to replay")
+    );
+    let mut selected = replayed_ids(
+        &database
+            .get_events_to_replay(&job, 10)
+            .await
+            .expect("Failed to get events to replay"),
+    );
+    selected.sort();
+    let mut expected = vec![first.id, second.id];
+    expected.sort();
+    assert_eq!(expected, selected);
+}
+
+async fn events_to_replay_are_selected_by_type_and_window_oldest_first(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let start = clock.now();
+    publish_event(database, "test_event").await;
+    let mut in_window = vec![];
+    for _ in 0..3 {
+        clock.advance(ChronoDuration::minutes(1));
+        in_window.push(publish_event(database, "test_event").await);
+        publish_event(database, "other_event").await;
+    }
+    clock.advance(ChronoDuration::minutes(1));
+    publish_event(database, "test_event").await;
+    let job = ReplayJob {
+        event_type: Some(String::from("test_event")),
+        published_after: Some(start + ChronoDuration::seconds(30)),
+        published_before: Some(start + ChronoDuration::minutes(4)),
+        ..replay_job("vinnie")
+    };
+
+    assert_eq!(
+        3,
+        database
+            .count_events_to_replay(&job)
+            .await
+            .expect("Failed to count events to replay")
+    );
+    let first_page = database
+        .get_events_to_replay(&job, 2)
+        .await
+        .expect("Failed to get events to replay");
+    // The page after the cursor is unaffected by events before it being pruned
+    let (cursor_published_at, cursor_event) = first_page.last().unwrap();
+    database
+        .delete_events(&[in_window[0].id])
+        .await
+        .expect("Failed to delete events");
+    let job = ReplayJob {
+        cursor_published_at: Some(*cursor_published_at),
+        cursor_event_id: Some(cursor_event.id),
+        ..job
+    };
+    let second_page = database
+        .get_events_to_replay(&job, 2)
+        .await
+        .expect("Failed to get events to replay");
+    assert_eq!(event_ids(&in_window[..2]), replayed_ids(&first_page));
+    assert_eq!(event_ids(&in_window[2..]), replayed_ids(&second_page));
+    assert_eq!(trace_context(), first_page[0].1.trace_context);
+}
+
+async fn replays_are_claimed_once_their_lease_expires(database: &dyn Database, clock: &MockClock) {
+    let job = ReplayJob {
+        lease_owner: Some(String::from("instance-a")),
+        lease_expires_at: Some(clock.now() + ChronoDuration::minutes(1)),
+        ..replay_job("vinnie")
+    };
+    database
+        .create_replay_job(&job)
+        .await
+        .expect("Failed to create replay job");
+    let finished = ReplayJob {
+        status: ReplayStatus::Completed,
+        ..replay_job("vinnie")
+    };
+    database
+        .create_replay_job(&finished)
+        .await
+        .expect("Failed to create replay job");
+
+    let claimed = database
+        .claim_replay_jobs("instance-b", ChronoDuration::minutes(1))
+        .await
+        .expect("Failed to claim replay jobs");
+    assert!(claimed.is_empty());
+
+    // Progress renews the lease, and only its holder may save it
+    clock.advance(ChronoDuration::seconds(50));
+    let renewed = ReplayJob {
+        delivered: 1,
+        lease_expires_at: Some(clock.now() + ChronoDuration::minutes(1)),
+        ..job.clone()
+    };
+    database
+        .update_replay_job(&renewed)
+        .await
+        .expect("Failed to update replay job");
+    clock.advance(ChronoDuration::seconds(50));
+    let claimed = database
+        .claim_replay_jobs("instance-b", ChronoDuration::minutes(1))
+        .await
+        .expect("Failed to claim replay jobs");
+    assert!(claimed.is_empty());
+
+    clock.advance(ChronoDuration::minutes(1));
+    let claimed = database
+        .claim_replay_jobs("instance-b", ChronoDuration::minutes(1))
+        .await
+        .expect("Failed to claim replay jobs");
+    assert_eq!(1, claimed.len());
+    assert_eq!(job.id, claimed[0].id);
+    assert_eq!(1, claimed[0].delivered);
+    assert_eq!(Some(String::from("instance-b")), claimed[0].lease_owner);
+
+    database
+        .update_replay_job(&ReplayJob {
+            delivered: 2,
+            ..renewed
+        })
+        .await
+        .expect("Failed to update replay job");
+    let saved = database
+        .get_replay_job(job.id)
+        .await
+        .expect("Failed to get replay job");
+    assert_eq!(1, saved.delivered);
+    assert_eq!(Some(String::from("instance-b")), saved.lease_owner);
+}
+
+async fn deleted_events_are_removed_with_their_failures_and_attempts(database: &dyn Database) {
+    let deleted = publish_event(database, "test_event").await;
+    let kept = publish_event(database, "test_event").await;
+    for event in [&deleted, &kept] {
+        database
+            .add_failed_event(event)
+            .await
+            .expect("Failed to add failed event");
+        database
+            .update_retry_time(event.id, Utc::now() - ChronoDuration::minutes(1), 1)
+            .await
+            .expect("Failed to update retry time");
+        database
+            .record_delivery_attempt(&delivery_attempt(event, "vinnie", 0, Utc::now()))
+            .await
+            .expect("Failed to record delivery attempt");
+    }
+
+    database
+        .delete_events(&[deleted.id])
+        .await
+        .expect("Failed to delete events");
+
+    let err = database
+        .get_event(deleted.id)
+        .await
+        .expect_err("A deleted event should not be found");
+    assert!(matches!(err, VentrixError::EventNotFound(_)));
+    assert!(database
+        .get_delivery_attempts(deleted.id)
+        .await
+        .expect("Failed to get delivery attempts")
+        .is_empty());
+    let failed_events = claim_due_failed_events(database).await;
+    assert_eq!(vec![kept.id], event_ids(&failed_events));
+    assert_eq!(
+        1,
+        database
+            .get_delivery_attempts(kept.id)
+            .await
+            .expect("Failed to get delivery attempts")
+            .len()
+    );
+}
+
+async fn fulfilled_events_past_the_cutoff_are_selected_for_pruning_oldest_first(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let oldest = publish_event(database, "test_event").await;
+    database
+        .fulfil_event(&oldest)
+        .await
+        .expect("Failed to fulfil event");
+    clock.advance(ChronoDuration::days(1));
+    let cutoff = clock.now();
+    let retried = publish_event(database, "test_event").await;
+    database
+        .add_failed_event(&retried)
+        .await
+        .expect("Failed to add failed event");
+    database
+        .resolve_failed_event(retried.id)
+        .await
+        .expect("Failed to resolve failed event");
+    database
+        .fulfil_event(&retried)
+        .await
+        .expect("Failed to fulfil event");
+    let awaiting_retry = publish_event(database, "test_event").await;
+    database
+        .fulfil_event(&awaiting_retry)
+        .await
+        .expect("Failed to fulfil event");
+    database
+        .add_failed_event(&awaiting_retry)
+        .await
+        .expect("Failed to add failed event");
+    publish_event(database, "test_event").await;
+    clock.advance(ChronoDuration::days(1));
+
+    let all_fulfilled = prune_criteria(RetainedEventKind::Fulfilled, clock.now());
+    assert_eq!(
+        vec![oldest.id, retried.id],
+        events_to_prune(database, &all_fulfilled, 10).await
+    );
+    assert_eq!(
+        vec![oldest.id],
+        events_to_prune(database, &all_fulfilled, 1).await
+    );
+    assert_eq!(
+        vec![oldest.id],
+        events_to_prune(
+            database,
+            &prune_criteria(RetainedEventKind::Fulfilled, cutoff),
+            10
+        )
+        .await
+    );
+}
+
+async fn dead_letters_past_the_cutoff_are_selected_for_pruning(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let dead_letter = dead_letter_event(database, "test_event").await;
+    let other_dead_letter = dead_letter_event(database, "other_event").await;
+    let awaiting_retry = publish_event(database, "test_event").await;
+    database
+        .add_failed_event(&awaiting_retry)
+        .await
+        .expect("Failed to add failed event");
+    let fulfilled = publish_event(database, "test_event").await;
+    database
+        .fulfil_event(&fulfilled)
+        .await
+        .expect("Failed to fulfil event");
+    let recently_dead_lettered = publish_event(database, "test_event").await;
+    database
+        .add_failed_event(&recently_dead_lettered)
+        .await
+        .expect("Failed to add failed event");
+    clock.advance(ChronoDuration::days(1));
+    dead_letter_event(database, "test_event").await;
+    // Dead letters age from when they ran out of retries, not from when they were published
+    database
+        .update_retry_time(recently_dead_lettered.id, clock.now(), MAX_RETRIES)
+        .await
+        .expect("Failed to update retry time");
+
+    let cutoff = clock.now() - ChronoDuration::hours(1);
+    let dead_letters = prune_criteria(RetainedEventKind::DeadLetter, cutoff);
+    let mut selected = events_to_prune(database, &dead_letters, 10).await;
+    selected.sort();
+    let mut expected = vec![dead_letter.id, other_dead_letter.id];
+    expected.sort();
+    assert_eq!(expected, selected);
+
+    let excluding_other = PruneCriteria {
+        excluded_event_types: vec![String::from("other_event")],
+        ..dead_letters.clone()
+    };
+    assert_eq!(
+        vec![dead_letter.id],
+        events_to_prune(database, &excluding_other, 10).await
+    );
+    let only_other = PruneCriteria {
+        event_type: Some(String::from("other_event")),
+        ..dead_letters
+    };
+    assert_eq!(
+        vec![other_dead_letter.id],
+        events_to_prune(database, &only_other, 10).await
+    );
+}
+
+async fn pruning_lock_is_held_by_one_caller_at_a_time(database: &dyn Database) {
+    let pruning_lock = database
+        .try_lock_pruning()
+        .await
+        .expect("Failed to take the pruning lock")
+        .expect("The pruning lock was not free");
+    assert!(database
+        .try_lock_pruning()
+        .await
+        .expect("Failed to take the pruning lock")
+        .is_none());
+
+    drop(pruning_lock);
+    // Postgres releases the lock in the background
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while database
+            .try_lock_pruning()
+            .await
+            .expect("Failed to take the pruning lock")
+            .is_none()
+        {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("The pruning lock was not released");
+}
+
+async fn unfulfilled_events_past_the_cutoff_are_selected_for_pruning(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let unfulfilled = publish_event(database, "test_event").await;
+    let retried = publish_event(database, "test_event").await;
+    database
+        .add_failed_event(&retried)
+        .await
+        .expect("Failed to add failed event");
+    database
+        .resolve_failed_event(retried.id)
+        .await
+        .expect("Failed to resolve failed event");
+    let awaiting_retry = publish_event(database, "test_event").await;
+    database
+        .add_failed_event(&awaiting_retry)
+        .await
+        .expect("Failed to add failed event");
+    let fulfilled = publish_event(database, "test_event").await;
+    database
+        .fulfil_event(&fulfilled)
+        .await
+        .expect("Failed to fulfil event");
+    dead_letter_event(database, "test_event").await;
+    clock.advance(ChronoDuration::days(1));
+    publish_event(database, "test_event").await;
+
+    let cutoff = clock.now() - ChronoDuration::hours(1);
+    let mut selected = events_to_prune(
+        database,
+        &prune_criteria(RetainedEventKind::Unfulfilled, cutoff),
+        10,
+    )
+    .await;
+    selected.sort();
+    let mut expected = vec![unfulfilled.id, retried.id];
+    expected.sort();
+    assert_eq!(expected, selected);
+}
+
+async fn filtered_delivery_attempts_are_marked_as_filtered(database: &dyn Database) {
+    let event = publish_event(database, "test_event").await;
+    let filtered = DeliveryAttempt {
+        status_code: None,
+        succeeded: true,
+        latency_ms: 0,
+        error: None,
+        filtered: true,
+        ..delivery_attempt(&event, "vinnie", 0, Utc::now())
+    };
+    database
+        .record_delivery_attempt(&filtered)
+        .await
+        .expect("Failed to record delivery attempt");
+
+    let attempts = database
+        .get_delivery_attempts(event.id)
+        .await
+        .expect("Failed to get delivery attempts");
+
+    assert_eq!(1, attempts.len());
+    assert!(attempts[0].filtered);
+}
+
+async fn pattern_listeners_are_returned_for_matching_event_types(database: &dyn Database) {
+    for (name, pattern) in [("vinnie", "orders.*"), ("viktor", "orders.#")] {
+        database
+            .register_service(&service_request(name))
+            .await
+            .expect("Failed to register service");
+        database
+            .register_service_for_event_type(&listen_request(name, pattern, "/orders"))
+            .await
+            .expect("Failed to listen to event type pattern");
+    }
+    // Registered after the subscriptions, which still cover them
+    for event_type in ["orders.created", "orders.items.added", "invoices.created"] {
+        database
+            .register_event_type(&event_type_request(event_type))
+            .await
+            .expect("Failed to register event type");
+    }
+
+    for (event_type, expected) in [
+        ("orders.created", vec!["viktor", "vinnie"]),
+        ("orders.items.added", vec!["viktor"]),
+        ("invoices.created", vec![]),
+    ] {
+        let mut listeners: Vec<String> = database
+            .get_service_by_event_type(event_type)
+            .await
+            .expect("Failed to get listening services")
+            .into_iter()
+            .map(|listener| listener.name)
+            .collect();
+        listeners.sort();
+
+        assert_eq!(expected, listeners, "Listeners of {}", event_type);
+    }
+}
+
+async fn subscriptions_show_the_event_types_a_pattern_resolves_to(database: &dyn Database) {
+    for event_type in ["test_event", "orders.created", "orders.cancelled"] {
+        database
+            .register_event_type(&event_type_request(event_type))
+            .await
+            .expect("Failed to register event type");
+    }
+    database
+        .register_service(&service_request("vinnie"))
+        .await
+        .expect("Failed to register service");
+    for event_type in ["test_event", "orders.#"] {
+        database
+            .register_service_for_event_type(&listen_request("vinnie", event_type, "/events"))
+            .await
+            .expect("Failed to listen to event type");
+    }
+
+    let subscriptions = database
+        .get_subscriptions("vinnie")
+        .await
+        .expect("Failed to get subscriptions");
+
+    assert_eq!(
+        vec![
+            (
+                "orders.#",
+                vec!["orders.cancelled".to_string(), "orders.created".to_string()]
+            ),
+            ("test_event", vec!["test_event".to_string()]),
+        ],
+        subscriptions
+            .iter()
+            .map(|subscription| (
+                subscription.event_type.as_str(),
+                subscription.resolved_event_types.clone()
+            ))
+            .collect::<Vec<_>>()
+    );
+    let err = database
+        .get_subscriptions("unknown")
+        .await
+        .expect_err("Getting the subscriptions of an unknown service should fail");
+    assert!(matches!(err, VentrixError::ServiceNotFound(_)));
+}
+
+async fn invalid_event_type_patterns_are_rejected(database: &dyn Database) {
+    database
+        .register_service(&service_request("vinnie"))
+        .await
+        .expect("Failed to register service");
+
+    let err = database
+        .register_service_for_event_type(&listen_request("vinnie", "orders.cre*", "/events"))
+        .await
+        .expect_err("Listening to an invalid pattern should fail");
+    assert!(matches!(err, VentrixError::InvalidEventTypePattern(_)));
+
+    let err = database
+        .register_event_type(&event_type_request("orders.*"))
+        .await
+        .expect_err("Registering an event type with a wildcard should fail");
+    assert!(matches!(err, VentrixError::InvalidEventTypePattern(_)));
+}
+
+fn api_key(name: &str, service_name: Option<&str>, now: DateTime<Utc>) -> ApiKey {
+    let role = match service_name {
+        Some(_) => ApiKeyRole::Subscriber,
+        None => ApiKeyRole::Admin,
+    };
+    let request = CreateApiKeyRequest {
+        name: name.to_string(),
+        role,
+        event_types: None,
+        service_name: service_name.map(String::from),
+    };
+    new_api_key(request, now)
+        .expect("Failed to build API key")
+        .0
+}
+
+async fn stored_api_keys_can_be_found_until_they_are_revoked(
+    database: &dyn Database,
+    clock: &MockClock,
+) {
+    let first = api_key("first", None, clock.now());
+    database
+        .create_api_key(&first)
+        .await
+        .expect("Failed to create API key");
+    clock.advance(ChronoDuration::seconds(1));
+    let second = api_key("second", None, clock.now());
+    database
+        .create_api_key(&second)
+        .await
+        .expect("Failed to create API key");
+
+    let found = database
+        .find_api_key(&first.key_hash)
+        .await
+        .expect("Failed to find API key")
+        .expect("The API key should be found");
+    assert_eq!(first.id, found.id);
+    assert_eq!("first", found.name);
+
+    clock.advance(ChronoDuration::seconds(1));
+    let revoked = database
+        .revoke_api_key(first.id)
+        .await
+        .expect("Failed to revoke API key");
+    assert!(revoked.revoked_at.is_some());
+    assert!(database
+        .find_api_key(&first.key_hash)
+        .await
+        .expect("Failed to find API key")
+        .is_none());
+
+    // Revoking again keeps the original time
+    clock.advance(ChronoDuration::seconds(1));
+    let revoked_again = database
+        .revoke_api_key(first.id)
+        .await
+        .expect("Failed to revoke API key");
+    assert_eq!(
+        revoked.revoked_at.map(|at| at.timestamp_millis()),
+        revoked_again.revoked_at.map(|at| at.timestamp_millis())
+    );
+
+    let listed = database
+        .list_api_keys()
+        .await
+        .expect("Failed to list API keys");
+    assert_eq!(
+        vec![(first.id, true), (second.id, false)],
+        listed
+            .iter()
+            .map(|api_key| (api_key.id, api_key.revoked_at.is_some()))
+            .collect::<Vec<_>>()
+    );
+}
+
+async fn api_keys_of_a_removed_service_are_removed(database: &dyn Database) {
+    database
+        .register_service(&service_request("vinnie"))
+        .await
+        .expect("Failed to register service");
+    let subscriber = api_key("vinnie", Some("vinnie"), Utc::now());
+    database
+        .create_api_key(&subscriber)
+        .await
+        .expect("Failed to create API key");
+
+    database
+        .remove_service("vinnie")
+        .await
+        .expect("Failed to remove service");
+
+    assert!(database
+        .find_api_key(&subscriber.key_hash)
+        .await
+        .expect("Failed to find API key")
+        .is_none());
+    assert!(database
+        .list_api_keys()
+        .await
+        .expect("Failed to list API keys")
+        .is_empty());
+}
+
+async fn api_key_for_an_unknown_service_returns_referenced_entity_missing(database: &dyn Database) {
+    let err = database
+        .create_api_key(&api_key("ghost", Some("ghost"), Utc::now()))
+        .await
+        .expect_err("Creating a key for an unknown service should fail");
+
+    assert!(matches!(err, VentrixError::ReferencedEntityMissing(_)));
+}
+
+async fn revoking_an_unknown_api_key_returns_not_found(database: &dyn Database) {
+    let err = database
+        .revoke_api_key(Uuid::new_v4())
+        .await
+        .expect_err("Revoking an unknown key should fail");
+
+    assert!(matches!(err, VentrixError::ApiKeyNotFound(_)));
+}
+
+async fn event_type_publishers_are_returned_until_their_service_is_removed(
+    database: &dyn Database,
+) {
+    for service in ["payments", "billing"] {
+        database
+            .register_service(&service_request(service))
+            .await
+            .expect("Failed to register service");
+    }
+    database
+        .register_event_type(&NewEventTypeRequest {
+            publishers: Some(vec!["payments".to_string(), "billing".to_string()]),
+            ..event_type_request("payments.settled")
+        })
+        .await
+        .expect("Failed to register event type");
+    database
+        .register_event_type(&event_type_request("test_event"))
+        .await
+        .expect("Failed to register event type");
+
+    assert_eq!(
+        Some(vec!["payments".to_string(), "billing".to_string()]),
+        database
+            .get_publishers_for_event_type("payments.settled")
+            .await
+            .expect("Failed to get publishers")
+    );
+    assert_eq!(
+        None,
+        database
+            .get_publishers_for_event_type("test_event")
+            .await
+            .expect("Failed to get publishers")
+    );
+
+    database
+        .remove_service("billing")
+        .await
+        .expect("Failed to remove service");
+    assert_eq!(
+        Some(vec!["payments".to_string()]),
+        database
+            .get_publishers_for_event_type("payments.settled")
+            .await
+            .expect("Failed to get publishers")
+    );
+
+    let err = database
+        .get_publishers_for_event_type("unknown")
+        .await
+        .expect_err("Getting the publishers of an unknown event type should fail");
+    assert!(matches!(err, VentrixError::EventTypeNotFound(_)));
+}
+
+async fn event_type_with_an_unknown_publisher_returns_referenced_entity_missing(
+    database: &dyn Database,
+) {
+    let err = database
+        .register_event_type(&NewEventTypeRequest {
+            publishers: Some(vec!["ghost".to_string()]),
+            ..event_type_request("payments.settled")
+        })
+        .await
+        .expect_err("Registering an event type with an unknown publisher should fail");
+    assert!(matches!(err, VentrixError::ReferencedEntityMissing(_)));
+
+    let err = database
+        .get_publishers_for_event_type("payments.settled")
+        .await
+        .expect_err("The event type should not be registered");
+    assert!(matches!(err, VentrixError::EventTypeNotFound(_)));
+}
+
+fn publish_denial(event_type: &str, attempted_at: DateTime<Utc>) -> PublishDenial {
+    PublishDenial {
+        id: Uuid::new_v4(),
+        event_type: event_type.to_string(),
+        api_key_id: Uuid::new_v4(),
+        api_key_name: "orders".to_string(),
+        service_name: Some("orders".to_string()),
+        reason: "Service \"orders\" is not an allowed publisher".to_string(),
+        attempted_at,
+    }
+}
+
+async fn publish_denials_are_listed_newest_first(database: &dyn Database, clock: &MockClock) {
+    for event_type in ["payments.settled", "payments.refunded", "payments.failed"] {
+        database
+            .record_publish_denial(&publish_denial(event_type, clock.now()))
+            .await
+            .expect("Failed to record publish denial");
+        clock.advance(ChronoDuration::seconds(1));
+    }
+
+    let denials = database
+        .list_publish_denials(2)
+        .await
+        .expect("Failed to list publish denials");
+
+    assert_eq!(
+        vec!["payments.failed", "payments.refunded"],
+        denials
+            .iter()
+            .map(|denial| denial.event_type.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(Some("orders"), denials[0].service_name.as_deref());
+}
@@ -0,0 +1,693 @@
+use actix_web::web;
+use chrono::Utc;
+use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerName};
+use secrecy::Secret;
+use std::{collections::HashMap, net::TcpListener, sync::Arc, time::Duration};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    service::{interceptor::InterceptedService, Interceptor},
+    transport::{Channel, Endpoint, Uri},
+    Code, Request, Status,
+};
+use tower::service_fn;
+use ventrix::{
+    application::queue_service::ventrix_queue::VentrixQueue,
+    common::{
+        api_keys::new_api_key,
+        clock::SystemClock,
+        configuration::{
+            ApiTlsSettings, BackpressurePolicy, DeliveryAuthSettings, DeliveryTlsSettings,
+            DispatchMode, QueueSettings, RetentionSettings,
+        },
+        types::{ApiKeyRole, CreateApiKeyRequest},
+    },
+    infrastructure::{
+        authentication::Authenticator,
+        grpc::{
+            proto::{
+                ventrix_client::VentrixClient, AcknowledgeRequest, Delivery, GetServiceRequest,
+                PublishEventRequest, RegisterEventTypeRequest, RegisterServiceRequest,
+                SubscribeRequest,
+            },
+            startup::run_grpc,
+        },
+        persistence::{inmemory::InMemoryDatabase, Database},
+    },
+};
+
+#[path = "api/test_certificates.rs"]
+mod test_certificates;
+
+use test_certificates::TestCertificates;
+
+const TEST_ADMIN_KEY: &str = "test-bootstrap-admin-key";
+
+struct GrpcApp {
+    address: String,
+    database: web::Data<dyn Database>,
+}
+
+// Sends the key, if any, as `authorization: Bearer <key>` with every call
+struct Bearer(Option<MetadataValue<Ascii>>);
+
+impl Interceptor for Bearer {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(value) = &self.0 {
+            request
+                .metadata_mut()
+                .insert("authorization", value.clone());
+        }
+        Ok(request)
+    }
+}
+
+type Client = VentrixClient<InterceptedService<Channel, Bearer>>;
+
+impl GrpcApp {
+    async fn client(&self, key: Option<&str>) -> Client {
+        let channel = Channel::from_shared(self.address.clone())
+            .unwrap()
+            .connect()
+            .await
+            .expect("Failed to connect to gRPC server");
+        let bearer = key.map(|key| format!("Bearer {}", key).parse().unwrap());
+        VentrixClient::with_interceptor(channel, Bearer(bearer))
+    }
+
+    // Connects over TLS, trusting the test CA and presenting the client certificate if asked to
+    async fn tls_client(
+        &self,
+        key: &str,
+        certificates: &TestCertificates,
+        client_certificate: bool,
+    ) -> Client {
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(
+                &certificates
+                    .ca
+                    .certificate
+                    .to_der()
+                    .map(Certificate)
+                    .unwrap(),
+            )
+            .unwrap();
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        let mut config = match client_certificate {
+            true => builder
+                .with_single_cert(
+                    vec![Certificate(
+                        certificates.client.certificate.to_der().unwrap(),
+                    )],
+                    PrivateKey(
+                        rustls_pemfile::pkcs8_private_keys(
+                            &mut certificates.client.key_pem().as_bytes(),
+                        )
+                        .unwrap()
+                        .remove(0),
+                    ),
+                )
+                .unwrap(),
+            false => builder.with_no_client_auth(),
+        };
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        let connector = TlsConnector::from(Arc::new(config));
+
+        let channel = Endpoint::from_shared(self.address.clone())
+            .unwrap()
+            .connect_with_connector(service_fn(move |uri: Uri| {
+                let connector = connector.clone();
+                async move {
+                    let address = format!("{}:{}", uri.host().unwrap(), uri.port_u16().unwrap());
+                    let stream = TcpStream::connect(address).await?;
+                    let server_name = ServerName::try_from("localhost").unwrap();
+                    connector.connect(server_name, stream).await
+                }
+            }))
+            .await
+            .expect("Failed to connect to gRPC server");
+        let bearer = format!("Bearer {}", key).parse().unwrap();
+        VentrixClient::with_interceptor(channel, Bearer(Some(bearer)))
+    }
+
+    async fn admin_client(&self) -> Client {
+        self.client(Some(TEST_ADMIN_KEY)).await
+    }
+
+    // Stores a key of the role and returns it
+    async fn create_api_key(
+        &self,
+        role: ApiKeyRole,
+        event_types: Option<Vec<String>>,
+        service_name: Option<&str>,
+    ) -> String {
+        let request = CreateApiKeyRequest {
+            name: String::from("grpc-tests"),
+            role,
+            event_types,
+            service_name: service_name.map(String::from),
+        };
+        let (api_key, key) = new_api_key(request, Utc::now()).expect("Invalid key request");
+        self.database
+            .create_api_key(&api_key)
+            .await
+            .expect("Failed to store key");
+        key
+    }
+}
+
+async fn spawn_grpc_app() -> GrpcApp {
+    spawn_grpc_app_with_stream_ack_timeout(30_000).await
+}
+
+async fn spawn_grpc_app_with_stream_ack_timeout(stream_ack_timeout_milliseconds: u64) -> GrpcApp {
+    spawn_grpc_app_with_settings(stream_ack_timeout_milliseconds, None).await
+}
+
+async fn spawn_grpc_app_with_settings(
+    stream_ack_timeout_milliseconds: u64,
+    tls_settings: Option<ApiTlsSettings>,
+) -> GrpcApp {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to random port");
+    let port = listener.local_addr().unwrap().port();
+
+    let db_arc: Arc<dyn Database> = Arc::new(InMemoryDatabase::default());
+    let database = web::Data::from(db_arc);
+    let queue_settings = QueueSettings {
+        retry_reconcile_interval_milliseconds: 30_000,
+        retry_release_per_second: 20,
+        delivery_timeout_milliseconds: 10_000,
+        dispatch_mode: DispatchMode::InProcess,
+        capacity: 50,
+        backpressure: BackpressurePolicy::Block,
+        backpressure_timeout_milliseconds: 5_000,
+        retry_after_seconds: 1,
+        readiness_depth_threshold: 40,
+        task_restart_backoff_milliseconds: 10,
+        task_restart_max_backoff_milliseconds: 1000,
+        replay_per_second: 10,
+        stream_ack_timeout_milliseconds,
+        retention: RetentionSettings {
+            prune_interval_milliseconds: 3_600_000,
+            batch_size: 500,
+            batch_pause_milliseconds: 100,
+            fulfilled_days: None,
+            dead_letter_days: None,
+            unfulfilled_days: None,
+            event_types: vec![],
+            archive_directory: None,
+        },
+        delivery_auth: DeliveryAuthSettings {
+            encryption_key: Some(Secret::new(
+                "dGVzdC1kZWxpdmVyeS1lbmNyeXB0aW9uLWtleS0zMmI=".to_string(),
+            )),
+            token_refresh_margin_seconds: 30,
+        },
+        delivery_tls: DeliveryTlsSettings {
+            ca_bundle_paths: vec![],
+            client_certificate_path: None,
+            client_key_path: None,
+        },
+    };
+    let ventrix_queue = web::Data::new(
+        VentrixQueue::new(database.clone(), queue_settings, Arc::new(SystemClock))
+            .await
+            .expect("Invalid queue settings"),
+    );
+
+    let authenticator = Authenticator::new(
+        database.clone(),
+        Some(&Secret::new(TEST_ADMIN_KEY.to_string())),
+    );
+    let server = run_grpc(
+        listener,
+        database.clone(),
+        ventrix_queue,
+        HashMap::new(),
+        tls_settings.as_ref(),
+        authenticator,
+    )
+    .expect("Failed to bind address");
+    tokio::spawn(server);
+
+    GrpcApp {
+        address: format!("http://127.0.0.1:{}", port),
+        database,
+    }
+}
+
+#[tokio::test]
+async fn registered_service_can_be_read_back_over_grpc() {
+    let app = spawn_grpc_app().await;
+    let mut client = app.admin_client().await;
+
+    client
+        .register_service(RegisterServiceRequest {
+            name: String::from("vinnie"),
+            url: String::from("http://localhost:8081"),
+            delivery: Some(String::from(r#"{"headers":{"X-Tenant":"acme"}}"#)),
+        })
+        .await
+        .expect("Failed to register service");
+
+    let service = client
+        .get_service(GetServiceRequest {
+            name: String::from("vinnie"),
+        })
+        .await
+        .expect("Failed to get service")
+        .into_inner();
+
+    assert_eq!("vinnie", service.name);
+    assert_eq!("http://localhost:8081", service.url);
+}
+
+#[tokio::test]
+async fn registering_a_service_twice_returns_already_exists() {
+    let app = spawn_grpc_app().await;
+    let mut client = app.admin_client().await;
+
+    let request = RegisterServiceRequest {
+        name: String::from("viktor"),
+        url: String::from("http://localhost:8082"),
+        delivery: None,
+    };
+
+    client
+        .register_service(request.clone())
+        .await
+        .expect("Failed to register service");
+    let status = client
+        .register_service(request)
+        .await
+        .expect_err("Registering a duplicate service should fail");
+
+    assert_eq!(Code::AlreadyExists, status.code());
+}
+
+#[tokio::test]
+async fn invalid_delivery_settings_return_invalid_argument() {
+    let app = spawn_grpc_app().await;
+    let mut client = app.admin_client().await;
+
+    let status = client
+        .register_service(RegisterServiceRequest {
+            name: String::from("vinnie"),
+            url: String::from("http://localhost:8081"),
+            delivery: Some(String::from(r#"{"auth":{"type":"digest"}}"#)),
+        })
+        .await
+        .expect_err("Registering with unknown auth should fail");
+
+    assert_eq!(Code::InvalidArgument, status.code());
+}
+
+#[tokio::test]
+async fn calls_without_a_valid_key_return_unauthenticated() {
+    let app = spawn_grpc_app().await;
+    let request = GetServiceRequest {
+        name: String::from("vinnie"),
+    };
+
+    for key in [None, Some("vtx_unknown")] {
+        let status = app
+            .client(key)
+            .await
+            .get_service(request.clone())
+            .await
+            .expect_err("Calls without a valid key should fail");
+        assert_eq!(Code::Unauthenticated, status.code(), "{:?}", key);
+    }
+}
+
+#[tokio::test]
+async fn calls_outside_the_key_role_return_permission_denied() {
+    let app = spawn_grpc_app().await;
+    let mut admin = app.admin_client().await;
+    admin
+        .register_service(RegisterServiceRequest {
+            name: String::from("vinnie"),
+            url: String::from("http://localhost:8081"),
+            delivery: None,
+        })
+        .await
+        .expect("Failed to register service");
+    admin
+        .register_event_type(RegisterEventTypeRequest {
+            name: String::from("payments.settled"),
+            description: String::from("A payment was settled"),
+            payload_definition: String::from(r#"{"type":"object"}"#),
+            publishers: vec![],
+        })
+        .await
+        .expect("Failed to register event type");
+
+    let subscriber_key = app
+        .create_api_key(ApiKeyRole::Subscriber, None, Some("vinnie"))
+        .await;
+    let mut subscriber = app.client(Some(&subscriber_key)).await;
+    subscriber
+        .get_service(GetServiceRequest {
+            name: String::from("vinnie"),
+        })
+        .await
+        .expect("A subscriber should read its own service");
+    let status = subscriber
+        .register_service(RegisterServiceRequest {
+            name: String::from("tony"),
+            url: String::from("http://localhost:8082"),
+            delivery: None,
+        })
+        .await
+        .expect_err("Subscribers may not register services");
+    assert_eq!(Code::PermissionDenied, status.code());
+
+    let publisher_key = app
+        .create_api_key(
+            ApiKeyRole::Publisher,
+            Some(vec![String::from("orders.*")]),
+            None,
+        )
+        .await;
+    let status = app
+        .client(Some(&publisher_key))
+        .await
+        .publish_event(PublishEventRequest {
+            event_type: String::from("payments.settled"),
+            payload: String::from("{}"),
+        })
+        .await
+        .expect_err("Publishing outside the key scope should fail");
+    assert_eq!(Code::PermissionDenied, status.code());
+    let status = app
+        .client(Some(&publisher_key))
+        .await
+        .publish_event(PublishEventRequest {
+            event_type: String::from("payments.refunded"),
+            payload: String::from("{}"),
+        })
+        .await
+        .expect_err("Publishing an unregistered event type outside the key scope should fail");
+    assert_eq!(Code::PermissionDenied, status.code());
+}
+
+#[tokio::test]
+async fn publishing_over_grpc_requires_a_client_certificate_when_a_client_ca_is_set() {
+    let certificates = TestCertificates::generate();
+    let tls_settings = ApiTlsSettings {
+        certificate_path: TestCertificates::write(&certificates.server.certificate_pem()),
+        key_path: TestCertificates::write(&certificates.server.key_pem()),
+        client_ca_path: Some(TestCertificates::write(&certificates.ca.certificate_pem())),
+        reload_interval_milliseconds: 50,
+    };
+    let app = spawn_grpc_app_with_settings(30_000, Some(tls_settings)).await;
+    let mut client = app.tls_client(TEST_ADMIN_KEY, &certificates, false).await;
+    client
+        .register_event_type(RegisterEventTypeRequest {
+            name: String::from("orders.created"),
+            description: String::from("An order was created"),
+            payload_definition: String::from(r#"{"type":"object"}"#),
+            publishers: vec![],
+        })
+        .await
+        .expect("Only publishing requires a client certificate");
+    let request = PublishEventRequest {
+        event_type: String::from("orders.created"),
+        payload: String::from("{}"),
+    };
+
+    let status = client
+        .publish_event(request.clone())
+        .await
+        .expect_err("Publishing without a client certificate should fail");
+    assert_eq!(Code::PermissionDenied, status.code());
+    let status = client
+        .publish_events(tokio_stream::iter(vec![request.clone()]))
+        .await
+        .expect_err("Publishing without a client certificate should fail");
+    assert_eq!(Code::PermissionDenied, status.code());
+
+    app.tls_client(TEST_ADMIN_KEY, &certificates, true)
+        .await
+        .publish_event(request)
+        .await
+        .expect("Publishing with a client certificate should succeed");
+}
+
+#[tokio::test]
+async fn only_allowed_services_publish_owned_event_types_over_grpc() {
+    let app = spawn_grpc_app().await;
+    let mut admin = app.admin_client().await;
+    for name in ["payments", "orders"] {
+        admin
+            .register_service(RegisterServiceRequest {
+                name: String::from(name),
+                url: String::from("http://localhost:8081"),
+                delivery: None,
+            })
+            .await
+            .expect("Failed to register service");
+    }
+    let registered = admin
+        .register_event_type(RegisterEventTypeRequest {
+            name: String::from("payments.settled"),
+            description: String::from("A payment was settled"),
+            payload_definition: String::from(
+                r#"{"type":"object","properties":{"name":{"type":"string"}}}"#,
+            ),
+            publishers: vec![String::from("payments")],
+        })
+        .await
+        .expect("Failed to register event type")
+        .into_inner();
+    assert_eq!(vec![String::from("payments")], registered.publishers);
+    let request = PublishEventRequest {
+        event_type: String::from("payments.settled"),
+        payload: String::from(r#"{"name":"John Rustsworth"}"#),
+    };
+
+    let payments_key = app
+        .create_api_key(
+            ApiKeyRole::Publisher,
+            Some(vec![String::from("payments.*")]),
+            Some("payments"),
+        )
+        .await;
+    app.client(Some(&payments_key))
+        .await
+        .publish_event(request.clone())
+        .await
+        .expect("The owning service should publish");
+
+    let orders_key = app
+        .create_api_key(
+            ApiKeyRole::Publisher,
+            Some(vec![String::from("payments.*")]),
+            Some("orders"),
+        )
+        .await;
+    let status = app
+        .client(Some(&orders_key))
+        .await
+        .publish_event(request)
+        .await
+        .expect_err("Other services may not publish the event type");
+    assert_eq!(Code::PermissionDenied, status.code());
+
+    let denials = app
+        .database
+        .list_publish_denials(10)
+        .await
+        .expect("Failed to list denials");
+    assert_eq!(1, denials.len());
+    assert_eq!("payments.settled", denials[0].event_type);
+    assert_eq!(Some(String::from("orders")), denials[0].service_name);
+}
+
+const ORDER_SCHEMA: &str = r#"{"type":"object","properties":{"amount":{"type":"number"}}}"#;
+
+// Registers vinnie and the order event types, and returns a client with vinnie's key
+async fn order_stream_subscriber(app: &GrpcApp) -> Client {
+    let mut admin = app.admin_client().await;
+    admin
+        .register_service(RegisterServiceRequest {
+            name: String::from("vinnie"),
+            url: String::from("http://localhost:8081"),
+            delivery: None,
+        })
+        .await
+        .expect("Failed to register service");
+    for name in ["orders.created", "orders.cancelled", "payments.settled"] {
+        admin
+            .register_event_type(RegisterEventTypeRequest {
+                name: String::from(name),
+                description: String::from("An order event"),
+                payload_definition: String::from(ORDER_SCHEMA),
+                publishers: vec![],
+            })
+            .await
+            .expect("Failed to register event type");
+    }
+    let key = app
+        .create_api_key(ApiKeyRole::Subscriber, None, Some("vinnie"))
+        .await;
+    app.client(Some(&key)).await
+}
+
+async fn publish(app: &GrpcApp, event_type: &str, payload: &str) -> String {
+    app.admin_client()
+        .await
+        .publish_event(PublishEventRequest {
+            event_type: String::from(event_type),
+            payload: String::from(payload),
+        })
+        .await
+        .expect("Failed to publish event")
+        .into_inner()
+        .id
+}
+
+async fn next_delivery(stream: &mut tonic::Streaming<Delivery>) -> Delivery {
+    tokio::time::timeout(Duration::from_secs(5), stream.message())
+        .await
+        .expect("No delivery arrived in time")
+        .expect("The stream failed")
+        .expect("The stream ended")
+}
+
+// Waits for the queue to record a failed stream delivery of the event
+async fn failed_stream_attempt(app: &GrpcApp, event_id: &str) -> String {
+    let event_id = uuid::Uuid::parse_str(event_id).unwrap();
+    for _ in 0..50 {
+        let attempts = app
+            .database
+            .get_delivery_attempts(event_id)
+            .await
+            .expect("Failed to read delivery attempts");
+        if let Some(attempt) = attempts.iter().find(|attempt| !attempt.succeeded) {
+            assert_eq!("stream", attempt.endpoint);
+            return attempt.error.clone().unwrap_or_default();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!(
+        "No failed delivery attempt was recorded for event {}",
+        event_id
+    );
+}
+
+#[tokio::test]
+async fn streams_take_patterns_filters_and_transforms() {
+    let app = spawn_grpc_app().await;
+    let mut vinnie = order_stream_subscriber(&app).await;
+
+    let mut stream = vinnie
+        .subscribe(SubscribeRequest {
+            service_name: String::from("vinnie"),
+            event_type: String::from("orders.*"),
+            filter: Some(String::from("payload.amount > 10")),
+            transform: Some(String::from(
+                r#"{"total":"payload.amount","kind":"event_type"}"#,
+            )),
+            target_schema: None,
+        })
+        .await
+        .expect("Failed to subscribe")
+        .into_inner();
+
+    publish(&app, "payments.settled", r#"{"amount":50}"#).await;
+    publish(&app, "orders.created", r#"{"amount":5}"#).await;
+    let event_id = publish(&app, "orders.cancelled", r#"{"amount":50}"#).await;
+
+    let delivery = next_delivery(&mut stream).await;
+    assert_eq!(event_id, delivery.id);
+    assert_eq!("orders.cancelled", delivery.event_type);
+    let payload: serde_json::Value = serde_json::from_str(&delivery.payload).unwrap();
+    assert_eq!(
+        serde_json::json!({ "total": 50, "kind": "orders.cancelled" }),
+        payload
+    );
+    vinnie
+        .acknowledge(AcknowledgeRequest {
+            service_name: String::from("vinnie"),
+            event_id,
+            success: true,
+        })
+        .await
+        .expect("Failed to acknowledge the delivery");
+
+    let status = vinnie
+        .subscribe(SubscribeRequest {
+            service_name: String::from("vinnie"),
+            event_type: String::from("orders.created"),
+            filter: Some(String::from("payload.surname == \"Rustsworth\"")),
+            transform: None,
+            target_schema: None,
+        })
+        .await
+        .expect_err("A filter on fields outside the schema should be refused");
+    assert_eq!(Code::InvalidArgument, status.code());
+}
+
+#[tokio::test]
+async fn unacknowledged_stream_deliveries_fail_after_the_ack_timeout() {
+    let app = spawn_grpc_app_with_stream_ack_timeout(200).await;
+    let mut vinnie = order_stream_subscriber(&app).await;
+    let mut stream = vinnie
+        .subscribe(SubscribeRequest {
+            service_name: String::from("vinnie"),
+            event_type: String::from("orders.created"),
+            filter: None,
+            transform: None,
+            target_schema: None,
+        })
+        .await
+        .expect("Failed to subscribe")
+        .into_inner();
+
+    let event_id = publish(&app, "orders.created", r#"{"amount":5}"#).await;
+    assert_eq!(event_id, next_delivery(&mut stream).await.id);
+
+    let error = failed_stream_attempt(&app, &event_id).await;
+    assert!(error.contains("did not acknowledge"), "{}", error);
+    assert!(app.database.get_next_retry_time().await.unwrap().is_some());
+
+    // A late acknowledgement no longer finds the delivery
+    let status = vinnie
+        .acknowledge(AcknowledgeRequest {
+            service_name: String::from("vinnie"),
+            event_id,
+            success: true,
+        })
+        .await
+        .expect_err("The timed out delivery should be gone");
+    assert_eq!(Code::NotFound, status.code());
+}
+
+#[tokio::test]
+async fn stream_deliveries_of_disconnected_subscribers_fail() {
+    let app = spawn_grpc_app_with_stream_ack_timeout(200).await;
+    let mut vinnie = order_stream_subscriber(&app).await;
+    let mut stream = vinnie
+        .subscribe(SubscribeRequest {
+            service_name: String::from("vinnie"),
+            event_type: String::from("orders.#"),
+            filter: None,
+            transform: None,
+            target_schema: None,
+        })
+        .await
+        .expect("Failed to subscribe")
+        .into_inner();
+
+    let event_id = publish(&app, "orders.created", r#"{"amount":5}"#).await;
+    assert_eq!(event_id, next_delivery(&mut stream).await.id);
+    drop(stream);
+
+    let error = failed_stream_attempt(&app, &event_id).await;
+    assert!(error.contains("disconnected"), "{}", error);
+}
@@ -1,127 +0,0 @@
-use actix_web::web;
-use once_cell::sync::Lazy;
-use secrecy::ExposeSecret;
-use sqlx::{Connection, Executor, PgConnection, PgPool};
-use std::sync::Arc;
-use std::{collections::HashMap, net::TcpListener};
-use uuid::Uuid;
-use ventrix::common::types::FeatureFlagConfig;
-use ventrix::infrastructure::persistence::postgres::PostgresDatabase;
-use ventrix::infrastructure::persistence::Database;
-use ventrix::{
-    common::{
-        configuration::{get_configuration, DatabaseSettings},
-        telemetry::{get_subscriber, init_tracing_subscriber},
-    },
-    infrastructure::web::startup::run,
-};
-
-static TRACING: Lazy<()> = Lazy::new(|| {
-    let default_filter_level = "info".to_string();
-    let subscriber_name = "test".to_string();
-
-    if std::env::var("TEST_LOG").is_ok() {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
-        init_tracing_subscriber(subscriber);
-    } else {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
-        init_tracing_subscriber(subscriber);
-    };
-});
-
-#[tokio::test]
-#[ignore = "Here for reference"]
-async fn health_check_works() {
-    let test_app = spawn_app().await;
-    let client = reqwest::Client::new();
-
-    let response = client
-        .get(&format!("{}/health_check", &test_app.address))
-        .send()
-        .await
-        .expect("Failed to execute request.");
-
-    assert!(response.status().is_success());
-    assert_eq!(Some(0), response.content_length());
-}
-
-#[tokio::test]
-#[ignore = "Here for reference"]
-async fn subscribe_returns_a_400_when_data_is_missing() {
-    let test_app = spawn_app().await;
-    let client = reqwest::Client::new();
-    let test_cases = vec![
-        ("name=cameron%20raw", "missing the email"),
-        ("email=cameron.raw89%40gmail.com", "missing the name"),
-        ("", "missing both the name and email"),
-    ];
-
-    for (invalid_body, error_message) in test_cases {
-        let response = client
-            .post(&format!("{}/subscriptions", &test_app.address))
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(invalid_body)
-            .send()
-            .await
-            .expect("Failed to execute request.");
-
-        assert_eq!(
-            400,
-            response.status().as_u16(),
-            "The API did not fail with 400 Bad Request when the payload was {}",
-            error_message
-        );
-    }
-}
-
-pub struct TestApp {
-    pub address: String,
-    pub db_pool: PgPool,
-}
-
-async fn spawn_app() -> TestApp {
-    Lazy::force(&TRACING);
-    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to random port");
-    let port = listener.local_addr().unwrap().port();
-    let address = format!("http://127.0.0.1:{}", port);
-
-    let mut configuration = get_configuration().expect("Failed to read configuration");
-
-    configuration.database.database_name = Uuid::new_v4().to_string();
-    let pool = configure_database(&configuration.database).await;
-
-    let feature_flags: FeatureFlagConfig = HashMap::new();
-
-    let db_arc: Arc<dyn Database> = Arc::new(PostgresDatabase::new(pool.clone()));
-
-    let server = run(listener, web::Data::from(db_arc), feature_flags)
-        .await
-        .expect("Failed to bind address");
-    let _ = tokio::spawn(server).await;
-
-    TestApp {
-        address,
-        db_pool: pool,
-    }
-}
-
-async fn configure_database(config: &DatabaseSettings) -> PgPool {
-    let mut connection =
-        PgConnection::connect(config.connection_string_without_db().expose_secret())
-            .await
-            .expect("Failed to connect to Postgres");
-    connection
-        .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
-        .await
-        .expect("Failed to create database");
-
-    let connection_pool = PgPool::connect(config.connection_string().expose_secret())
-        .await
-        .expect("Failed to connect to Postgres");
-    sqlx::migrate!("./migrations")
-        .run(&connection_pool)
-        .await
-        .expect("Failed to migrate the database");
-
-    connection_pool
-}